@@ -0,0 +1,311 @@
+use super::basics::{Address, Register, Value, PROGRAM_START};
+use super::program::Instruction;
+use std::collections::HashMap;
+use std::fmt;
+
+/// An error in assembly source, with the line (counting from 1) it occurred in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    V(u8),
+    I,
+    /// `[I]`, the memory that I points to.
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    B,
+    Number(u16),
+}
+
+/// A line of source without comment and label.
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+impl Statement<'_> {
+    /// The number of bytes the statement assembles to.
+    fn size(&self) -> u16 {
+        match self.mnemonic.as_str() {
+            "DB" => self.operands.len() as u16,
+            "DW" => 2 * self.operands.len() as u16,
+            _ => 2,
+        }
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits the source into statements and collects the addresses of the
+/// labels.
+fn parse_lines(source: &str) -> Result<(Vec<Statement<'_>>, HashMap<String, u16>), AsmError> {
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut addr = PROGRAM_START;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = text.split(';').next().unwrap().trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(AsmError {
+                    line,
+                    message: format!("invalid label '{}'", label),
+                });
+            }
+            if labels.insert(label.to_string(), addr).is_some() {
+                return Err(AsmError {
+                    line,
+                    message: format!("label '{}' is defined twice", label),
+                });
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (
+                mnemonic,
+                operands.split(',').map(str::trim).collect::<Vec<_>>(),
+            ),
+            None => (text, Vec::new()),
+        };
+        let statement = Statement {
+            line,
+            mnemonic: mnemonic.to_uppercase(),
+            operands,
+        };
+        addr = addr.wrapping_add(statement.size());
+        statements.push(statement);
+    }
+    Ok((statements, labels))
+}
+
+fn parse_operand(text: &str, labels: &HashMap<String, u16>) -> Result<Operand, String> {
+    let upper = text.to_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        _ => {
+            if let Some(reg) = upper
+                .strip_prefix('V')
+                .filter(|reg| reg.len() == 1)
+                .and_then(|reg| u8::from_str_radix(reg, 16).ok())
+            {
+                Operand::V(reg)
+            } else if let Some(addr) = labels.get(text) {
+                Operand::Number(*addr)
+            } else {
+                let number = match upper.strip_prefix("0X") {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => text.parse(),
+                };
+                Operand::Number(number.map_err(|_| format!("unknown operand '{}'", text))?)
+            }
+        }
+    };
+    Ok(operand)
+}
+
+fn limit(value: u16, max: u16, what: &str) -> Result<u16, String> {
+    if value <= max {
+        Ok(value)
+    } else {
+        Err(format!("{} {:#X} is out of range", what, value))
+    }
+}
+
+/// Builds the instruction that a mnemonic with operands stands for.
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+    use Instruction::*;
+    use Operand::*;
+    let addr = |nnn: u16| limit(nnn, 0xFFF, "address").map(Address);
+    let byte = |kk: u16| limit(kk, 0xFF, "byte").map(|kk| Value(kk as u8));
+    let instruction = match (mnemonic, operands) {
+        ("NOP", []) => Noop,
+        ("CLS", []) => ClearDisplay,
+        ("RET", []) => ReturnSubroutine,
+        ("SYS", [Number(nnn)]) => MachineCodeRoutine(addr(*nnn)?),
+        ("JP", [Number(nnn)]) => Jump(addr(*nnn)?),
+        ("JP", [V(0), Number(nnn)]) => JumpAdd(addr(*nnn)?),
+        ("CALL", [Number(nnn)]) => CallSubroutine(addr(*nnn)?),
+        ("SE", [V(x), Number(kk)]) => IfNotEqualConst(Register(*x), byte(*kk)?),
+        ("SNE", [V(x), Number(kk)]) => IfEqualConst(Register(*x), byte(*kk)?),
+        ("SE", [V(x), V(y)]) => IfNotEqual(Register(*x), Register(*y)),
+        ("LD", [V(x), Number(kk)]) => SetConst(Register(*x), byte(*kk)?),
+        ("ADD", [V(x), Number(kk)]) => AddConst(Register(*x), byte(*kk)?),
+        ("LD", [V(x), V(y)]) => Set(Register(*x), Register(*y)),
+        ("OR", [V(x), V(y)]) => Or(Register(*x), Register(*y)),
+        ("AND", [V(x), V(y)]) => And(Register(*x), Register(*y)),
+        ("XOR", [V(x), V(y)]) => Xor(Register(*x), Register(*y)),
+        ("ADD", [V(x), V(y)]) => Add(Register(*x), Register(*y)),
+        ("SUB", [V(x), V(y)]) => Sub(Register(*x), Register(*y)),
+        // Without a source register, a register is shifted by itself, which
+        // works with and without the shift_vy quirk.
+        ("SHR", [V(x)]) => RightShift(Register(*x), Register(*x)),
+        ("SHR", [V(x), V(y)]) => RightShift(Register(*x), Register(*y)),
+        ("SUBN", [V(x), V(y)]) => NegSub(Register(*x), Register(*y)),
+        ("SHL", [V(x)]) => LeftShift(Register(*x), Register(*x)),
+        ("SHL", [V(x), V(y)]) => LeftShift(Register(*x), Register(*y)),
+        ("SNE", [V(x), V(y)]) => IfEqual(Register(*x), Register(*y)),
+        ("LD", [I, Number(nnn)]) => SetI(addr(*nnn)?),
+        ("RND", [V(x), Number(kk)]) => Rand(Register(*x), byte(*kk)?),
+        ("DRW", [V(x), V(y), Number(n)]) => Draw(
+            Register(*x),
+            Register(*y),
+            Value(limit(*n, 0xF, "height")? as u8),
+        ),
+        ("SKP", [V(x)]) => IfNotKey(Register(*x)),
+        ("SKNP", [V(x)]) => IfKey(Register(*x)),
+        ("LD", [V(x), DT]) => GetDelayTimer(Register(*x)),
+        ("LD", [V(x), K]) => WaitKey(Register(*x)),
+        ("LD", [DT, V(x)]) => SetDelayTimer(Register(*x)),
+        ("LD", [ST, V(x)]) => SetSoundTimer(Register(*x)),
+        ("ADD", [I, V(x)]) => AddToI(Register(*x)),
+        ("LD", [F, V(x)]) => SpriteAddr(Register(*x)),
+        ("LD", [B, V(x)]) => Decimal(Register(*x)),
+        ("LD", [IndirectI, V(x)]) => StoreRegisters(Register(*x)),
+        ("LD", [V(x), IndirectI]) => LoadRegisters(Register(*x)),
+        _ => return Err(format!("invalid operands for {}", mnemonic)),
+    };
+    Ok(instruction)
+}
+
+const MNEMONICS: [&str; 21] = [
+    "NOP", "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+    "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP",
+];
+
+/// Assembles a program in the syntax of Cowgod's CHIP-8 reference, which is
+/// also the syntax of `disasm`. Every line holds an optional `label:`, an
+/// instruction like `LD V3, 0x1F` or `JP loop`, or a `db`/`dw` directive with
+/// comma-separated bytes or words, followed by an optional `; comment`.
+/// Labels stand for the address of the line they are defined in, with the
+/// program starting at `PROGRAM_START`.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let (statements, labels) = parse_lines(source)?;
+    let mut program = Vec::new();
+    for statement in statements {
+        let error = |message: String| AsmError {
+            line: statement.line,
+            message,
+        };
+        let operands = statement
+            .operands
+            .iter()
+            .map(|text| parse_operand(text, &labels))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+        let numbers = || {
+            operands
+                .iter()
+                .map(|operand| match operand {
+                    Operand::Number(number) => Ok(*number),
+                    _ => Err(error(format!("{} takes numbers", statement.mnemonic))),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for number in numbers()? {
+                    program.push(limit(number, 0xFF, "byte").map_err(error)? as u8);
+                }
+            }
+            "DW" => {
+                for number in numbers()? {
+                    program.extend(&number.to_be_bytes());
+                }
+            }
+            mnemonic if MNEMONICS.contains(&mnemonic) => {
+                let instruction = encode(mnemonic, &operands).map_err(error)?;
+                program.extend(&instruction.to_16bit());
+            }
+            mnemonic => return Err(error(format!("unknown instruction '{}'", mnemonic))),
+        }
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{self, disassemble};
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ; Counts V0 up forever.
+            start:  LD V0, 0
+            loop:   ADD V0, 1     ; next
+                    JP loop
+            data:   db 0x12, 255
+                    dw start, data
+        ";
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0x60, 0x00, 0x70, 0x01, 0x12, 0x02, 0x12, 0xFF, 0x02, 0x00, 0x02, 0x06
+            ])
+        );
+        assert_eq!(assemble("shl va").unwrap(), vec![0x8A, 0xAE]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source).unwrap_err().to_string();
+        assert_eq!(error("CLS\nFOO V1"), "line 2: unknown instruction 'FOO'");
+        assert_eq!(error("LD V1, 0x100"), "line 1: byte 0x100 is out of range");
+        assert_eq!(error("JP nowhere"), "line 1: unknown operand 'nowhere'");
+        assert_eq!(error("DRW V1, 5"), "line 1: invalid operands for DRW");
+        assert_eq!(
+            error("a: CLS\na: CLS"),
+            "line 2: label 'a' is defined twice"
+        );
+        assert_eq!(error("db V1"), "line 1: DB takes numbers");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Every opcode that the disassembler turns into an instruction
+        // assembles back to itself.
+        for opcode in 0..=u16::MAX {
+            let [a, b] = opcode.to_be_bytes();
+            let text = disasm::mnemonic(a, b);
+            if !text.starts_with("DW") {
+                assert_eq!(assemble(&text), Ok(vec![a, b]), "{}", text);
+            }
+        }
+        let program = [0x6A, 0x02, 0xDA, 0xB6, 0x50, 0x01, 0x12, 0x00];
+        let source: Vec<_> = disassemble(&program)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(assemble(&source.join("\n")), Ok(program.to_vec()));
+    }
+}
@@ -1,7 +1,12 @@
 pub const MEMORY_SIZE: usize = 4096;
 pub const SCREEN_WIDTH: u8 = 64;
 pub const SCREEN_HEIGHT: u8 = 32;
+/// The height of the two-page hi-res variant. Screen buffers are sized for it,
+/// while the visible height is chosen at runtime.
+pub const HIRES_SCREEN_HEIGHT: u8 = 64;
 pub const FONT_OFFSET: u16 = 0;
+/// Where programs are loaded and start executing.
+pub const PROGRAM_START: u16 = 0x200;
 pub const STACK_DEPTH: usize = 16;
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -0,0 +1,210 @@
+use super::basics::{Address, PROGRAM_START};
+use super::decompile::{analyze, decode, offset_of};
+use super::program::Instruction;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// How control gets from one basic block to another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    /// Execution simply goes on with the next instruction.
+    Next,
+    Jump,
+    /// A BNNN jump, which lands at NNN only if V0 is zero.
+    JumpWithOffset,
+    Call,
+    /// The instruction after a call, where the subroutine returns to.
+    Return,
+    /// A skip instruction skipped the next instruction.
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub to: Address,
+    pub kind: EdgeKind,
+}
+
+/// Instructions that are always executed one after another, ending at a
+/// jump, call, return or skip or right before another block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: Address,
+    /// The address and the assembly text of every instruction.
+    pub instructions: Vec<(Address, String)>,
+    pub edges: Vec<Edge>,
+}
+
+fn to_addr(offset: usize) -> Address {
+    Address(PROGRAM_START + offset as u16)
+}
+
+/// The edges out of a block that ends with `instruction` at `offset`, or
+/// `None` if the instruction does not end a block.
+fn exits(instruction: &Instruction, offset: usize) -> Option<Vec<Edge>> {
+    let edge = |to, kind| Edge { to, kind };
+    let next = to_addr(offset + 2);
+    let exits = match instruction {
+        Instruction::Jump(addr) => vec![edge(*addr, EdgeKind::Jump)],
+        Instruction::JumpAdd(addr) => vec![edge(*addr, EdgeKind::JumpWithOffset)],
+        Instruction::CallSubroutine(addr) => {
+            vec![edge(*addr, EdgeKind::Call), edge(next, EdgeKind::Return)]
+        }
+        Instruction::ReturnSubroutine => Vec::new(),
+        Instruction::IfNotEqualConst(..)
+        | Instruction::IfEqualConst(..)
+        | Instruction::IfNotEqual(..)
+        | Instruction::IfEqual(..)
+        | Instruction::IfNotKey(..)
+        | Instruction::IfKey(..) => vec![
+            edge(next, EdgeKind::Next),
+            edge(to_addr(offset + 4), EdgeKind::Skip),
+        ],
+        _ => return None,
+    };
+    Some(exits)
+}
+
+/// The control flow graph of the instructions that are reachable from the
+/// start of a program, as found by the decompiler.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlFlowGraph {
+    /// The basic blocks, ordered by address.
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(program: &[u8]) -> ControlFlowGraph {
+        let code = analyze(program).code;
+        let mut leaders: BTreeSet<usize> = code.iter().take(1).copied().collect();
+        for offset in &code {
+            let instruction = decode(program, *offset).unwrap();
+            if let Some(exits) = exits(&instruction, *offset) {
+                leaders.extend(
+                    exits
+                        .iter()
+                        .filter_map(|edge| offset_of(program, edge.to))
+                        .filter(|target| code.contains(target)),
+                );
+            }
+        }
+        let blocks = leaders
+            .iter()
+            .map(|leader| {
+                let mut instructions = Vec::new();
+                let mut edges = Vec::new();
+                let mut offset = *leader;
+                loop {
+                    let instruction = decode(program, offset).unwrap();
+                    instructions.push((to_addr(offset), instruction.to_string()));
+                    if let Some(exits) = exits(&instruction, offset) {
+                        edges.extend(exits.into_iter().filter(|edge| {
+                            offset_of(program, edge.to)
+                                .is_some_and(|target| leaders.contains(&target))
+                        }));
+                        break;
+                    }
+                    offset += 2;
+                    if leaders.contains(&offset) {
+                        edges.push(Edge {
+                            to: to_addr(offset),
+                            kind: EdgeKind::Next,
+                        });
+                        break;
+                    }
+                    if !code.contains(&offset) {
+                        break;
+                    }
+                }
+                BasicBlock {
+                    start: to_addr(*leader),
+                    instructions,
+                    edges,
+                }
+            })
+            .collect();
+        ControlFlowGraph { blocks }
+    }
+
+    /// Writes the graph in the DOT language of Graphviz, with one box per
+    /// block listing its instructions. Calls are dashed, and skips and jumps
+    /// with offset are labelled.
+    pub fn to_dot(&self) -> String {
+        let mut dot =
+            String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in &self.blocks {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|(addr, text)| format!("{:#05X}: {}\\l", addr.0, text))
+                .collect();
+            let _ = writeln!(dot, "    b{:03x} [label=\"{}\"];", block.start.0, label);
+        }
+        for block in &self.blocks {
+            for edge in &block.edges {
+                let attributes = match edge.kind {
+                    EdgeKind::Next | EdgeKind::Jump => "",
+                    EdgeKind::JumpWithOffset => " [label=\"+V0\"]",
+                    EdgeKind::Call => " [style=dashed, label=\"call\"]",
+                    EdgeKind::Return => " [style=dotted]",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                };
+                let _ = writeln!(
+                    dot,
+                    "    b{:03x} -> b{:03x}{};",
+                    block.start.0, edge.to.0, attributes
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: [u8; 14] = [
+        0x60, 0x00, // 0x200: LD V0, 0x00
+        0x22, 0x0A, // 0x202: CALL 0x20A
+        0x30, 0x05, // 0x204: SE V0, 0x05
+        0x12, 0x02, // 0x206: JP 0x202
+        0x12, 0x08, // 0x208: JP 0x208
+        0x70, 0x01, // 0x20A: ADD V0, 0x01
+        0x00, 0xEE, // 0x20C: RET
+    ];
+
+    #[test]
+    fn test_blocks() {
+        let cfg = ControlFlowGraph::new(&PROGRAM);
+        let starts: Vec<_> = cfg.blocks.iter().map(|block| block.start.0).collect();
+        assert_eq!(starts, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A]);
+        let edge = |to, kind| Edge {
+            to: Address(to),
+            kind,
+        };
+        assert_eq!(cfg.blocks[0].edges, [edge(0x202, EdgeKind::Next)]);
+        assert_eq!(
+            cfg.blocks[1].edges,
+            [edge(0x20A, EdgeKind::Call), edge(0x204, EdgeKind::Return)]
+        );
+        assert_eq!(
+            cfg.blocks[2].edges,
+            [edge(0x206, EdgeKind::Next), edge(0x208, EdgeKind::Skip)]
+        );
+        assert_eq!(cfg.blocks[4].edges, [edge(0x208, EdgeKind::Jump)]);
+        assert_eq!(cfg.blocks[5].instructions.len(), 2);
+        assert!(cfg.blocks[5].edges.is_empty());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = ControlFlowGraph::new(&PROGRAM).to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b20a [label=\"0x20A: ADD V0, 0x01\\l0x20C: RET\\l\"];\n"));
+        assert!(dot.contains("    b202 -> b20a [style=dashed, label=\"call\"];\n"));
+        assert!(dot.contains("    b204 -> b208 [label=\"skip\"];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,170 @@
+use super::core::Chip8Core;
+use super::quirks::Quirks;
+use super::testing::ScriptedKeypad;
+use super::vm::VirtualMachine;
+
+/// The first frame at which two VMs running the same program show different
+/// displays, along with both VMs as they were at the end of that frame.
+pub struct Divergence {
+    pub frame: u32,
+    pub differing_pixels: usize,
+    pub a: VirtualMachine,
+    pub b: VirtualMachine,
+}
+
+/// The first line of a frame hash trace, which names its format version.
+const TRACE_HEADER: &str = "chip8 frame hashes v1";
+/// The seed of all VMs run here, so that random numbers neither make two VMs
+/// diverge nor change the hashes from one run to the next.
+const SEED: u64 = 0;
+
+fn seeded_vm(program: &[u8], quirks: Quirks) -> VirtualMachine {
+    let mut vm = VirtualMachine::with_quirks(program, quirks);
+    vm.set_seed(SEED);
+    vm
+}
+
+/// Runs one frame of a VM: sets the key of the input for the frame, executes
+/// the instructions and ticks the timers.
+fn run_frame(vm: &mut VirtualMachine, input: &ScriptedKeypad, frame: u32, instructions: u32) {
+    vm.interface.lock().unwrap().keys_down = input.keys_down(frame);
+    for _ in 0..instructions {
+        // A VM that fails stays on the failing instruction.
+        if vm.step().is_err() {
+            break;
+        }
+    }
+    vm.interface.lock().unwrap().tick_timers();
+}
+
+/// Counts the pixels that differ between the displays of two VMs.
+fn differing_pixels(a: &VirtualMachine, b: &VirtualMachine) -> usize {
+    let (width, height) = a.screen_size();
+    (0..width)
+        .flat_map(|x| (0..height).map(move |y| (x, y)))
+        .filter(|(x, y)| a.pixel(*x, *y) != b.pixel(*x, *y))
+        .count()
+}
+
+/// Runs a program in two VMs with different quirks in lockstep, starting from
+/// the same state and pressing the same keys. Returns the first frame after
+/// which their displays differ, or `None` if they agree for all `frames`.
+pub fn find_divergence(
+    program: &[u8],
+    quirks_a: Quirks,
+    quirks_b: Quirks,
+    input: &ScriptedKeypad,
+    instructions_per_frame: u32,
+    frames: u32,
+) -> Option<Divergence> {
+    let mut a = seeded_vm(program, quirks_a);
+    let mut b = seeded_vm(program, quirks_b);
+    for frame in 0..frames {
+        for vm in [&mut a, &mut b] {
+            run_frame(vm, input, frame, instructions_per_frame);
+        }
+        let differing_pixels = differing_pixels(&a, &b);
+        if differing_pixels > 0 {
+            return Some(Divergence {
+                frame,
+                differing_pixels,
+                a,
+                b,
+            });
+        }
+    }
+    None
+}
+
+/// Runs a program like `find_divergence` does and returns the display hash
+/// (see `Chip8Core::display_hash`) after every frame. Comparing the hashes
+/// of two builds of the emulator shows whether a change of the interpreter
+/// changed what a ROM displays.
+pub fn frame_hashes(
+    program: &[u8],
+    quirks: Quirks,
+    input: &ScriptedKeypad,
+    instructions_per_frame: u32,
+    frames: u32,
+) -> Vec<u64> {
+    let mut vm = seeded_vm(program, quirks);
+    (0..frames)
+        .map(|frame| {
+            run_frame(&mut vm, input, frame, instructions_per_frame);
+            vm.display_hash()
+        })
+        .collect()
+}
+
+/// Writes frame hashes as a trace file: a header line, then one hash per
+/// frame in hexadecimal.
+pub fn format_trace(hashes: &[u64]) -> String {
+    let mut trace = format!("{}\n", TRACE_HEADER);
+    for hash in hashes {
+        trace.push_str(&format!("{:016x}\n", hash));
+    }
+    trace
+}
+
+/// Reads a trace file written by `format_trace`.
+pub fn parse_trace(trace: &str) -> Result<Vec<u64>, String> {
+    let mut lines = trace.lines();
+    if lines.next() != Some(TRACE_HEADER) {
+        return Err(format!("expected \"{}\" in the first line", TRACE_HEADER));
+    }
+    lines
+        .enumerate()
+        .map(|(index, line)| {
+            u64::from_str_radix(line, 16)
+                .map_err(|_| format!("invalid hash \"{}\" in line {}", line, index + 2))
+        })
+        .collect()
+}
+
+/// The first frame at which two traces differ, including a frame that only
+/// one of them has.
+pub fn first_mismatch(a: &[u64], b: &[u64]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(frame) => Some(frame),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_divergence() {
+        // 6002: V0 = 2, 8016: V0 = V0 >> 1 or V1 >> 1 depending on the shift
+        // quirk, F029: I = digit V0, D005: draw it, 1208: halt
+        let program = [0x60, 0x02, 0x80, 0x16, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x08];
+        let input = ScriptedKeypad::new();
+        let vip = Quirks::cosmac_vip();
+        assert!(find_divergence(&program, vip, vip, &input, 10, 5).is_none());
+
+        let divergence = find_divergence(&program, Quirks::default(), vip, &input, 10, 5).unwrap();
+        assert_eq!(divergence.frame, 0);
+        assert!(divergence.differing_pixels > 0);
+    }
+
+    #[test]
+    fn test_frame_hashes() {
+        // 7001: V0 += 1, F029: I = digit V0, D005: draw it, 1200: loop.
+        let program = [0x70, 0x01, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x00];
+        let input = ScriptedKeypad::new();
+        let hashes = frame_hashes(&program, Quirks::default(), &input, 4, 3);
+        assert_eq!(hashes.len(), 3);
+        assert_ne!(hashes[0], hashes[1]);
+        assert_eq!(parse_trace(&format_trace(&hashes)), Ok(hashes.clone()));
+        assert_eq!(first_mismatch(&hashes, &hashes), None);
+
+        let mut changed = hashes.clone();
+        changed[2] ^= 1;
+        assert_eq!(first_mismatch(&hashes, &changed), Some(2));
+        assert_eq!(first_mismatch(&hashes, &hashes[..1]), Some(1));
+        assert!(parse_trace("0123\n").is_err());
+        assert!(parse_trace(&format!("{}\nxyz\n", TRACE_HEADER)).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use super::basics::{Address, Register, Value, MEMORY_SIZE};
+use super::core::Chip8Core;
+use super::debugger::Condition;
+use super::disasm;
+use super::save_state::{SaveState, SaveStateError};
+use super::vm::VmError;
+use std::sync::mpsc::{self, Receiver, Sender, TryIter, TryRecvError};
+use std::time::Duration;
+
+/// A command from a frontend to the `Executor`, handled before its next
+/// instruction.
+pub enum Command {
+    /// Replaces the running core, e.g. to load another ROM. The new core takes
+    /// over the display and the audio of the old one.
+    Load(Box<dyn Chip8Core>),
+    /// Stops executing instructions and freezes the timers.
+    Pause,
+    /// Undoes `Pause`, also after stopping at a breakpoint.
+    Resume,
+    /// Executes a single instruction while paused.
+    Step,
+    /// Which of the CHIP-8 keys are currently held down, indexed by key.
+    Keys([bool; 16]),
+    /// A press or release of a CHIP-8 key, queued such that the program sees
+    /// every press, see `VMInterface::key_events`.
+    KeyEvent {
+        key: u8,
+        pressed: bool,
+    },
+    /// A press of a CHIP-8 key that the program sees until it reads it, see
+    /// `VMInterface::latched_key`.
+    LatchKey(u8),
+    AddBreakpoint(Address),
+    RemoveBreakpoint(Address),
+    /// Adds a breakpoint that stops execution when the condition becomes true.
+    AddCondition(Condition),
+    RemoveCondition(Condition),
+    /// Turns on or off sending `Event::State` once per frame, e.g. for a
+    /// debug overlay.
+    Inspect(bool),
+    /// Captures the state of the core, which is sent back as `Event::Saved`
+    /// together with `slot`.
+    SaveState {
+        slot: u8,
+    },
+    /// Restores a state captured with `SaveState`, answered by `Event::Loaded`
+    /// together with `slot`.
+    LoadState {
+        slot: u8,
+        state: Box<SaveState>,
+    },
+    /// Starts (true) or stops (false) running backwards through the recent
+    /// snapshots of the core, see `Executor::set_rewind`.
+    Rewind(bool),
+    /// Ends `Executor::run_concurrent`.
+    Shutdown,
+}
+
+/// A notification from the `Executor` to its frontend.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Execution stopped at `pc` because of a breakpoint, a pause or a single
+    /// step. `state` is the state summary of the core.
+    Stopped { pc: Address, state: String },
+    /// The program stopped with an error.
+    Error(VmError),
+    /// The state of the core, sent every frame while inspecting.
+    State(CpuState),
+    /// The answer to `Command::SaveState`.
+    Saved {
+        slot: u8,
+        state: Result<Box<SaveState>, SaveStateError>,
+    },
+    /// The answer to `Command::LoadState`.
+    Loaded {
+        slot: u8,
+        result: Result<(), SaveStateError>,
+    },
+    /// Something the user should know about that does not stop the program.
+    Warning(String),
+}
+
+/// A snapshot of the registers of a core, for display by a frontend.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuState {
+    pub pc: Address,
+    /// The next instruction in assembly syntax.
+    pub next: String,
+    pub registers: [Value; 16],
+    pub i: Address,
+    pub delay_timer: Value,
+    pub sound_timer: Value,
+    /// The return addresses, innermost last.
+    pub stack: Vec<Address>,
+    /// The number of pixels erased by the most recent draw, see
+    /// `Chip8Core::draw_collisions`.
+    pub draw_collisions: usize,
+    /// The keys that the core sees held down, see `VMInterface::keypad`.
+    pub keypad: u16,
+    /// The watches of the executor with their current values, e.g.
+    /// `score = 12`.
+    pub watches: Vec<String>,
+    /// The median and the 99th percentile of the time the executor spent
+    /// executing instructions per frame, once a frame was measured.
+    pub emulation_time: Option<(Duration, Duration)>,
+}
+
+impl CpuState {
+    /// The state of `core`, without any watches or emulation time.
+    pub fn of(core: &dyn Chip8Core) -> CpuState {
+        let pc = core.program_counter();
+        let next = if (pc.0 as usize) + 1 < MEMORY_SIZE {
+            let a = core.memory(pc).0;
+            let b = core.memory(Address(pc.0 + 1)).0;
+            disasm::mnemonic(a, b)
+        } else {
+            String::from("-")
+        };
+        let mut registers = [Value(0); 16];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = core.register(Register(i as u8));
+        }
+        let (delay_timer, sound_timer, keypad) = {
+            let interface = core.interface();
+            let interface = interface.lock().unwrap();
+            (
+                interface.delay_timer,
+                interface.sound_timer,
+                interface.keypad(),
+            )
+        };
+        CpuState {
+            pc,
+            next,
+            registers,
+            i: core.register_i(),
+            delay_timer,
+            sound_timer,
+            stack: core.stack(),
+            draw_collisions: core.draw_collisions(),
+            keypad,
+            watches: Vec::new(),
+            emulation_time: None,
+        }
+    }
+}
+
+/// The frontend end of the connection to an `Executor`, see
+/// `Executor::control_bus`.
+pub struct ControlBus {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+}
+
+/// The executor end of a `ControlBus`.
+pub(crate) struct ControlEndpoint {
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+}
+
+/// Creates both ends of a control bus.
+pub(crate) fn bus() -> (ControlBus, ControlEndpoint) {
+    let (command_sender, command_receiver) = mpsc::channel();
+    let (event_sender, event_receiver) = mpsc::channel();
+    (
+        ControlBus {
+            commands: command_sender,
+            events: event_receiver,
+        },
+        ControlEndpoint {
+            commands: command_receiver,
+            events: event_sender,
+        },
+    )
+}
+
+impl ControlBus {
+    /// Sends a command. Commands to an executor that is gone are dropped.
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    /// Returns a sender for commands from another thread.
+    pub fn commands(&self) -> Sender<Command> {
+        self.commands.clone()
+    }
+
+    /// The events that arrived since the last call, without blocking.
+    pub fn events(&self) -> TryIter<'_, Event> {
+        self.events.try_iter()
+    }
+}
+
+impl ControlEndpoint {
+    /// Takes all pending commands. Returns `None` once all senders are gone,
+    /// i.e. nobody is in control anymore.
+    pub(crate) fn commands(&self) -> Option<Vec<Command>> {
+        let mut commands = Vec::new();
+        loop {
+            match self.commands.try_recv() {
+                Ok(command) => commands.push(command),
+                Err(TryRecvError::Empty) => return Some(commands),
+                Err(TryRecvError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Sends an event. Events without a receiver are dropped.
+    pub(crate) fn send(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+}
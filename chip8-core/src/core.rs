@@ -0,0 +1,238 @@
+use super::basics::{Address, Register, Value, SCREEN_HEIGHT, SCREEN_WIDTH};
+use super::save_state::{SaveState, SaveStateError};
+use super::vm::{StepOutcome, VMInterface, VirtualMachine, VmError};
+use std::sync::{Arc, Mutex};
+
+/// The parts of a CHIP-8 implementation that the `Executor` and a frontend
+/// rely on. The interpreter in `vm` is one implementation; other cores (e.g.
+/// a reference interpreter or CHIP-8 variants) can be swapped in behind it.
+pub trait Chip8Core: Send {
+    /// Executes the next instruction.
+    fn step(&mut self) -> Result<StepOutcome, VmError>;
+
+    /// Returns whether the pixel at the given coordinate is currently set.
+    fn pixel(&self, x: u8, y: u8) -> bool;
+
+    /// Returns the byte at the given address in memory.
+    fn memory(&self, addr: Address) -> Value;
+
+    /// The size of the display as (width, height).
+    fn screen_size(&self) -> (u8, u8) {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    /// The address of the next instruction.
+    fn program_counter(&self) -> Address;
+
+    /// Returns the value of one of the registers V0 to VF.
+    fn register(&self, reg: Register) -> Value;
+
+    /// Returns the value of the I register.
+    fn register_i(&self) -> Address;
+
+    /// The return addresses on the stack, innermost last. Empty for cores that
+    /// do not expose their stack.
+    fn stack(&self) -> Vec<Address> {
+        Vec::new()
+    }
+
+    /// The number of pixels erased by the most recent draw instruction. Always
+    /// 0 for cores that do not count them.
+    fn draw_collisions(&self) -> usize {
+        0
+    }
+
+    /// A human-readable dump of the registers and other state, for debugging.
+    fn state_summary(&self) -> String;
+
+    /// The state shared with the outside, i.e. timers, input and display.
+    fn interface(&self) -> Arc<Mutex<VMInterface>>;
+
+    /// Captures the complete state of the core. Fails for cores without save
+    /// states.
+    fn save_state(&self) -> Result<SaveState, SaveStateError> {
+        Err(SaveStateError::Unsupported)
+    }
+
+    /// Restores a state captured by `save_state`, leaving the core unchanged
+    /// if that fails.
+    fn load_state(&mut self, _state: &SaveState) -> Result<(), SaveStateError> {
+        Err(SaveStateError::Unsupported)
+    }
+
+    /// The seed of the random numbers, for cores that can be seeded.
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+
+    /// Restarts the random numbers from `seed`, so that runs with the same
+    /// input are reproducible. Cores that cannot be seeded ignore it.
+    fn set_seed(&mut self, _seed: u64) {}
+
+    /// Returns a hash of the display content which is stable across platforms
+    /// and builds (64-bit FNV-1a over all pixels, column by column).
+    fn display_hash(&self) -> u64 {
+        let (width, height) = self.screen_size();
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for x in 0..width {
+            for y in 0..height {
+                hash ^= self.pixel(x, y) as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+
+    /// Returns a downscaled copy of the display, where every `factor` x
+    /// `factor` block of pixels is averaged into one brightness value. Indexed
+    /// as `[x][y]` like the display itself.
+    fn thumbnail(&self, factor: u8) -> Vec<Vec<u8>> {
+        let (width, height) = self.screen_size();
+        downscale(width, height, factor, |x, y| self.pixel(x, y))
+    }
+}
+
+/// Averages every `factor` x `factor` block of a display of the given size,
+/// given as `lit(x, y)`, into one brightness value, see
+/// `Chip8Core::thumbnail`.
+pub(crate) fn downscale(
+    width: u8,
+    height: u8,
+    factor: u8,
+    lit: impl Fn(u8, u8) -> bool,
+) -> Vec<Vec<u8>> {
+    assert!(factor > 0);
+    let width = (width / factor) as usize;
+    let height = (height / factor) as usize;
+    let block = factor as usize * factor as usize;
+    let mut thumbnail = vec![vec![0; height]; width];
+    for (x, column) in thumbnail.iter_mut().enumerate() {
+        for (y, pixel) in column.iter_mut().enumerate() {
+            let mut count = 0;
+            for dx in 0..factor {
+                for dy in 0..factor {
+                    if lit(x as u8 * factor + dx, y as u8 * factor + dy) {
+                        count += 1;
+                    }
+                }
+            }
+            *pixel = (count * 255 / block) as u8;
+        }
+    }
+    thumbnail
+}
+
+impl Chip8Core for VirtualMachine {
+    fn step(&mut self) -> Result<StepOutcome, VmError> {
+        VirtualMachine::step(self)
+    }
+
+    fn pixel(&self, x: u8, y: u8) -> bool {
+        VirtualMachine::pixel(self, x, y)
+    }
+
+    fn screen_size(&self) -> (u8, u8) {
+        (SCREEN_WIDTH, self.screen_height())
+    }
+
+    fn memory(&self, addr: Address) -> Value {
+        VirtualMachine::memory(self, addr)
+    }
+
+    fn program_counter(&self) -> Address {
+        self.program_counter
+    }
+
+    fn register(&self, reg: Register) -> Value {
+        self.get_register(reg)
+    }
+
+    fn register_i(&self) -> Address {
+        self.get_register_i()
+    }
+
+    fn stack(&self) -> Vec<Address> {
+        VirtualMachine::stack(self).to_vec()
+    }
+
+    fn draw_collisions(&self) -> usize {
+        VirtualMachine::draw_collisions(self)
+    }
+
+    fn state_summary(&self) -> String {
+        VirtualMachine::state_summary(self, false)
+    }
+
+    fn interface(&self) -> Arc<Mutex<VMInterface>> {
+        self.interface.clone()
+    }
+
+    fn save_state(&self) -> Result<SaveState, SaveStateError> {
+        Ok(VirtualMachine::save_state(self))
+    }
+
+    fn load_state(&mut self, state: &SaveState) -> Result<(), SaveStateError> {
+        VirtualMachine::load_state(self, state)
+    }
+
+    fn seed(&self) -> Option<u64> {
+        VirtualMachine::seed(self)
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        VirtualMachine::set_seed(self, seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vm_as_core() {
+        // 6005: V0 = 5, F015: delay timer = V0, A000: I = 0, D005: draw "0"
+        let mut core: Box<dyn Chip8Core> = Box::new(VirtualMachine::new(&[
+            0x60, 0x05, 0xF0, 0x15, 0xA0, 0x00, 0xD0, 0x05,
+        ]));
+        for _ in 0..4 {
+            core.step().unwrap();
+        }
+        let interface = core.interface();
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(5));
+        interface.lock().unwrap().tick_timers();
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(4));
+        assert!(core.pixel(5, 5));
+        assert!(!core.pixel(6, 6));
+    }
+
+    #[test]
+    fn test_display_hash() {
+        let mut vm = VirtualMachine::new(&[0xD0, 0x05, 0xD0, 0x05]);
+        let empty = vm.display_hash();
+        assert_eq!(empty, VirtualMachine::new(&[]).display_hash());
+        vm.step().unwrap();
+        let drawn = vm.display_hash();
+        assert_ne!(drawn, empty);
+        vm.step().unwrap();
+        assert_eq!(vm.display_hash(), empty);
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        // I = sprite, draw 1 row at (0, 0), V1 = 2, draw 2 rows at (2, 2), loop.
+        // The sprite rows at 0x20C are both 0b11000000.
+        let mut vm = VirtualMachine::new(&[
+            0xA2, 0x0C, 0xD0, 0x11, 0x61, 0x02, 0xD1, 0x12, 0x12, 0x08, 0x00, 0x00, 0xC0, 0xC0,
+        ]);
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        let thumbnail = vm.thumbnail(2);
+        assert_eq!(thumbnail.len(), SCREEN_WIDTH as usize / 2);
+        assert_eq!(thumbnail[0].len(), SCREEN_HEIGHT as usize / 2);
+        assert_eq!(thumbnail[0][0], 127);
+        assert_eq!(thumbnail[1][1], 255);
+        assert_eq!(thumbnail[1][0], 0);
+        assert_eq!(thumbnail[0][1], 0);
+    }
+}
@@ -0,0 +1,498 @@
+use super::basics::{Address, Register, Value, MEMORY_SIZE};
+use super::core::Chip8Core;
+use super::disasm;
+use super::vm::{StepOutcome, VirtualMachine, VmError};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// Why `Debugger::run` returned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stop {
+    /// The program counter reached a breakpoint. The instruction there has not
+    /// been executed yet.
+    Breakpoint(Address),
+    /// A conditional breakpoint became true.
+    Condition(Condition),
+    /// The program jumped to itself.
+    Halted,
+    /// The maximal number of steps was executed.
+    StepLimit,
+}
+
+/// The register a `Condition` looks at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    V(Register),
+    I,
+    PC,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A condition on the registers for a conditional breakpoint, written like
+/// `V3 == 0x1F` or `I >= 0x300`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessOrEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+impl Condition {
+    /// Evaluates the condition on the current state of a core.
+    pub fn holds(&self, core: &dyn Chip8Core) -> bool {
+        let actual = match self.operand {
+            Operand::V(reg) => core.register(reg).0 as u16,
+            Operand::I => core.register_i().0,
+            Operand::PC => core.program_counter().0,
+        };
+        match self.comparison {
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Greater => actual > self.value,
+            Comparison::GreaterOrEqual => actual >= self.value,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Condition, String> {
+        let (symbol, comparison, index) = COMPARISONS
+            .iter()
+            .find_map(|(symbol, comparison)| {
+                text.find(symbol).map(|index| (*symbol, *comparison, index))
+            })
+            .ok_or_else(|| format!("no comparison in condition '{}'", text))?;
+        let operand = text[..index].trim().to_uppercase();
+        let operand = match operand.as_str() {
+            "I" => Operand::I,
+            "PC" => Operand::PC,
+            _ => match operand
+                .strip_prefix('V')
+                .and_then(|reg| u8::from_str_radix(reg, 16).ok())
+            {
+                Some(reg) if reg < 16 => Operand::V(Register(reg)),
+                _ => return Err(format!("unknown register '{}'", operand)),
+            },
+        };
+        let value = text[index + symbol.len()..].trim();
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map_err(|_| format!("invalid value '{}'", value))?;
+        Ok(Condition {
+            operand,
+            comparison,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.operand {
+            Operand::V(reg) => write!(f, "V{:X}", reg.0)?,
+            Operand::I => write!(f, "I")?,
+            Operand::PC => write!(f, "PC")?,
+        }
+        let (symbol, _) = COMPARISONS
+            .iter()
+            .find(|(_, comparison)| *comparison == self.comparison)
+            .unwrap();
+        write!(f, " {} {:#X}", symbol, self.value)
+    }
+}
+
+/// Addresses and conditions at which execution stops. A conditional
+/// breakpoint stops execution when its condition becomes true, not for as
+/// long as it stays true.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Breakpoints {
+    addresses: BTreeSet<u16>,
+    /// Every condition along with whether it held after the last check.
+    conditions: Vec<(Condition, bool)>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Breakpoints {
+        Breakpoints::default()
+    }
+
+    /// Adds a breakpoint, returning false if it already existed.
+    pub fn add(&mut self, addr: Address) -> bool {
+        self.addresses.insert(addr.0)
+    }
+
+    /// Removes a breakpoint, returning false if it did not exist.
+    pub fn remove(&mut self, addr: Address) -> bool {
+        self.addresses.remove(&addr.0)
+    }
+
+    pub fn contains(&self, addr: Address) -> bool {
+        self.addresses.contains(&addr.0)
+    }
+
+    /// Adds a conditional breakpoint, returning false if it already existed.
+    pub fn add_condition(&mut self, condition: Condition) -> bool {
+        if self.conditions.iter().any(|(c, _)| *c == condition) {
+            return false;
+        }
+        self.conditions.push((condition, false));
+        true
+    }
+
+    /// Removes a conditional breakpoint, returning false if it did not exist.
+    pub fn remove_condition(&mut self, condition: Condition) -> bool {
+        let len = self.conditions.len();
+        self.conditions.retain(|(c, _)| *c != condition);
+        self.conditions.len() < len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.conditions.is_empty()
+    }
+
+    /// All address breakpoints in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = Address> + '_ {
+        self.addresses.iter().map(|addr| Address(*addr))
+    }
+
+    /// All conditional breakpoints in the order they were added.
+    pub fn conditions(&self) -> impl Iterator<Item = Condition> + '_ {
+        self.conditions.iter().map(|(condition, _)| *condition)
+    }
+
+    /// Checks the breakpoints against the state of a core after a step.
+    /// Returns why execution should stop, if it should.
+    pub fn check(&mut self, core: &dyn Chip8Core) -> Option<Stop> {
+        if self.is_empty() {
+            return None;
+        }
+        let pc = core.program_counter();
+        let mut stop = if self.contains(pc) {
+            Some(Stop::Breakpoint(pc))
+        } else {
+            None
+        };
+        // Update all conditions, so that each one stops only when it changes.
+        for (condition, held) in self.conditions.iter_mut() {
+            let holds = condition.holds(core);
+            if holds && !*held && stop.is_none() {
+                stop = Some(Stop::Condition(*condition));
+            }
+            *held = holds;
+        }
+        stop
+    }
+}
+
+/// How `MemorySearch::narrow` selects the candidates to keep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFilter {
+    /// The byte at the address has this value.
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+/// A search for the address of a variable like the score or the lives: the
+/// candidate addresses are narrowed down by comparing each snapshot of memory
+/// with the previous one, e.g. to the bytes that decreased after losing a life.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemorySearch {
+    candidates: Vec<u16>,
+    snapshot: Vec<u8>,
+}
+
+fn snapshot(vm: &VirtualMachine) -> Vec<u8> {
+    (0..MEMORY_SIZE)
+        .map(|addr| vm.memory(Address(addr as u16)).0)
+        .collect()
+}
+
+impl MemorySearch {
+    /// Starts a search with every address as a candidate.
+    pub fn new(vm: &VirtualMachine) -> MemorySearch {
+        MemorySearch {
+            candidates: (0..MEMORY_SIZE as u16).collect(),
+            snapshot: snapshot(vm),
+        }
+    }
+
+    /// Keeps the candidates that match the filter, comparing against the
+    /// snapshot taken by the previous call or by `new`.
+    pub fn narrow(&mut self, vm: &VirtualMachine, filter: SearchFilter) {
+        let current = snapshot(vm);
+        let previous = &self.snapshot;
+        self.candidates.retain(|addr| {
+            let (old, new) = (previous[*addr as usize], current[*addr as usize]);
+            match filter {
+                SearchFilter::Equal(value) => new == value,
+                SearchFilter::Changed => new != old,
+                SearchFilter::Unchanged => new == old,
+                SearchFilter::Increased => new > old,
+                SearchFilter::Decreased => new < old,
+            }
+        });
+        self.snapshot = current;
+    }
+
+    /// The remaining candidates in ascending order.
+    pub fn candidates(&self) -> impl Iterator<Item = Address> + '_ {
+        self.candidates.iter().map(|addr| Address(*addr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+}
+
+/// Runs a `VirtualMachine` under control of the caller: instruction by
+/// instruction or until a breakpoint is reached, with access to its state in
+/// between. Timers are not ticked; see `Executor` for real-time debugging.
+pub struct Debugger {
+    vm: VirtualMachine,
+    breakpoints: Breakpoints,
+}
+
+impl Debugger {
+    pub fn new(vm: VirtualMachine) -> Debugger {
+        Debugger {
+            vm,
+            breakpoints: Breakpoints::new(),
+        }
+    }
+
+    pub fn breakpoints(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    pub fn vm(&self) -> &VirtualMachine {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VirtualMachine {
+        &mut self.vm
+    }
+
+    pub fn into_vm(self) -> VirtualMachine {
+        self.vm
+    }
+
+    /// Executes the next instruction, regardless of breakpoints.
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        self.vm.step()
+    }
+
+    /// Executes instructions until the program counter reaches a breakpoint, a
+    /// condition becomes true, the program halts or `max_steps` instructions were executed. A
+    /// breakpoint at the current instruction does not stop it, so that `run`
+    /// can continue after stopping at a breakpoint.
+    pub fn run(&mut self, max_steps: u64) -> Result<Stop, VmError> {
+        for _ in 0..max_steps {
+            if self.vm.step()? == StepOutcome::Halted {
+                return Ok(Stop::Halted);
+            }
+            if let Some(stop) = self.breakpoints.check(&self.vm) {
+                return Ok(stop);
+            }
+        }
+        Ok(Stop::StepLimit)
+    }
+
+    /// The values of V0 to VF.
+    pub fn registers(&self) -> [Value; 16] {
+        let mut registers = [Value(0); 16];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = self.vm.get_register(Register(i as u8));
+        }
+        registers
+    }
+
+    /// The return addresses on the stack, innermost last.
+    pub fn stack(&self) -> &[Address] {
+        self.vm.stack()
+    }
+
+    /// Reads `len` bytes of memory starting at `start`, stopping at the end
+    /// of memory.
+    pub fn memory(&self, start: Address, len: u16) -> Vec<Value> {
+        let end = (start.0 as usize + len as usize).min(MEMORY_SIZE);
+        (start.0 as usize..end)
+            .map(|addr| self.vm.memory(Address(addr as u16)))
+            .collect()
+    }
+
+    /// The addresses at which `pattern` occurs in memory.
+    pub fn find(&self, pattern: &[u8]) -> Vec<Address> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+        snapshot(&self.vm)
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(_, bytes)| *bytes == pattern)
+            .map(|(addr, _)| Address(addr as u16))
+            .collect()
+    }
+
+    /// Decodes `count` instructions starting at `start`, stopping at the end
+    /// of memory. Bytes that are no valid instruction are listed as data.
+    pub fn disassemble(&self, start: Address, count: u16) -> Vec<(Address, String)> {
+        (0..count)
+            .map(|i| start.0 as usize + 2 * i as usize)
+            .take_while(|addr| addr + 1 < MEMORY_SIZE)
+            .map(|addr| {
+                let a = self.vm.memory(Address(addr as u16)).0;
+                let b = self.vm.memory(Address(addr as u16 + 1)).0;
+                (Address(addr as u16), disasm::mnemonic(a, b))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 6001: V0 = 1, 2206: call 0x206, 1204: jump to itself,
+    /// 7101: V1 += 1, 00EE: return.
+    const PROGRAM: [u8; 10] = [0x60, 0x01, 0x22, 0x06, 0x12, 0x04, 0x71, 0x01, 0x00, 0xEE];
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = Debugger::new(VirtualMachine::new(&PROGRAM));
+        assert!(debugger.breakpoints().add(Address(0x206)));
+        assert!(!debugger.breakpoints().add(Address(0x206)));
+        assert_eq!(debugger.run(100), Ok(Stop::Breakpoint(Address(0x206))));
+        assert_eq!(debugger.registers()[0], Value(1));
+        assert_eq!(debugger.stack(), &[Address(0x204)]);
+        assert_eq!(debugger.step(), Ok(StepOutcome::Continued));
+        assert_eq!(debugger.registers()[1], Value(1));
+        assert_eq!(debugger.run(100), Ok(Stop::Halted));
+        assert!(debugger.stack().is_empty());
+        assert_eq!(debugger.run(0), Ok(Stop::StepLimit));
+    }
+
+    #[test]
+    fn test_condition_parsing() {
+        let condition: Condition = "V3 == 0x1F".parse().unwrap();
+        assert_eq!(
+            condition,
+            Condition {
+                operand: Operand::V(Register(3)),
+                comparison: Comparison::Equal,
+                value: 0x1F
+            }
+        );
+        assert_eq!(condition.to_string(), "V3 == 0x1F");
+        let condition: Condition = "i>=768".parse().unwrap();
+        assert_eq!(condition.operand, Operand::I);
+        assert_eq!(condition.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(condition.value, 0x300);
+        assert_eq!(
+            "pc < 0x300".parse::<Condition>().unwrap().operand,
+            Operand::PC
+        );
+        assert!("V3".parse::<Condition>().is_err());
+        assert!("VG == 1".parse::<Condition>().is_err());
+        assert!("V1 == x".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_conditional_breakpoints() {
+        // V1 += 1, jump back to start.
+        let mut debugger = Debugger::new(VirtualMachine::new(&[0x71, 0x01, 0x12, 0x00]));
+        let condition = "V1 >= 3".parse().unwrap();
+        assert!(debugger.breakpoints().add_condition(condition));
+        assert!(!debugger.breakpoints().add_condition(condition));
+        assert_eq!(debugger.run(100), Ok(Stop::Condition(condition)));
+        assert_eq!(debugger.registers()[1], Value(3));
+        // The condition stays true, which does not stop execution again.
+        assert_eq!(debugger.run(100), Ok(Stop::StepLimit));
+        assert!(debugger.breakpoints().remove_condition(condition));
+        assert!(debugger.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn test_memory() {
+        let debugger = Debugger::new(VirtualMachine::new(&PROGRAM));
+        assert_eq!(
+            debugger.memory(Address(0x200), 2),
+            vec![Value(0x60), Value(0x01)]
+        );
+        assert_eq!(debugger.memory(Address(0xFFE), 4).len(), 2);
+    }
+
+    #[test]
+    fn test_find() {
+        let debugger = Debugger::new(VirtualMachine::new(&PROGRAM));
+        assert_eq!(debugger.find(&[0x71, 0x01]), vec![Address(0x206)]);
+        assert!(debugger.find(&[0x71, 0x02]).is_empty());
+        assert!(debugger.find(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_memory_search() {
+        // I = 0x300, V0 += 1, store V0 at I, jump back to V0 += 1.
+        let program = [0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x02];
+        let mut debugger = Debugger::new(VirtualMachine::new(&program));
+        let mut search = MemorySearch::new(debugger.vm());
+        assert_eq!(search.len(), MEMORY_SIZE);
+        debugger.run(4).unwrap();
+        search.narrow(debugger.vm(), SearchFilter::Increased);
+        assert_eq!(
+            search.candidates().collect::<Vec<_>>(),
+            vec![Address(0x300)]
+        );
+        search.narrow(debugger.vm(), SearchFilter::Unchanged);
+        assert_eq!(search.len(), 1);
+        debugger.run(3).unwrap();
+        search.narrow(debugger.vm(), SearchFilter::Equal(2));
+        assert_eq!(search.len(), 1);
+        search.narrow(debugger.vm(), SearchFilter::Changed);
+        assert!(search.is_empty());
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let debugger = Debugger::new(VirtualMachine::new(&[0x60, 0x01, 0x50, 0x01]));
+        assert_eq!(
+            debugger.disassemble(Address(0x200), 2),
+            vec![
+                (Address(0x200), "LD V0, 0x01".to_string()),
+                (Address(0x202), "DW 0x5001".to_string())
+            ]
+        );
+        assert_eq!(debugger.disassemble(Address(0xFFC), 4).len(), 2);
+    }
+}
@@ -0,0 +1,234 @@
+use super::basics::{Address, PROGRAM_START};
+use super::program::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// How many bytes a `db` line of a data block holds at most.
+const DATA_PER_LINE: usize = 8;
+
+/// Why an address gets a label, in increasing precedence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    /// Loaded into I, so it most likely holds sprites or other data.
+    Data,
+    /// Jumped to.
+    Jump,
+    /// Called as a subroutine.
+    Subroutine,
+}
+
+impl Target {
+    fn prefix(self) -> &'static str {
+        match self {
+            Target::Data => "data",
+            Target::Jump => "label",
+            Target::Subroutine => "sub",
+        }
+    }
+}
+
+/// The offsets of the reachable instructions and the labelled targets of a
+/// program.
+pub(crate) struct Analysis {
+    pub(crate) code: BTreeSet<usize>,
+    targets: BTreeMap<usize, Target>,
+}
+
+pub(crate) fn decode(program: &[u8], offset: usize) -> Option<Instruction> {
+    match program.get(offset..offset + 2) {
+        Some([a, b]) => Instruction::try_from_16bit(*a, *b).ok(),
+        _ => None,
+    }
+}
+
+/// The offset of an address in the program, if it lies within.
+pub(crate) fn offset_of(program: &[u8], addr: Address) -> Option<usize> {
+    (addr.0 as usize)
+        .checked_sub(PROGRAM_START as usize)
+        .filter(|offset| *offset < program.len())
+}
+
+/// Follows every path of execution from the start of the program. The target
+/// of a BNNN jump depends on V0, so only NNN itself is followed.
+pub(crate) fn analyze(program: &[u8]) -> Analysis {
+    let mut code = BTreeSet::new();
+    let mut targets = BTreeMap::new();
+    let mut mark = |addr: Address, target: Target| {
+        let offset = offset_of(program, addr)?;
+        let entry = targets.entry(offset).or_insert(target);
+        *entry = (*entry).max(target);
+        Some(offset)
+    };
+    let mut pending = vec![0];
+    while let Some(offset) = pending.pop() {
+        if code.contains(&offset) {
+            continue;
+        }
+        let instruction = match decode(program, offset) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        code.insert(offset);
+        let next = offset + 2;
+        match instruction {
+            Instruction::Jump(addr) | Instruction::JumpAdd(addr) => {
+                pending.extend(mark(addr, Target::Jump));
+            }
+            Instruction::CallSubroutine(addr) => {
+                pending.extend(mark(addr, Target::Subroutine));
+                pending.push(next);
+            }
+            Instruction::ReturnSubroutine => {}
+            Instruction::IfNotEqualConst(..)
+            | Instruction::IfEqualConst(..)
+            | Instruction::IfNotEqual(..)
+            | Instruction::IfEqual(..)
+            | Instruction::IfNotKey(..)
+            | Instruction::IfKey(..) => pending.extend([next, next + 2]),
+            Instruction::SetI(addr) => {
+                mark(addr, Target::Data);
+                pending.push(next);
+            }
+            _ => pending.push(next),
+        }
+    }
+    Analysis { code, targets }
+}
+
+impl Analysis {
+    fn label(&self, offset: usize) -> Option<String> {
+        let target = self.targets.get(&offset)?;
+        Some(format!(
+            "{}_{:03x}",
+            target.prefix(),
+            PROGRAM_START as usize + offset
+        ))
+    }
+
+    /// The text of an instruction, with addresses in the program replaced by
+    /// their labels.
+    fn text(&self, program: &[u8], instruction: &Instruction) -> String {
+        let label =
+            |addr: &Address| offset_of(program, *addr).and_then(|offset| self.label(offset));
+        let text = match instruction {
+            Instruction::Jump(addr) => label(addr).map(|label| format!("JP {}", label)),
+            Instruction::CallSubroutine(addr) => label(addr).map(|label| format!("CALL {}", label)),
+            Instruction::SetI(addr) => label(addr).map(|label| format!("LD I, {}", label)),
+            Instruction::JumpAdd(addr) => label(addr).map(|label| format!("JP V0, {}", label)),
+            _ => None,
+        };
+        text.unwrap_or_else(|| instruction.to_string())
+    }
+}
+
+fn flush_data(output: &mut String, data: &mut Vec<u8>) {
+    if !data.is_empty() {
+        let bytes: Vec<_> = data.iter().map(|byte| format!("{:#04X}", byte)).collect();
+        let _ = writeln!(output, "    db {}", bytes.join(", "));
+        data.clear();
+    }
+}
+
+/// Decompiles a program as loaded at `PROGRAM_START` into labelled assembly
+/// that `asm::assemble` turns back into the same bytes. Only instructions
+/// that are reachable from the start are listed as code; subroutines are set
+/// apart with a comment, and everything else is listed as data blocks.
+pub fn decompile(program: &[u8]) -> String {
+    let analysis = analyze(program);
+    let mut output = String::new();
+    let mut data = Vec::new();
+    let mut in_data = false;
+    let mut offset = 0;
+    while offset < program.len() {
+        // An instruction that overlaps a label is kept as data, so that the
+        // label can be placed.
+        let instruction = decode(program, offset).filter(|_| {
+            analysis.code.contains(&offset) && !analysis.targets.contains_key(&(offset + 1))
+        });
+        if instruction.is_none() && !in_data {
+            let _ = writeln!(output, "\n; data");
+        }
+        if instruction.is_some() || analysis.targets.contains_key(&offset) {
+            flush_data(&mut output, &mut data);
+        }
+        if let Some(label) = analysis.label(offset) {
+            if instruction.is_some() && analysis.targets[&offset] == Target::Subroutine {
+                let _ = writeln!(output, "\n; subroutine");
+            }
+            let _ = writeln!(output, "{}:", label);
+        }
+        in_data = instruction.is_none();
+        match instruction {
+            Some(instruction) => {
+                let _ = writeln!(output, "    {}", analysis.text(program, &instruction));
+                offset += 2;
+            }
+            None => {
+                data.push(program[offset]);
+                if data.len() == DATA_PER_LINE {
+                    flush_data(&mut output, &mut data);
+                }
+                offset += 1;
+            }
+        }
+    }
+    flush_data(&mut output, &mut data);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    const PROGRAM: [u8; 19] = [
+        0xA2, 0x10, // LD I, data_210
+        0x22, 0x0C, // CALL sub_20c
+        0x30, 0x01, // SE V0, 0x01
+        0x12, 0x02, // JP label_202
+        0x12, 0x08, // JP label_208
+        0xD0, 0x15, // DRW V0, V1, 5, which is unreachable
+        0x60, 0x01, // LD V0, 0x01
+        0x00, 0xEE, // RET
+        0xF0, 0x90, 0xF0,
+    ];
+
+    #[test]
+    fn test_decompile() {
+        let expected = "
+            LD I, data_210
+        label_202:
+            CALL sub_20c
+            SE V0, 0x01
+            JP label_202
+        label_208:
+            JP label_208
+
+        ; data
+            db 0xD0, 0x15
+
+        ; subroutine
+        sub_20c:
+            LD V0, 0x01
+            RET
+
+        ; data
+        data_210:
+            db 0xF0, 0x90, 0xF0
+        ";
+        let lines: Vec<_> = expected
+            .lines()
+            .skip(1)
+            .map(|line| line.strip_prefix("        ").unwrap_or(line))
+            .collect();
+        assert_eq!(decompile(&PROGRAM), lines.join("\n"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(assemble(&decompile(&PROGRAM)), Ok(PROGRAM.to_vec()));
+        // A jump into the middle of the jump at 0x202.
+        let program = [0x30, 0x00, 0x12, 0x03, 0x00, 0xE0];
+        assert_eq!(assemble(&decompile(&program)), Ok(program.to_vec()));
+    }
+}
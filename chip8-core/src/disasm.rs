@@ -0,0 +1,68 @@
+use super::basics::{Address, PROGRAM_START};
+use super::program::{DecodeError, Instruction};
+use std::fmt;
+
+/// The assembly text of the instruction made up of the bytes `a` and `b`, or
+/// a `DW` data directive if they are no valid instruction.
+pub fn mnemonic(a: u8, b: u8) -> String {
+    match Instruction::try_from_16bit(a, b) {
+        Ok(instruction) => instruction.to_string(),
+        Err(DecodeError { opcode }) => format!("DW {:#06X}", opcode),
+    }
+}
+
+/// One line of a disassembly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub addr: Address,
+    /// The raw bytes, two for instructions and one for a trailing odd byte.
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+/// Written like `0x200: 6A02  LD VA, 0x02`.
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let raw: String = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:#05X}: {:4}  {}", self.addr.0, raw, self.text)
+    }
+}
+
+/// Disassembles a whole program as loaded at `PROGRAM_START`, two bytes at a
+/// time. Data in between the code is listed as `DW` directives, or as
+/// instructions if it happens to decode as one.
+pub fn disassemble(program: &[u8]) -> Vec<Line> {
+    program
+        .chunks(2)
+        .enumerate()
+        .map(|(i, bytes)| Line {
+            addr: Address(PROGRAM_START + 2 * i as u16),
+            bytes: bytes.to_vec(),
+            text: match bytes {
+                [a, b] => mnemonic(*a, *b),
+                _ => format!("DB {:#04X}", bytes[0]),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let lines: Vec<_> = disassemble(&[0x6A, 0x02, 0xFF, 0xFF, 0x12])
+            .iter()
+            .map(Line::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0x200: 6A02  LD VA, 0x02",
+                "0x202: FFFF  DW 0xFFFF",
+                "0x204: 12    DB 0x12",
+            ]
+        );
+    }
+}
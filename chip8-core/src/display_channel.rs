@@ -0,0 +1,176 @@
+use super::testing::{Frame, EMPTY_FRAME};
+use super::vm::Display;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// A change of the display, as sent by a `ChannelDisplay`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DisplayEvent {
+    Clear,
+    /// The listed pixels were flipped.
+    Draw(Vec<(u8, u8)>),
+    Resize(u8, u8),
+    /// The current frame is complete and can be shown.
+    Frame,
+}
+
+/// A display that forwards every change over a channel, so that a renderer
+/// in another thread keeps its own copy of the screen instead of sharing one
+/// with the VM.
+pub struct ChannelDisplay {
+    sender: Sender<DisplayEvent>,
+    pixels: Frame,
+    size: (u8, u8),
+}
+
+/// The receiving end of a `ChannelDisplay`. It applies the events to its copy
+/// of the screen and shows every pixel that was lit at any time during the
+/// last frame, so that sprites which are erased and redrawn within a frame do
+/// not flicker.
+pub struct DisplayMirror {
+    receiver: Receiver<DisplayEvent>,
+    pixels: Frame,
+    lit_since_frame: Frame,
+    shown: Frame,
+    size: (u8, u8),
+}
+
+/// Creates a connected display and mirror with the given resolution.
+pub fn channel(width: u8, height: u8) -> (ChannelDisplay, DisplayMirror) {
+    let (sender, receiver) = mpsc::channel();
+    let display = ChannelDisplay {
+        sender,
+        pixels: EMPTY_FRAME,
+        size: (width, height),
+    };
+    let mirror = DisplayMirror {
+        receiver,
+        pixels: EMPTY_FRAME,
+        lit_since_frame: EMPTY_FRAME,
+        shown: EMPTY_FRAME,
+        size: (width, height),
+    };
+    (display, mirror)
+}
+
+impl ChannelDisplay {
+    fn send(&self, event: DisplayEvent) {
+        // Without a receiver, nobody is watching; the VM keeps running.
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Display for ChannelDisplay {
+    fn clear(&mut self) {
+        self.pixels = EMPTY_FRAME;
+        self.send(DisplayEvent::Clear);
+    }
+
+    fn draw_pixels(&mut self, pixels: &[(u8, u8)]) {
+        for (x, y) in pixels {
+            let pixel = &mut self.pixels[*x as usize][*y as usize];
+            *pixel = !*pixel;
+        }
+        self.send(DisplayEvent::Draw(pixels.to_vec()));
+    }
+
+    fn get(&self, x: u8, y: u8) -> u8 {
+        if self.pixels[x as usize][y as usize] {
+            255
+        } else {
+            0
+        }
+    }
+
+    fn frame(&mut self) {
+        self.send(DisplayEvent::Frame);
+    }
+
+    fn set_size(&mut self, width: u8, height: u8) {
+        self.pixels = EMPTY_FRAME;
+        self.size = (width, height);
+        self.send(DisplayEvent::Resize(width, height));
+    }
+
+    fn size(&self) -> (u8, u8) {
+        self.size
+    }
+}
+
+impl DisplayMirror {
+    /// Applies incoming events until a frame is complete or `timeout` has
+    /// passed. Returns whether a new frame is shown.
+    pub fn wait_for_frame(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(remaining) {
+                Ok(DisplayEvent::Frame) => {
+                    self.shown = self.lit_since_frame;
+                    self.lit_since_frame = self.pixels;
+                    return true;
+                }
+                Ok(event) => self.apply(event),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return false
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self, event: DisplayEvent) {
+        match event {
+            DisplayEvent::Clear => self.pixels = EMPTY_FRAME,
+            DisplayEvent::Draw(pixels) => {
+                for (x, y) in pixels {
+                    let pixel = &mut self.pixels[x as usize][y as usize];
+                    *pixel = !*pixel;
+                    self.lit_since_frame[x as usize][y as usize] |= *pixel;
+                }
+            }
+            DisplayEvent::Resize(width, height) => {
+                self.pixels = EMPTY_FRAME;
+                self.lit_since_frame = EMPTY_FRAME;
+                self.shown = EMPTY_FRAME;
+                self.size = (width, height);
+            }
+            DisplayEvent::Frame => {}
+        }
+    }
+
+    /// Returns whether the pixel is lit in the frame that is shown.
+    pub fn get(&self, x: u8, y: u8) -> bool {
+        self.shown[x as usize][y as usize]
+    }
+
+    /// The resolution as (width, height), as of the last applied event.
+    pub fn size(&self) -> (u8, u8) {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basics::{Register, Value, SCREEN_HEIGHT, SCREEN_WIDTH};
+    use crate::program::Instruction;
+    use crate::vm::VirtualMachine;
+
+    #[test]
+    fn test_mirror_shows_pixels_lit_during_frame() {
+        let (display, mut mirror) = channel(SCREEN_WIDTH, SCREEN_HEIGHT);
+        let mut vm = VirtualMachine::new(&[]);
+        vm.interface.lock().unwrap().display = Box::new(display);
+        // Draw and erase the "0" digit within the first frame.
+        let draw = Instruction::Draw(Register(0), Register(0), Value(5));
+        vm.execute_instruction(&draw).unwrap();
+        vm.execute_instruction(&draw).unwrap();
+        assert!(!mirror.wait_for_frame(Duration::from_millis(1)));
+        vm.interface.lock().unwrap().tick_timers();
+        assert!(mirror.wait_for_frame(Duration::from_millis(1)));
+        assert!(mirror.get(0, 0));
+        vm.interface.lock().unwrap().tick_timers();
+        assert!(mirror.wait_for_frame(Duration::from_millis(1)));
+        assert!(!mirror.get(0, 0));
+    }
+}
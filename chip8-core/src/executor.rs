@@ -0,0 +1,1049 @@
+use super::clock::{Clock, SystemClock};
+use super::control::{self, Command, ControlBus, ControlEndpoint, CpuState, Event};
+use super::core::Chip8Core;
+use super::debugger::Breakpoints;
+use super::key_events::KeyEvent;
+use super::movie::{Movie, MovieEvent};
+use super::rewind::RewindBuffer;
+use super::stats::FrameStats;
+use super::vm::{StepOutcome, VmError};
+use super::watch::Watch;
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often skipped instructions and timer ticks are reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// The number of frames over which the emulation time is measured.
+const EMULATION_STATS_CAPACITY: usize = 600;
+
+/// Overrides of the instruction sleep for classes of instructions, so that
+/// e.g. only drawing is slowed down. `None` keeps the instruction sleep.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Throttle {
+    /// The time after a draw (DXYN) or clear (00E0) instruction.
+    pub draw: Option<Duration>,
+    /// The time after a key wait (FX0A) that found no key pressed.
+    pub wait_key: Option<Duration>,
+}
+
+impl Throttle {
+    /// The time until the instruction following one with the given outcome.
+    fn sleep_after(&self, outcome: Option<StepOutcome>, instruction_sleep: Duration) -> Duration {
+        match outcome {
+            Some(StepOutcome::DrewFrame) => self.draw,
+            Some(StepOutcome::WaitingForKey) => self.wait_key,
+            _ => None,
+        }
+        .unwrap_or(instruction_sleep)
+    }
+}
+
+/// Runs a core in real time: instructions are executed every
+/// `instruction_sleep` and the timers are decremented every `timer_interval`.
+pub struct Executor {
+    instruction_sleep: Duration,
+    throttle: Throttle,
+    timer_interval: Duration,
+    next_instruction: Duration,
+    next_timer_tick: Duration,
+    skipped_instructions: u64,
+    skipped_timer_ticks: u64,
+    next_report: Duration,
+    reported_skips: (u64, u64),
+    /// The time spent executing instructions since the last timer tick.
+    frame_emulation_time: Duration,
+    /// The time spent executing instructions in each recent frame.
+    emulation_stats: FrameStats,
+    watches: Vec<Watch>,
+    paused: bool,
+    paused_since: Option<Duration>,
+    /// Run first on the thread started by `run_concurrent`.
+    thread_setup: Option<Box<dyn FnOnce() + Send>>,
+    control: Option<ControlEndpoint>,
+    breakpoints: Breakpoints,
+    pending_steps: u32,
+    /// Whether to send the state of the core every frame.
+    inspect: bool,
+    error: Option<VmError>,
+    rewind: Option<RewindBuffer>,
+    rewinding: bool,
+    /// The movie being recorded, with the number of instructions executed
+    /// since the recording started.
+    movie: Option<(Movie, u64)>,
+    /// The movie whose recording was stopped by `stop_recording`.
+    stopped_movie: Option<Movie>,
+    shut_down: bool,
+    clock: Box<dyn Clock>,
+    core: Box<dyn Chip8Core>,
+}
+
+impl Executor {
+    pub fn new<C: Chip8Core + 'static>(
+        instruction_sleep: Duration,
+        timer_interval: Duration,
+        core: C,
+    ) -> Executor {
+        Executor::with_clock(instruction_sleep, timer_interval, core, SystemClock::new())
+    }
+
+    /// Creates an executor that takes its time from the given clock.
+    pub fn with_clock<C: Chip8Core + 'static, T: Clock + 'static>(
+        instruction_sleep: Duration,
+        timer_interval: Duration,
+        core: C,
+        clock: T,
+    ) -> Executor {
+        let now = clock.now();
+        Executor {
+            instruction_sleep,
+            throttle: Throttle::default(),
+            timer_interval,
+            next_instruction: now,
+            next_timer_tick: now,
+            skipped_instructions: 0,
+            skipped_timer_ticks: 0,
+            next_report: now + REPORT_INTERVAL,
+            reported_skips: (0, 0),
+            frame_emulation_time: Duration::ZERO,
+            emulation_stats: FrameStats::new(EMULATION_STATS_CAPACITY),
+            watches: Vec::new(),
+            paused: false,
+            paused_since: None,
+            thread_setup: None,
+            control: None,
+            breakpoints: Breakpoints::new(),
+            pending_steps: 0,
+            inspect: false,
+            error: None,
+            rewind: None,
+            rewinding: false,
+            movie: None,
+            stopped_movie: None,
+            shut_down: false,
+            clock: Box::new(clock),
+            core: Box::new(core),
+        }
+    }
+
+    /// Sets the instruction sleep of some classes of instructions.
+    pub fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = throttle;
+    }
+
+    /// Keeps recent snapshots of the core in `rewind`, so that
+    /// `Command::Rewind` can run it backwards. `None` turns rewinding off.
+    pub fn set_rewind(&mut self, rewind: Option<RewindBuffer>) {
+        self.rewind = rewind;
+    }
+
+    /// Starts recording the keys and timer ticks that reach the core into a
+    /// movie, which should happen before the core executes anything.
+    /// Loading another core or a save state and rewinding cannot be replayed,
+    /// so they stop the recording.
+    pub fn record_movie(&mut self) {
+        let movie = match self.core.seed() {
+            Some(seed) => Movie::with_seed(seed),
+            None => Movie::new(),
+        };
+        self.movie = Some((movie, 0));
+        self.stopped_movie = None;
+    }
+
+    /// Stops recording and returns the movie, if one was recorded.
+    pub fn take_movie(&mut self) -> Option<Movie> {
+        self.movie
+            .take()
+            .map(|(movie, _)| movie)
+            .or_else(|| self.stopped_movie.take())
+    }
+
+    /// Stops recording the movie, if one is being recorded, because `action`
+    /// changes the core in a way a replay cannot follow. The movie keeps what
+    /// was recorded so far, and a warning is sent.
+    fn stop_recording(&mut self, action: &str) {
+        // Repeating the held keys ends the movie at this instruction, since a
+        // replay stops after the last event.
+        let keys = self.core.interface().lock().unwrap().keys_down;
+        self.record_event(MovieEvent::Keys(keys));
+        if let Some((movie, _)) = self.movie.take() {
+            self.send_event(Event::Warning(format!(
+                "{} stopped the movie recording at {} frames",
+                action,
+                movie.frames()
+            )));
+            self.stopped_movie = Some(movie);
+        }
+    }
+
+    /// Adds an event to the movie, if one is being recorded.
+    fn record_event(&mut self, event: MovieEvent) {
+        if let Some((movie, instructions)) = &mut self.movie {
+            movie.record(*instructions, event);
+        }
+    }
+
+    /// Runs `setup` on the thread started by `run_concurrent` before the
+    /// core, e.g. to change the scheduling of that thread.
+    pub fn set_thread_setup(&mut self, setup: impl FnOnce() + Send + 'static) {
+        self.thread_setup = Some(Box::new(setup));
+    }
+
+    /// Connects a frontend: commands sent through the returned bus are
+    /// handled before the next instruction, and events are sent back through
+    /// it. Replaces a previously connected bus.
+    pub fn control_bus(&mut self) -> ControlBus {
+        let (bus, endpoint) = control::bus();
+        self.control = Some(endpoint);
+        bus
+    }
+
+    /// Sends an event to the control bus, if any.
+    fn send_event(&self, event: Event) {
+        if let Some(control) = &self.control {
+            control.send(event);
+        }
+    }
+
+    /// Handles all commands that arrived through the control bus. Shuts down
+    /// once all of its senders are gone.
+    fn handle_commands(&mut self) {
+        let commands = match self.control.as_ref().map(ControlEndpoint::commands) {
+            Some(Some(commands)) => commands,
+            Some(None) => {
+                self.shut_down = true;
+                return;
+            }
+            None => return,
+        };
+        for command in commands {
+            match command {
+                Command::Load(core) => {
+                    self.stop_recording("Loading another program");
+                    self.load(core);
+                }
+                Command::Pause => {
+                    self.paused = true;
+                    self.send_stopped();
+                }
+                Command::Resume => self.paused = false,
+                Command::Step => self.pending_steps += 1,
+                Command::Keys(keys) => {
+                    self.core.interface().lock().unwrap().keys_down = keys;
+                    self.record_event(MovieEvent::Keys(keys));
+                }
+                Command::KeyEvent { key, pressed } => {
+                    self.core
+                        .interface()
+                        .lock()
+                        .unwrap()
+                        .key_events
+                        .push(KeyEvent {
+                            key,
+                            pressed,
+                            time: self.clock.now(),
+                        });
+                    self.record_event(MovieEvent::KeyEvent { key, pressed });
+                }
+                Command::LatchKey(key) => {
+                    self.core.interface().lock().unwrap().latched_key = Some(key);
+                    self.record_event(MovieEvent::LatchKey(key));
+                }
+                Command::AddBreakpoint(addr) => {
+                    self.breakpoints.add(addr);
+                }
+                Command::RemoveBreakpoint(addr) => {
+                    self.breakpoints.remove(addr);
+                }
+                Command::AddCondition(condition) => {
+                    self.breakpoints.add_condition(condition);
+                }
+                Command::RemoveCondition(condition) => {
+                    self.breakpoints.remove_condition(condition);
+                }
+                Command::Inspect(inspect) => {
+                    self.inspect = inspect;
+                    self.send_state();
+                }
+                Command::SaveState { slot } => {
+                    let state = self.core.save_state().map(Box::new);
+                    self.send_event(Event::Saved { slot, state });
+                }
+                Command::LoadState { slot, state } => {
+                    let result = self.core.load_state(&state);
+                    if result.is_ok() {
+                        self.error = None;
+                        self.stop_recording("Loading a save state");
+                    }
+                    self.send_event(Event::Loaded { slot, result });
+                }
+                Command::Rewind(rewinding) => self.set_rewinding(rewinding),
+                Command::Shutdown => self.shut_down = true,
+            }
+        }
+    }
+
+    /// Replaces the core, handing over the display, the audio and the held key
+    /// of the old one, and starts the new one on a fresh schedule.
+    fn load(&mut self, core: Box<dyn Chip8Core>) {
+        {
+            let old_interface = self.core.interface();
+            let new_interface = core.interface();
+            let (width, height) = core.screen_size();
+            let mut old_interface = old_interface.lock().unwrap();
+            let mut new_interface = new_interface.lock().unwrap();
+            std::mem::swap(&mut old_interface.display, &mut new_interface.display);
+            new_interface.take_audio(&mut old_interface);
+            new_interface.keys_down = old_interface.keys_down;
+            new_interface.key_events = std::mem::take(&mut old_interface.key_events);
+            new_interface.display.set_size(width, height);
+        }
+        self.core = core;
+        let now = self.clock.now();
+        self.next_instruction = now;
+        self.next_timer_tick = now;
+        self.paused_since = self.paused_since.map(|_| now);
+        self.pending_steps = 0;
+        self.error = None;
+    }
+
+    /// Switches between running and rewinding. Rewinding is silent, and
+    /// running goes on from the restored state on a fresh schedule.
+    fn set_rewinding(&mut self, rewinding: bool) {
+        let rewind = match &mut self.rewind {
+            Some(rewind) if rewinding != self.rewinding => rewind,
+            _ => return,
+        };
+        rewind.restart();
+        self.rewinding = rewinding;
+        if rewinding {
+            self.stop_recording("Rewinding");
+        }
+        self.core.interface().lock().unwrap().set_muted(rewinding);
+        if !rewinding {
+            let now = self.clock.now();
+            self.next_instruction = now;
+            self.next_timer_tick = now;
+            self.paused_since = self.paused_since.map(|_| now);
+        }
+    }
+
+    /// Restores the next older snapshot, if one is due in this frame.
+    fn rewind_frame(&mut self) {
+        let state = match self.rewind.as_mut().and_then(RewindBuffer::rewind_frame) {
+            Some(state) => state,
+            None => return,
+        };
+        if self.core.load_state(&state).is_ok() {
+            self.error = None;
+        }
+        self.send_state();
+    }
+
+    /// Tells the control bus where execution stopped.
+    fn send_stopped(&self) {
+        self.send_event(Event::Stopped {
+            pc: self.core.program_counter(),
+            state: self.core.state_summary(),
+        });
+    }
+
+    /// Sends the state of the core, the values of the watches and the
+    /// emulation time to the control bus while inspecting.
+    fn send_state(&self) {
+        if self.inspect {
+            let mut state = CpuState::of(&*self.core);
+            state.emulation_time = self
+                .emulation_stats
+                .percentile(50)
+                .zip(self.emulation_stats.percentile(99));
+            state.watches = self
+                .watches
+                .iter()
+                .map(|watch| watch.format(watch.read(&*self.core)))
+                .collect();
+            self.send_event(Event::State(state));
+        }
+    }
+
+    pub fn core(&self) -> &dyn Chip8Core {
+        &*self.core
+    }
+
+    /// Adds a watch whose value is sent along with the state of the core
+    /// while inspecting.
+    pub fn add_watch(&mut self, watch: Watch) {
+        self.watches.push(watch);
+    }
+
+    /// The number of instructions that were skipped so far because the
+    /// executor fell behind schedule.
+    pub fn skipped_instructions(&self) -> u64 {
+        self.skipped_instructions
+    }
+
+    /// The number of timer ticks that were skipped so far because the
+    /// executor fell behind schedule.
+    pub fn skipped_timer_ticks(&self) -> u64 {
+        self.skipped_timer_ticks
+    }
+
+    /// Performs everything that is due at the current time, then sleeps until
+    /// the next instruction or timer tick is due. If the executor fell behind
+    /// by more than one interval, it skips ahead instead of catching up.
+    fn run_next(&mut self) {
+        let now = self.clock.now();
+        self.handle_commands();
+        if self.shut_down {
+            return;
+        }
+        if self.rewinding {
+            if now >= self.next_timer_tick {
+                self.rewind_frame();
+                let (next, _) =
+                    Executor::next_deadline(self.next_timer_tick, self.timer_interval, now);
+                self.next_timer_tick = next;
+            }
+            self.clock.sleep(self.next_timer_tick.saturating_sub(now));
+            return;
+        }
+        if self.paused {
+            if self.pending_steps > 0 {
+                self.pending_steps -= 1;
+                self.execute_next();
+                self.send_stopped();
+                self.send_state();
+            }
+            self.paused_since.get_or_insert(now);
+            self.clock.sleep(self.instruction_sleep);
+            return;
+        }
+        if let Some(paused_since) = self.paused_since.take() {
+            // Resume on the old schedule, shifted by the time spent paused.
+            self.next_timer_tick += now - paused_since;
+            self.next_instruction += now - paused_since;
+        }
+        if now >= self.next_timer_tick {
+            let emulation_time = std::mem::take(&mut self.frame_emulation_time);
+            self.emulation_stats.record(emulation_time);
+            self.tick_timers();
+            if let Some(rewind) = &mut self.rewind {
+                rewind.record_frame(&*self.core);
+            }
+            let (next, skipped) =
+                Executor::next_deadline(self.next_timer_tick, self.timer_interval, now);
+            self.next_timer_tick = next;
+            self.skipped_timer_ticks += skipped;
+        }
+        if now >= self.next_instruction {
+            let start = self.clock.now();
+            let outcome = self.execute_next();
+            self.frame_emulation_time += self.clock.now().saturating_sub(start);
+            let sleep = self.throttle.sleep_after(outcome, self.instruction_sleep);
+            let (next, skipped) = Executor::next_deadline(self.next_instruction, sleep, now);
+            self.next_instruction = next;
+            self.skipped_instructions += skipped;
+        }
+        if now >= self.next_report {
+            self.report_skips();
+            self.next_report = now + REPORT_INTERVAL;
+        }
+        let next = self.next_timer_tick.min(self.next_instruction);
+        if next > now {
+            self.clock.sleep(next - now);
+        }
+    }
+
+    /// Decrements the timers.
+    fn tick_timers(&mut self) {
+        self.core.interface().lock().unwrap().tick_timers();
+        self.record_event(MovieEvent::Tick);
+        self.send_state();
+    }
+
+    /// Executes the next instruction, unless the core already failed. The
+    /// first error is reported and sent to the control bus. Hitting a
+    /// breakpoint pauses the executor. Returns the outcome of the step, if
+    /// there was one.
+    fn execute_next(&mut self) -> Option<StepOutcome> {
+        if self.error.is_some() {
+            return None;
+        }
+        let outcome = match self.core.step() {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                eprintln!("Error: {}, the program stopped.", error);
+                self.error = Some(error);
+                self.send_event(Event::Error(error));
+                return None;
+            }
+        };
+        if let Some((_, instructions)) = &mut self.movie {
+            *instructions += 1;
+        }
+        if !self.paused && self.breakpoints.check(&*self.core).is_some() {
+            self.paused = true;
+            self.send_stopped();
+        }
+        Some(outcome)
+    }
+
+    /// Computes the deadline following `deadline`, along with the number of
+    /// deadlines that were missed and will be skipped.
+    fn next_deadline(deadline: Duration, interval: Duration, now: Duration) -> (Duration, u64) {
+        if deadline + interval < now {
+            let skipped = (now - deadline).as_nanos() / interval.as_nanos();
+            (now + interval, skipped as u64)
+        } else {
+            (deadline + interval, 0)
+        }
+    }
+
+    /// Prints a warning if instructions or timer ticks were skipped since the
+    /// last report.
+    fn report_skips(&mut self) {
+        let instructions = self.skipped_instructions - self.reported_skips.0;
+        let timer_ticks = self.skipped_timer_ticks - self.reported_skips.1;
+        if instructions > 0 || timer_ticks > 0 {
+            eprintln!(
+                "Warning: emulation fell behind, skipped {} instruction(s) and {} timer tick(s) in the last {:?}.",
+                instructions, timer_ticks, REPORT_INTERVAL
+            );
+        }
+        self.reported_skips = (self.skipped_instructions, self.skipped_timer_ticks);
+    }
+
+    /// Runs the core on the current thread for the given amount of clock time,
+    /// or until it is shut down through the control bus.
+    pub fn run_for(&mut self, duration: Duration) {
+        let end = self.clock.now() + duration;
+        while self.clock.now() < end && !self.shut_down {
+            self.run_next();
+        }
+    }
+
+    /// Runs the core on a new thread until `Command::Shutdown` arrives or all
+    /// senders of the control bus are gone. The thread returns the executor,
+    /// e.g. to take its movie.
+    pub fn run_concurrent(mut self) -> JoinHandle<Executor> {
+        thread::spawn(move || {
+            if let Some(setup) = self.thread_setup.take() {
+                setup();
+            }
+            while !self.shut_down {
+                self.run_next();
+            }
+            self
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basics::{Address, Register, Value};
+    use crate::clock::VirtualClock;
+    use crate::control::{Command, Event};
+    use crate::vm::{Audio, VirtualMachine, VmError};
+    use crate::watch::{WatchFormat, WatchWidth};
+    use std::sync::{Arc, Mutex};
+
+    const TIMER_INTERVAL: Duration = Duration::from_micros(16667);
+
+    /// V0 = 0xFF, delay timer = V0, sound timer = V0, then loop forever.
+    const TIMER_PROGRAM: [u8; 8] = [0x60, 0xFF, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06];
+
+    fn executor(program: &[u8]) -> Executor {
+        Executor::with_clock(
+            Duration::from_millis(1),
+            TIMER_INTERVAL,
+            VirtualMachine::new(program),
+            VirtualClock::new(),
+        )
+    }
+
+    /// The state of the keypad with only `key` held down.
+    fn key_down(key: u8) -> [bool; 16] {
+        let mut keys = [false; 16];
+        keys[key as usize] = true;
+        keys
+    }
+
+    #[test]
+    fn test_timer_rate() {
+        let mut executor = executor(&TIMER_PROGRAM);
+        executor.run_for(Duration::from_millis(3));
+        let interface = executor.core().interface();
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(255));
+        assert_eq!(interface.lock().unwrap().sound_timer, Value(255));
+        // Ticks happen at 16.667ms, 33.334ms, ..., 999.96ms.
+        executor.run_for(Duration::from_millis(997));
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(255 - 59));
+        assert_eq!(interface.lock().unwrap().sound_timer, Value(255 - 59));
+    }
+
+    #[test]
+    fn test_instruction_rate() {
+        // V0 += 1, delay timer = V0, jump back to start.
+        let program = [0x70, 0x01, 0xF0, 0x15, 0x12, 0x00];
+        let mut executor = Executor::with_clock(
+            Duration::from_millis(2),
+            Duration::from_secs(3600),
+            VirtualMachine::new(&program),
+            VirtualClock::new(),
+        );
+        // 50 steps, 17 of which set the delay timer.
+        executor.run_for(Duration::from_millis(100));
+        let interface = executor.core().interface();
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(17));
+    }
+
+    #[test]
+    fn test_throttle() {
+        // V0 += 1, draw, jump back to start.
+        let program = [0x70, 0x01, 0xD0, 0x05, 0x12, 0x00];
+        let mut executor = Executor::with_clock(
+            Duration::from_millis(1),
+            Duration::from_secs(3600),
+            VirtualMachine::new(&program),
+            VirtualClock::new(),
+        );
+        executor.set_throttle(Throttle {
+            draw: Some(Duration::from_millis(10)),
+            wait_key: None,
+        });
+        // One loop takes 1ms + 10ms + 1ms, so V0 is incremented at 0ms, 12ms,
+        // ..., 96ms.
+        executor.run_for(Duration::from_millis(100));
+        assert_eq!(executor.core().register(Register(0)), Value(9));
+    }
+
+    /// A clock that oversleeps by a factor of four.
+    struct SlowClock(VirtualClock);
+
+    impl Clock for SlowClock {
+        fn now(&self) -> Duration {
+            self.0.now()
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.0.sleep(duration * 4);
+        }
+    }
+
+    #[test]
+    fn test_skipped_instructions() {
+        let mut executor = Executor::with_clock(
+            Duration::from_millis(1),
+            Duration::from_secs(3600),
+            VirtualMachine::new(&TIMER_PROGRAM),
+            SlowClock(VirtualClock::new()),
+        );
+        assert_eq!(executor.skipped_instructions(), 0);
+        // Steps at 0ms, 4ms, 8ms, 12ms and 16ms, each time skipping three.
+        executor.run_for(Duration::from_millis(20));
+        assert_eq!(executor.skipped_instructions(), 12);
+        assert_eq!(executor.skipped_timer_ticks(), 0);
+    }
+
+    #[test]
+    fn test_next_deadline() {
+        let interval = Duration::from_millis(10);
+        assert_eq!(
+            Executor::next_deadline(Duration::from_millis(0), interval, Duration::from_millis(5)),
+            (Duration::from_millis(10), 0)
+        );
+        assert_eq!(
+            Executor::next_deadline(
+                Duration::from_millis(0),
+                interval,
+                Duration::from_millis(10)
+            ),
+            (Duration::from_millis(10), 0)
+        );
+        assert_eq!(
+            Executor::next_deadline(
+                Duration::from_millis(0),
+                interval,
+                Duration::from_millis(35)
+            ),
+            (Duration::from_millis(45), 3)
+        );
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut executor = executor(&TIMER_PROGRAM);
+        let bus = executor.control_bus();
+        bus.send(Command::Pause);
+        executor.run_for(Duration::from_millis(100));
+        let interface = executor.core().interface();
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(0));
+        bus.send(Command::Resume);
+        executor.run_for(Duration::from_millis(3));
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(255));
+    }
+
+    #[test]
+    fn test_pause_freezes_timers() {
+        let mut executor = executor(&TIMER_PROGRAM);
+        let bus = executor.control_bus();
+        executor.run_for(Duration::from_millis(3));
+        let interface = executor.core().interface();
+        bus.send(Command::Pause);
+        executor.run_for(Duration::from_secs(1));
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(255));
+        assert_eq!(interface.lock().unwrap().sound_timer, Value(255));
+        // No catch-up after resuming: the next tick is still 16.667ms after
+        // the last one, not counting the pause.
+        bus.send(Command::Resume);
+        executor.run_for(Duration::from_millis(20));
+        assert_eq!(interface.lock().unwrap().delay_timer, Value(254));
+        assert_eq!(executor.skipped_timer_ticks(), 0);
+        assert_eq!(executor.skipped_instructions(), 0);
+    }
+
+    #[test]
+    fn test_error_stops_program() {
+        // V0 = 5, then return without a subroutine call.
+        let mut executor = executor(&[0x60, 0x05, 0x00, 0xEE]);
+        let bus = executor.control_bus();
+        executor.run_for(Duration::from_millis(10));
+        let error = VmError::StackUnderflow { pc: Address(0x202) };
+        assert_eq!(bus.events().collect::<Vec<_>>(), vec![Event::Error(error)]);
+        assert_eq!(executor.core().program_counter(), Address(0x202));
+    }
+
+    /// Records the tone changes it is told.
+    struct RecordedAudio(Arc<Mutex<Vec<bool>>>);
+
+    impl Audio for RecordedAudio {
+        fn set_tone(&mut self, on: bool) {
+            self.0.lock().unwrap().push(on);
+        }
+    }
+
+    #[test]
+    fn test_sound() {
+        let mut executor = executor(&TIMER_PROGRAM);
+        let tones = Arc::new(Mutex::new(Vec::new()));
+        executor.core().interface().lock().unwrap().audio = Box::new(RecordedAudio(tones.clone()));
+        executor.run_for(Duration::from_secs(5));
+        assert_eq!(*tones.lock().unwrap(), [true, false]);
+    }
+
+    #[test]
+    fn test_rewind_is_silent() {
+        let mut executor = executor(&TIMER_PROGRAM);
+        executor.set_rewind(Some(RewindBuffer::new(100, 1)));
+        let tones = Arc::new(Mutex::new(Vec::new()));
+        executor.core().interface().lock().unwrap().audio = Box::new(RecordedAudio(tones.clone()));
+        let bus = executor.control_bus();
+        executor.run_for(TIMER_INTERVAL * 5);
+        bus.send(Command::Rewind(true));
+        executor.run_for(TIMER_INTERVAL * 3);
+        assert_eq!(*tones.lock().unwrap(), [true, false]);
+        bus.send(Command::Rewind(false));
+        executor.run_for(TIMER_INTERVAL);
+        assert_eq!(*tones.lock().unwrap(), [true, false, true]);
+    }
+
+    #[test]
+    fn test_debugging() {
+        // V0 += 1, V1 += 1, jump back to start.
+        let mut executor = executor(&[0x70, 0x01, 0x71, 0x01, 0x12, 0x00]);
+        let bus = executor.control_bus();
+        bus.send(Command::AddBreakpoint(Address(0x202)));
+        executor.run_for(Duration::from_millis(10));
+        match bus.events().next().unwrap() {
+            Event::Stopped { pc, state } => {
+                assert_eq!(pc, Address(0x202));
+                assert!(state.contains("V0 01"));
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(executor.paused);
+        assert!(bus.events().next().is_none());
+
+        bus.send(Command::Step);
+        executor.run_for(Duration::from_millis(10));
+        assert!(matches!(
+            bus.events().next(),
+            Some(Event::Stopped {
+                pc: Address(0x204),
+                ..
+            })
+        ));
+
+        bus.send(Command::RemoveBreakpoint(Address(0x202)));
+        bus.send(Command::Resume);
+        executor.run_for(Duration::from_millis(10));
+        assert!(!executor.paused);
+        assert!(bus.events().next().is_none());
+
+        bus.send(Command::AddCondition("V1 == 0x20".parse().unwrap()));
+        executor.run_for(Duration::from_millis(100));
+        assert!(executor.paused);
+        assert_eq!(executor.core().register(Register(1)), Value(0x20));
+    }
+
+    #[test]
+    fn test_inspect() {
+        // V0 = 5, call 0x206, 0x204: loop forever, 0x206: jump to 0x204.
+        let mut executor = executor(&[0x60, 0x05, 0x22, 0x06, 0x12, 0x04, 0x12, 0x04]);
+        let bus = executor.control_bus();
+        executor.run_for(Duration::from_millis(50));
+        assert!(bus.events().next().is_none());
+
+        bus.send(Command::Inspect(true));
+        executor.run_for(Duration::from_millis(50));
+        let states: Vec<_> = bus
+            .events()
+            .filter_map(|event| match event {
+                Event::State(state) => Some(state),
+                _ => None,
+            })
+            .collect();
+        assert!(states.len() >= 2);
+        let state = states.last().unwrap();
+        assert_eq!(state.pc, Address(0x204));
+        assert_eq!(state.next, "JP 0x204");
+        assert_eq!(state.registers[0], Value(5));
+        assert_eq!(state.stack, vec![Address(0x204)]);
+        assert_eq!(state.keypad, 0);
+        assert_eq!(state.draw_collisions, 0);
+        assert!(state.watches.is_empty());
+        // The virtual clock does not advance while executing.
+        assert_eq!(state.emulation_time, Some((Duration::ZERO, Duration::ZERO)));
+
+        bus.send(Command::Keys(key_down(7)));
+        executor.run_for(Duration::from_millis(20));
+        match bus.events().last() {
+            Some(Event::State(state)) => assert_eq!(state.keypad, 0x80),
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        bus.send(Command::Inspect(false));
+        executor.run_for(Duration::from_millis(50));
+        assert!(bus.events().next().is_none());
+    }
+
+    #[test]
+    fn test_inspect_watches() {
+        // V0 = 0x2A, I = 0x300, store V0 at I, loop forever.
+        let program = [0x60, 0x2A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+        let mut executor = executor(&program);
+        executor.add_watch(Watch::new(
+            "score",
+            Address(0x300),
+            WatchWidth::Byte,
+            WatchFormat::Decimal,
+        ));
+        let bus = executor.control_bus();
+        bus.send(Command::Inspect(true));
+        executor.run_for(Duration::from_millis(50));
+        let state = bus
+            .events()
+            .filter_map(|event| match event {
+                Event::State(state) => Some(state),
+                _ => None,
+            })
+            .last()
+            .unwrap();
+        assert_eq!(state.watches, vec![String::from("score = 42")]);
+    }
+
+    #[test]
+    fn test_movie() {
+        // Wait for a key into V0, add it to V1, loop.
+        let program = [0xF0, 0x0A, 0x81, 0x04, 0x12, 0x00];
+        let mut executor = executor(&program);
+        executor.record_movie();
+        let bus = executor.control_bus();
+        executor.run_for(Duration::from_millis(20));
+        bus.send(Command::Keys(key_down(3)));
+        executor.run_for(Duration::from_millis(10));
+        bus.send(Command::Keys([false; 16]));
+        bus.send(Command::LatchKey(1));
+        executor.run_for(Duration::from_millis(40));
+        let movie = executor.take_movie().unwrap();
+        assert!(movie.frames() >= 4);
+        assert_eq!(movie.seed(), executor.core().seed());
+
+        let mut vm = VirtualMachine::new(&program);
+        movie.replay(&mut vm).unwrap();
+        for register in [Register(0), Register(1)] {
+            assert_eq!(
+                vm.get_register(register),
+                executor.core().register(register)
+            );
+        }
+        assert_eq!(vm.get_register(Register(0)), Value(1));
+    }
+
+    #[test]
+    fn test_movie_stops_on_load_state() {
+        // V0 += 1, jump back to start.
+        let program = [0x70, 0x01, 0x12, 0x00];
+        let mut executor = executor(&program);
+        executor.record_movie();
+        let bus = executor.control_bus();
+        executor.run_for(TIMER_INTERVAL * 3);
+        bus.send(Command::SaveState { slot: 0 });
+        executor.run_for(TIMER_INTERVAL * 2);
+        let state = bus
+            .events()
+            .find_map(|event| match event {
+                Event::Saved { state, .. } => state.ok(),
+                _ => None,
+            })
+            .unwrap();
+        let recorded = executor.core().register(Register(0));
+        bus.send(Command::LoadState { slot: 0, state });
+        executor.run_for(TIMER_INTERVAL);
+        assert!(bus.events().any(|event| matches!(event, Event::Warning(_))));
+
+        // The movie ends where the state was loaded.
+        let movie = executor.take_movie().unwrap();
+        let mut vm = VirtualMachine::new(&program);
+        movie.replay(&mut vm).unwrap();
+        assert_eq!(vm.get_register(Register(0)), recorded);
+        assert_ne!(executor.core().register(Register(0)), recorded);
+    }
+
+    #[test]
+    fn test_movie_stops_on_rewind() {
+        let mut executor = executor(&[0x70, 0x01, 0x12, 0x00]);
+        executor.set_rewind(Some(RewindBuffer::new(100, 1)));
+        executor.record_movie();
+        let bus = executor.control_bus();
+        executor.run_for(TIMER_INTERVAL * 5);
+        bus.send(Command::Rewind(true));
+        executor.run_for(TIMER_INTERVAL * 2);
+        bus.send(Command::Rewind(false));
+        executor.run_for(TIMER_INTERVAL * 5);
+        let warnings: Vec<_> = bus
+            .events()
+            .filter(|event| matches!(event, Event::Warning(_)))
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(executor.take_movie().unwrap().frames() <= 6);
+    }
+
+    #[test]
+    fn test_keys_and_loading() {
+        let mut executor = executor(&TIMER_PROGRAM);
+        let bus = executor.control_bus();
+        bus.send(Command::Keys(key_down(7)));
+        executor.run_for(Duration::from_millis(3));
+        let interface = executor.core().interface();
+        assert_eq!(interface.lock().unwrap().keys_down, key_down(7));
+        bus.send(Command::LatchKey(2));
+        executor.run_for(Duration::from_millis(3));
+        assert_eq!(interface.lock().unwrap().latched_key, Some(2));
+
+        // V0 = 1, then loop forever.
+        bus.send(Command::Load(Box::new(VirtualMachine::new(&[
+            0x60, 0x01, 0x12, 0x02,
+        ]))));
+        executor.run_for(Duration::from_millis(3));
+        assert_eq!(executor.core().program_counter(), Address(0x202));
+        assert_eq!(
+            executor.core().interface().lock().unwrap().delay_timer,
+            Value(0)
+        );
+        assert_eq!(
+            executor.core().interface().lock().unwrap().keys_down,
+            key_down(7)
+        );
+    }
+
+    #[test]
+    fn test_save_states() {
+        // V0 += 1, jump back to start.
+        let mut executor = executor(&[0x70, 0x01, 0x12, 0x00]);
+        let bus = executor.control_bus();
+        executor.run_for(Duration::from_millis(10));
+        bus.send(Command::Pause);
+        bus.send(Command::SaveState { slot: 3 });
+        executor.run_for(Duration::from_millis(1));
+        let state = bus
+            .events()
+            .find_map(|event| match event {
+                Event::Saved { slot, state } => Some((slot, state)),
+                _ => None,
+            })
+            .unwrap();
+        let saved = match state {
+            (3, Ok(state)) => state,
+            state => panic!("unexpected state {:?}", state),
+        };
+        let v0 = executor.core().register(Register(0));
+        assert_eq!(saved.registers[0], v0.0);
+
+        bus.send(Command::Resume);
+        executor.run_for(Duration::from_millis(10));
+        assert_ne!(executor.core().register(Register(0)), v0);
+        bus.send(Command::Pause);
+        bus.send(Command::LoadState {
+            slot: 5,
+            state: saved,
+        });
+        executor.run_for(Duration::from_millis(1));
+        assert_eq!(executor.core().register(Register(0)), v0);
+        assert!(bus.events().any(|event| event
+            == Event::Loaded {
+                slot: 5,
+                result: Ok(())
+            }));
+    }
+
+    #[test]
+    fn test_rewind() {
+        // V0 += 1, jump back to start.
+        let mut executor = executor(&[0x70, 0x01, 0x12, 0x00]);
+        executor.set_rewind(Some(RewindBuffer::new(100, 1)));
+        let bus = executor.control_bus();
+        executor.run_for(TIMER_INTERVAL * 10);
+        let v0 = executor.core().register(Register(0)).0;
+
+        bus.send(Command::Rewind(true));
+        executor.run_for(TIMER_INTERVAL * 5);
+        let rewound = executor.core().register(Register(0)).0;
+        assert!(rewound < v0);
+        executor.run_for(TIMER_INTERVAL * 5);
+        assert!(executor.core().register(Register(0)).0 < rewound);
+
+        bus.send(Command::Rewind(false));
+        let rewound = executor.core().register(Register(0)).0;
+        executor.run_for(TIMER_INTERVAL * 5);
+        assert!(executor.core().register(Register(0)).0 > rewound);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let mut commanded = executor(&TIMER_PROGRAM);
+        let bus = commanded.control_bus();
+        bus.send(Command::Shutdown);
+        commanded.run_for(Duration::from_secs(3600));
+        assert!(commanded.shut_down);
+
+        let mut abandoned = executor(&TIMER_PROGRAM);
+        drop(abandoned.control_bus());
+        abandoned.run_for(Duration::from_secs(3600));
+        assert!(abandoned.shut_down);
+    }
+
+    #[test]
+    fn test_thread_setup() {
+        let mut executor = executor(&TIMER_PROGRAM);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        executor.set_thread_setup(move || sender.send(thread::current().id()).unwrap());
+        drop(executor.control_bus());
+        let handle = executor.run_concurrent();
+        let setup_thread = receiver.recv().unwrap();
+        assert_eq!(setup_thread, handle.thread().id());
+        assert!(handle.join().unwrap().shut_down);
+    }
+}
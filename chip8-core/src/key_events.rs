@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// A CHIP-8 key being pressed or released.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEvent {
+    pub key: u8,
+    pub pressed: bool,
+    /// When the event reached the core, on the clock of the executor.
+    pub time: Duration,
+}
+
+/// Key events that the VM has not applied yet. The VM samples the keys only
+/// when a key instruction runs, so a press that is released before that
+/// would be lost. Instead, the release of a key is held back until a key
+/// instruction saw the key pressed.
+#[derive(Clone, Debug, Default)]
+pub struct KeyEventQueue {
+    events: VecDeque<KeyEvent>,
+    /// Keys that were pressed but not yet seen by a key instruction.
+    unseen: [bool; 16],
+    /// Keys whose release is held back until they were seen.
+    release_pending: [bool; 16],
+}
+
+impl KeyEventQueue {
+    pub fn push(&mut self, event: KeyEvent) {
+        assert!(event.key < 16);
+        self.events.push_back(event);
+    }
+
+    /// The events not applied yet, oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = &KeyEvent> {
+        self.events.iter()
+    }
+
+    /// Applies all queued events to the keys held down, except for releases
+    /// of keys that were not seen yet.
+    pub(crate) fn apply(&mut self, keys_down: &mut [bool; 16]) {
+        while let Some(event) = self.events.pop_front() {
+            let key = event.key as usize;
+            if event.pressed {
+                keys_down[key] = true;
+                self.unseen[key] = true;
+                self.release_pending[key] = false;
+            } else if self.unseen[key] {
+                self.release_pending[key] = true;
+            } else {
+                keys_down[key] = false;
+            }
+        }
+    }
+
+    /// Called when a key instruction saw `key` pressed. Carries out its held
+    /// back release.
+    pub(crate) fn seen(&mut self, key: u8, keys_down: &mut [bool; 16]) {
+        let key = key as usize;
+        self.unseen[key] = false;
+        if self.release_pending[key] {
+            self.release_pending[key] = false;
+            keys_down[key] = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(key: u8, pressed: bool) -> KeyEvent {
+        KeyEvent {
+            key,
+            pressed,
+            time: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_release_held_back() {
+        let mut queue = KeyEventQueue::default();
+        let mut keys = [false; 16];
+        queue.push(event(4, true));
+        queue.push(event(4, false));
+        queue.push(event(9, true));
+        assert_eq!(queue.pending().count(), 3);
+        queue.apply(&mut keys);
+        assert_eq!(queue.pending().count(), 0);
+        assert!(keys[4] && keys[9]);
+
+        queue.seen(4, &mut keys);
+        assert!(!keys[4] && keys[9]);
+
+        // Once seen, a key is released right away.
+        queue.seen(9, &mut keys);
+        queue.push(event(9, false));
+        queue.apply(&mut keys);
+        assert!(!keys[9]);
+    }
+}
@@ -0,0 +1,28 @@
+pub mod asm;
+pub mod basics;
+pub mod cfg;
+pub mod clock;
+pub mod compare;
+pub mod control;
+pub mod core;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+pub mod display_channel;
+pub mod executor;
+pub mod key_events;
+pub mod mmio;
+pub mod movie;
+pub mod octo_asm;
+pub mod pgm;
+pub mod program;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod save_state;
+pub mod self_test;
+pub mod sprites;
+pub mod stats;
+pub mod testing;
+pub mod vm;
+pub mod watch;
@@ -0,0 +1,89 @@
+use super::basics::{Address, MEMORY_SIZE};
+
+/// A device that can be mapped into the memory of a VM. Reads and writes by
+/// the program into the mapped window are routed to the device instead of
+/// memory, with the offset relative to the start of the window.
+pub trait Peripheral: Send {
+    fn read(&mut self, offset: u16) -> u8;
+    fn write(&mut self, offset: u16, value: u8);
+}
+
+/// An address window `[start, start + len)` that belongs to a peripheral.
+pub struct MappedRegion {
+    start: Address,
+    len: u16,
+    peripheral: Box<dyn Peripheral>,
+}
+
+impl MappedRegion {
+    pub fn new(start: Address, len: u16, peripheral: Box<dyn Peripheral>) -> MappedRegion {
+        MappedRegion {
+            start,
+            len,
+            peripheral,
+        }
+    }
+
+    /// Returns the offset of an address inside of this region, if it is part of it.
+    fn offset(&self, addr: Address) -> Option<u16> {
+        if addr.0 >= self.start.0 && addr.0 - self.start.0 < self.len {
+            Some(addr.0 - self.start.0)
+        } else {
+            None
+        }
+    }
+
+    /// The address after the last one of this region. Computed in `u32`, since
+    /// a region may end at the top of the address space.
+    fn end(&self) -> u32 {
+        self.start.0 as u32 + self.len as u32
+    }
+
+    fn overlaps(&self, other: &MappedRegion) -> bool {
+        (self.start.0 as u32) < other.end() && (other.start.0 as u32) < self.end()
+    }
+}
+
+/// All peripherals mapped into a VM.
+#[derive(Default)]
+pub struct MemoryMap {
+    regions: Vec<MappedRegion>,
+}
+
+impl MemoryMap {
+    /// Adds a region. Panics if it reaches past the end of memory or overlaps
+    /// with an already mapped one.
+    pub fn map(&mut self, region: MappedRegion) {
+        if region.end() > MEMORY_SIZE as u32 {
+            panic!(
+                "Memory region at {:?} with {} bytes reaches past the end of memory.",
+                region.start, region.len
+            );
+        }
+        if self.regions.iter().any(|r| r.overlaps(&region)) {
+            panic!(
+                "Memory region at {:?} overlaps with a mapped region.",
+                region.start
+            );
+        }
+        self.regions.push(region);
+    }
+
+    /// Reads from a peripheral, if the address is mapped.
+    pub fn read(&mut self, addr: Address) -> Option<u8> {
+        self.regions
+            .iter_mut()
+            .find_map(|r| r.offset(addr).map(|offset| r.peripheral.read(offset)))
+    }
+
+    /// Writes to a peripheral. Returns false if the address is not mapped.
+    pub fn write(&mut self, addr: Address, value: u8) -> bool {
+        for region in self.regions.iter_mut() {
+            if let Some(offset) = region.offset(addr) {
+                region.peripheral.write(offset, value);
+                return true;
+            }
+        }
+        false
+    }
+}
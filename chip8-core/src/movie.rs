@@ -0,0 +1,286 @@
+use super::core::Chip8Core;
+use super::key_events::KeyEvent;
+use super::vm::VmError;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The first line of a movie file, which names its format version.
+const MOVIE_HEADER: &str = "chip8 movie v2";
+
+/// An input to the core, or a timer tick.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovieEvent {
+    /// The keys held down changed, see `VMInterface::keys_down`.
+    Keys([bool; 16]),
+    /// A key was pressed or released, see `VMInterface::key_events`.
+    KeyEvent { key: u8, pressed: bool },
+    /// A key press was latched, see `VMInterface::latched_key`.
+    LatchKey(u8),
+    /// The timers ticked, which also ends a frame.
+    Tick,
+}
+
+/// Everything that reached a core from the outside since it was started,
+/// each event with the number of instructions executed before it, and the
+/// seed of its random numbers. Replaying a movie on a fresh core reproduces
+/// the run exactly, independent of the timing of the executor.
+///
+/// In a movie file, the header is followed by `seed <seed>` if the core was
+/// seeded, then every event is one line: `<instructions> keys <hex keys>`
+/// with the keys held down as hex digits, e.g. `48`, or `-` for none,
+/// `<instructions> press <hex key>`, `<instructions> release <hex key>`,
+/// `<instructions> latch <hex key>` or `<instructions> tick`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Movie {
+    seed: Option<u64>,
+    events: Vec<(u64, MovieEvent)>,
+}
+
+impl Movie {
+    pub fn new() -> Movie {
+        Movie::default()
+    }
+
+    /// A movie of a core whose random numbers were seeded with `seed`.
+    pub fn with_seed(seed: u64) -> Movie {
+        Movie {
+            seed: Some(seed),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Adds an event after `instructions` executed instructions, which must
+    /// not be less than for the previous event.
+    pub fn record(&mut self, instructions: u64, event: MovieEvent) {
+        debug_assert!(self
+            .events
+            .last()
+            .is_none_or(|(last, _)| *last <= instructions));
+        self.events.push((instructions, event));
+    }
+
+    /// The number of frames, i.e. timer ticks.
+    pub fn frames(&self) -> usize {
+        self.events
+            .iter()
+            .filter(|(_, event)| *event == MovieEvent::Tick)
+            .count()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", MOVIE_HEADER);
+        if let Some(seed) = self.seed {
+            let _ = writeln!(text, "seed {}", seed);
+        }
+        for (instructions, event) in &self.events {
+            let _ = match event {
+                MovieEvent::Keys(keys) => {
+                    writeln!(text, "{} keys {}", instructions, format_keys(keys))
+                }
+                MovieEvent::KeyEvent { key, pressed } => {
+                    let action = if *pressed { "press" } else { "release" };
+                    writeln!(text, "{} {} {:X}", instructions, action, key)
+                }
+                MovieEvent::LatchKey(key) => writeln!(text, "{} latch {:X}", instructions, key),
+                MovieEvent::Tick => writeln!(text, "{} tick", instructions),
+            };
+        }
+        text
+    }
+
+    /// Reads a movie written by `to_text`.
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(MOVIE_HEADER) {
+            return Err(format!("expected \"{}\" in the first line", MOVIE_HEADER));
+        }
+        let mut movie = Movie::new();
+        let mut lines = lines.enumerate().peekable();
+        if let Some((_, line)) = lines.peek() {
+            if let Some(seed) = line.strip_prefix("seed ") {
+                let seed = seed
+                    .parse()
+                    .map_err(|_| format!("invalid seed \"{}\" in line 2", seed))?;
+                movie.seed = Some(seed);
+                lines.next();
+            }
+        }
+        for (index, line) in lines {
+            let error = || format!("invalid event \"{}\" in line {}", line, index + 2);
+            let parse_key = |key: &str| {
+                u8::from_str_radix(key, 16)
+                    .ok()
+                    .filter(|key| *key < 16)
+                    .ok_or_else(error)
+            };
+            let parts: Vec<_> = line.split_whitespace().collect();
+            let (instructions, event) = match parts.as_slice() {
+                [instructions, "keys", "-"] => (instructions, MovieEvent::Keys([false; 16])),
+                [instructions, "keys", keys] => {
+                    let mut down = [false; 16];
+                    for key in keys.chars() {
+                        down[key.to_digit(16).ok_or_else(error)? as usize] = true;
+                    }
+                    (instructions, MovieEvent::Keys(down))
+                }
+                [instructions, action @ ("press" | "release"), key] => (
+                    instructions,
+                    MovieEvent::KeyEvent {
+                        key: parse_key(key)?,
+                        pressed: *action == "press",
+                    },
+                ),
+                [instructions, "latch", key] => {
+                    (instructions, MovieEvent::LatchKey(parse_key(key)?))
+                }
+                [instructions, "tick"] => (instructions, MovieEvent::Tick),
+                _ => return Err(error()),
+            };
+            let instructions: u64 = instructions.parse().map_err(|_| error())?;
+            if movie
+                .events
+                .last()
+                .is_some_and(|(last, _)| *last > instructions)
+            {
+                return Err(format!("line {} goes back in time", index + 2));
+            }
+            movie.record(instructions, event);
+        }
+        Ok(movie)
+    }
+
+    /// Seeds a freshly started core and feeds the events to it, executing as
+    /// many instructions between them as during the recording. Stops after
+    /// the last event, or at the first error of the core.
+    pub fn replay(&self, core: &mut dyn Chip8Core) -> Result<(), VmError> {
+        if let Some(seed) = self.seed {
+            core.set_seed(seed);
+        }
+        let interface = core.interface();
+        let mut executed = 0;
+        for (instructions, event) in &self.events {
+            while executed < *instructions {
+                core.step()?;
+                executed += 1;
+            }
+            let mut interface = interface.lock().unwrap();
+            match event {
+                MovieEvent::Keys(keys) => interface.keys_down = *keys,
+                MovieEvent::KeyEvent { key, pressed } => interface.key_events.push(KeyEvent {
+                    key: *key,
+                    pressed: *pressed,
+                    time: Duration::ZERO,
+                }),
+                MovieEvent::LatchKey(key) => interface.latched_key = Some(*key),
+                MovieEvent::Tick => interface.tick_timers(),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The keys held down as hex digits, or `-` for none.
+fn format_keys(keys: &[bool; 16]) -> String {
+    let digits: String = (0..16)
+        .filter(|key| keys[*key])
+        .map(|key| format!("{:X}", key))
+        .collect();
+    if digits.is_empty() {
+        String::from("-")
+    } else {
+        digits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basics::{Register, Value};
+    use crate::vm::VirtualMachine;
+
+    /// Waits for a key into V0, adds it to V1, loops.
+    const PROGRAM: [u8; 6] = [0xF0, 0x0A, 0x81, 0x04, 0x12, 0x00];
+
+    fn movie() -> Movie {
+        let mut movie = Movie::new();
+        movie.record(0, MovieEvent::Tick);
+        let mut keys = [false; 16];
+        keys[5] = true;
+        movie.record(2, MovieEvent::Keys(keys));
+        movie.record(4, MovieEvent::Keys([false; 16]));
+        movie.record(4, MovieEvent::LatchKey(0xA));
+        movie.record(7, MovieEvent::Tick);
+        movie
+    }
+
+    #[test]
+    fn test_text() {
+        let movie = movie();
+        let text = movie.to_text();
+        assert!(text.ends_with("2 keys 5\n4 keys -\n4 latch A\n7 tick\n"));
+        assert_eq!(Movie::parse(&text), Ok(movie.clone()));
+        assert_eq!(movie.frames(), 2);
+        assert!(Movie::parse("0 tick\n").is_err());
+        assert!(Movie::parse(&format!("{}\n1 keys G\n", MOVIE_HEADER)).is_err());
+        let parsed = Movie::parse(&format!("{}\n1 keys 2F\n", MOVIE_HEADER)).unwrap();
+        let mut keys = [false; 16];
+        keys[2] = true;
+        keys[0xF] = true;
+        assert_eq!(parsed.events, [(1, MovieEvent::Keys(keys))]);
+        let events = format!("{}\n3 press C\n5 release C\n", MOVIE_HEADER);
+        let parsed = Movie::parse(&events).unwrap();
+        assert_eq!(
+            parsed.events[0],
+            (
+                3,
+                MovieEvent::KeyEvent {
+                    key: 0xC,
+                    pressed: true
+                }
+            )
+        );
+        assert_eq!(parsed.to_text(), events);
+        assert_eq!(
+            Movie::parse(&format!("{}\n2 tick\n1 tick\n", MOVIE_HEADER)),
+            Err("line 3 goes back in time".to_string())
+        );
+
+        let seeded = Movie::with_seed(42);
+        assert_eq!(seeded.to_text(), format!("{}\nseed 42\n", MOVIE_HEADER));
+        assert_eq!(Movie::parse(&seeded.to_text()), Ok(seeded));
+        assert!(Movie::parse(&format!("{}\nseed x\n", MOVIE_HEADER)).is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut vm = VirtualMachine::new(&PROGRAM);
+        movie().replay(&mut vm).unwrap();
+        // Two waits without a key, then 5 and the latched A are read and
+        // added up.
+        assert_eq!(vm.get_register(Register(0)), Value(0xA));
+        assert_eq!(vm.get_register(Register(1)), Value(0xF));
+        assert_eq!(vm.executed_instructions(), 7);
+    }
+
+    #[test]
+    fn test_replay_seed() {
+        // V0 = random byte.
+        let program = [0xC0, 0xFF];
+        let mut recorded = VirtualMachine::new(&program);
+        recorded.set_seed(3);
+        recorded.step().unwrap();
+
+        let mut movie = Movie::with_seed(3);
+        movie.record(1, MovieEvent::Tick);
+        let mut vm = VirtualMachine::new(&program);
+        movie.replay(&mut vm).unwrap();
+        assert_eq!(
+            vm.get_register(Register(0)),
+            recorded.get_register(Register(0))
+        );
+    }
+}
@@ -0,0 +1,682 @@
+use super::asm::AsmError;
+use super::basics::{Address, Register, Value, PROGRAM_START};
+use super::program::Instruction;
+use std::collections::HashMap;
+
+/// Octo statements of the SUPER-CHIP and XO-CHIP extensions, which have no
+/// CHIP-8 instruction to compile to.
+const EXTENSIONS: [&str; 13] = [
+    "hires",
+    "lores",
+    "scroll-down",
+    "scroll-left",
+    "scroll-right",
+    "scroll-up",
+    "exit",
+    "bighex",
+    "saveflags",
+    "loadflags",
+    "plane",
+    "audio",
+    "pitch",
+];
+
+/// Octo directives that this assembler does not support.
+const UNSUPPORTED_DIRECTIVES: [&str; 11] = [
+    ":org",
+    ":next",
+    ":unpack",
+    ":macro",
+    ":calc",
+    ":byte",
+    ":pointer",
+    ":assert",
+    ":breakpoint",
+    ":monitor",
+    ":stringmode",
+];
+
+/// The condition of an `if` or `while`.
+#[derive(Clone, Copy)]
+enum Condition {
+    EqualConst(Register, Value),
+    NotEqualConst(Register, Value),
+    Equal(Register, Register),
+    NotEqual(Register, Register),
+    Key(Register),
+    NotKey(Register),
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        match self {
+            Condition::EqualConst(x, nn) => Condition::NotEqualConst(x, nn),
+            Condition::NotEqualConst(x, nn) => Condition::EqualConst(x, nn),
+            Condition::Equal(x, y) => Condition::NotEqual(x, y),
+            Condition::NotEqual(x, y) => Condition::Equal(x, y),
+            Condition::Key(x) => Condition::NotKey(x),
+            Condition::NotKey(x) => Condition::Key(x),
+        }
+    }
+
+    /// The skip instruction that executes the next instruction only if the
+    /// condition holds.
+    fn skip(self) -> Instruction {
+        match self {
+            Condition::EqualConst(x, nn) => Instruction::IfEqualConst(x, nn),
+            Condition::NotEqualConst(x, nn) => Instruction::IfNotEqualConst(x, nn),
+            Condition::Equal(x, y) => Instruction::IfEqual(x, y),
+            Condition::NotEqual(x, y) => Instruction::IfNotEqual(x, y),
+            Condition::Key(x) => Instruction::IfKey(x),
+            Condition::NotKey(x) => Instruction::IfNotKey(x),
+        }
+    }
+}
+
+/// A block that is still open, with the offsets of the jumps that are patched
+/// once the block ends.
+enum Block {
+    /// `if ... begin`, jumping to the `else` or `end`.
+    If { jump: usize },
+    /// `else`, jumping to the `end`.
+    Else { jump: usize },
+    /// `loop`, whose `while`s jump past the `again`.
+    Loop { start: u16, exits: Vec<usize> },
+}
+
+struct Assembler<'a> {
+    /// The tokens of the source along with their lines.
+    tokens: Vec<(usize, &'a str)>,
+    position: usize,
+    /// The line of the last token read.
+    line: usize,
+    program: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, i32>,
+    aliases: HashMap<&'a str, Register>,
+    /// Instructions that refer to a label before its definition: their
+    /// offset, the label and the line.
+    fixups: Vec<(usize, &'a str, usize)>,
+    /// The open blocks, innermost last, with the lines they start in.
+    blocks: Vec<(usize, Block)>,
+}
+
+fn is_name(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_number(token: &str) -> Option<i32> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let number = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2)
+    } else {
+        digits.parse()
+    }
+    .ok()?;
+    Some(if negative { -number } else { number })
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &'a str) -> Assembler<'a> {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, text)| {
+                let code = text.split('#').next().unwrap();
+                code.split_whitespace().map(move |token| (index + 1, token))
+            })
+            .collect();
+        Assembler {
+            tokens,
+            position: 0,
+            line: 1,
+            program: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn error(&self, message: String) -> AsmError {
+        AsmError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Result<&'a str, AsmError> {
+        let (line, token) = *self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| self.error("unexpected end of the program".to_string()))?;
+        self.position += 1;
+        self.line = line;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(self.error(format!("expected '{}', got '{}'", expected, token))),
+        }
+    }
+
+    /// The address of the next instruction.
+    fn here(&self) -> u16 {
+        PROGRAM_START + self.program.len() as u16
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.program.extend(&instruction.to_16bit());
+    }
+
+    /// Sets the address of the instruction at `offset`.
+    fn patch(&mut self, offset: usize, addr: u16) {
+        self.program[offset] = self.program[offset] & 0xF0 | (addr >> 8) as u8 & 0x0F;
+        self.program[offset + 1] = addr as u8;
+    }
+
+    /// Emits a jump whose target is patched later, and returns its offset.
+    fn emit_open_jump(&mut self) -> usize {
+        let offset = self.program.len();
+        self.emit(Instruction::Jump(Address(0)));
+        offset
+    }
+
+    fn register_of(&self, token: &str) -> Option<Register> {
+        if let Some(register) = self.aliases.get(token) {
+            return Some(*register);
+        }
+        let digit = token
+            .strip_prefix('v')
+            .or_else(|| token.strip_prefix('V'))?;
+        match u8::from_str_radix(digit, 16) {
+            Ok(x) if digit.len() == 1 => Some(Register(x)),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<Register, AsmError> {
+        let token = self.next()?;
+        self.register_of(token)
+            .ok_or_else(|| self.error(format!("expected a register, got '{}'", token)))
+    }
+
+    /// The value of a number, constant or defined label.
+    fn value_of(&self, token: &str) -> Option<i32> {
+        parse_number(token)
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|addr| *addr as i32))
+    }
+
+    fn value(&mut self) -> Result<i32, AsmError> {
+        let token = self.next()?;
+        self.value_of(token)
+            .ok_or_else(|| self.error(format!("unknown name '{}'", token)))
+    }
+
+    fn byte_of(&self, value: i32) -> Result<Value, AsmError> {
+        if (-128..=255).contains(&value) {
+            Ok(Value(value as u8))
+        } else {
+            Err(self.error(format!("byte {} is out of range", value)))
+        }
+    }
+
+    fn byte(&mut self) -> Result<Value, AsmError> {
+        let value = self.value()?;
+        self.byte_of(value)
+    }
+
+    /// Emits an instruction with the address that `token` stands for, which
+    /// may be a label that is defined further down.
+    fn emit_addressed(
+        &mut self,
+        token: &'a str,
+        instruction: fn(Address) -> Instruction,
+    ) -> Result<(), AsmError> {
+        match self.value_of(token) {
+            Some(addr) if (0..=0xFFF).contains(&addr) => {
+                self.emit(instruction(Address(addr as u16)));
+            }
+            Some(addr) => return Err(self.error(format!("address {:#X} is out of range", addr))),
+            None if is_name(token) => {
+                self.fixups.push((self.program.len(), token, self.line));
+                self.emit(instruction(Address(0)));
+            }
+            None => return Err(self.error(format!("expected an address, got '{}'", token))),
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()?;
+        let condition = match self.next()? {
+            "key" => Condition::Key(x),
+            "-key" => Condition::NotKey(x),
+            operator @ ("==" | "!=") => {
+                let token = self.next()?;
+                let equal = operator == "==";
+                match self.register_of(token) {
+                    Some(y) if equal => Condition::Equal(x, y),
+                    Some(y) => Condition::NotEqual(x, y),
+                    None => {
+                        let value = self
+                            .value_of(token)
+                            .ok_or_else(|| self.error(format!("unknown name '{}'", token)))?;
+                        let nn = self.byte_of(value)?;
+                        if equal {
+                            Condition::EqualConst(x, nn)
+                        } else {
+                            Condition::NotEqualConst(x, nn)
+                        }
+                    }
+                }
+            }
+            operator => {
+                return Err(self.error(format!("unsupported comparison '{}'", operator)));
+            }
+        };
+        Ok(condition)
+    }
+
+    /// Compiles a statement that starts with the register `x`.
+    fn register_statement(&mut self, x: Register) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        let token = self.next()?;
+        let instruction = match (operator, self.register_of(token)) {
+            (":=", Some(y)) => Instruction::Set(x, y),
+            ("+=", Some(y)) => Instruction::Add(x, y),
+            ("-=", Some(y)) => Instruction::Sub(x, y),
+            ("=-", Some(y)) => Instruction::NegSub(x, y),
+            ("|=", Some(y)) => Instruction::Or(x, y),
+            ("&=", Some(y)) => Instruction::And(x, y),
+            ("^=", Some(y)) => Instruction::Xor(x, y),
+            (">>=", Some(y)) => Instruction::RightShift(x, y),
+            ("<<=", Some(y)) => Instruction::LeftShift(x, y),
+            (":=", None) if token == "random" => Instruction::Rand(x, self.byte()?),
+            (":=", None) if token == "delay" => Instruction::GetDelayTimer(x),
+            (":=", None) if token == "key" => Instruction::WaitKey(x),
+            (":=" | "+=" | "-=", None) => {
+                let value = self
+                    .value_of(token)
+                    .ok_or_else(|| self.error(format!("unknown name '{}'", token)))?;
+                let nn = self.byte_of(value)?;
+                match operator {
+                    ":=" => Instruction::SetConst(x, nn),
+                    "+=" => Instruction::AddConst(x, nn),
+                    _ => Instruction::AddConst(x, Value(nn.0.wrapping_neg())),
+                }
+            }
+            _ => return Err(self.error(format!("invalid operation '{} {}'", operator, token))),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn statement(&mut self, token: &'a str) -> Result<(), AsmError> {
+        match token {
+            ":" => {
+                let label = self.next()?;
+                self.define_label(label)?;
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":call" => {
+                let target = self.next()?;
+                self.emit_addressed(target, Instruction::CallSubroutine)?;
+            }
+            "clear" => self.emit(Instruction::ClearDisplay),
+            "return" | ";" => self.emit(Instruction::ReturnSubroutine),
+            "jump" => {
+                let target = self.next()?;
+                self.emit_addressed(target, Instruction::Jump)?;
+            }
+            "jump0" => {
+                let target = self.next()?;
+                self.emit_addressed(target, Instruction::JumpAdd)?;
+            }
+            "native" => {
+                let target = self.next()?;
+                self.emit_addressed(target, Instruction::MachineCodeRoutine)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.value()?;
+                if !(0..=15).contains(&height) {
+                    return Err(self.error(format!("sprite height {} is out of range", height)));
+                }
+                self.emit(Instruction::Draw(x, y, Value(height as u8)));
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::Decimal(x));
+            }
+            "save" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreRegisters(x));
+            }
+            "load" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadRegisters(x));
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(if token == "delay" {
+                    Instruction::SetDelayTimer(x)
+                } else {
+                    Instruction::SetSoundTimer(x)
+                });
+            }
+            "i" => match self.next()? {
+                "+=" => {
+                    let x = self.register()?;
+                    self.emit(Instruction::AddToI(x));
+                }
+                ":=" => match self.next()? {
+                    "hex" => {
+                        let x = self.register()?;
+                        self.emit(Instruction::SpriteAddr(x));
+                    }
+                    target => self.emit_addressed(target, Instruction::SetI)?,
+                },
+                operator => return Err(self.error(format!("invalid operation 'i {}'", operator))),
+            },
+            "if" => {
+                let condition = self.condition()?;
+                match self.next()? {
+                    "then" => self.emit(condition.skip()),
+                    "begin" => {
+                        self.emit(condition.negate().skip());
+                        let jump = self.emit_open_jump();
+                        self.blocks.push((self.line, Block::If { jump }));
+                    }
+                    token => {
+                        return Err(
+                            self.error(format!("expected 'then' or 'begin', got '{}'", token))
+                        )
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((line, Block::If { jump })) => {
+                    let end_jump = self.emit_open_jump();
+                    let here = self.here();
+                    self.patch(jump, here);
+                    self.blocks.push((line, Block::Else { jump: end_jump }));
+                }
+                _ => return Err(self.error("'else' without 'begin'".to_string())),
+            },
+            "end" => match self.blocks.pop() {
+                Some((_, Block::If { jump })) | Some((_, Block::Else { jump })) => {
+                    let here = self.here();
+                    self.patch(jump, here);
+                }
+                _ => return Err(self.error("'end' without 'begin'".to_string())),
+            },
+            "loop" => {
+                let start = self.here();
+                self.blocks.push((
+                    self.line,
+                    Block::Loop {
+                        start,
+                        exits: Vec::new(),
+                    },
+                ));
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit(condition.negate().skip());
+                let jump = self.emit_open_jump();
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(_, block)| match block {
+                        Block::Loop { exits, .. } => Some(exits),
+                        _ => None,
+                    }) {
+                    Some(exits) => exits.push(jump),
+                    None => return Err(self.error("'while' outside of a loop".to_string())),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((_, Block::Loop { start, exits })) => {
+                    self.emit(Instruction::Jump(Address(start)));
+                    let here = self.here();
+                    for exit in exits {
+                        self.patch(exit, here);
+                    }
+                }
+                _ => return Err(self.error("'again' without 'loop'".to_string())),
+            },
+            _ if UNSUPPORTED_DIRECTIVES.contains(&token) => {
+                return Err(self.error(format!("unsupported directive '{}'", token)))
+            }
+            _ if EXTENSIONS.contains(&token) => {
+                return Err(self.error(format!(
+                    "'{}' needs SUPER-CHIP or XO-CHIP, which are not supported",
+                    token
+                )))
+            }
+            _ => {
+                if let Some(x) = self.register_of(token) {
+                    self.register_statement(x)?;
+                } else if let Some(value) =
+                    parse_number(token).or_else(|| self.constants.get(token).copied())
+                {
+                    // Numbers on their own are data.
+                    let byte = self.byte_of(value)?;
+                    self.program.push(byte.0);
+                } else if is_name(token) {
+                    // Names on their own call the label of that name.
+                    self.emit_addressed(token, Instruction::CallSubroutine)?;
+                } else if let Some(label) = token.strip_prefix(':').filter(|label| is_name(label)) {
+                    self.define_label(label)?;
+                } else {
+                    return Err(self.error(format!("unknown statement '{}'", token)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, label: &'a str) -> Result<(), AsmError> {
+        if !is_name(label) {
+            return Err(self.error(format!("invalid label '{}'", label)));
+        }
+        let here = self.here();
+        if self.labels.insert(label, here).is_some() {
+            return Err(self.error(format!("label '{}' is defined twice", label)));
+        }
+        Ok(())
+    }
+
+    fn assemble(mut self) -> Result<Vec<u8>, AsmError> {
+        // Like Octo, start with a jump to `main`, wherever it is.
+        self.fixups.push((0, "main", 1));
+        self.emit(Instruction::Jump(Address(0)));
+        while self.position < self.tokens.len() {
+            let token = self.next()?;
+            self.statement(token)?;
+        }
+        if let Some((line, block)) = self.blocks.pop() {
+            let message = match block {
+                Block::Loop { .. } => "'loop' without 'again'",
+                _ => "'begin' without 'end'",
+            };
+            return Err(AsmError {
+                line,
+                message: message.to_string(),
+            });
+        }
+        for (offset, label, line) in std::mem::take(&mut self.fixups) {
+            match self.labels.get(label) {
+                Some(addr) => {
+                    let addr = *addr;
+                    self.patch(offset, addr);
+                }
+                None => {
+                    return Err(AsmError {
+                        line,
+                        message: format!("unknown label '{}'", label),
+                    })
+                }
+            }
+        }
+        Ok(self.program)
+    }
+}
+
+/// Assembles a program written in Octo's language, limited to the
+/// instructions of CHIP-8. The program starts with a jump to the label
+/// `main`, and supports labels (`: name`), `:const`, `:alias`, `:call`,
+/// register operations like `v0 += 5`, `if ... then`, `if ... begin ...
+/// else ... end`, `loop ... while ... again` and numbers as data. Comparisons
+/// with `<` and `>` and the SUPER-CHIP and XO-CHIP extensions are rejected.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    Assembler::new(source).assemble()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            :const SPEED 3
+            :alias x v2
+            : main
+                clear
+                x := 0   # start on the left
+                i := ball
+                loop
+                    sprite x v3 4
+                    x += SPEED
+                    v1 := key
+                    if v1 == 5 then x -= 1
+                    draw
+                again
+            : draw
+                v0 := random 0xFF
+                delay := v0
+                ;
+            : ball
+                0b01100000 0xF0 -1 0x60
+        ";
+        let expected = asm::assemble(
+            "
+                JP main
+            main:
+                CLS
+                LD V2, 0
+                LD I, ball
+            loop:
+                DRW V2, V3, 4
+                ADD V2, 3
+                LD V1, K
+                SNE V1, 5
+                ADD V2, 0xFF
+                CALL draw
+                JP loop
+            draw:
+                RND V0, 0xFF
+                LD DT, V0
+                RET
+            ball:
+                db 0x60, 0xF0, 0xFF, 0x60
+            ",
+        );
+        assert_eq!(assemble(source), expected);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let source = "
+            : main
+                loop
+                    while v0 != 10
+                    if v0 key begin
+                        v1 := v0
+                    else
+                        vf =- v1
+                    end
+                    v0 += 1
+                again
+        ";
+        let expected = asm::assemble(
+            "
+                JP main
+            main:
+                SNE V0, 10
+                JP done
+                SKP V0
+                JP else
+                LD V1, V0
+                JP end
+            else:
+                SUBN VF, V1
+            end:
+                ADD V0, 1
+                JP main
+            done:
+            ",
+        );
+        assert_eq!(assemble(source), expected);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source).unwrap_err().to_string();
+        assert_eq!(error(": main\nclear\ngame"), "line 3: unknown label 'game'");
+        assert_eq!(error("clear"), "line 1: unknown label 'main'");
+        assert_eq!(
+            error(": main\nv0 := 256"),
+            "line 2: byte 256 is out of range"
+        );
+        assert_eq!(
+            error(": main\nif v0 < 3 then"),
+            "line 2: unsupported comparison '<'"
+        );
+        assert_eq!(
+            error(": main\nloop\nclear"),
+            "line 2: 'loop' without 'again'"
+        );
+        assert_eq!(error(": main\nend"), "line 2: 'end' without 'begin'");
+        assert_eq!(
+            error(": main\nhires"),
+            "line 2: 'hires' needs SUPER-CHIP or XO-CHIP, which are not supported"
+        );
+        assert_eq!(
+            error(": main\n:org 0x300"),
+            "line 2: unsupported directive ':org'"
+        );
+        assert_eq!(
+            error(": main\nv0 +="),
+            "line 2: unexpected end of the program"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Images in the binary PGM (grayscale) and PPM (color) formats, e.g. for
+//! the thumbnails from `Chip8Core::thumbnail`.
+
+/// An RGB color.
+pub type Rgb = [u8; 3];
+
+/// Encodes an image indexed as `[x][y]` as a binary PGM image.
+pub fn encode(image: &[Vec<u8>]) -> Vec<u8> {
+    let width = image.len();
+    let height = image.first().map_or(0, Vec::len);
+    let mut pgm = format!("P5\n{} {}\n255\n", width, height).into_bytes();
+    for y in 0..height {
+        pgm.extend(image.iter().map(|column| column[y]));
+    }
+    pgm
+}
+
+/// Encodes a color image indexed as `[x][y]` as a binary PPM image.
+pub fn encode_rgb(image: &[Vec<Rgb>]) -> Vec<u8> {
+    let width = image.len();
+    let height = image.first().map_or(0, Vec::len);
+    let mut ppm = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for y in 0..height {
+        ppm.extend(image.iter().flat_map(|column| column[y]));
+    }
+    ppm
+}
+
+/// Splits an image written by `encode` or `encode_rgb` into its magic
+/// number, width, height and pixel data.
+fn parse_header(image: &[u8]) -> Result<(String, usize, usize, &[u8]), String> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    // The magic number, the width, the height and the maximum value, each
+    // followed by a single whitespace character.
+    while fields.len() < 4 {
+        let end = image[start..]
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .map(|length| start + length)
+            .ok_or("truncated header")?;
+        fields.push(String::from_utf8_lossy(&image[start..end]).into_owned());
+        start = end + 1;
+    }
+    if !["P5", "P6"].contains(&fields[0].as_str()) || fields[3] != "255" {
+        return Err(String::from("not an 8-bit binary PGM or PPM image"));
+    }
+    let size = |field: &str| {
+        field
+            .parse::<usize>()
+            .map_err(|_| format!("invalid size {:?}", field))
+    };
+    let (width, height) = (size(&fields[1])?, size(&fields[2])?);
+    let pixels = &image[start..];
+    let channels = if fields[0] == "P6" { 3 } else { 1 };
+    if pixels.len() != width * height * channels {
+        return Err(format!(
+            "{} bytes of pixels for a {}x{} image",
+            pixels.len(),
+            width,
+            height
+        ));
+    }
+    Ok((fields.swap_remove(0), width, height, pixels))
+}
+
+/// Decodes a binary PGM image written by `encode` into an image indexed as
+/// `[x][y]`. Comments and other maximum values than 255 are not supported.
+pub fn decode(pgm: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let (magic, width, height, pixels) = parse_header(pgm)?;
+    if magic != "P5" {
+        return Err(String::from("not a PGM image"));
+    }
+    Ok((0..width)
+        .map(|x| (0..height).map(|y| pixels[y * width + x]).collect())
+        .collect())
+}
+
+/// Decodes an image written by `encode_rgb` or `encode` into a color image
+/// indexed as `[x][y]`, with the gray of a PGM image in all channels.
+pub fn decode_rgb(image: &[u8]) -> Result<Vec<Vec<Rgb>>, String> {
+    let (magic, width, height, pixels) = parse_header(image)?;
+    let pixel = |x: usize, y: usize| {
+        let i = y * width + x;
+        match magic.as_str() {
+            "P6" => [pixels[3 * i], pixels[3 * i + 1], pixels[3 * i + 2]],
+            _ => [pixels[i]; 3],
+        }
+    };
+    Ok((0..width)
+        .map(|x| (0..height).map(|y| pixel(x, y)).collect())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let image = vec![vec![0, 10], vec![20, 30], vec![40, 255]];
+        let pgm = encode(&image);
+        assert_eq!(pgm, b"P5\n3 2\n255\n\x00\x14\x28\x0A\x1E\xFF");
+        assert_eq!(decode(&pgm), Ok(image));
+        assert_eq!(decode_rgb(&pgm).unwrap()[2][1], [255; 3]);
+    }
+
+    #[test]
+    fn test_round_trip_rgb() {
+        let image = vec![vec![[1, 2, 3], [4, 5, 6]], vec![[7, 8, 9], [10, 11, 12]]];
+        let ppm = encode_rgb(&image);
+        assert_eq!(
+            ppm,
+            b"P6\n2 2\n255\n\x01\x02\x03\x07\x08\x09\x04\x05\x06\x0A\x0B\x0C"
+        );
+        assert_eq!(decode_rgb(&ppm), Ok(image));
+        assert!(decode(&ppm).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(decode(b"P5\n3 2\n").is_err());
+        assert!(decode(b"P2\n1 1\n255\n\x00").is_err());
+        assert!(decode(b"P5\n2 2\n255\n\x00").is_err());
+        assert!(decode_rgb(b"P6\n1 1\n255\n\x00").is_err());
+    }
+}
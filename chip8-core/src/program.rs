@@ -0,0 +1,286 @@
+use super::basics::{Address, Register, Value};
+use std::fmt;
+
+pub enum Instruction {
+    Noop,
+    MachineCodeRoutine(Address),
+    ClearDisplay,
+    ReturnSubroutine,
+    Jump(Address),
+    CallSubroutine(Address),
+    IfNotEqualConst(Register, Value),
+    IfEqualConst(Register, Value),
+    IfNotEqual(Register, Register),
+    SetConst(Register, Value),
+    AddConst(Register, Value),
+    Set(Register, Register),
+    Or(Register, Register),
+    And(Register, Register),
+    Xor(Register, Register),
+    Add(Register, Register),
+    Sub(Register, Register),
+    RightShift(Register, Register),
+    NegSub(Register, Register),
+    LeftShift(Register, Register),
+    IfEqual(Register, Register),
+    SetI(Address),
+    JumpAdd(Address),
+    Rand(Register, Value),
+    Draw(Register, Register, Value),
+    IfNotKey(Register),
+    IfKey(Register),
+    GetDelayTimer(Register),
+    WaitKey(Register),
+    SetDelayTimer(Register),
+    SetSoundTimer(Register),
+    AddToI(Register),
+    SpriteAddr(Register),
+    Decimal(Register),
+    StoreRegisters(Register),
+    LoadRegisters(Register),
+}
+
+/// Returned by `Instruction::try_from_16bit` for opcodes that are no CHIP-8
+/// instruction, e.g. when a program jumps into its data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid opcode {:#06X}", self.opcode)
+    }
+}
+
+macro_rules! NNN {
+    ($x:expr) => {
+        Address(($x.1 as u16) * 256 + ($x.2 as u16) * 16 + ($x.3 as u16))
+    };
+}
+
+macro_rules! NN {
+    ($x:expr) => {
+        Value($x.2 * 16 + $x.3)
+    };
+}
+
+macro_rules! N {
+    ($x:expr) => {
+        Value($x.3)
+    };
+}
+
+macro_rules! X {
+    ($x:expr) => {
+        Register($x.1)
+    };
+}
+
+macro_rules! Y {
+    ($x:expr) => {
+        Register($x.2)
+    };
+}
+
+impl Instruction {
+    /// Decodes an instruction. Panics on invalid opcodes, see `try_from_16bit`.
+    pub fn from_16bit(a: u8, b: u8) -> Instruction {
+        match Instruction::try_from_16bit(a, b) {
+            Ok(instruction) => instruction,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Decodes the instruction made up of the two bytes `a` and `b`.
+    pub fn try_from_16bit(a: u8, b: u8) -> Result<Instruction, DecodeError> {
+        let bytes = (a >> 4 & 0x0F, a & 0x0F, b >> 4 & 0x0F, b & 0x0F);
+        let instruction = match bytes {
+            (0, 0, 0, 0) => Instruction::Noop,
+            (0, 0, 14, 0) => Instruction::ClearDisplay,
+            (0, 0, 14, 14) => Instruction::ReturnSubroutine,
+            (0, 2..=15, _, _) => Instruction::MachineCodeRoutine(NNN!(bytes)),
+            (1, _, _, _) => Instruction::Jump(NNN!(bytes)),
+            (2, _, _, _) => Instruction::CallSubroutine(NNN!(bytes)),
+            (3, _, _, _) => Instruction::IfNotEqualConst(X!(bytes), NN!(bytes)),
+            (4, _, _, _) => Instruction::IfEqualConst(X!(bytes), NN!(bytes)),
+            (5, _, _, 0) => Instruction::IfNotEqual(X!(bytes), Y!(bytes)),
+            (6, _, _, _) => Instruction::SetConst(X!(bytes), NN!(bytes)),
+            (7, _, _, _) => Instruction::AddConst(X!(bytes), NN!(bytes)),
+            (8, _, _, 0) => Instruction::Set(X!(bytes), Y!(bytes)),
+            (8, _, _, 1) => Instruction::Or(X!(bytes), Y!(bytes)),
+            (8, _, _, 2) => Instruction::And(X!(bytes), Y!(bytes)),
+            (8, _, _, 3) => Instruction::Xor(X!(bytes), Y!(bytes)),
+            (8, _, _, 4) => Instruction::Add(X!(bytes), Y!(bytes)),
+            (8, _, _, 5) => Instruction::Sub(X!(bytes), Y!(bytes)),
+            (8, _, _, 6) => Instruction::RightShift(X!(bytes), Y!(bytes)),
+            (8, _, _, 7) => Instruction::NegSub(X!(bytes), Y!(bytes)),
+            (8, _, _, 14) => Instruction::LeftShift(X!(bytes), Y!(bytes)),
+            (9, _, _, 0) => Instruction::IfEqual(X!(bytes), Y!(bytes)),
+            (10, _, _, _) => Instruction::SetI(NNN!(bytes)),
+            (11, _, _, _) => Instruction::JumpAdd(NNN!(bytes)),
+            (12, _, _, _) => Instruction::Rand(X!(bytes), NN!(bytes)),
+            (13, _, _, _) => Instruction::Draw(X!(bytes), Y!(bytes), N!(bytes)),
+            (14, _, 9, 14) => Instruction::IfNotKey(X!(bytes)),
+            (14, _, 10, 1) => Instruction::IfKey(X!(bytes)),
+            (15, _, 0, 7) => Instruction::GetDelayTimer(X!(bytes)),
+            (15, _, 0, 10) => Instruction::WaitKey(X!(bytes)),
+            (15, _, 1, 5) => Instruction::SetDelayTimer(X!(bytes)),
+            (15, _, 1, 8) => Instruction::SetSoundTimer(X!(bytes)),
+            (15, _, 1, 14) => Instruction::AddToI(X!(bytes)),
+            (15, _, 2, 9) => Instruction::SpriteAddr(X!(bytes)),
+            (15, _, 3, 3) => Instruction::Decimal(X!(bytes)),
+            (15, _, 5, 5) => Instruction::StoreRegisters(X!(bytes)),
+            (15, _, 6, 5) => Instruction::LoadRegisters(X!(bytes)),
+            _ => {
+                return Err(DecodeError {
+                    opcode: (a as u16) << 8 | b as u16,
+                })
+            }
+        };
+        Ok(instruction)
+    }
+
+    /// Encodes the instruction as its two bytes, the inverse of
+    /// `try_from_16bit`. Operands are cut to the bits the opcode has room for.
+    pub fn to_16bit(&self) -> [u8; 2] {
+        let nnn = |high: u16, addr: &Address| high << 12 | addr.0 & 0xFFF;
+        let xnn = |high: u16, x: &Register, nn: &Value| {
+            high << 12 | (x.0 as u16 & 0xF) << 8 | nn.0 as u16
+        };
+        let xy = |high: u16, x: &Register, y: &Register, low: u16| {
+            high << 12 | (x.0 as u16 & 0xF) << 8 | (y.0 as u16 & 0xF) << 4 | low
+        };
+        let x = |x: &Register, low: u16| 0xF000 | (x.0 as u16 & 0xF) << 8 | low;
+        let opcode = match self {
+            Instruction::Noop => 0x0000,
+            Instruction::MachineCodeRoutine(addr) => nnn(0x0, addr),
+            Instruction::ClearDisplay => 0x00E0,
+            Instruction::ReturnSubroutine => 0x00EE,
+            Instruction::Jump(addr) => nnn(0x1, addr),
+            Instruction::CallSubroutine(addr) => nnn(0x2, addr),
+            Instruction::IfNotEqualConst(vx, nn) => xnn(0x3, vx, nn),
+            Instruction::IfEqualConst(vx, nn) => xnn(0x4, vx, nn),
+            Instruction::IfNotEqual(vx, vy) => xy(0x5, vx, vy, 0x0),
+            Instruction::SetConst(vx, nn) => xnn(0x6, vx, nn),
+            Instruction::AddConst(vx, nn) => xnn(0x7, vx, nn),
+            Instruction::Set(vx, vy) => xy(0x8, vx, vy, 0x0),
+            Instruction::Or(vx, vy) => xy(0x8, vx, vy, 0x1),
+            Instruction::And(vx, vy) => xy(0x8, vx, vy, 0x2),
+            Instruction::Xor(vx, vy) => xy(0x8, vx, vy, 0x3),
+            Instruction::Add(vx, vy) => xy(0x8, vx, vy, 0x4),
+            Instruction::Sub(vx, vy) => xy(0x8, vx, vy, 0x5),
+            Instruction::RightShift(vx, vy) => xy(0x8, vx, vy, 0x6),
+            Instruction::NegSub(vx, vy) => xy(0x8, vx, vy, 0x7),
+            Instruction::LeftShift(vx, vy) => xy(0x8, vx, vy, 0xE),
+            Instruction::IfEqual(vx, vy) => xy(0x9, vx, vy, 0x0),
+            Instruction::SetI(addr) => nnn(0xA, addr),
+            Instruction::JumpAdd(addr) => nnn(0xB, addr),
+            Instruction::Rand(vx, nn) => xnn(0xC, vx, nn),
+            Instruction::Draw(vx, vy, n) => xy(0xD, vx, vy, n.0 as u16 & 0xF),
+            Instruction::IfNotKey(vx) => xnn(0xE, vx, &Value(0x9E)),
+            Instruction::IfKey(vx) => xnn(0xE, vx, &Value(0xA1)),
+            Instruction::GetDelayTimer(vx) => x(vx, 0x07),
+            Instruction::WaitKey(vx) => x(vx, 0x0A),
+            Instruction::SetDelayTimer(vx) => x(vx, 0x15),
+            Instruction::SetSoundTimer(vx) => x(vx, 0x18),
+            Instruction::AddToI(vx) => x(vx, 0x1E),
+            Instruction::SpriteAddr(vx) => x(vx, 0x29),
+            Instruction::Decimal(vx) => x(vx, 0x33),
+            Instruction::StoreRegisters(vx) => x(vx, 0x55),
+            Instruction::LoadRegisters(vx) => x(vx, 0x65),
+        };
+        opcode.to_be_bytes()
+    }
+}
+
+/// Writes the instruction in the assembly syntax of Cowgod's CHIP-8 reference,
+/// e.g. `LD V3, 0x1F` or `DRW V0, V1, 5`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Noop => write!(f, "NOP"),
+            Instruction::MachineCodeRoutine(addr) => write!(f, "SYS {:#05X}", addr.0),
+            Instruction::ClearDisplay => write!(f, "CLS"),
+            Instruction::ReturnSubroutine => write!(f, "RET"),
+            Instruction::Jump(addr) => write!(f, "JP {:#05X}", addr.0),
+            Instruction::CallSubroutine(addr) => write!(f, "CALL {:#05X}", addr.0),
+            Instruction::IfNotEqualConst(x, nn) => write!(f, "SE V{:X}, {:#04X}", x.0, nn.0),
+            Instruction::IfEqualConst(x, nn) => write!(f, "SNE V{:X}, {:#04X}", x.0, nn.0),
+            Instruction::IfNotEqual(x, y) => write!(f, "SE V{:X}, V{:X}", x.0, y.0),
+            Instruction::SetConst(x, nn) => write!(f, "LD V{:X}, {:#04X}", x.0, nn.0),
+            Instruction::AddConst(x, nn) => write!(f, "ADD V{:X}, {:#04X}", x.0, nn.0),
+            Instruction::Set(x, y) => write!(f, "LD V{:X}, V{:X}", x.0, y.0),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x.0, y.0),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x.0, y.0),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x.0, y.0),
+            Instruction::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x.0, y.0),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x.0, y.0),
+            Instruction::RightShift(x, y) => write!(f, "SHR V{:X}, V{:X}", x.0, y.0),
+            Instruction::NegSub(x, y) => write!(f, "SUBN V{:X}, V{:X}", x.0, y.0),
+            Instruction::LeftShift(x, y) => write!(f, "SHL V{:X}, V{:X}", x.0, y.0),
+            Instruction::IfEqual(x, y) => write!(f, "SNE V{:X}, V{:X}", x.0, y.0),
+            Instruction::SetI(addr) => write!(f, "LD I, {:#05X}", addr.0),
+            Instruction::JumpAdd(addr) => write!(f, "JP V0, {:#05X}", addr.0),
+            Instruction::Rand(x, nn) => write!(f, "RND V{:X}, {:#04X}", x.0, nn.0),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x.0, y.0, n.0),
+            Instruction::IfNotKey(x) => write!(f, "SKP V{:X}", x.0),
+            Instruction::IfKey(x) => write!(f, "SKNP V{:X}", x.0),
+            Instruction::GetDelayTimer(x) => write!(f, "LD V{:X}, DT", x.0),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x.0),
+            Instruction::SetDelayTimer(x) => write!(f, "LD DT, V{:X}", x.0),
+            Instruction::SetSoundTimer(x) => write!(f, "LD ST, V{:X}", x.0),
+            Instruction::AddToI(x) => write!(f, "ADD I, V{:X}", x.0),
+            Instruction::SpriteAddr(x) => write!(f, "LD F, V{:X}", x.0),
+            Instruction::Decimal(x) => write!(f, "LD B, V{:X}", x.0),
+            Instruction::StoreRegisters(x) => write!(f, "LD [I], V{:X}", x.0),
+            Instruction::LoadRegisters(x) => write!(f, "LD V{:X}, [I]", x.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from_16bit() {
+        assert!(matches!(
+            Instruction::try_from_16bit(0x12, 0x34),
+            Ok(Instruction::Jump(Address(0x234)))
+        ));
+        assert_eq!(
+            Instruction::try_from_16bit(0x50, 0x01).err(),
+            Some(DecodeError { opcode: 0x5001 })
+        );
+        assert_eq!(
+            Instruction::try_from_16bit(0xFF, 0xFF)
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid opcode 0xFFFF"
+        );
+    }
+
+    #[test]
+    fn test_to_16bit() {
+        for opcode in 0..=u16::MAX {
+            let [a, b] = opcode.to_be_bytes();
+            if let Ok(instruction) = Instruction::try_from_16bit(a, b) {
+                assert_eq!(instruction.to_16bit(), [a, b], "{}", instruction);
+            }
+        }
+        assert_eq!(Instruction::Jump(Address(0xF234)).to_16bit(), [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_display() {
+        let text = |a, b| Instruction::from_16bit(a, b).to_string();
+        assert_eq!(text(0x00, 0xE0), "CLS");
+        assert_eq!(text(0x22, 0xA4), "CALL 0x2A4");
+        assert_eq!(text(0x63, 0x1F), "LD V3, 0x1F");
+        assert_eq!(text(0x8A, 0xB4), "ADD VA, VB");
+        assert_eq!(text(0xD0, 0x15), "DRW V0, V1, 5");
+        assert_eq!(text(0xF2, 0x65), "LD V2, [I]");
+    }
+}
@@ -0,0 +1,133 @@
+use serde::Deserialize;
+
+/// What happens when an instruction accesses memory past `MEMORY_SIZE`
+/// through the I register.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryOverflow {
+    /// The address wraps around to the start of memory.
+    Wrap,
+    /// The address is clamped to the last byte of memory.
+    Saturate,
+    /// The instruction fails with `VmError::OutOfBounds` before accessing
+    /// any memory.
+    #[serde(alias = "panic")]
+    Error,
+}
+
+/// Behavior variants of different CHIP-8 interpreters that ROMs depend on.
+/// The default reproduces the behavior this emulator always had.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quirks {
+    /// Sprite pixels beyond the right edge of the screen continue on the left
+    /// edge instead of being clipped.
+    pub wrap_x: bool,
+    /// Sprite pixels beyond the bottom edge of the screen continue on the top
+    /// edge instead of being clipped.
+    pub wrap_y: bool,
+    /// The logical operations OR, AND and XOR (8XY1, 8XY2, 8XY3) reset VF to
+    /// 0, like on the COSMAC VIP.
+    pub vf_reset: bool,
+    /// Storing and loading registers (FX55, FX65) leaves I incremented by
+    /// X + 1, like on the COSMAC VIP.
+    pub increment_i: bool,
+    /// The jump with offset (BNNN) is read as BXNN and adds VX instead of V0,
+    /// like on CHIP-48 and SUPER-CHIP.
+    pub jump_vx: bool,
+    /// The shifts (8XY6, 8XYE) store VY shifted by one bit in VX instead of
+    /// shifting VX in place, like on the COSMAC VIP.
+    pub shift_vy: bool,
+    /// Drawing (DXYN) waits for the next timer tick, the vertical blank, so
+    /// that at most one sprite is drawn per frame, like on the COSMAC VIP.
+    pub vblank_wait: bool,
+    /// The key wait (FX0A) completes only when the pressed key is released
+    /// again, like on the COSMAC VIP, so that one press cannot satisfy two
+    /// consecutive waits.
+    pub key_release_wait: bool,
+    /// Adding to I (FX1E) sets VF to 1 if I overflows past 0xFFF and to 0
+    /// otherwise, like on the Amiga interpreter.
+    pub vf_on_i_overflow: bool,
+    /// How memory accesses past the end of memory are handled.
+    pub memory_overflow: MemoryOverflow,
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            wrap_x: true,
+            wrap_y: true,
+            vf_reset: false,
+            increment_i: false,
+            jump_vx: false,
+            shift_vy: false,
+            vblank_wait: false,
+            key_release_wait: false,
+            vf_on_i_overflow: false,
+            memory_overflow: MemoryOverflow::Wrap,
+        }
+    }
+}
+
+/// The names accepted by `Quirks::preset`.
+pub const PRESET_NAMES: [&str; 3] = ["default", "vip", "schip"];
+
+impl Quirks {
+    /// The behavior of the original COSMAC VIP interpreter, which test ROMs
+    /// like Timendus' quirks test call "original CHIP-8".
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            wrap_x: false,
+            wrap_y: false,
+            vf_reset: true,
+            increment_i: true,
+            jump_vx: false,
+            shift_vy: true,
+            vblank_wait: true,
+            key_release_wait: true,
+            vf_on_i_overflow: false,
+            memory_overflow: MemoryOverflow::Wrap,
+        }
+    }
+
+    /// The behavior of SUPER-CHIP 1.1 on the HP 48, which most CHIP-48 and
+    /// SCHIP-era ROMs expect, most notably BXNN jumping to XNN + VX.
+    pub fn schip() -> Quirks {
+        Quirks {
+            wrap_x: false,
+            wrap_y: false,
+            vf_reset: false,
+            increment_i: false,
+            jump_vx: true,
+            shift_vy: false,
+            vblank_wait: false,
+            key_release_wait: false,
+            vf_on_i_overflow: false,
+            memory_overflow: MemoryOverflow::Wrap,
+        }
+    }
+
+    /// Looks up a set of quirks by one of the `PRESET_NAMES`.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(Quirks::default()),
+            "vip" => Some(Quirks::cosmac_vip()),
+            "schip" => Some(Quirks::schip()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(Quirks::preset("default"), Some(Quirks::default()));
+        let vip = Quirks::preset("vip").unwrap();
+        assert!(vip.vf_reset && vip.shift_vy && vip.increment_i && vip.key_release_wait);
+        assert!(Quirks::preset("schip").unwrap().jump_vx);
+        assert_eq!(Quirks::preset("xo-chip"), None);
+    }
+}
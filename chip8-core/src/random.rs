@@ -0,0 +1,143 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// The source of the random numbers of CXNN. Abstracted so that tests can
+/// choose the numbers that a program draws.
+pub trait Randomizer: Send {
+    /// Draws a byte, each value being equally likely.
+    fn next_byte(&mut self) -> u8;
+
+    /// The seed that the numbers are drawn from, if there is one.
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+
+    /// Where the randomizer stands in its sequence, if it can be resumed
+    /// from there.
+    fn state(&self) -> Option<RandomState> {
+        None
+    }
+}
+
+/// The position of a `SeededRandomizer`: its seed and how many bytes it drew.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RandomState {
+    pub seed: u64,
+    pub draws: u64,
+}
+
+/// Pseudo-random numbers from a seed. Two randomizers with the same seed
+/// draw the same numbers.
+pub struct SeededRandomizer {
+    seed: u64,
+    draws: u64,
+    rng: StdRng,
+}
+
+impl SeededRandomizer {
+    pub fn new(seed: u64) -> SeededRandomizer {
+        SeededRandomizer {
+            seed,
+            draws: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Continues the sequence of a randomizer at the given state, such that
+    /// it draws the same numbers as the one that the state was taken from.
+    pub fn resume(state: RandomState) -> SeededRandomizer {
+        let mut randomizer = SeededRandomizer::new(state.seed);
+        for _ in 0..state.draws {
+            randomizer.next_byte();
+        }
+        randomizer
+    }
+
+    /// Seeded randomly, so that every run is different.
+    pub fn from_entropy() -> SeededRandomizer {
+        SeededRandomizer::new(rand::random())
+    }
+}
+
+impl Randomizer for SeededRandomizer {
+    fn next_byte(&mut self) -> u8 {
+        self.draws += 1;
+        self.rng.gen()
+    }
+
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+
+    fn state(&self) -> Option<RandomState> {
+        Some(RandomState {
+            seed: self.seed,
+            draws: self.draws,
+        })
+    }
+}
+
+/// Draws the given bytes in order, starting over after the last one.
+pub struct SequenceRandomizer {
+    bytes: Vec<u8>,
+    next: usize,
+}
+
+impl SequenceRandomizer {
+    pub fn new(bytes: &[u8]) -> SequenceRandomizer {
+        assert!(!bytes.is_empty());
+        SequenceRandomizer {
+            bytes: bytes.to_vec(),
+            next: 0,
+        }
+    }
+}
+
+impl Randomizer for SequenceRandomizer {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.next];
+        self.next = (self.next + 1) % self.bytes.len();
+        byte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded() {
+        let draw = |randomizer: &mut SeededRandomizer| {
+            (0..8).map(|_| randomizer.next_byte()).collect::<Vec<_>>()
+        };
+        let (mut a, mut b) = (SeededRandomizer::new(1), SeededRandomizer::new(1));
+        assert_eq!(draw(&mut a), draw(&mut b));
+        assert_eq!(a.seed(), Some(1));
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut a = SeededRandomizer::new(7);
+        for _ in 0..5 {
+            a.next_byte();
+        }
+        let state = a.state().unwrap();
+        assert_eq!(state, RandomState { seed: 7, draws: 5 });
+        let mut b = SeededRandomizer::resume(state);
+        let draw = |randomizer: &mut SeededRandomizer| {
+            (0..8).map(|_| randomizer.next_byte()).collect::<Vec<_>>()
+        };
+        assert_eq!(draw(&mut a), draw(&mut b));
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut randomizer = SequenceRandomizer::new(&[1, 2, 3]);
+        let bytes: Vec<_> = (0..5).map(|_| randomizer.next_byte()).collect();
+        assert_eq!(bytes, [1, 2, 3, 1, 2]);
+        assert_eq!(randomizer.seed(), None);
+        assert_eq!(randomizer.state(), None);
+    }
+}
@@ -0,0 +1,129 @@
+use super::core::Chip8Core;
+use super::save_state::SaveState;
+use std::collections::VecDeque;
+
+/// The snapshots kept by `RewindBuffer::default`, 5 seconds at 60 Hz.
+const DEFAULT_CAPACITY: usize = 50;
+/// The frames between two snapshots of `RewindBuffer::default`.
+const DEFAULT_INTERVAL: u32 = 6;
+
+/// The most recent save states of a core, taken every `interval` frames.
+/// Once `capacity` states are kept, the oldest one is dropped for every new
+/// one, so the memory use stays bounded by `capacity` save states of about
+/// 8 KiB each.
+pub struct RewindBuffer {
+    snapshots: VecDeque<SaveState>,
+    capacity: usize,
+    interval: u32,
+    /// Counts the frames up to the next snapshot, or while rewinding, up to
+    /// the next restored one.
+    frame: u32,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> RewindBuffer {
+        RewindBuffer::new(DEFAULT_CAPACITY, DEFAULT_INTERVAL)
+    }
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: u32) -> RewindBuffer {
+        assert!(capacity > 0 && interval > 0);
+        RewindBuffer {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval,
+            frame: 0,
+        }
+    }
+
+    /// Called once per frame while the core runs. Takes a snapshot every
+    /// `interval` frames, unless the core does not support save states.
+    pub fn record_frame(&mut self, core: &dyn Chip8Core) {
+        self.frame += 1;
+        if self.frame < self.interval {
+            return;
+        }
+        self.frame = 0;
+        if let Ok(state) = core.save_state() {
+            if self.snapshots.len() == self.capacity {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back(state);
+        }
+    }
+
+    /// Called once per frame while rewinding. Returns the next older snapshot
+    /// on the first frame and then every `interval` frames, so that the game
+    /// runs backwards at about its normal speed. The returned snapshot is
+    /// removed.
+    pub fn rewind_frame(&mut self) -> Option<SaveState> {
+        let due = self.frame == 0;
+        self.frame = (self.frame + 1) % self.interval;
+        if due {
+            self.snapshots.pop_back()
+        } else {
+            None
+        }
+    }
+
+    /// Starts counting frames anew, when switching between running and
+    /// rewinding.
+    pub fn restart(&mut self) {
+        self.frame = 0;
+    }
+
+    /// The number of snapshots kept.
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basics::{Register, Value};
+    use crate::vm::VirtualMachine;
+
+    /// V0 += 1, then jump back.
+    const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    #[test]
+    fn test_record_frame() {
+        let mut vm = VirtualMachine::new(&COUNTER);
+        let mut buffer = RewindBuffer::new(3, 2);
+        for _ in 0..10 {
+            vm.step().unwrap();
+            buffer.record_frame(&vm);
+        }
+        // Snapshots after every second step, i.e. V0 from 1 to 5, of which
+        // the last 3 are kept.
+        assert_eq!(buffer.len(), 3);
+        buffer.restart();
+        let registers: Vec<_> = (0..6)
+            .filter_map(|_| buffer.rewind_frame())
+            .map(|state| state.registers[0])
+            .collect();
+        assert_eq!(registers, [5, 4, 3]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_rewind_frame_restores() {
+        let mut vm = VirtualMachine::new(&COUNTER);
+        let mut buffer = RewindBuffer::new(10, 1);
+        vm.step().unwrap();
+        buffer.record_frame(&vm);
+        for _ in 0..5 {
+            vm.step().unwrap();
+        }
+        let state = buffer.rewind_frame().unwrap();
+        vm.load_state(&state).unwrap();
+        assert_eq!(vm.get_register(Register(0)), Value(1));
+        assert_eq!(buffer.rewind_frame(), None);
+    }
+}
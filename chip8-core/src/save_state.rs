@@ -0,0 +1,310 @@
+use super::basics::{MEMORY_SIZE, SCREEN_WIDTH, STACK_DEPTH};
+use super::core::downscale;
+use super::random::RandomState;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The version of the save state format that `SaveState::to_json` writes.
+/// It is raised whenever the format changes, and states of other versions
+/// are rejected.
+pub const SAVE_STATE_VERSION: u32 = 2;
+
+const LIT: char = '#';
+const DARK: char = '.';
+
+/// Why a save state cannot be read or loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SaveStateError {
+    /// The text is no JSON object with the fields of a save state.
+    Parse(String),
+    /// The state was written in another version of the format.
+    UnsupportedVersion(u32),
+    /// A field has the wrong size or an impossible value.
+    Invalid(String),
+    /// The core cannot save or load its state.
+    Unsupported,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::Parse(message) => write!(f, "invalid save state: {}", message),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported, expected {}",
+                version, SAVE_STATE_VERSION
+            ),
+            SaveStateError::Invalid(message) => write!(f, "invalid save state: {}", message),
+            SaveStateError::Unsupported => write!(f, "the core does not support save states"),
+        }
+    }
+}
+
+/// The complete state of a running VM, created by
+/// `VirtualMachine::save_state`.
+///
+/// On disk, a state is a JSON object with these fields, all numbers being
+/// plain integers. `display` holds one string per row of the logical display,
+/// with `#` for lit and `.` for dark pixels. `random` is the position of the
+/// seeded random numbers of CXNN, so that a loaded state draws the same
+/// numbers as the original run; it is missing if the VM used a randomizer
+/// without a seed. Quirks, mapped devices and pressed keys are not part of
+/// the state; they come from the VM that the state is loaded into.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SaveState {
+    /// Always `SAVE_STATE_VERSION`.
+    pub version: u32,
+    pub program_counter: u16,
+    /// The return addresses of the active subroutine calls, innermost last.
+    pub stack: Vec<u16>,
+    pub registers: [u8; 16],
+    pub register_i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// All `MEMORY_SIZE` bytes of memory, including the font.
+    pub memory: Vec<u8>,
+    pub display: Vec<String>,
+    pub executed_instructions: u64,
+    pub random: Option<RandomState>,
+}
+
+/// Only the version, which is checked before the rest is parsed.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl SaveState {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Reads a state written by `to_json`, checking its version and the
+    /// sizes of its fields.
+    pub fn from_json(json: &str) -> Result<SaveState, SaveStateError> {
+        let parse_error = |e: serde_json::Error| SaveStateError::Parse(e.to_string());
+        let header: Header = serde_json::from_str(json).map_err(parse_error)?;
+        if header.version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(header.version));
+        }
+        let state: SaveState = serde_json::from_str(json).map_err(parse_error)?;
+        state.check()?;
+        Ok(state)
+    }
+
+    /// Checks the sizes and addresses that the type system does not.
+    pub(crate) fn check(&self) -> Result<(), SaveStateError> {
+        let invalid = |message: String| Err(SaveStateError::Invalid(message));
+        if self.memory.len() != MEMORY_SIZE {
+            return invalid(format!(
+                "memory has {} bytes, expected {}",
+                self.memory.len(),
+                MEMORY_SIZE
+            ));
+        }
+        if self.stack.len() > STACK_DEPTH {
+            return invalid(format!(
+                "stack has {} entries, at most {} are possible",
+                self.stack.len(),
+                STACK_DEPTH
+            ));
+        }
+        if self.program_counter as usize >= MEMORY_SIZE {
+            return invalid(format!(
+                "program counter {:#05X} is past the end of memory",
+                self.program_counter
+            ));
+        }
+        if let Some(addr) = self
+            .stack
+            .iter()
+            .find(|addr| **addr as usize >= MEMORY_SIZE)
+        {
+            return invalid(format!(
+                "return address {:#05X} is past the end of memory",
+                addr
+            ));
+        }
+        for (y, row) in self.display.iter().enumerate() {
+            if row.len() != SCREEN_WIDTH as usize || row.chars().any(|c| c != LIT && c != DARK) {
+                return invalid(format!(
+                    "display row {} must be {} characters of '{}' and '{}'",
+                    y, SCREEN_WIDTH, LIT, DARK
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes a display of `height` rows, given as `lit(x, y)`.
+    pub(crate) fn encode_display(height: u8, lit: impl Fn(u8, u8) -> bool) -> Vec<String> {
+        (0..height)
+            .map(|y| {
+                (0..SCREEN_WIDTH)
+                    .map(|x| if lit(x, y) { LIT } else { DARK })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// A downscaled copy of the display, see `Chip8Core::thumbnail`.
+    pub fn thumbnail(&self, factor: u8) -> Vec<Vec<u8>> {
+        let rows: Vec<&[u8]> = self.display.iter().map(|row| row.as_bytes()).collect();
+        downscale(SCREEN_WIDTH, rows.len() as u8, factor, |x, y| {
+            rows[y as usize][x as usize] == LIT as u8
+        })
+    }
+
+    /// The coordinates of the lit pixels of the display.
+    pub(crate) fn lit_pixels(&self) -> Vec<(u8, u8)> {
+        let mut pixels = Vec::new();
+        for (y, row) in self.display.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == LIT {
+                    pixels.push((x as u8, y as u8));
+                }
+            }
+        }
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basics::{Address, Register, Value};
+    use crate::core::Chip8Core;
+    use crate::vm::VirtualMachine;
+
+    const PROGRAM: [u8; 12] = [
+        0x60, 0x05, // LD V0, 0x05
+        0xF0, 0x15, // LD DT, V0
+        0xF0, 0x29, // LD F, V0
+        0xD0, 0x05, // DRW V0, V0, 5
+        0x22, 0x0A, // CALL 0x20A
+        0x12, 0x0A, // JP 0x20A
+    ];
+
+    fn run(vm: &mut VirtualMachine, steps: usize) {
+        for _ in 0..steps {
+            vm.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut vm = VirtualMachine::new(&PROGRAM);
+        run(&mut vm, 5);
+        let json = vm.save_state().to_json();
+        let state = SaveState::from_json(&json).unwrap();
+        assert_eq!(state, vm.save_state());
+        assert_eq!(state.display[5].matches(LIT).count(), 4);
+
+        let mut restored = VirtualMachine::new(&[]);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.program_counter, Address(0x20A));
+        assert_eq!(restored.stack(), [Address(0x20A)]);
+        assert_eq!(restored.get_register(Register(0)), Value(5));
+        assert_eq!(restored.memory(Address(0x200)), Value(0x60));
+        assert_eq!(restored.executed_instructions(), 5);
+        assert!(restored.pixel(5, 5));
+        let interface = restored.interface.lock().unwrap();
+        assert_eq!(interface.delay_timer, Value(5));
+        assert_eq!(interface.display.get(5, 5), 255);
+        assert_eq!(interface.display.get(0, 0), 0);
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        let mut vm = VirtualMachine::new(&PROGRAM);
+        run(&mut vm, 4);
+        assert_eq!(vm.save_state().thumbnail(2), vm.thumbnail(2));
+    }
+
+    #[test]
+    fn test_random_state() {
+        // C0FF: V0 = random byte, jump back.
+        let program = [0xC0, 0xFF, 0x12, 0x00];
+        let mut vm = VirtualMachine::new(&program);
+        vm.set_seed(42);
+        run(&mut vm, 6);
+        let state = SaveState::from_json(&vm.save_state().to_json()).unwrap();
+        assert_eq!(state.random, Some(RandomState { seed: 42, draws: 3 }));
+
+        let mut restored = VirtualMachine::new(&program);
+        restored.load_state(&state).unwrap();
+        for _ in 0..4 {
+            run(&mut vm, 2);
+            run(&mut restored, 2);
+            assert_eq!(
+                restored.get_register(Register(0)),
+                vm.get_register(Register(0))
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_resets_transient_state() {
+        let mut vm = VirtualMachine::new(&PROGRAM);
+        let state = vm.save_state();
+        run(&mut vm, 4);
+        vm.interface.lock().unwrap().latched_key = Some(3);
+        vm.load_state(&state).unwrap();
+        assert_eq!(vm.draw_collisions(), 0);
+        assert_eq!(vm.interface.lock().unwrap().latched_key, None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut state = VirtualMachine::new(&PROGRAM).save_state();
+        state.version = 1;
+        assert_eq!(
+            SaveState::from_json(&state.to_json()),
+            Err(SaveStateError::UnsupportedVersion(1))
+        );
+        assert!(matches!(
+            SaveState::from_json("{}"),
+            Err(SaveStateError::Parse(_))
+        ));
+
+        let mut state = VirtualMachine::new(&PROGRAM).save_state();
+        state.memory.pop();
+        assert_eq!(
+            SaveState::from_json(&state.to_json()),
+            Err(SaveStateError::Invalid(
+                "memory has 4095 bytes, expected 4096".to_string()
+            ))
+        );
+
+        let mut state = VirtualMachine::new(&PROGRAM).save_state();
+        state.program_counter = 0xFFFF;
+        assert_eq!(
+            SaveState::from_json(&state.to_json()),
+            Err(SaveStateError::Invalid(
+                "program counter 0xFFFF is past the end of memory".to_string()
+            ))
+        );
+        let mut vm = VirtualMachine::new(&PROGRAM);
+        assert!(vm.load_state(&state).is_err());
+        assert_eq!(vm.program_counter, Address(0x200));
+
+        let mut state = VirtualMachine::new(&PROGRAM).save_state();
+        state.stack.push(0x1000);
+        assert_eq!(
+            SaveState::from_json(&state.to_json()),
+            Err(SaveStateError::Invalid(
+                "return address 0x1000 is past the end of memory".to_string()
+            ))
+        );
+
+        // A hi-res state does not fit a VM with the normal resolution.
+        let hires = VirtualMachine::new(&[0x12, 0x60]).save_state();
+        let mut vm = VirtualMachine::new(&PROGRAM);
+        assert!(matches!(
+            vm.load_state(&hires),
+            Err(SaveStateError::Invalid(_))
+        ));
+        assert_eq!(vm.program_counter, Address(0x200));
+    }
+}
@@ -0,0 +1,302 @@
+use super::asm::assemble;
+use super::basics::{Register, Value};
+use super::vm::{StepOutcome, VirtualMachine};
+
+/// How many instructions a case may execute before it counts as stuck.
+const MAX_STEPS: u32 = 1000;
+
+/// A program that exercises one opcode family, ending in a jump to itself,
+/// and the registers it must end with.
+struct Case {
+    name: &'static str,
+    source: &'static str,
+    /// The key held down while the program runs.
+    key: Option<u8>,
+    expected: &'static [(u8, u8)],
+}
+
+const CASES: [Case; 13] = [
+    Case {
+        name: "1NNN jump",
+        source: "
+                LD V0, 5
+                JP skip
+                LD V0, 1
+            skip:
+            halt: JP halt",
+        key: None,
+        expected: &[(0x0, 5)],
+    },
+    Case {
+        name: "2NNN/00EE subroutines",
+        source: "
+                CALL sub
+                LD V1, 2
+            halt: JP halt
+            sub:
+                LD V0, 1
+                RET",
+        key: None,
+        expected: &[(0x0, 1), (0x1, 2)],
+    },
+    Case {
+        name: "3XNN/4XNN/5XY0/9XY0 skips",
+        source: "
+                LD V0, 3
+                SE V0, 3
+                LD V1, 1
+                SNE V0, 3
+                LD V2, 1
+                LD V3, 3
+                SE V0, V3
+                LD V4, 1
+                SNE V0, V3
+                LD V5, 1
+            halt: JP halt",
+        key: None,
+        expected: &[(0x1, 0), (0x2, 1), (0x4, 0), (0x5, 1)],
+    },
+    Case {
+        name: "6XNN/7XNN constants",
+        source: "
+                LD VF, 7
+                LD V0, 0xFE
+                ADD V0, 3
+            halt: JP halt",
+        key: None,
+        expected: &[(0x0, 0x01), (0xF, 7)],
+    },
+    Case {
+        name: "8XY0-8XY3 logic",
+        source: "
+                LD V0, 0x0F
+                LD V1, 0xF0
+                LD V2, V0
+                OR V2, V1
+                LD V3, V0
+                AND V3, V1
+                LD V4, V2
+                XOR V4, V0
+            halt: JP halt",
+        key: None,
+        expected: &[(0x2, 0xFF), (0x3, 0x00), (0x4, 0xF0)],
+    },
+    Case {
+        name: "8XY4-8XYE arithmetic",
+        source: "
+                LD V6, 2
+                LD V0, 0xFF
+                ADD V0, V6
+                LD V1, VF
+                LD V2, 1
+                SUB V2, V6
+                LD V3, VF
+                LD V4, 1
+                SUBN V4, V6
+                LD V5, VF
+                LD V7, 0x81
+                SHR V7
+                LD V8, VF
+                LD V9, 0x81
+                SHL V9
+                LD VA, VF
+            halt: JP halt",
+        key: None,
+        expected: &[
+            (0x0, 0x01),
+            (0x1, 1),
+            (0x2, 0xFF),
+            (0x3, 0),
+            (0x4, 0x01),
+            (0x5, 1),
+            (0x7, 0x40),
+            (0x8, 1),
+            (0x9, 0x02),
+            (0xA, 1),
+        ],
+    },
+    Case {
+        name: "ANNN/FX1E/FX55/FX65 memory",
+        source: "
+                LD I, data
+                LD V0, 0x11
+                LD V1, 0x22
+                LD [I], V1
+                LD I, data
+                LD V0, 0
+                LD V1, 0
+                LD V1, [I]
+                LD V2, V0
+                LD V3, V1
+                LD I, data
+                LD V4, 1
+                ADD I, V4
+                LD V0, [I]
+            halt: JP halt
+            data: db 0, 0",
+        key: None,
+        expected: &[(0x0, 0x22), (0x2, 0x11), (0x3, 0x22)],
+    },
+    Case {
+        name: "BNNN jump with offset",
+        source: "
+                LD V0, 4
+                JP V0, table
+            table:
+                LD V1, 1
+                LD V1, 2
+                LD V2, 9
+            halt: JP halt",
+        key: None,
+        expected: &[(0x1, 0), (0x2, 9)],
+    },
+    Case {
+        name: "CXNN random",
+        source: "
+                LD V0, 0xFF
+                RND V0, 0
+            halt: JP halt",
+        key: None,
+        expected: &[(0x0, 0)],
+    },
+    Case {
+        name: "00E0/DXYN/FX29 display",
+        source: "
+                LD V0, 0
+                LD F, V0
+                DRW V0, V0, 5
+                LD V1, VF
+                DRW V0, V0, 5
+                LD V2, VF
+                DRW V0, V0, 5
+                CLS
+                DRW V0, V0, 5
+                LD V3, VF
+            halt: JP halt",
+        key: None,
+        expected: &[(0x1, 0), (0x2, 1), (0x3, 0)],
+    },
+    Case {
+        name: "EX9E/EXA1 keys",
+        source: "
+                LD V0, 5
+                SKP V0
+                LD V1, 1
+                SKNP V0
+                LD V2, 1
+                LD V3, 6
+                SKP V3
+                LD V4, 1
+            halt: JP halt",
+        key: Some(5),
+        expected: &[(0x1, 0), (0x2, 1), (0x4, 1)],
+    },
+    Case {
+        name: "FX07/FX15/FX18 timers",
+        source: "
+                LD V0, 10
+                LD DT, V0
+                LD ST, V0
+                LD V1, DT
+            halt: JP halt",
+        key: None,
+        expected: &[(0x1, 10)],
+    },
+    Case {
+        name: "FX0A/FX33 key wait and BCD",
+        source: "
+                LD V3, K
+                LD V0, 219
+                LD I, data
+                LD B, V0
+                LD V2, [I]
+            halt: JP halt
+            data: db 0, 0, 0",
+        key: Some(7),
+        expected: &[(0x0, 2), (0x1, 1), (0x2, 9), (0x3, 7)],
+    },
+];
+
+/// The outcome of one case of the self test.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaseResult {
+    /// The opcodes that the case covers.
+    pub name: &'static str,
+    /// What went wrong, if anything.
+    pub result: Result<(), String>,
+}
+
+fn run_case(case: &Case) -> Result<(), String> {
+    let program = assemble(case.source).map_err(|e| format!("cannot assemble: {}", e))?;
+    let mut vm = VirtualMachine::new(&program);
+    if let Some(key) = case.key {
+        vm.interface.lock().unwrap().keys_down[key as usize] = true;
+    }
+    let mut halted = false;
+    for _ in 0..MAX_STEPS {
+        if vm.step().map_err(|e| e.to_string())? == StepOutcome::Halted {
+            halted = true;
+            break;
+        }
+    }
+    if !halted {
+        return Err(format!("did not finish within {} steps", MAX_STEPS));
+    }
+    for (register, value) in case.expected {
+        let actual = vm.get_register(Register(*register));
+        if actual != Value(*value) {
+            return Err(format!(
+                "V{:X} is {:#04X}, expected {:#04X}",
+                register, actual.0, value
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Runs small built-in programs that cover every opcode family on a VM with
+/// the default quirks, and checks the registers they end with. Meant as a
+/// quick sanity check of a build on an unusual platform.
+pub fn run_self_test() -> Vec<CaseResult> {
+    CASES
+        .iter()
+        .map(|case| CaseResult {
+            name: case.name,
+            result: run_case(case),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test_passes() {
+        for case in run_self_test() {
+            assert_eq!(case.result, Ok(()), "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn test_failing_case() {
+        let case = Case {
+            name: "wrong",
+            source: "LD V0, 1\nhalt: JP halt",
+            key: None,
+            expected: &[(0x0, 2)],
+        };
+        assert_eq!(
+            run_case(&case),
+            Err("V0 is 0x01, expected 0x02".to_string())
+        );
+        let endless = Case {
+            source: "loop: ADD V0, 1\nJP loop",
+            ..case
+        };
+        assert_eq!(
+            run_case(&endless),
+            Err(format!("did not finish within {} steps", MAX_STEPS))
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use super::basics::{Address, FONT_OFFSET, MEMORY_SIZE};
+use super::core::Chip8Core;
+use super::pgm::Rgb;
+
+/// Sprites are always one byte, i.e. 8 pixels, wide.
+const SPRITE_WIDTH: usize = 8;
+/// How many sprites a row of a sheet holds at most.
+const SHEET_COLUMNS: usize = 16;
+/// The grid lines between the sprites of a sheet, in pixels.
+const SHEET_GAP: usize = 1;
+const LIT: u8 = 255;
+const UNLIT: u8 = 0;
+const GRID: u8 = 64;
+/// The color types of PNG images.
+const PNG_GRAYSCALE: u8 = 0;
+const PNG_RGB: u8 = 2;
+
+/// `count` sprites of `height` bytes each, stored one after another in memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteRange {
+    pub start: Address,
+    pub height: u8,
+    pub count: u16,
+}
+
+impl SpriteRange {
+    /// The hex digits 0 to F of the built-in font.
+    pub fn font() -> SpriteRange {
+        SpriteRange {
+            start: Address(FONT_OFFSET),
+            height: 5,
+            count: 16,
+        }
+    }
+}
+
+/// A grayscale image of the sprites of a `SpriteRange`, laid out in rows of
+/// up to 16 sprites on a grid. Bytes past the end of memory are left blank.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteSheet {
+    width: usize,
+    height: usize,
+    /// The brightness of every pixel, row by row.
+    pixels: Vec<u8>,
+}
+
+impl SpriteSheet {
+    pub fn new(core: &dyn Chip8Core, range: SpriteRange) -> SpriteSheet {
+        let count = range.count as usize;
+        let sprite_height = range.height as usize;
+        let columns = count.clamp(1, SHEET_COLUMNS);
+        let rows = count.max(1).div_ceil(SHEET_COLUMNS);
+        let width = columns * (SPRITE_WIDTH + SHEET_GAP) + SHEET_GAP;
+        let height = rows * (sprite_height + SHEET_GAP) + SHEET_GAP;
+        let mut pixels = vec![GRID; width * height];
+        for sprite in 0..count {
+            let left = SHEET_GAP + (sprite % SHEET_COLUMNS) * (SPRITE_WIDTH + SHEET_GAP);
+            let top = SHEET_GAP + (sprite / SHEET_COLUMNS) * (sprite_height + SHEET_GAP);
+            for row in 0..sprite_height {
+                let addr = range.start.0 as usize + sprite * sprite_height + row;
+                let byte = if addr < MEMORY_SIZE {
+                    core.memory(Address(addr as u16)).0
+                } else {
+                    0
+                };
+                for x in 0..SPRITE_WIDTH {
+                    let lit = byte >> (SPRITE_WIDTH - 1 - x) & 1 == 1;
+                    pixels[(top + row) * width + left + x] = if lit { LIT } else { UNLIT };
+                }
+            }
+        }
+        SpriteSheet {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The brightness of a pixel: 255 for lit sprite pixels, 0 for unlit ones
+    /// and a dark gray for the grid.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Encodes the sheet as a grayscale PNG image, with every pixel enlarged
+    /// to `scale` x `scale` pixels.
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        self.encode_png(scale, PNG_GRAYSCALE, |brightness| vec![brightness])
+    }
+
+    /// Encodes the sheet like `to_png`, but as a color image with lit sprite
+    /// pixels in `fill` and unlit ones in `background`. The grid stays gray.
+    pub fn to_png_rgb(&self, scale: usize, fill: Rgb, background: Rgb) -> Vec<u8> {
+        self.encode_png(scale, PNG_RGB, |brightness| match brightness {
+            LIT => fill.to_vec(),
+            UNLIT => background.to_vec(),
+            gray => vec![gray; 3],
+        })
+    }
+
+    /// Encodes the sheet as a PNG image of a color type, with the samples of
+    /// each pixel given by `samples` for its brightness.
+    fn encode_png(&self, scale: usize, color_type: u8, samples: impl Fn(u8) -> Vec<u8>) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for y in 0..self.height * scale {
+            // No filter for this scanline.
+            raw.push(0);
+            for x in 0..self.width * scale {
+                raw.extend(samples(self.pixel(x / scale, y / scale)));
+            }
+        }
+        let mut header = Vec::new();
+        header.extend(&((self.width * scale) as u32).to_be_bytes());
+        header.extend(&((self.height * scale) as u32).to_be_bytes());
+        // 8 bits per sample, deflate, no filtering extensions, no interlacing.
+        header.extend(&[8, color_type, 0, 0, 0]);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend(&(data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    let checksum = crc32(kind.iter().chain(data));
+    png.extend(&checksum.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks. Sprite sheets
+/// are small enough that compressing them is not worth a dependency.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        stream.extend(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        stream.push(last as u8);
+        let len = block.len() as u16;
+        stream.extend(&len.to_le_bytes());
+        stream.extend(&(!len).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32<'a>(data: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                crc >> 1 ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::VirtualMachine;
+
+    #[test]
+    fn test_font_sheet() {
+        let vm = VirtualMachine::new(&[]);
+        let sheet = SpriteSheet::new(&vm, SpriteRange::font());
+        assert_eq!((sheet.width(), sheet.height()), (16 * 9 + 1, 7));
+        assert_eq!(sheet.pixel(0, 0), GRID);
+        // The top row of "0" is 0xF0.
+        let top: Vec<_> = (1..9).map(|x| sheet.pixel(x, 1)).collect();
+        assert_eq!(top, [LIT, LIT, LIT, LIT, UNLIT, UNLIT, UNLIT, UNLIT]);
+        // The second row of "1" is 0x60.
+        assert_eq!(sheet.pixel(10 + 1, 2), LIT);
+        assert_eq!(sheet.pixel(10, 2), UNLIT);
+    }
+
+    #[test]
+    fn test_sheet_layout() {
+        let vm = VirtualMachine::new(&[0xFF; 40]);
+        let range = SpriteRange {
+            start: Address(0x200),
+            height: 2,
+            count: 20,
+        };
+        let sheet = SpriteSheet::new(&vm, range);
+        assert_eq!((sheet.width(), sheet.height()), (16 * 9 + 1, 2 * 3 + 1));
+        // The last sprite is the fourth one in the second row.
+        assert_eq!(sheet.pixel(3 * 9 + 1, 5), LIT);
+        assert_eq!(sheet.pixel(4 * 9 + 1, 5), GRID);
+    }
+
+    #[test]
+    fn test_png() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        let vm = VirtualMachine::new(&[]);
+        let png = SpriteSheet::new(&vm, SpriteRange::font()).to_png(2);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0DIHDR"));
+        assert_eq!(&png[16..20], &290u32.to_be_bytes());
+        assert_eq!(&png[20..24], &14u32.to_be_bytes());
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+        assert_eq!(png[25], PNG_GRAYSCALE);
+        let rgb = SpriteSheet::new(&vm, SpriteRange::font()).to_png_rgb(2, [255, 0, 0], [0; 3]);
+        assert_eq!(rgb[25], PNG_RGB);
+        // The first scanline is the grid, the third one the top row of "0".
+        let scanline = 1 + 290 * 3;
+        let data = &rgb[8 + 8 + 13 + 4 + 8 + 2 + 5..];
+        assert_eq!(&data[..4], &[0, GRID, GRID, GRID]);
+        assert_eq!(
+            &data[2 * scanline + 1 + 2 * 3..][..6],
+            &[255, 0, 0, 255, 0, 0]
+        );
+        assert_eq!(&data[2 * scanline + 1 + 10 * 3..][..3], &[0, 0, 0]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Keeps the most recent duration samples of some periodic task (like rendering
+/// a frame) and computes percentiles over them.
+pub struct FrameStats {
+    samples: VecDeque<Duration>,
+    capacity: usize,
+}
+
+impl FrameStats {
+    /// Creates an empty statistic which remembers up to `capacity` samples.
+    pub fn new(capacity: usize) -> FrameStats {
+        assert!(capacity > 0);
+        FrameStats {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds a sample, dropping the oldest one if the capacity is reached.
+    pub fn record(&mut self, sample: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the sample below which `percent` percent of all samples lie.
+    pub fn percentile(&self, percent: u32) -> Option<Duration> {
+        assert!(percent <= 100);
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<_> = self.samples.iter().copied().collect();
+        sorted.sort();
+        let index = (sorted.len() - 1) * percent as usize / 100;
+        Some(sorted[index])
+    }
+
+    /// The jitter is the distance between the median and the 99th percentile.
+    pub fn jitter(&self) -> Option<Duration> {
+        Some(self.percentile(99)? - self.percentile(50)?)
+    }
+
+    /// A one-line human readable summary of the statistic.
+    pub fn summary(&self) -> String {
+        match (self.percentile(50), self.percentile(99), self.jitter()) {
+            (Some(p50), Some(p99), Some(jitter)) => format!(
+                "p50 {:?}, p99 {:?}, jitter {:?} ({} samples)",
+                p50,
+                p99,
+                jitter,
+                self.len()
+            ),
+            _ => String::from("no samples"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut stats = FrameStats::new(100);
+        assert!(stats.is_empty());
+        assert_eq!(stats.percentile(50), None);
+        for ms in (1..=100).rev() {
+            stats.record(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.len(), 100);
+        assert_eq!(stats.percentile(0), Some(Duration::from_millis(1)));
+        assert_eq!(stats.percentile(50), Some(Duration::from_millis(50)));
+        assert_eq!(stats.percentile(99), Some(Duration::from_millis(99)));
+        assert_eq!(stats.percentile(100), Some(Duration::from_millis(100)));
+        assert_eq!(stats.jitter(), Some(Duration::from_millis(49)));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut stats = FrameStats::new(2);
+        stats.record(Duration::from_millis(100));
+        stats.record(Duration::from_millis(1));
+        stats.record(Duration::from_millis(2));
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats.percentile(100), Some(Duration::from_millis(2)));
+    }
+}
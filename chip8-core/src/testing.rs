@@ -0,0 +1,160 @@
+use super::basics::{HIRES_SCREEN_HEIGHT, SCREEN_HEIGHT, SCREEN_WIDTH};
+use super::vm::{Display, VMInterface};
+use std::sync::{Arc, Mutex};
+
+/// The pixels of one frame, indexed by x, then y. Rows below the visible
+/// height stay unset.
+pub type Frame = [[bool; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+
+pub(crate) const EMPTY_FRAME: Frame =
+    [[false; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+
+/// Plays a fixed sequence of key presses, frame by frame.
+#[derive(Clone, Default)]
+pub struct ScriptedKeypad {
+    presses: Vec<(u32, u8, u32)>,
+    frame: u32,
+}
+
+impl ScriptedKeypad {
+    pub fn new() -> ScriptedKeypad {
+        ScriptedKeypad::default()
+    }
+
+    /// Holds `key` down for `duration` frames, starting at `frame`. Presses
+    /// of different keys may overlap.
+    pub fn press(mut self, frame: u32, key: u8, duration: u32) -> ScriptedKeypad {
+        assert!(key < 16);
+        self.presses.push((frame, key, duration));
+        self
+    }
+
+    /// Which keys are held down during the given frame, indexed by key.
+    pub fn keys_down(&self, frame: u32) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (_, key, _) in self
+            .presses
+            .iter()
+            .filter(|(start, _, duration)| *start <= frame && frame < start + duration)
+        {
+            keys[*key as usize] = true;
+        }
+        keys
+    }
+
+    /// The number of frames played so far.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Sets the keys of the current frame on the interface and advances to
+    /// the next frame.
+    pub fn next_frame(&mut self, interface: &mut VMInterface) {
+        interface.keys_down = self.keys_down(self.frame);
+        self.frame += 1;
+    }
+}
+
+/// A display that records the state of the screen at the end of every frame.
+/// Keep a handle from `frames()` before moving the display into a VM.
+pub struct CapturingDisplay {
+    pixels: Frame,
+    size: (u8, u8),
+    frames: Arc<Mutex<Vec<Frame>>>,
+}
+
+impl CapturingDisplay {
+    pub fn new() -> CapturingDisplay {
+        CapturingDisplay {
+            pixels: EMPTY_FRAME,
+            size: (SCREEN_WIDTH, SCREEN_HEIGHT),
+            frames: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The frames captured so far.
+    pub fn frames(&self) -> Arc<Mutex<Vec<Frame>>> {
+        self.frames.clone()
+    }
+}
+
+impl Default for CapturingDisplay {
+    fn default() -> CapturingDisplay {
+        CapturingDisplay::new()
+    }
+}
+
+impl Display for CapturingDisplay {
+    fn clear(&mut self) {
+        self.pixels = EMPTY_FRAME;
+    }
+
+    fn draw_pixels(&mut self, pixels: &[(u8, u8)]) {
+        for (x, y) in pixels {
+            let pixel = &mut self.pixels[*x as usize][*y as usize];
+            *pixel = !*pixel;
+        }
+    }
+
+    fn get(&self, x: u8, y: u8) -> u8 {
+        if self.pixels[x as usize][y as usize] {
+            255
+        } else {
+            0
+        }
+    }
+
+    fn frame(&mut self) {
+        self.frames.lock().unwrap().push(self.pixels);
+    }
+
+    fn set_size(&mut self, width: u8, height: u8) {
+        self.pixels = EMPTY_FRAME;
+        self.size = (width, height);
+    }
+
+    fn size(&self) -> (u8, u8) {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::VirtualMachine;
+
+    #[test]
+    fn test_scripted_keypad() {
+        let mut keypad = ScriptedKeypad::new().press(1, 5, 2).press(2, 7, 1);
+        let vm = VirtualMachine::new(&[]);
+        let mut keys = Vec::new();
+        for _ in 0..4 {
+            let mut interface = vm.interface.lock().unwrap();
+            keypad.next_frame(&mut interface);
+            let down = interface.keys_down;
+            keys.push((0..16).filter(|key| down[*key]).collect::<Vec<_>>());
+        }
+        assert_eq!(keys, [vec![], vec![5], vec![5, 7], vec![]]);
+        assert_eq!(keypad.frame(), 4);
+    }
+
+    #[test]
+    fn test_capturing_display() {
+        // Draw the "0" digit at (0, 0).
+        let program = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05];
+        let mut vm = VirtualMachine::new(&program);
+        let display = CapturingDisplay::new();
+        let frames = display.frames();
+        vm.interface.lock().unwrap().display = Box::new(display);
+        vm.interface.lock().unwrap().display.frame();
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+        vm.interface.lock().unwrap().display.frame();
+        let frames = frames.lock().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert!(!frames[0][0][0]);
+        assert!(frames[1][0][0]);
+        assert!(!frames[1][1][1]);
+    }
+}
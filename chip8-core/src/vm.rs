@@ -0,0 +1,2464 @@
+use super::basics::{
+    Address, Register, Value, FONT_OFFSET, HIRES_SCREEN_HEIGHT, MEMORY_SIZE, PROGRAM_START,
+    SCREEN_HEIGHT, SCREEN_WIDTH, STACK_DEPTH,
+};
+use super::key_events::KeyEventQueue;
+use super::mmio::{MappedRegion, MemoryMap, Peripheral};
+use super::program::{DecodeError, Instruction};
+use super::quirks::{MemoryOverflow, Quirks};
+use super::random::{Randomizer, SeededRandomizer};
+use super::save_state::{SaveState, SaveStateError, SAVE_STATE_VERSION};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// The first instruction of hi-res programs, which jumps into the display
+/// routines of the original two-page interpreter. Its program starts at
+/// `HIRES_ENTRY_POINT` instead.
+const HIRES_MARKER: [u8; 2] = [0x12, 0x60];
+const HIRES_ENTRY_POINT: Address = Address(0x2C0);
+
+/// Holds the logic of a virtual machine in action, including things like the
+/// program counter and the memory.
+pub struct VirtualMachine {
+    pub program_counter: Address,
+    stack: Vec<Address>,
+    registers: [Value; 16],
+    register_i: Address,
+    memory: [Value; MEMORY_SIZE],
+    logical_display: [[bool; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+    draw_collisions: usize,
+    screen_height: u8,
+    executed_instructions: u64,
+    instruction_budget: Option<u64>,
+    devices: MemoryMap,
+    /// The key that a key wait received, which it holds on to until the key
+    /// is released with the `key_release_wait` quirk.
+    key_to_release: Option<u8>,
+    randomizer: Box<dyn Randomizer>,
+    pub quirks: Quirks,
+    pub interface: Arc<Mutex<VMInterface>>,
+}
+
+/// What happened during one `step` of the VM.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    /// The instruction was executed and the program goes on.
+    Continued,
+    /// FX0A found no key pressed and runs again on the next step.
+    WaitingForKey,
+    /// DXYN waits for the vertical blank and runs again on the next step.
+    WaitingForVblank,
+    /// The display was drawn to or cleared.
+    DrewFrame,
+    /// The program jumped to itself, so it never goes on.
+    Halted,
+}
+
+/// Why the VM cannot execute the next instruction. Nothing was executed and
+/// the program counter still points at the failing instruction, whose address
+/// is `pc`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VmError {
+    /// The opcode is no valid instruction, e.g. because the program jumped
+    /// into its data.
+    InvalidOpcode { pc: Address, opcode: u16 },
+    /// 2NNN with `STACK_DEPTH` subroutine calls already on the stack.
+    StackOverflow { pc: Address },
+    /// 00EE outside of any subroutine.
+    StackUnderflow { pc: Address },
+    /// 0NNN, which runs machine code of the original hardware.
+    MachineCodeRoutine { pc: Address, addr: Address },
+    /// An access to the `len` bytes starting at I reaches past the end of
+    /// memory, with `MemoryOverflow::Error`.
+    OutOfBounds { pc: Address, i: Address, len: u16 },
+    /// The instruction at `pc` moves the program counter to `target`, where
+    /// no complete instruction fits into memory. Both are the same if the
+    /// program counter already got there, e.g. by skipping the last
+    /// instruction of memory.
+    PcOutOfRange { pc: Address, target: Address },
+    /// Returned by `try_step` once the instruction budget is used up.
+    BudgetExceeded { budget: u64 },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:#06X} at {:#05X}", opcode, pc.0)
+            }
+            VmError::StackOverflow { pc } => write!(
+                f,
+                "stack overflow at {:#05X}, more than {} nested subroutine calls",
+                pc.0, STACK_DEPTH
+            ),
+            VmError::StackUnderflow { pc } => {
+                write!(f, "return without a subroutine call at {:#05X}", pc.0)
+            }
+            VmError::MachineCodeRoutine { pc, addr } => write!(
+                f,
+                "machine code routine {:#05X} called at {:#05X} is not supported",
+                addr.0, pc.0
+            ),
+            VmError::OutOfBounds { pc, i, len } => write!(
+                f,
+                "access to {} byte(s) at I = {:#05X} past the end of memory at {:#05X}",
+                len, i.0, pc.0
+            ),
+            VmError::PcOutOfRange { pc, target } if pc == target => {
+                write!(f, "program counter {:#05X} is past the end of memory", pc.0)
+            }
+            VmError::PcOutOfRange { pc, target } => write!(
+                f,
+                "jump to {:#05X} at {:#05X} is past the end of memory",
+                target.0, pc.0
+            ),
+            VmError::BudgetExceeded { budget } => {
+                write!(f, "instruction budget of {} exceeded", budget)
+            }
+        }
+    }
+}
+
+/// The "Interface" contains those parts of the VM that are used to communicate
+/// with the "outside".
+pub struct VMInterface {
+    pub delay_timer: Value,
+    pub sound_timer: Value,
+    /// Which of the 16 keys are held down, indexed by key.
+    pub keys_down: [bool; 16],
+    /// Presses and releases applied to `keys_down` by the next key
+    /// instruction, such that every press is seen at least once.
+    pub key_events: KeyEventQueue,
+    /// A key press that the VM sees until a key instruction reads it, even if
+    /// the key was released before. Set by frontends that latch short taps.
+    pub latched_key: Option<u8>,
+    pub display: Box<dyn Display>,
+    /// Plays the tone while the sound timer is active.
+    pub audio: Box<dyn Audio + Send>,
+    /// Whether `audio` was told to play the tone.
+    tone: bool,
+    /// Keeps the tone off while the sound timer runs, e.g. while rewinding.
+    muted: bool,
+    /// Set by every timer tick and cleared by a draw that waited for it.
+    pub vblank: bool,
+}
+
+impl VMInterface {
+    /// Decrements the delay and sound timers by one, if they are not zero yet.
+    /// A timer tick also completes the current frame of the display.
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer.0 > 0 {
+            self.delay_timer.0 -= 1;
+        }
+        if self.sound_timer.0 > 0 {
+            self.sound_timer.0 -= 1;
+        }
+        self.update_tone();
+        self.vblank = true;
+        self.display.frame();
+    }
+
+    /// Sets the sound timer, which starts or stops the tone.
+    pub fn set_sound_timer(&mut self, value: Value) {
+        self.sound_timer = value;
+        self.update_tone();
+    }
+
+    /// Silences (true) or restores (false) the tone without changing the
+    /// sound timer.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.update_tone();
+    }
+
+    /// Replaces the audio, which starts playing right away if the tone is on.
+    pub fn set_audio(&mut self, audio: Box<dyn Audio + Send>) {
+        self.audio = audio;
+        if self.tone {
+            self.audio.set_tone(true);
+        }
+    }
+
+    /// Takes over the audio of `old`, which falls silent, e.g. when another
+    /// core replaces the one of `old`.
+    pub fn take_audio(&mut self, old: &mut VMInterface) {
+        if old.tone {
+            old.audio.set_tone(false);
+            old.tone = false;
+        }
+        std::mem::swap(&mut self.audio, &mut old.audio);
+        self.muted = old.muted;
+        self.tone = false;
+        self.update_tone();
+    }
+
+    fn update_tone(&mut self) {
+        let tone = self.sound_timer.0 > 0 && !self.muted;
+        if tone != self.tone {
+            self.tone = tone;
+            self.audio.set_tone(tone);
+        }
+    }
+
+    /// Whether a key instruction sees `key` as pressed. Reading a latched key
+    /// releases the latch.
+    fn is_key_pressed(&mut self, key: u8) -> bool {
+        if self.latched_key == Some(key) {
+            self.latched_key = None;
+            return true;
+        }
+        self.apply_key_events();
+        let pressed = self.keys_down.get(key as usize) == Some(&true);
+        if pressed {
+            self.key_events.seen(key, &mut self.keys_down);
+        }
+        pressed
+    }
+
+    /// Applies the queued key events to `keys_down`.
+    fn apply_key_events(&mut self) {
+        self.key_events.apply(&mut self.keys_down);
+    }
+
+    /// The keys held down as a bit mask, with bit K set for key K.
+    pub fn keypad(&self) -> u16 {
+        self.keys_down
+            .iter()
+            .enumerate()
+            .filter(|(_, down)| **down)
+            .fold(0, |mask, (key, _)| mask | 1 << key)
+    }
+
+    /// The lowest key held down, if any.
+    pub fn lowest_key_down(&self) -> Option<u8> {
+        self.keys_down
+            .iter()
+            .position(|down| *down)
+            .map(|key| key as u8)
+    }
+
+    /// The key that a key wait (FX0A) receives, if any. Releases the latch.
+    fn take_key(&mut self) -> Option<u8> {
+        let latched = self.latched_key.take();
+        self.apply_key_events();
+        let key = self.lowest_key_down();
+        if let Some(key) = key {
+            self.key_events.seen(key, &mut self.keys_down);
+        }
+        key.or(latched)
+    }
+}
+
+/// A "display", which is called whenever a drawing instruction is executed.
+pub trait Display: Send {
+    fn clear(&mut self);
+    fn draw_pixels(&mut self, pixels: &[(u8, u8)]);
+    fn get(&self, x: u8, y: u8) -> u8;
+    /// Called on every timer tick, which completes a frame.
+    fn frame(&mut self);
+    /// Changes the visible resolution and clears the display.
+    fn set_size(&mut self, width: u8, height: u8);
+    /// The visible resolution as (width, height).
+    fn size(&self) -> (u8, u8);
+
+    /// The intensities of all visible pixels as returned by `get`, row by
+    /// row, i.e. the pixel (x, y) is at `y * width + x`.
+    fn snapshot(&self) -> Vec<u8> {
+        let (width, height) = self.size();
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.get(x, y))
+            .collect()
+    }
+}
+
+/// A sound output, which is told when the sound timer starts and stops the
+/// tone. Like `Display`, it lets every frontend implement sound its own way.
+pub trait Audio {
+    /// Starts (true) or stops (false) the tone.
+    fn set_tone(&mut self, on: bool);
+}
+
+/// Plays nothing, e.g. for headless runs.
+pub struct Silence;
+
+impl Audio for Silence {
+    fn set_tone(&mut self, _on: bool) {}
+}
+
+/// A display that shows every pixel which was lit at any time since the last
+/// frame, so that sprites which are erased and redrawn within a frame do not
+/// flicker.
+struct SimpleDisplay {
+    display: [[bool; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+    lit_since_frame: [[bool; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+    size: (u8, u8),
+}
+
+impl Display for SimpleDisplay {
+    fn clear(&mut self) {
+        for column in self.display.iter_mut() {
+            for pixel in column.iter_mut() {
+                *pixel = false;
+            }
+        }
+    }
+
+    fn draw_pixels(&mut self, pixels: &[(u8, u8)]) {
+        for (x, y) in pixels {
+            let pixel = &mut self.display[*x as usize][*y as usize];
+            *pixel = !*pixel;
+            self.lit_since_frame[*x as usize][*y as usize] |= *pixel;
+        }
+    }
+
+    fn get(&self, x: u8, y: u8) -> u8 {
+        if self.lit_since_frame[x as usize][y as usize] {
+            255
+        } else {
+            0
+        }
+    }
+
+    fn frame(&mut self) {
+        self.lit_since_frame = self.display;
+    }
+
+    fn set_size(&mut self, width: u8, height: u8) {
+        self.clear();
+        self.lit_since_frame = self.display;
+        self.size = (width, height);
+    }
+
+    fn size(&self) -> (u8, u8) {
+        self.size
+    }
+}
+
+impl VirtualMachine {
+    /// Creates a new VM instance with all registers and memory set accordingly.
+    /// Programs starting with `HIRES_MARKER` run in the 64x64 hi-res mode.
+    pub fn new(program: &[u8]) -> VirtualMachine {
+        VirtualMachine::with_quirks(program, Quirks::default())
+    }
+
+    /// Like `new`, but emulating the given behavior variants.
+    pub fn with_quirks(program: &[u8], quirks: Quirks) -> VirtualMachine {
+        let hires = program.starts_with(&HIRES_MARKER);
+        let screen_height = if hires {
+            HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        };
+        let mut interface = VMInterface {
+            delay_timer: Value(0),
+            sound_timer: Value(0),
+            keys_down: [false; 16],
+            key_events: KeyEventQueue::default(),
+            latched_key: None,
+            display: Box::new(SimpleDisplay {
+                display: [[false; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+                lit_since_frame: [[false; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+                size: (SCREEN_WIDTH, SCREEN_HEIGHT),
+            }),
+            audio: Box::new(Silence),
+            tone: false,
+            muted: false,
+            vblank: true,
+        };
+        interface.display.set_size(SCREEN_WIDTH, screen_height);
+
+        VirtualMachine {
+            program_counter: if hires {
+                HIRES_ENTRY_POINT
+            } else {
+                Address(PROGRAM_START)
+            },
+            stack: Vec::new(),
+            registers: [Value(0); 16],
+            register_i: Address(0),
+            memory: VirtualMachine::setup_memory(program),
+            logical_display: [[false; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+            draw_collisions: 0,
+            screen_height,
+            executed_instructions: 0,
+            instruction_budget: None,
+            devices: MemoryMap::default(),
+            key_to_release: None,
+            randomizer: Box::new(SeededRandomizer::from_entropy()),
+            quirks,
+            interface: Arc::new(Mutex::new(interface)),
+        }
+    }
+
+    fn setup_memory(program: &[u8]) -> [Value; MEMORY_SIZE] {
+        let mut memory = [Value(0); MEMORY_SIZE];
+        let font_sprites = [
+            0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80,
+            0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0,
+            0x10, 0xF0, 0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90,
+            0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0,
+            0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0,
+            0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+        ];
+        for (mem_cell, font_byte) in memory
+            .iter_mut()
+            .skip(FONT_OFFSET as usize)
+            .zip(font_sprites.iter())
+        {
+            *mem_cell = Value(*font_byte);
+        }
+        for (mem_cell, prog_byte) in memory
+            .iter_mut()
+            .skip(PROGRAM_START as usize)
+            .zip(program.iter())
+        {
+            *mem_cell = Value(*prog_byte);
+        }
+        memory
+    }
+
+    /// Restarts the random numbers of CXNN from `seed`. Two VMs with the same
+    /// seed draw the same numbers, so runs with the same input are
+    /// reproducible. New VMs are seeded randomly.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.randomizer = Box::new(SeededRandomizer::new(seed));
+    }
+
+    /// The seed given to `set_seed`, or the random one of a new VM. `None`
+    /// after `set_randomizer` with a randomizer that has no seed.
+    pub fn seed(&self) -> Option<u64> {
+        self.randomizer.seed()
+    }
+
+    /// Replaces the source of the random numbers of CXNN, e.g. by a
+    /// `SequenceRandomizer` in tests.
+    pub fn set_randomizer(&mut self, randomizer: Box<dyn Randomizer>) {
+        self.randomizer = randomizer;
+    }
+
+    /// Maps a peripheral into the address window `[start, start + len)`.
+    /// Data accesses of the program to that window are routed to the
+    /// peripheral instead of memory, including the sprites read by DXYN.
+    /// Instructions are always fetched from memory. Panics if the window
+    /// reaches past the end of memory or overlaps with a mapped one.
+    pub fn map_device(&mut self, start: Address, len: u16, device: Box<dyn Peripheral>) {
+        self.devices.map(MappedRegion::new(start, len, device));
+    }
+
+    /// Checks that the instruction at `pc` may access `len` bytes starting at
+    /// I, which fails only with `MemoryOverflow::Error` if they reach past the
+    /// end of memory.
+    fn check_index_range(&self, pc: Address, len: u16) -> Result<(), VmError> {
+        let end = self.register_i.0 as usize + len as usize;
+        if end > MEMORY_SIZE && self.quirks.memory_overflow == MemoryOverflow::Error {
+            return Err(VmError::OutOfBounds {
+                pc,
+                i: self.register_i,
+                len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Computes the address `offset` bytes after I, handling accesses past the
+    /// end of memory according to the quirks.
+    fn index_address(&self, offset: u16) -> Address {
+        let addr = self.register_i.0 as usize + offset as usize;
+        match self.quirks.memory_overflow {
+            MemoryOverflow::Saturate => Address(addr.min(MEMORY_SIZE - 1) as u16),
+            _ => Address((addr % MEMORY_SIZE) as u16),
+        }
+    }
+
+    /// Reads a byte of data, either from memory or from a mapped device.
+    fn read_memory(&mut self, addr: Address) -> Value {
+        match self.devices.read(addr) {
+            Some(value) => Value(value),
+            None => self.memory[addr.0 as usize],
+        }
+    }
+
+    /// Writes a byte of data, either to memory or to a mapped device.
+    fn write_memory(&mut self, addr: Address, value: Value) {
+        if !self.devices.write(addr, value.0) {
+            self.memory[addr.0 as usize] = value;
+        }
+    }
+
+    /// Whether the next instruction is FX0A and no key is pressed, i.e. the VM
+    /// does not make progress until a key is pressed.
+    pub fn is_waiting_for_key(&self) -> bool {
+        let pc = self.program_counter.0 as usize;
+        let waits = match self.memory.get(pc..pc + 2) {
+            Some(bytes) => bytes[0].0 & 0xF0 == 0xF0 && bytes[1].0 == 0x0A,
+            None => false,
+        };
+        let interface = self.interface.lock().unwrap();
+        waits
+            && interface.lowest_key_down().is_none()
+            && interface.latched_key.is_none()
+            && !interface.key_events.pending().any(|event| event.pressed)
+    }
+
+    /// A multi-line dump of the registers, PC, I, stack pointer, timers and
+    /// the opcode of the next instruction, with the labels highlighted by ANSI
+    /// colors if `color` is set.
+    pub fn state_summary(&self, color: bool) -> String {
+        let label = |text: &str| {
+            if color {
+                format!("\x1b[36m{}\x1b[0m", text)
+            } else {
+                text.to_string()
+            }
+        };
+        let (delay_timer, sound_timer) = {
+            let interface = self.interface.lock().unwrap();
+            (interface.delay_timer.0, interface.sound_timer.0)
+        };
+        let pc = self.program_counter.0 as usize;
+        let opcode = match self.memory.get(pc..pc + 2) {
+            Some(bytes) => format!("{:#06X}", (bytes[0].0 as u16) << 8 | bytes[1].0 as u16),
+            None => String::from("-"),
+        };
+        let mut summary = format!(
+            "{} {:#06X}  {} {:#06X}  {} {:2}  {} {:3}  {} {:3}  {} {}\n",
+            label("PC"),
+            pc,
+            label("I"),
+            self.register_i.0,
+            label("SP"),
+            self.stack.len(),
+            label("DT"),
+            delay_timer,
+            label("ST"),
+            sound_timer,
+            label("next"),
+            opcode
+        );
+        for (row, values) in self.registers.chunks(8).enumerate() {
+            let line: Vec<_> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    format!("{} {:02X}", label(&format!("V{:X}", row * 8 + i)), value.0)
+                })
+                .collect();
+            summary.push_str(&line.join("  "));
+            summary.push('\n');
+        }
+        summary
+    }
+
+    pub fn current_instruction(&self) -> Result<Instruction, VmError> {
+        let pc = self.program_counter;
+        let (a, b) = match self.memory.get(pc.0 as usize..pc.0 as usize + 2) {
+            Some(bytes) => (bytes[0].0, bytes[1].0),
+            None => return Err(VmError::PcOutOfRange { pc, target: pc }),
+        };
+        Instruction::try_from_16bit(a, b).map_err(|DecodeError { opcode }| VmError::InvalidOpcode {
+            pc: self.program_counter,
+            opcode,
+        })
+    }
+
+    /// Returns the number of pixels that were erased by the most recent draw
+    /// instruction. Unlike VF, which only signals that some collision happened,
+    /// this tells how many pixels of the sprite overlapped.
+    pub fn draw_collisions(&self) -> usize {
+        self.draw_collisions
+    }
+
+    /// Returns the current value of one of the registers V0 to VF.
+    pub fn get_register(&self, reg: Register) -> Value {
+        self.registers[reg.0 as usize]
+    }
+
+    /// Returns the current value of the I register.
+    pub fn get_register_i(&self) -> Address {
+        self.register_i
+    }
+
+    /// Returns the `n` rows of the sprite that DXYN draws, read from I on
+    /// like the instruction does. Mapped devices are ignored, since reading
+    /// them may change them; DXYN itself reads them.
+    pub fn sprite_rows(&self, n: u8) -> Vec<u8> {
+        (0..n as u16)
+            .map(|offset| self.memory[self.index_address(offset).0 as usize].0)
+            .collect()
+    }
+
+    /// Returns where DXYN with the registers `vx` and `vy` puts the pixel in
+    /// column `x_off` and row `y_off` of its sprite, or `None` if the pixel
+    /// is clipped.
+    pub fn sprite_position(
+        &self,
+        vx: Register,
+        vy: Register,
+        x_off: u8,
+        y_off: u8,
+    ) -> Option<(u8, u8)> {
+        let x0 = self.get_register(vx).0 % SCREEN_WIDTH;
+        let y0 = self.get_register(vy).0 % self.screen_height;
+        let x = VirtualMachine::sprite_coordinate(x0, x_off, SCREEN_WIDTH, self.quirks.wrap_x)?;
+        let y =
+            VirtualMachine::sprite_coordinate(y0, y_off, self.screen_height, self.quirks.wrap_y)?;
+        Some((x, y))
+    }
+
+    /// Returns the return addresses on the stack, innermost last.
+    pub fn stack(&self) -> &[Address] {
+        &self.stack
+    }
+
+    /// Returns the byte at the given address in memory, ignoring mapped devices.
+    pub fn memory(&self, addr: Address) -> Value {
+        self.memory[addr.0 as usize]
+    }
+
+    /// Returns whether the pixel at the given coordinate is set.
+    pub fn pixel(&self, x: u8, y: u8) -> bool {
+        self.logical_display[x as usize][y as usize]
+    }
+
+    /// Returns whether each pixel of the screen is set, row by row, i.e. the
+    /// pixel (x, y) is at `y * SCREEN_WIDTH + x`. Unlike the display of the
+    /// interface, this is the state that the program drew, without any
+    /// smoothing of flicker.
+    pub fn framebuffer(&self) -> Vec<bool> {
+        (0..self.screen_height)
+            .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| self.pixel(x, y))
+            .collect()
+    }
+
+    /// The height of the screen, which is `HIRES_SCREEN_HEIGHT` for hi-res
+    /// programs and `SCREEN_HEIGHT` otherwise.
+    pub fn screen_height(&self) -> u8 {
+        self.screen_height
+    }
+
+    /// Executes the next instruction of the VM, according to the program counter.
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        let pc = self.program_counter;
+        let instruction = self.current_instruction()?;
+        self.execute_instruction(&instruction)?;
+        self.executed_instructions += 1;
+        let stuck = self.program_counter == pc;
+        Ok(match instruction {
+            Instruction::Jump(_) if stuck => StepOutcome::Halted,
+            Instruction::WaitKey(_) if stuck => StepOutcome::WaitingForKey,
+            Instruction::Draw(..) if stuck => StepOutcome::WaitingForVblank,
+            Instruction::Draw(..) | Instruction::ClearDisplay => StepOutcome::DrewFrame,
+            _ => StepOutcome::Continued,
+        })
+    }
+
+    /// The number of instructions executed by `step` so far.
+    pub fn executed_instructions(&self) -> u64 {
+        self.executed_instructions
+    }
+
+    /// Limits the total number of instructions `try_step` executes, so that
+    /// headless runs of ROMs that never halt terminate. `None` removes the
+    /// limit.
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.instruction_budget = budget;
+    }
+
+    /// Like `step`, but fails without executing anything once the instruction
+    /// budget is used up.
+    pub fn try_step(&mut self) -> Result<StepOutcome, VmError> {
+        if let Some(budget) = self.instruction_budget {
+            if self.executed_instructions >= budget {
+                return Err(VmError::BudgetExceeded { budget });
+            }
+        }
+        self.step()
+    }
+
+    /// Captures the complete state of the VM, including the timers of its
+    /// interface.
+    pub fn save_state(&self) -> SaveState {
+        let interface = self.interface.lock().unwrap();
+        SaveState {
+            version: SAVE_STATE_VERSION,
+            program_counter: self.program_counter.0,
+            stack: self.stack.iter().map(|addr| addr.0).collect(),
+            registers: self.registers.map(|value| value.0),
+            register_i: self.register_i.0,
+            delay_timer: interface.delay_timer.0,
+            sound_timer: interface.sound_timer.0,
+            memory: self.memory.iter().map(|value| value.0).collect(),
+            display: SaveState::encode_display(self.screen_height, |x, y| self.pixel(x, y)),
+            executed_instructions: self.executed_instructions,
+            random: self.randomizer.state(),
+        }
+    }
+
+    /// Restores a state captured by `save_state` and redraws the display of
+    /// the interface. The state must have the resolution of this VM. Nothing
+    /// is changed if the state is invalid. The key latch and the collision
+    /// count are reset, and the randomizer is kept if the state has none.
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), SaveStateError> {
+        state.check()?;
+        if state.display.len() != self.screen_height as usize {
+            return Err(SaveStateError::Invalid(format!(
+                "display has {} rows, but the screen is {} pixels high",
+                state.display.len(),
+                self.screen_height
+            )));
+        }
+        self.program_counter = Address(state.program_counter);
+        self.key_to_release = None;
+        self.stack = state.stack.iter().map(|addr| Address(*addr)).collect();
+        self.registers = state.registers.map(Value);
+        self.register_i = Address(state.register_i);
+        for (cell, byte) in self.memory.iter_mut().zip(&state.memory) {
+            *cell = Value(*byte);
+        }
+        self.executed_instructions = state.executed_instructions;
+        self.draw_collisions = 0;
+        if let Some(random) = state.random {
+            self.randomizer = Box::new(SeededRandomizer::resume(random));
+        }
+        let pixels = state.lit_pixels();
+        self.logical_display = [[false; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+        for (x, y) in &pixels {
+            self.logical_display[*x as usize][*y as usize] = true;
+        }
+        let mut interface = self.interface.lock().unwrap();
+        interface.delay_timer = Value(state.delay_timer);
+        interface.latched_key = None;
+        interface.set_sound_timer(Value(state.sound_timer));
+        interface.vblank = true;
+        interface.display.set_size(SCREEN_WIDTH, self.screen_height);
+        interface.display.draw_pixels(&pixels);
+        interface.display.frame();
+        Ok(())
+    }
+
+    /// Clears the entire display of a running VM to black.
+    fn clear_display(&mut self) {
+        for x in 0..SCREEN_WIDTH as usize {
+            for y in 0..HIRES_SCREEN_HEIGHT as usize {
+                self.logical_display[x][y] = false;
+            }
+        }
+        self.interface.lock().unwrap().display.clear();
+    }
+
+    /// Returns the control flow from a subroutine. `pc` is the address of the
+    /// return instruction.
+    fn return_subroutine(&mut self, pc: Address) -> Result<(), VmError> {
+        let addr = self.stack.pop().ok_or(VmError::StackUnderflow { pc })?;
+        self.program_counter = addr;
+        Ok(())
+    }
+
+    /// Calls a subroutine, unless the stack depth would be exceeded. `pc` is
+    /// the address of the call instruction.
+    fn call_subroutine(&mut self, pc: Address, addr: &Address) -> Result<(), VmError> {
+        if self.stack.len() >= STACK_DEPTH {
+            return Err(VmError::StackOverflow { pc });
+        }
+        self.stack.push(self.program_counter);
+        self.program_counter = *addr;
+        Ok(())
+    }
+
+    /// Returns the value of one of the registers.
+    fn register(&mut self, reg: &Register) -> &mut Value {
+        assert!(reg.0 < 16);
+        &mut self.registers[reg.0 as usize]
+    }
+
+    /// Sets the VF register to a given value.
+    fn set_vf(&mut self, value: u8) {
+        self.registers[15] = Value(value);
+    }
+
+    /// Draws the sprite at I with `n` rows. The start coordinate always wraps
+    /// around the screen; pixels crossing an edge wrap or are clipped
+    /// depending on the quirks.
+    fn draw_shape(&mut self, vx: &Register, vy: &Register, n: &Value) {
+        self.set_vf(0);
+        self.draw_collisions = 0;
+        let rows: Vec<u8> = (0..n.0 as u16)
+            .map(|offset| self.read_memory(self.index_address(offset)).0)
+            .collect();
+        let mut pixels = Vec::new();
+        for (y_off, row) in rows.into_iter().enumerate() {
+            for x_off in 0..8 {
+                if row & (128 >> x_off) > 0 {
+                    if let Some(position) = self.sprite_position(*vx, *vy, x_off, y_off as u8) {
+                        pixels.push(position);
+                    }
+                }
+            }
+        }
+        self.draw_pixels(&pixels);
+    }
+
+    /// The register that is shifted into VX, depending on the quirks.
+    fn shift_source<'r>(&self, vx: &'r Register, vy: &'r Register) -> &'r Register {
+        if self.quirks.shift_vy {
+            vy
+        } else {
+            vx
+        }
+    }
+
+    /// Returns whether a timer tick happened since the last call, consuming it.
+    fn take_vblank(&mut self) -> bool {
+        std::mem::replace(&mut self.interface.lock().unwrap().vblank, false)
+    }
+
+    /// Computes the coordinate `start + offset` on an axis of length `size`,
+    /// which either wraps around or is `None` if it lies outside the screen.
+    fn sprite_coordinate(start: u8, offset: u8, size: u8, wrap: bool) -> Option<u8> {
+        let coordinate = start as u16 + offset as u16;
+        if coordinate < size as u16 {
+            Some(coordinate as u8)
+        } else if wrap {
+            Some((coordinate % size as u16) as u8)
+        } else {
+            None
+        }
+    }
+
+    fn draw_pixels(&mut self, pixels: &[(u8, u8)]) {
+        for (x, y) in pixels {
+            self.draw_pixel(*x, *y);
+        }
+        self.interface.lock().unwrap().display.draw_pixels(pixels);
+    }
+
+    /// Draws a pixel at a given coordinate on the display.
+    /// If the pixel is already active, it is deactivated, the VF register is
+    /// set to 1 and the collision is counted.
+    fn draw_pixel(&mut self, x: u8, y: u8) {
+        let was_cleared = {
+            let pixel = &mut self.logical_display[x as usize][y as usize];
+            *pixel = !*pixel;
+            !*pixel
+        };
+        if was_cleared {
+            self.set_vf(1);
+            self.draw_collisions += 1;
+        }
+    }
+
+    /// Executes a single instruction. The program counter is updated,
+    /// meaning for most instructions it will increase by 1 and move
+    /// arbitrarily for others. On error, it stays on the instruction.
+    pub fn execute_instruction(&mut self, instruction: &Instruction) -> Result<(), VmError> {
+        let pc = self.program_counter;
+        self.program_counter.0 += 2;
+        let result = self.run_instruction(pc, instruction);
+        if result.is_err() {
+            self.program_counter = pc;
+        }
+        result
+    }
+
+    /// Carries out `execute_instruction` once the program counter was moved
+    /// past the instruction at `pc`.
+    fn run_instruction(&mut self, pc: Address, instruction: &Instruction) -> Result<(), VmError> {
+        match instruction {
+            // Jumps
+            Instruction::CallSubroutine(addr) => self.call_subroutine(pc, addr)?,
+            Instruction::ReturnSubroutine => self.return_subroutine(pc)?,
+            Instruction::Jump(addr) => self.program_counter = *addr,
+            Instruction::JumpAdd(addr) => {
+                let offset_register = if self.quirks.jump_vx {
+                    Register(((addr.0 >> 8) & 0xF) as u8)
+                } else {
+                    Register(0)
+                };
+                let target = Address(addr.0 + self.register(&offset_register).0 as u16);
+                if target.0 as usize + 1 >= MEMORY_SIZE {
+                    return Err(VmError::PcOutOfRange { pc, target });
+                }
+                self.program_counter = target;
+            }
+
+            // Conditionals
+            Instruction::IfNotEqualConst(vx, n) => {
+                if *self.register(vx) == *n {
+                    self.program_counter.0 += 2;
+                }
+            }
+            Instruction::IfEqualConst(vx, n) => {
+                if *self.register(vx) != *n {
+                    self.program_counter.0 += 2;
+                }
+            }
+            Instruction::IfNotEqual(vx, vy) => {
+                let x = *self.register(vx);
+                let y = *self.register(vy);
+                if x == y {
+                    self.program_counter.0 += 2;
+                }
+            }
+            Instruction::IfEqual(vx, vy) => {
+                let x = *self.register(vx);
+                let y = *self.register(vy);
+                if x != y {
+                    self.program_counter.0 += 2;
+                }
+            }
+
+            // Register Arithmetic
+            Instruction::SetConst(vx, n) => *self.register(vx) = *n,
+            Instruction::AddConst(vx, n) => {
+                let value = Value(self.register(vx).0.wrapping_add(n.0));
+                *self.register(vx) = value;
+            }
+            Instruction::Set(vx, vy) => *self.register(vx) = *self.register(vy),
+            Instruction::Or(vx, vy) => {
+                let value_vx = *self.register(vx);
+                let value_vy = *self.register(vy);
+                *self.register(&vx) = Value(value_vx.0 | value_vy.0);
+                if self.quirks.vf_reset {
+                    self.set_vf(0);
+                }
+            }
+            Instruction::And(vx, vy) => {
+                let value_vx = *self.register(vx);
+                let value_vy = *self.register(vy);
+                *self.register(&vx) = Value(value_vx.0 & value_vy.0);
+                if self.quirks.vf_reset {
+                    self.set_vf(0);
+                }
+            }
+            Instruction::Xor(vx, vy) => {
+                let value_vx = *self.register(vx);
+                let value_vy = *self.register(vy);
+                *self.register(&vx) = Value(value_vx.0 ^ value_vy.0);
+                if self.quirks.vf_reset {
+                    self.set_vf(0);
+                }
+            }
+            Instruction::Add(vx, vy) => {
+                let value_vx = *self.register(vx);
+                let value_vy = *self.register(vy);
+                self.set_vf(value_vx.0.checked_add(value_vy.0).is_none() as u8);
+                *self.register(&vx) = Value(value_vx.0.wrapping_add(value_vy.0));
+            }
+            Instruction::Sub(vx, vy) => {
+                let value_vx = *self.register(vx);
+                let value_vy = *self.register(vy);
+                self.set_vf((value_vx.0 > value_vy.0) as u8);
+                *self.register(&vx) = Value(value_vx.0.wrapping_sub(value_vy.0));
+            }
+            Instruction::NegSub(vx, vy) => {
+                let value_vx = *self.register(vx);
+                let value_vy = *self.register(vy);
+                self.set_vf((value_vy.0 > value_vx.0) as u8);
+                *self.register(&vx) = Value(value_vy.0.wrapping_sub(value_vx.0));
+            }
+            Instruction::RightShift(vx, vy) => {
+                let value = *self.register(self.shift_source(vx, vy));
+                self.set_vf((value.0 & 1) as u8);
+                *self.register(&vx) = Value(value.0 >> 1);
+            }
+            Instruction::LeftShift(vx, vy) => {
+                let value = *self.register(self.shift_source(vx, vy));
+                self.set_vf((value.0 & 128 > 0) as u8);
+                *self.register(&vx) = Value(value.0 << 1);
+            }
+
+            // Key presses
+            Instruction::IfNotKey(vx) => {
+                let target_key = self.register(vx).0;
+                if self.interface.lock().unwrap().is_key_pressed(target_key) {
+                    self.program_counter.0 += 2;
+                }
+            }
+            Instruction::IfKey(vx) => {
+                let target_key = self.register(vx).0;
+                if !self.interface.lock().unwrap().is_key_pressed(target_key) {
+                    self.program_counter.0 += 2;
+                }
+            }
+            Instruction::WaitKey(vx) => {
+                let mut interface = self.interface.lock().unwrap();
+                interface.apply_key_events();
+                let key = match self.key_to_release {
+                    Some(key) if interface.keys_down[key as usize] => None,
+                    Some(key) => {
+                        self.key_to_release = None;
+                        Some(key)
+                    }
+                    None => match interface.take_key() {
+                        Some(key) if self.quirks.key_release_wait => {
+                            self.key_to_release = Some(key);
+                            None
+                        }
+                        key => key,
+                    },
+                };
+                drop(interface);
+                if let Some(k) = key {
+                    *self.register(vx) = Value(k);
+                } else {
+                    self.program_counter.0 -= 2;
+                }
+            }
+
+            // Graphics
+            Instruction::Draw(vx, vy, n) => {
+                if self.quirks.vblank_wait && !self.take_vblank() {
+                    self.program_counter.0 -= 2;
+                } else {
+                    self.check_index_range(pc, n.0 as u16)?;
+                    self.draw_shape(vx, vy, n);
+                }
+            }
+            Instruction::ClearDisplay => self.clear_display(),
+            Instruction::SpriteAddr(vx) => {
+                let digit = self.register(vx).0;
+                self.register_i = Address(FONT_OFFSET + (digit as u16) * 5);
+            }
+
+            // Timers
+            Instruction::GetDelayTimer(vx) => {
+                let value = self.interface.lock().unwrap().delay_timer;
+                *self.register(vx) = value;
+            }
+            Instruction::SetDelayTimer(vx) => {
+                self.interface.lock().unwrap().delay_timer = *self.register(vx)
+            }
+            Instruction::SetSoundTimer(vx) => {
+                let value = *self.register(vx);
+                self.interface.lock().unwrap().set_sound_timer(value)
+            }
+
+            // I register
+            Instruction::SetI(addr) => self.register_i = *addr,
+            Instruction::AddToI(vx) => {
+                let value = self.register(vx).0 as u16;
+                self.register_i.0 = self.register_i.0.wrapping_add(value);
+                if self.quirks.vf_on_i_overflow {
+                    self.set_vf((self.register_i.0 > 0xFFF) as u8);
+                }
+            }
+            Instruction::Decimal(vx) => {
+                let value = self.register(vx).0;
+                let digits = [value / 100, value / 10 % 10, value % 10];
+                self.check_index_range(pc, digits.len() as u16)?;
+                for (i, digit) in digits.iter().enumerate() {
+                    let addr = self.index_address(i as u16);
+                    self.write_memory(addr, Value(*digit));
+                }
+            }
+            Instruction::StoreRegisters(vx) => {
+                self.check_index_range(pc, vx.0 as u16 + 1)?;
+                for i in 0..=vx.0 {
+                    let value = *self.register(&Register(i));
+                    let addr = self.index_address(i as u16);
+                    self.write_memory(addr, value);
+                }
+                if self.quirks.increment_i {
+                    self.register_i.0 = self.register_i.0.wrapping_add(vx.0 as u16 + 1);
+                }
+            }
+            Instruction::LoadRegisters(vx) => {
+                self.check_index_range(pc, vx.0 as u16 + 1)?;
+                for i in 0..=vx.0 {
+                    let addr = self.index_address(i as u16);
+                    *self.register(&Register(i)) = self.read_memory(addr);
+                }
+                if self.quirks.increment_i {
+                    self.register_i.0 = self.register_i.0.wrapping_add(vx.0 as u16 + 1);
+                }
+            }
+
+            // Misc
+            Instruction::Noop => (),
+            Instruction::Rand(vx, n) => {
+                let rand = self.randomizer.next_byte();
+                *self.register(vx) = Value(rand & n.0);
+            }
+            Instruction::MachineCodeRoutine(addr) => {
+                return Err(VmError::MachineCodeRoutine { pc, addr: *addr })
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key_events::KeyEvent;
+    use crate::random::SequenceRandomizer;
+    use std::time::Duration;
+
+    #[test]
+    fn test_vm_new() {
+        let vm = VirtualMachine::new(&[]);
+        assert_eq!(vm.program_counter, Address(0x200));
+        assert!(vm.stack.is_empty());
+        for r in vm.registers.iter() {
+            assert_eq!(*r, Value(0));
+        }
+        assert_eq!(vm.register_i, Address(0));
+        assert_eq!(vm.interface.lock().unwrap().delay_timer, Value(0));
+        assert_eq!(vm.interface.lock().unwrap().sound_timer, Value(0));
+        for x in vm.memory.iter().skip(FONT_OFFSET as usize).take(5 * 16) {
+            assert_ne!(*x, Value(0));
+        }
+        for x in vm.memory.iter().skip(512) {
+            assert_eq!(*x, Value(0));
+        }
+        assert_eq!(vm.interface.lock().unwrap().keys_down, [false; 16]);
+        for x in 0..SCREEN_WIDTH as usize {
+            for y in 0..SCREEN_HEIGHT as usize {
+                assert!(!vm.logical_display[x][y]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_instruction_budget() {
+        // Jump to itself forever.
+        let mut vm = VirtualMachine::new(&[0x12, 0x00]);
+        vm.set_instruction_budget(Some(3));
+        assert_eq!(vm.try_step(), Ok(StepOutcome::Halted));
+        assert_eq!(vm.try_step(), Ok(StepOutcome::Halted));
+        assert_eq!(vm.try_step(), Ok(StepOutcome::Halted));
+        assert_eq!(vm.try_step(), Err(VmError::BudgetExceeded { budget: 3 }));
+        assert_eq!(vm.executed_instructions(), 3);
+        vm.set_instruction_budget(None);
+        assert_eq!(vm.try_step(), Ok(StepOutcome::Halted));
+        assert_eq!(vm.executed_instructions(), 4);
+    }
+
+    #[test]
+    fn test_step_outcome() {
+        // 00E0: clear, 6005: V0 = 5, F00A: wait for a key, 1206: jump to itself
+        let mut vm = VirtualMachine::new(&[0x00, 0xE0, 0x60, 0x05, 0xF0, 0x0A, 0x12, 0x06]);
+        assert_eq!(vm.step(), Ok(StepOutcome::DrewFrame));
+        assert_eq!(vm.step(), Ok(StepOutcome::Continued));
+        assert_eq!(vm.step(), Ok(StepOutcome::WaitingForKey));
+        vm.interface.lock().unwrap().keys_down[3] = true;
+        assert_eq!(vm.step(), Ok(StepOutcome::Continued));
+        assert_eq!(vm.step(), Ok(StepOutcome::Halted));
+        assert_eq!(vm.program_counter, Address(0x206));
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        // 6005: V0 = 5, then the invalid opcode 5001.
+        let mut vm = VirtualMachine::new(&[0x60, 0x05, 0x50, 0x01]);
+        assert_eq!(vm.step(), Ok(StepOutcome::Continued));
+        let error = VmError::InvalidOpcode {
+            pc: Address(0x202),
+            opcode: 0x5001,
+        };
+        assert_eq!(vm.step(), Err(error));
+        assert_eq!(vm.step(), Err(error));
+        assert_eq!(vm.program_counter, Address(0x202));
+        assert_eq!(vm.executed_instructions(), 1);
+    }
+
+    #[test]
+    fn test_pc_out_of_range() {
+        // 6010: V0 = 0x10, BFFF: jump to 0xFFF + V0.
+        let mut vm = VirtualMachine::new(&[0x60, 0x10, 0xBF, 0xFF]);
+        assert_eq!(vm.step(), Ok(StepOutcome::Continued));
+        let error = VmError::PcOutOfRange {
+            pc: Address(0x202),
+            target: Address(0x100F),
+        };
+        assert_eq!(vm.step(), Err(error));
+        assert_eq!(vm.program_counter, Address(0x202));
+        assert_eq!(
+            error.to_string(),
+            "jump to 0x100F at 0x202 is past the end of memory"
+        );
+
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0xFFF);
+        let error = VmError::PcOutOfRange {
+            pc: Address(0xFFF),
+            target: Address(0xFFF),
+        };
+        assert_eq!(vm.step(), Err(error));
+        assert_eq!(
+            error.to_string(),
+            "program counter 0xFFF is past the end of memory"
+        );
+    }
+
+    #[test]
+    fn test_skip_past_memory() {
+        // 3000 at 0xFFE skips, since V0 is 0.
+        let mut vm = VirtualMachine::new(&[]);
+        vm.memory[0xFFE] = Value(0x30);
+        vm.program_counter = Address(0xFFE);
+        assert_eq!(vm.step(), Ok(StepOutcome::Continued));
+        assert_eq!(
+            vm.step(),
+            Err(VmError::PcOutOfRange {
+                pc: Address(0x1002),
+                target: Address(0x1002),
+            })
+        );
+        assert_eq!(vm.program_counter, Address(0x1002));
+    }
+
+    #[test]
+    fn test_state_summary() {
+        let mut vm = VirtualMachine::new(&[0x6A, 0xFF]);
+        vm.registers[10] = Value(0x2B);
+        vm.register_i = Address(0x123);
+        vm.interface.lock().unwrap().delay_timer = Value(60);
+        assert_eq!(
+            vm.state_summary(false),
+            "PC 0x0200  I 0x0123  SP  0  DT  60  ST   0  next 0x6AFF\n\
+             V0 00  V1 00  V2 00  V3 00  V4 00  V5 00  V6 00  V7 00\n\
+             V8 00  V9 00  VA 2B  VB 00  VC 00  VD 00  VE 00  VF 00\n"
+        );
+        assert!(vm.state_summary(true).contains("\x1b[36mVA\x1b[0m 2B"));
+    }
+
+    #[test]
+    fn test_noop() {
+        let mut vm = VirtualMachine::new(&[]);
+        let noop = Instruction::Noop;
+        assert_eq!(vm.program_counter, Address(0x200));
+        vm.execute_instruction(&noop).unwrap();
+        assert_eq!(vm.program_counter, Address(0x202));
+        vm.execute_instruction(&noop).unwrap();
+        assert_eq!(vm.program_counter, Address(0x204));
+    }
+
+    #[test]
+    fn test_subroutines() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0);
+        assert_eq!(vm.program_counter, Address(0));
+        vm.execute_instruction(&Instruction::Noop).unwrap();
+        assert_eq!(vm.program_counter, Address(2));
+        assert_eq!(vm.stack.len(), 0);
+        vm.execute_instruction(&Instruction::CallSubroutine(Address(123)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(123));
+        assert_eq!(vm.stack.len(), 1);
+        vm.execute_instruction(&Instruction::Noop).unwrap();
+        assert_eq!(vm.program_counter, Address(125));
+        vm.execute_instruction(&Instruction::CallSubroutine(Address(456)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(456));
+        assert_eq!(vm.stack.len(), 2);
+        vm.execute_instruction(&Instruction::ReturnSubroutine)
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(127));
+        assert_eq!(vm.stack.len(), 1);
+        vm.execute_instruction(&Instruction::Noop).unwrap();
+        assert_eq!(vm.program_counter, Address(129));
+        vm.execute_instruction(&Instruction::ReturnSubroutine)
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(4));
+        assert_eq!(vm.stack.len(), 0);
+    }
+
+    #[test]
+    fn test_stack_no_overflow() {
+        let mut vm = VirtualMachine::new(&[]);
+        let call = Instruction::CallSubroutine(Address(0));
+        for _ in 0..STACK_DEPTH {
+            vm.execute_instruction(&call).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut vm = VirtualMachine::new(&[]);
+        let call = Instruction::CallSubroutine(Address(0));
+        for _ in 0..STACK_DEPTH {
+            vm.execute_instruction(&call).unwrap();
+        }
+        assert_eq!(
+            vm.execute_instruction(&call),
+            Err(VmError::StackOverflow { pc: Address(0) })
+        );
+        assert_eq!(vm.program_counter, Address(0));
+    }
+
+    #[test]
+    fn test_stack_empty() {
+        let mut vm = VirtualMachine::new(&[]);
+        let call = Instruction::ReturnSubroutine;
+        assert_eq!(
+            vm.execute_instruction(&call),
+            Err(VmError::StackUnderflow { pc: Address(0x200) })
+        );
+        assert_eq!(vm.program_counter, Address(0x200));
+    }
+
+    #[test]
+    fn test_machine_code_routine() {
+        let mut vm = VirtualMachine::new(&[0x03, 0x45]);
+        let error = vm.step().unwrap_err();
+        assert_eq!(
+            error,
+            VmError::MachineCodeRoutine {
+                pc: Address(0x200),
+                addr: Address(0x345)
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "machine code routine 0x345 called at 0x200 is not supported"
+        );
+    }
+
+    #[test]
+    fn test_jumps() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0);
+        assert_eq!(vm.program_counter, Address(0));
+        vm.execute_instruction(&Instruction::Noop).unwrap();
+        assert_eq!(vm.program_counter, Address(2));
+        vm.execute_instruction(&Instruction::Jump(Address(42)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(42));
+        assert_eq!(vm.registers[0], Value(0));
+        vm.execute_instruction(&Instruction::JumpAdd(Address(100)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(100));
+        vm.registers[0] = Value(13);
+        vm.execute_instruction(&Instruction::JumpAdd(Address(100)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(113));
+        vm.execute_instruction(&Instruction::Jump(Address(50)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(50));
+    }
+
+    #[test]
+    fn test_jump_vx() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.quirks.jump_vx = true;
+        vm.registers[0] = Value(1);
+        vm.registers[3] = Value(7);
+        vm.execute_instruction(&Instruction::JumpAdd(Address(0x320)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x327));
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0);
+        vm.registers = [
+            Value(0),
+            Value(1),
+            Value(2),
+            Value(3),
+            Value(4),
+            Value(5),
+            Value(6),
+            Value(7),
+            Value(8),
+            Value(9),
+            Value(10),
+            Value(11),
+            Value(12),
+            Value(13),
+            Value(14),
+            Value(0),
+        ];
+        assert_eq!(vm.program_counter, Address(0));
+        vm.execute_instruction(&Instruction::IfEqualConst(Register(0), Value(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(2));
+        vm.execute_instruction(&Instruction::IfEqualConst(Register(1), Value(2)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(6));
+        vm.execute_instruction(&Instruction::IfNotEqualConst(Register(1), Value(1)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(10));
+        vm.execute_instruction(&Instruction::IfNotEqualConst(Register(2), Value(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(12));
+        vm.execute_instruction(&Instruction::IfEqual(Register(4), Register(4)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(14));
+        vm.execute_instruction(&Instruction::IfEqual(Register(4), Register(5)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(18));
+        vm.execute_instruction(&Instruction::IfEqual(Register(0), Register(15)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(20));
+        vm.execute_instruction(&Instruction::IfNotEqual(Register(4), Register(4)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(24));
+        vm.execute_instruction(&Instruction::IfNotEqual(Register(4), Register(5)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(26));
+        vm.execute_instruction(&Instruction::IfNotEqual(Register(0), Register(15)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(30));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0);
+        vm.registers = [
+            Value(0),
+            Value(1),
+            Value(2),
+            Value(3),
+            Value(4),
+            Value(5),
+            Value(6),
+            Value(7),
+            Value(8),
+            Value(9),
+            Value(10),
+            Value(11),
+            Value(12),
+            Value(13),
+            Value(14),
+            Value(0),
+        ];
+        assert_eq!(vm.program_counter, Address(0));
+        assert_eq!(vm.registers[0], Value(0));
+        vm.execute_instruction(&Instruction::SetConst(Register(0), Value(5)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(2));
+        assert_eq!(vm.registers[0], Value(5));
+        vm.execute_instruction(&Instruction::AddConst(Register(1), Value(2)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(4));
+        assert_eq!(vm.registers[1], Value(3));
+        vm.execute_instruction(&Instruction::Set(Register(0), Register(2)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(6));
+        assert_eq!(vm.registers[0], Value(2));
+        assert_eq!(vm.registers[2], Value(2));
+        vm.execute_instruction(&Instruction::Or(Register(4), Register(1)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(8));
+        assert_eq!(vm.registers[4], Value(7));
+        assert_eq!(vm.registers[1], Value(3));
+        vm.execute_instruction(&Instruction::And(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(10));
+        assert_eq!(vm.registers[0], Value(2));
+        assert_eq!(vm.registers[1], Value(3));
+        vm.execute_instruction(&Instruction::Xor(Register(14), Register(4)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(12));
+        assert_eq!(vm.registers[14], Value(9));
+        assert_eq!(vm.registers[4], Value(7));
+        vm.execute_instruction(&Instruction::Add(Register(6), Register(7)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(14));
+        assert_eq!(vm.registers[6], Value(13));
+        assert_eq!(vm.registers[7], Value(7));
+        vm.execute_instruction(&Instruction::Sub(Register(6), Register(5)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(16));
+        assert_eq!(vm.registers[6], Value(8));
+        assert_eq!(vm.registers[5], Value(5));
+        vm.execute_instruction(&Instruction::NegSub(Register(1), Register(4)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(18));
+        assert_eq!(vm.registers[1], Value(4));
+        assert_eq!(vm.registers[4], Value(7));
+        vm.execute_instruction(&Instruction::LeftShift(Register(0), Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(20));
+        assert_eq!(vm.registers[0], Value(4));
+        vm.execute_instruction(&Instruction::RightShift(Register(7), Register(7)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(22));
+        assert_eq!(vm.registers[7], Value(3));
+    }
+
+    #[test]
+    fn test_shift_vy() {
+        let mut vm = VirtualMachine::with_quirks(
+            &[],
+            Quirks {
+                shift_vy: true,
+                ..Quirks::default()
+            },
+        );
+        vm.registers[0] = Value(0xFF);
+        vm.registers[1] = Value(0b0110);
+        vm.execute_instruction(&Instruction::RightShift(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(0b0011));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.execute_instruction(&Instruction::LeftShift(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(0b1100));
+        assert_eq!(vm.registers[1], Value(0b0110));
+    }
+
+    #[test]
+    fn test_vblank_wait() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.quirks.vblank_wait = true;
+        vm.register_i = Address(FONT_OFFSET);
+        let draw = Instruction::Draw(Register(0), Register(0), Value(5));
+        vm.execute_instruction(&draw).unwrap();
+        assert_eq!(vm.program_counter, Address(0x202));
+        assert!(vm.pixel(0, 0));
+        // The second draw within the same frame waits for the next tick.
+        vm.execute_instruction(&draw).unwrap();
+        assert_eq!(vm.program_counter, Address(0x202));
+        assert!(vm.pixel(0, 0));
+        vm.interface.lock().unwrap().tick_timers();
+        vm.execute_instruction(&draw).unwrap();
+        assert_eq!(vm.program_counter, Address(0x204));
+        assert!(!vm.pixel(0, 0));
+    }
+
+    #[test]
+    fn test_logic_vf_reset() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers[0] = Value(0b1100);
+        vm.registers[1] = Value(0b1010);
+        vm.registers[15] = Value(7);
+        vm.execute_instruction(&Instruction::Or(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(0b1110));
+        assert_eq!(vm.registers[15], Value(7));
+
+        vm.quirks.vf_reset = true;
+        vm.execute_instruction(&Instruction::Or(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[15], Value(0));
+        vm.registers[15] = Value(7);
+        vm.execute_instruction(&Instruction::And(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(0b1010));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.registers[15] = Value(7);
+        vm.execute_instruction(&Instruction::Xor(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(0));
+        assert_eq!(vm.registers[15], Value(0));
+    }
+
+    #[test]
+    fn test_arithmetic_overflow() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0);
+        vm.registers = [
+            Value(100),
+            Value(100),
+            Value(60),
+            Value(40),
+            Value(100),
+            Value(0),
+            Value(8),
+            Value(9),
+            Value(0),
+            Value(65),
+            Value(129),
+            Value(0),
+            Value(0),
+            Value(0),
+            Value(0),
+            Value(0),
+        ];
+        assert_eq!(vm.program_counter, Address(0));
+        vm.execute_instruction(&Instruction::Add(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(2));
+        assert_eq!(vm.registers[0], Value(200));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.execute_instruction(&Instruction::Add(Register(0), Register(1)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(4));
+        assert_eq!(vm.registers[0], Value(44));
+        assert_eq!(vm.registers[15], Value(1));
+        vm.execute_instruction(&Instruction::Sub(Register(1), Register(2)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(6));
+        assert_eq!(vm.registers[1], Value(40));
+        assert_eq!(vm.registers[15], Value(1));
+        vm.execute_instruction(&Instruction::Sub(Register(1), Register(2)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(8));
+        assert_eq!(vm.registers[1], Value(236));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.execute_instruction(&Instruction::NegSub(Register(2), Register(3)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(10));
+        assert_eq!(vm.registers[2], Value(236));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.execute_instruction(&Instruction::NegSub(Register(3), Register(4)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(12));
+        assert_eq!(vm.registers[3], Value(60));
+        assert_eq!(vm.registers[15], Value(1));
+        vm.execute_instruction(&Instruction::RightShift(Register(6), Register(6)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(14));
+        assert_eq!(vm.registers[6], Value(4));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.execute_instruction(&Instruction::RightShift(Register(7), Register(7)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(16));
+        assert_eq!(vm.registers[7], Value(4));
+        assert_eq!(vm.registers[15], Value(1));
+        vm.execute_instruction(&Instruction::LeftShift(Register(9), Register(9)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(18));
+        assert_eq!(vm.registers[9], Value(130));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.execute_instruction(&Instruction::LeftShift(Register(10), Register(10)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(20));
+        assert_eq!(vm.registers[10], Value(2));
+        assert_eq!(vm.registers[15], Value(1));
+    }
+
+    #[test]
+    fn test_key_conditionals() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0);
+        assert_eq!(vm.interface.lock().unwrap().keys_down, [false; 16]);
+        vm.registers[0] = Value(0);
+
+        assert_eq!(vm.program_counter, Address(0));
+        vm.execute_instruction(&Instruction::IfKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(4));
+        vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(6));
+        vm.interface.lock().unwrap().keys_down[1] = true;
+        vm.execute_instruction(&Instruction::IfKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(10));
+        vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(12));
+        vm.registers[0] = Value(1);
+        vm.execute_instruction(&Instruction::IfKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(14));
+        vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(18));
+    }
+
+    #[test]
+    fn test_keypad() {
+        let vm = VirtualMachine::new(&[]);
+        let mut interface = vm.interface.lock().unwrap();
+        assert_eq!(interface.keypad(), 0);
+        interface.keys_down[3] = true;
+        interface.keys_down[0xF] = true;
+        assert_eq!(interface.keypad(), 0x8008);
+    }
+
+    #[test]
+    fn test_several_keys() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.interface.lock().unwrap().keys_down[3] = true;
+        vm.interface.lock().unwrap().keys_down[9] = true;
+        for (key, pressed) in [(3, true), (9, true), (4, false), (0x23, false)] {
+            vm.program_counter = Address(0x200);
+            vm.registers[0] = Value(key);
+            vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+                .unwrap();
+            let skipped = vm.program_counter == Address(0x204);
+            assert_eq!(skipped, pressed, "key {:X}", key);
+        }
+        vm.execute_instruction(&Instruction::WaitKey(Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[1], Value(3));
+    }
+
+    #[test]
+    fn test_key_events() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers[0] = Value(5);
+        // A tap that is over before the program checks the key.
+        for pressed in [true, false] {
+            vm.interface.lock().unwrap().key_events.push(KeyEvent {
+                key: 5,
+                pressed,
+                time: Duration::ZERO,
+            });
+        }
+        vm.program_counter = Address(0x200);
+        vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x204));
+        // The release was carried out once the press was seen.
+        vm.program_counter = Address(0x200);
+        vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x202));
+    }
+
+    #[test]
+    fn test_is_waiting_for_key() {
+        // V0 = 1, wait for a key into V3.
+        let mut vm = VirtualMachine::new(&[0x60, 0x01, 0xF3, 0x0A]);
+        assert!(!vm.is_waiting_for_key());
+        vm.step().unwrap();
+        assert!(vm.is_waiting_for_key());
+        vm.step().unwrap();
+        assert!(vm.is_waiting_for_key());
+        vm.interface.lock().unwrap().keys_down[2] = true;
+        assert!(!vm.is_waiting_for_key());
+    }
+
+    #[test]
+    fn test_latched_key() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers[0] = Value(3);
+        vm.interface.lock().unwrap().latched_key = Some(5);
+        // Checking another key keeps the latch.
+        vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x202));
+        vm.registers[0] = Value(5);
+        vm.execute_instruction(&Instruction::IfNotKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x206));
+        // The press was consumed.
+        vm.execute_instruction(&Instruction::IfKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x20A));
+
+        vm.interface.lock().unwrap().latched_key = Some(7);
+        assert!(!vm.is_waiting_for_key());
+        vm.execute_instruction(&Instruction::WaitKey(Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[1], Value(7));
+        assert_eq!(vm.interface.lock().unwrap().latched_key, None);
+    }
+
+    #[test]
+    fn test_key_release_wait() {
+        // Wait for a key into V0, then into V1.
+        let mut vm = VirtualMachine::new(&[0xF0, 0x0A, 0xF1, 0x0A]);
+        vm.quirks.key_release_wait = true;
+        vm.interface.lock().unwrap().keys_down[6] = true;
+        assert_eq!(vm.step(), Ok(StepOutcome::WaitingForKey));
+        assert_eq!(vm.step(), Ok(StepOutcome::WaitingForKey));
+        vm.interface.lock().unwrap().keys_down[6] = false;
+        assert_eq!(vm.step(), Ok(StepOutcome::Continued));
+        assert_eq!(vm.registers[0], Value(6));
+        // The released press does not satisfy the second wait.
+        assert_eq!(vm.step(), Ok(StepOutcome::WaitingForKey));
+
+        // A latched tap completes the wait right after it is taken.
+        vm.interface.lock().unwrap().latched_key = Some(2);
+        assert_eq!(vm.step(), Ok(StepOutcome::WaitingForKey));
+        assert_eq!(vm.step(), Ok(StepOutcome::Continued));
+        assert_eq!(vm.registers[1], Value(2));
+    }
+
+    #[test]
+    fn test_key_wait() {
+        let mut vm = VirtualMachine::new(&[]);
+        assert_eq!(vm.interface.lock().unwrap().lowest_key_down(), None);
+        assert_eq!(vm.program_counter, Address(0x200));
+        vm.execute_instruction(&Instruction::WaitKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x200));
+        vm.interface.lock().unwrap().keys_down[4] = true;
+        vm.execute_instruction(&Instruction::WaitKey(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(0x202));
+        assert_eq!(vm.registers[0], Value(4));
+    }
+
+    #[test]
+    fn test_graphics_draw_simple() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers = [
+            Value(0),
+            Value(1),
+            Value(2),
+            Value(3),
+            Value(4),
+            Value(5),
+            Value(6),
+            Value(7),
+            Value(8),
+            Value(9),
+            Value(10),
+            Value(11),
+            Value(12),
+            Value(13),
+            Value(14),
+            Value(0),
+        ];
+        vm.register_i = Address(0x200);
+
+        assert!(!vm.logical_display[0][0]);
+        vm.draw_pixel(0, 0);
+        assert!(vm.logical_display[0][0]);
+
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(1), Value(1)))
+            .unwrap();
+        assert!(!vm.logical_display[0][1]);
+        assert!(!vm.logical_display[1][1]);
+        assert!(!vm.logical_display[2][1]);
+        assert!(!vm.logical_display[3][1]);
+        assert!(!vm.logical_display[4][1]);
+        assert!(!vm.logical_display[5][1]);
+        assert!(!vm.logical_display[6][1]);
+        assert!(!vm.logical_display[7][1]);
+        assert_eq!(vm.registers[15], Value(0));
+
+        vm.memory[vm.register_i.0 as usize] = Value(0b01010101);
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(1), Value(1)))
+            .unwrap();
+        assert!(!vm.logical_display[0][1]);
+        assert!(vm.logical_display[1][1]);
+        assert!(!vm.logical_display[2][1]);
+        assert!(vm.logical_display[3][1]);
+        assert!(!vm.logical_display[4][1]);
+        assert!(vm.logical_display[5][1]);
+        assert!(!vm.logical_display[6][1]);
+        assert!(vm.logical_display[7][1]);
+        assert_eq!(vm.registers[15], Value(0));
+
+        vm.execute_instruction(&Instruction::ClearDisplay).unwrap();
+        assert!(!vm.logical_display[0][0]);
+        assert!(!vm.logical_display[0][1]);
+        assert!(!vm.logical_display[1][1]);
+        assert!(!vm.logical_display[2][1]);
+        assert!(!vm.logical_display[3][1]);
+        assert!(!vm.logical_display[4][1]);
+        assert!(!vm.logical_display[5][1]);
+        assert!(!vm.logical_display[6][1]);
+        assert!(!vm.logical_display[7][1]);
+        assert_eq!(vm.registers[15], Value(0));
+    }
+
+    #[test]
+    fn test_graphics_draw_collision() {
+        let mut vm = VirtualMachine::new(&[]);
+        assert_eq!(vm.registers[15], Value(0));
+        // Sprite 1:
+        /*
+        10101
+        01010
+        10101
+        01010
+        */
+        vm.memory[0x200] = Value(0b10101000);
+        vm.memory[0x201] = Value(0b01010000);
+        vm.memory[0x202] = Value(0b10101000);
+        vm.memory[0x203] = Value(0b01010000);
+        vm.register_i = Address(0x200);
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(0), Value(4)))
+            .unwrap();
+        assert_eq!(vm.registers[15], Value(0));
+        assert_eq!(vm.draw_collisions(), 0);
+        // Sprite 2:
+        /*
+        11111
+        10001
+        10001
+        11111
+        */
+        vm.memory[0x204] = Value(0b11111000);
+        vm.memory[0x205] = Value(0b10001000);
+        vm.memory[0x206] = Value(0b10001000);
+        vm.memory[0x207] = Value(0b11111000);
+        vm.register_i = Address(0x204);
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(0), Value(4)))
+            .unwrap();
+        assert_eq!(vm.registers[15], Value(1));
+        assert_eq!(vm.draw_collisions(), 7);
+        // Target Sprite:
+        /*
+        01010
+        11011
+        00100
+        10101
+        */
+        assert!(!vm.logical_display[0][0]);
+        assert!(vm.logical_display[1][0]);
+        assert!(!vm.logical_display[2][0]);
+        assert!(vm.logical_display[3][0]);
+        assert!(!vm.logical_display[4][0]);
+        assert!(vm.logical_display[0][1]);
+        assert!(vm.logical_display[1][1]);
+        assert!(!vm.logical_display[2][1]);
+        assert!(vm.logical_display[3][1]);
+        assert!(vm.logical_display[4][1]);
+        assert!(!vm.logical_display[0][2]);
+        assert!(!vm.logical_display[1][2]);
+        assert!(vm.logical_display[2][2]);
+        assert!(!vm.logical_display[3][2]);
+        assert!(!vm.logical_display[4][2]);
+        assert!(vm.logical_display[0][3]);
+        assert!(!vm.logical_display[1][3]);
+        assert!(vm.logical_display[2][3]);
+        assert!(!vm.logical_display[3][3]);
+        assert!(vm.logical_display[4][3]);
+    }
+
+    fn draw_corner_sprite(quirks: Quirks) -> VirtualMachine {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.quirks = quirks;
+        vm.memory[0x300] = Value(0b11000000);
+        vm.memory[0x301] = Value(0b11000000);
+        vm.register_i = Address(0x300);
+        // The start coordinate wraps in every mode.
+        vm.registers[0] = Value(SCREEN_WIDTH * 2 - 1);
+        vm.registers[1] = Value(SCREEN_HEIGHT * 3 - 1);
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(1), Value(2)))
+            .unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_graphics_wrap_both() {
+        let vm = draw_corner_sprite(Quirks::default());
+        assert!(vm.logical_display[63][31]);
+        assert!(vm.logical_display[0][31]);
+        assert!(vm.logical_display[63][0]);
+        assert!(vm.logical_display[0][0]);
+    }
+
+    #[test]
+    fn test_graphics_wrap_x_clip_y() {
+        let vm = draw_corner_sprite(Quirks {
+            wrap_x: true,
+            wrap_y: false,
+            ..Quirks::default()
+        });
+        assert!(vm.logical_display[63][31]);
+        assert!(vm.logical_display[0][31]);
+        assert!(!vm.logical_display[63][0]);
+        assert!(!vm.logical_display[0][0]);
+    }
+
+    #[test]
+    fn test_graphics_clip_x_wrap_y() {
+        let vm = draw_corner_sprite(Quirks {
+            wrap_x: false,
+            wrap_y: true,
+            ..Quirks::default()
+        });
+        assert!(vm.logical_display[63][31]);
+        assert!(!vm.logical_display[0][31]);
+        assert!(vm.logical_display[63][0]);
+        assert!(!vm.logical_display[0][0]);
+    }
+
+    #[test]
+    fn test_graphics_clip_both() {
+        let vm = draw_corner_sprite(Quirks {
+            wrap_x: false,
+            wrap_y: false,
+            ..Quirks::default()
+        });
+        assert!(vm.logical_display[63][31]);
+        assert!(!vm.logical_display[0][31]);
+        assert!(!vm.logical_display[63][0]);
+        assert!(!vm.logical_display[0][0]);
+    }
+
+    #[test]
+    fn test_display_lit_since_frame() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers[0] = Value(0);
+        vm.register_i = Address(FONT_OFFSET);
+        // Draw and erase the "0" digit within one frame.
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(0), Value(5)))
+            .unwrap();
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(0), Value(5)))
+            .unwrap();
+        assert!(!vm.pixel(0, 0));
+        let interface = vm.interface.clone();
+        assert_eq!(interface.lock().unwrap().display.get(0, 0), 255);
+        interface.lock().unwrap().display.frame();
+        assert_eq!(interface.lock().unwrap().display.get(0, 0), 0);
+    }
+
+    #[test]
+    fn test_framebuffer() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers[0] = Value(1);
+        vm.registers[1] = Value(2);
+        vm.register_i = Address(FONT_OFFSET);
+        // The top row of the "0" digit is 0xF0.
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(1), Value(1)))
+            .unwrap();
+        let framebuffer = vm.framebuffer();
+        assert_eq!(framebuffer.len(), 64 * 32);
+        let lit: Vec<_> = (0..framebuffer.len()).filter(|i| framebuffer[*i]).collect();
+        assert_eq!(lit, [2 * 64 + 1, 2 * 64 + 2, 2 * 64 + 3, 2 * 64 + 4]);
+
+        let snapshot = vm.interface.lock().unwrap().display.snapshot();
+        assert_eq!(snapshot.len(), 64 * 32);
+        assert_eq!(snapshot[2 * 64 + 1], 255);
+        assert_eq!(snapshot[2 * 64], 0);
+    }
+
+    #[test]
+    fn test_hires_mode() {
+        let vm = VirtualMachine::new(&[0x60, 0x00]);
+        assert_eq!(vm.screen_height(), SCREEN_HEIGHT);
+        assert_eq!(vm.program_counter, Address(0x200));
+
+        let mut vm = VirtualMachine::new(&[0x12, 0x60]);
+        assert_eq!(vm.screen_height(), HIRES_SCREEN_HEIGHT);
+        assert_eq!(vm.program_counter, HIRES_ENTRY_POINT);
+        let size = vm.interface.lock().unwrap().display.size();
+        assert_eq!(size, (SCREEN_WIDTH, HIRES_SCREEN_HEIGHT));
+        // Rows below 32 are on screen and only wrap at 64.
+        vm.registers[0] = Value(0);
+        vm.registers[1] = Value(40);
+        vm.registers[2] = Value(100);
+        vm.register_i = Address(FONT_OFFSET);
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(1), Value(1)))
+            .unwrap();
+        assert!(vm.pixel(0, 40));
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(2), Value(1)))
+            .unwrap();
+        assert!(vm.pixel(0, 36));
+    }
+
+    #[test]
+    fn test_graphics_sprite_addr() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.register_i = Address(0x200);
+        vm.registers[0] = Value(5);
+        vm.execute_instruction(&Instruction::SpriteAddr(Register(0)))
+            .unwrap();
+        vm.execute_instruction(&Instruction::Draw(Register(1), Register(1), Value(5)))
+            .unwrap();
+        assert!(vm.logical_display[0][0]);
+        assert!(vm.logical_display[1][0]);
+        assert!(vm.logical_display[2][0]);
+        assert!(vm.logical_display[3][0]);
+        assert!(vm.logical_display[0][1]);
+        assert!(!vm.logical_display[1][1]);
+        assert!(!vm.logical_display[2][1]);
+        assert!(!vm.logical_display[3][1]);
+        assert!(vm.logical_display[0][2]);
+        assert!(vm.logical_display[1][2]);
+        assert!(vm.logical_display[2][2]);
+        assert!(vm.logical_display[3][2]);
+        assert!(!vm.logical_display[0][3]);
+        assert!(!vm.logical_display[1][3]);
+        assert!(!vm.logical_display[2][3]);
+        assert!(vm.logical_display[3][3]);
+        assert!(vm.logical_display[0][4]);
+        assert!(vm.logical_display[1][4]);
+        assert!(vm.logical_display[2][4]);
+        assert!(vm.logical_display[3][4]);
+    }
+
+    #[test]
+    fn test_timers() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.program_counter = Address(0);
+        vm.registers[0] = Value(42);
+        assert_eq!(vm.program_counter, Address(0));
+        vm.execute_instruction(&Instruction::SetDelayTimer(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(2));
+        assert_eq!(vm.interface.lock().unwrap().delay_timer, Value(42));
+        vm.registers[0] = Value(130);
+        vm.execute_instruction(&Instruction::SetSoundTimer(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(4));
+        assert_eq!(vm.interface.lock().unwrap().sound_timer, Value(130));
+        vm.execute_instruction(&Instruction::GetDelayTimer(Register(0)))
+            .unwrap();
+        assert_eq!(vm.program_counter, Address(6));
+        assert_eq!(vm.registers[0], Value(42));
+    }
+
+    /// Records the tone changes it is told.
+    struct RecordedAudio(Arc<Mutex<Vec<bool>>>);
+
+    impl Audio for RecordedAudio {
+        fn set_tone(&mut self, on: bool) {
+            self.0.lock().unwrap().push(on);
+        }
+    }
+
+    #[test]
+    fn test_audio() {
+        let mut vm = VirtualMachine::new(&[]);
+        let tones = Arc::new(Mutex::new(Vec::new()));
+        vm.interface.lock().unwrap().audio = Box::new(RecordedAudio(tones.clone()));
+        vm.program_counter = Address(0);
+        vm.registers[0] = Value(2);
+        vm.execute_instruction(&Instruction::SetSoundTimer(Register(0)))
+            .unwrap();
+        assert_eq!(*tones.lock().unwrap(), [true]);
+        for _ in 0..3 {
+            vm.interface.lock().unwrap().tick_timers();
+        }
+        assert_eq!(*tones.lock().unwrap(), [true, false]);
+    }
+
+    #[test]
+    fn test_audio_muted() {
+        let vm = VirtualMachine::new(&[]);
+        let tones = Arc::new(Mutex::new(Vec::new()));
+        let mut interface = vm.interface.lock().unwrap();
+        interface.audio = Box::new(RecordedAudio(tones.clone()));
+        interface.set_sound_timer(Value(5));
+        interface.set_muted(true);
+        interface.tick_timers();
+        assert_eq!(*tones.lock().unwrap(), [true, false]);
+        interface.set_muted(false);
+        assert_eq!(*tones.lock().unwrap(), [true, false, true]);
+
+        let replaced = Arc::new(Mutex::new(Vec::new()));
+        interface.set_audio(Box::new(RecordedAudio(replaced.clone())));
+        assert_eq!(*replaced.lock().unwrap(), [true]);
+        interface.set_audio(Box::new(RecordedAudio(tones.clone())));
+
+        // A new VM takes over the audio, which stops the old tone.
+        let other = VirtualMachine::new(&[]);
+        other.interface.lock().unwrap().take_audio(&mut interface);
+        assert_eq!(*tones.lock().unwrap(), [true, false, true, true, false]);
+        drop(interface);
+        vm.interface.lock().unwrap().set_sound_timer(Value(5));
+        other.interface.lock().unwrap().set_sound_timer(Value(5));
+        assert_eq!(
+            *tones.lock().unwrap(),
+            [true, false, true, true, false, true]
+        );
+    }
+
+    #[test]
+    fn test_i_register() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers = [
+            Value(0),
+            Value(1),
+            Value(11),
+            Value(0),
+            Value(213),
+            Value(0),
+            Value(0),
+            Value(50),
+            Value(43),
+            Value(212),
+            Value(0),
+            Value(0),
+            Value(0),
+            Value(0),
+            Value(0),
+            Value(0),
+        ];
+
+        assert_eq!(vm.register_i, Address(0));
+        vm.execute_instruction(&Instruction::SetI(Address(1247)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(1247));
+        vm.execute_instruction(&Instruction::AddToI(Register(2)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(1258));
+
+        vm.memory[1263] = Value(99);
+        vm.execute_instruction(&Instruction::StoreRegisters(Register(4)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(1258));
+        assert_eq!(vm.memory[1258], Value(0));
+        assert_eq!(vm.memory[1259], Value(1));
+        assert_eq!(vm.memory[1260], Value(11));
+        assert_eq!(vm.memory[1261], Value(0));
+        assert_eq!(vm.memory[1262], Value(213));
+        assert_eq!(vm.memory[1263], Value(99));
+
+        vm.execute_instruction(&Instruction::Decimal(Register(4)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(1258));
+        assert_eq!(vm.memory[1258], Value(2));
+        assert_eq!(vm.memory[1259], Value(1));
+        assert_eq!(vm.memory[1260], Value(3));
+
+        vm.memory[1261] = Value(4);
+        vm.memory[1262] = Value(5);
+        vm.execute_instruction(&Instruction::LoadRegisters(Register(3)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(2));
+        assert_eq!(vm.registers[1], Value(1));
+        assert_eq!(vm.registers[2], Value(3));
+        assert_eq!(vm.registers[3], Value(4));
+        assert_eq!(vm.registers[4], Value(213));
+    }
+
+    #[test]
+    fn test_increment_i() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.quirks.increment_i = true;
+        vm.registers[0] = Value(1);
+        vm.registers[1] = Value(2);
+        vm.register_i = Address(0x300);
+        vm.execute_instruction(&Instruction::StoreRegisters(Register(1)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(0x302));
+        assert_eq!(vm.memory[0x300], Value(1));
+        assert_eq!(vm.memory[0x301], Value(2));
+
+        vm.register_i = Address(0x300);
+        vm.execute_instruction(&Instruction::LoadRegisters(Register(2)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(0x303));
+        assert_eq!(vm.registers[0], Value(1));
+        assert_eq!(vm.registers[1], Value(2));
+    }
+
+    #[test]
+    fn test_memory_overflow_wrap() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.registers[0] = Value(1);
+        vm.registers[1] = Value(2);
+        vm.registers[2] = Value(255);
+        vm.register_i = Address(0xFFF);
+        vm.execute_instruction(&Instruction::StoreRegisters(Register(1)))
+            .unwrap();
+        assert_eq!(vm.memory[0xFFF], Value(1));
+        assert_eq!(vm.memory[0x000], Value(2));
+
+        vm.execute_instruction(&Instruction::Decimal(Register(2)))
+            .unwrap();
+        assert_eq!(vm.memory[0xFFF], Value(2));
+        assert_eq!(vm.memory[0x000], Value(5));
+        assert_eq!(vm.memory[0x001], Value(5));
+
+        vm.execute_instruction(&Instruction::LoadRegisters(Register(1)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(2));
+        assert_eq!(vm.registers[1], Value(5));
+
+        vm.register_i = Address(0xFFFF);
+        vm.execute_instruction(&Instruction::AddToI(Register(1)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(4));
+    }
+
+    #[test]
+    fn test_memory_overflow_error() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.quirks.memory_overflow = MemoryOverflow::Error;
+        let font = vm.memory[0x000];
+        vm.registers[0] = Value(7);
+        vm.register_i = Address(0xFFF);
+        vm.execute_instruction(&Instruction::StoreRegisters(Register(0)))
+            .unwrap();
+        assert_eq!(vm.memory[0xFFF], Value(7));
+
+        let error = VmError::OutOfBounds {
+            pc: Address(0x202),
+            i: Address(0xFFF),
+            len: 2,
+        };
+        assert_eq!(
+            vm.execute_instruction(&Instruction::StoreRegisters(Register(1))),
+            Err(error)
+        );
+        assert_eq!(vm.memory[0x000], font);
+        assert_eq!(vm.program_counter, Address(0x202));
+        assert_eq!(
+            error.to_string(),
+            "access to 2 byte(s) at I = 0xFFF past the end of memory at 0x202"
+        );
+
+        vm.register_i = Address(0xFFE);
+        assert!(vm
+            .execute_instruction(&Instruction::Decimal(Register(0)))
+            .is_err());
+        assert!(vm
+            .execute_instruction(&Instruction::Draw(Register(0), Register(0), Value(3)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_memory_overflow_saturate() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.quirks.memory_overflow = MemoryOverflow::Saturate;
+        vm.registers[0] = Value(1);
+        vm.registers[1] = Value(2);
+        vm.registers[2] = Value(3);
+        let font = vm.memory[0x000];
+        vm.register_i = Address(0xFFE);
+        vm.execute_instruction(&Instruction::StoreRegisters(Register(2)))
+            .unwrap();
+        assert_eq!(vm.memory[0xFFE], Value(1));
+        assert_eq!(vm.memory[0xFFF], Value(3));
+        assert_eq!(vm.memory[0x000], font);
+    }
+
+    #[test]
+    fn test_vf_on_i_overflow() {
+        let mut vm = VirtualMachine::new(&[]);
+        vm.quirks.vf_on_i_overflow = true;
+        vm.registers[0] = Value(0x10);
+        vm.register_i = Address(0xFE0);
+        vm.execute_instruction(&Instruction::AddToI(Register(0)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(0xFF0));
+        assert_eq!(vm.registers[15], Value(0));
+        vm.execute_instruction(&Instruction::AddToI(Register(0)))
+            .unwrap();
+        assert_eq!(vm.register_i, Address(0x1000));
+        assert_eq!(vm.registers[15], Value(1));
+    }
+
+    struct RecordingDevice {
+        writes: Arc<Mutex<Vec<(u16, u8)>>>,
+    }
+
+    impl Peripheral for RecordingDevice {
+        fn read(&mut self, offset: u16) -> u8 {
+            offset as u8 + 100
+        }
+
+        fn write(&mut self, offset: u16, value: u8) {
+            self.writes.lock().unwrap().push((offset, value));
+        }
+    }
+
+    #[test]
+    fn test_memory_mapped_device() {
+        let mut vm = VirtualMachine::new(&[]);
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        vm.map_device(
+            Address(0x300),
+            2,
+            Box::new(RecordingDevice {
+                writes: writes.clone(),
+            }),
+        );
+        vm.registers[0] = Value(1);
+        vm.registers[1] = Value(2);
+        vm.registers[2] = Value(3);
+        vm.register_i = Address(0x2FF);
+        vm.execute_instruction(&Instruction::StoreRegisters(Register(2)))
+            .unwrap();
+        assert_eq!(vm.memory[0x2FF], Value(1));
+        assert_eq!(*writes.lock().unwrap(), vec![(0, 2), (1, 3)]);
+        assert_eq!(vm.memory[0x300], Value(0));
+        assert_eq!(vm.memory[0x301], Value(0));
+
+        vm.execute_instruction(&Instruction::LoadRegisters(Register(3)))
+            .unwrap();
+        assert_eq!(vm.registers[0], Value(1));
+        assert_eq!(vm.registers[1], Value(100));
+        assert_eq!(vm.registers[2], Value(101));
+        assert_eq!(vm.registers[3], Value(0));
+
+        vm.register_i = Address(0x301);
+        vm.registers[4] = Value(123);
+        vm.execute_instruction(&Instruction::Decimal(Register(4)))
+            .unwrap();
+        assert_eq!(writes.lock().unwrap()[2], (1, 1));
+        assert_eq!(vm.memory[0x302], Value(2));
+        assert_eq!(vm.memory[0x303], Value(3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_memory_mapped_device_overlap() {
+        let mut vm = VirtualMachine::new(&[]);
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        vm.map_device(
+            Address(0x300),
+            4,
+            Box::new(RecordingDevice {
+                writes: writes.clone(),
+            }),
+        );
+        vm.map_device(Address(0x2FE), 3, Box::new(RecordingDevice { writes }));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_memory_mapped_device_past_end() {
+        let mut vm = VirtualMachine::new(&[]);
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        vm.map_device(Address(0xFFE), 3, Box::new(RecordingDevice { writes }));
+    }
+
+    #[test]
+    fn test_memory_mapped_device_at_end() {
+        let mut vm = VirtualMachine::new(&[]);
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        vm.map_device(
+            Address(0xFFE),
+            2,
+            Box::new(RecordingDevice {
+                writes: writes.clone(),
+            }),
+        );
+        vm.map_device(Address(0xF00), 0xFE, Box::new(RecordingDevice { writes }));
+    }
+
+    #[test]
+    fn test_draw_from_memory_mapped_device() {
+        let mut vm = VirtualMachine::new(&[]);
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        vm.map_device(Address(0x300), 1, Box::new(RecordingDevice { writes }));
+        vm.register_i = Address(0x300);
+        vm.execute_instruction(&Instruction::Draw(Register(0), Register(1), Value(1)))
+            .unwrap();
+        // The device returns 100 = 0b01100100 for offset 0.
+        let row: Vec<_> = (0..8).map(|x| vm.pixel(x, 0)).collect();
+        assert_eq!(
+            row,
+            vec![false, true, true, false, false, true, false, false]
+        );
+        assert_eq!(vm.sprite_rows(1), vec![0]);
+    }
+
+    #[test]
+    fn test_seed() {
+        // V0 to V3 = random bytes.
+        let program = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF];
+        let run = |seed| {
+            let mut vm = VirtualMachine::new(&program);
+            vm.set_seed(seed);
+            for _ in 0..4 {
+                vm.step().unwrap();
+            }
+            (0..4)
+                .map(|i| vm.get_register(Register(i)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+
+        let mut vm = VirtualMachine::new(&program);
+        vm.set_seed(7);
+        assert_eq!(vm.seed(), Some(7));
+    }
+
+    #[test]
+    fn test_rand() {
+        // V0 = rand & 0x0F, V1 = rand & 0xF0, V2 = rand.
+        let program = [0xC0, 0x0F, 0xC1, 0xF0, 0xC2, 0xFF];
+        let mut vm = VirtualMachine::new(&program);
+        vm.set_randomizer(Box::new(SequenceRandomizer::new(&[0xAB, 0xFF])));
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.get_register(Register(0)), Value(0x0B));
+        assert_eq!(vm.get_register(Register(1)), Value(0xF0));
+        assert_eq!(vm.get_register(Register(2)), Value(0xAB));
+        assert_eq!(vm.seed(), None);
+    }
+}
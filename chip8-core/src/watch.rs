@@ -0,0 +1,87 @@
+use super::basics::Address;
+use super::core::Chip8Core;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchWidth {
+    Byte,
+    /// Two bytes in big-endian order, like CHIP-8 instructions.
+    Word,
+}
+
+impl WatchWidth {
+    /// The number of bytes of a watched value.
+    pub fn bytes(self) -> usize {
+        match self {
+            WatchWidth::Byte => 1,
+            WatchWidth::Word => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchFormat {
+    Decimal,
+    Hex,
+}
+
+/// A named location in memory whose value is of interest while a ROM runs,
+/// e.g. the score of a game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watch {
+    pub name: String,
+    pub address: Address,
+    pub width: WatchWidth,
+    pub format: WatchFormat,
+}
+
+impl Watch {
+    pub fn new(name: &str, address: Address, width: WatchWidth, format: WatchFormat) -> Watch {
+        Watch {
+            name: name.to_string(),
+            address,
+            width,
+            format,
+        }
+    }
+
+    /// Reads the current value of the watched location.
+    pub fn read(&self, core: &dyn Chip8Core) -> u16 {
+        let high = core.memory(self.address).0 as u16;
+        match self.width {
+            WatchWidth::Byte => high,
+            WatchWidth::Word => high << 8 | core.memory(Address(self.address.0 + 1)).0 as u16,
+        }
+    }
+
+    /// Formats a value read from this watch, e.g. `score = 0x0A`.
+    pub fn format(&self, value: u16) -> String {
+        match (self.format, self.width) {
+            (WatchFormat::Decimal, _) => format!("{} = {}", self.name, value),
+            (WatchFormat::Hex, WatchWidth::Byte) => format!("{} = {:#04X}", self.name, value),
+            (WatchFormat::Hex, WatchWidth::Word) => format!("{} = {:#06X}", self.name, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::VirtualMachine;
+
+    #[test]
+    fn test_watch() {
+        let vm = VirtualMachine::new(&[0x12, 0x34]);
+        let byte = Watch::new("a", Address(0x200), WatchWidth::Byte, WatchFormat::Decimal);
+        assert_eq!(byte.read(&vm), 0x12);
+        assert_eq!(byte.format(byte.read(&vm)), "a = 18");
+        let word = Watch::new("b", Address(0x200), WatchWidth::Word, WatchFormat::Hex);
+        assert_eq!(word.read(&vm), 0x1234);
+        assert_eq!(word.format(word.read(&vm)), "b = 0x1234");
+        let hex = Watch::new("c", Address(0x201), WatchWidth::Byte, WatchFormat::Hex);
+        assert_eq!(hex.format(hex.read(&vm)), "c = 0x34");
+        assert_eq!((WatchWidth::Byte.bytes(), WatchWidth::Word.bytes()), (1, 2));
+    }
+}
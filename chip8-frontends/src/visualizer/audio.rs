@@ -0,0 +1,64 @@
+use chip8_core::vm::Audio;
+use sfml::audio::{Sound, SoundBuffer, SoundStatus};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// The audio of the VM in the visualizer. SFML sounds cannot be sent to the
+/// thread of the VM, so this forwards the tone to the window, where an
+/// `SfmlAudio` plays it.
+pub struct ToneSender(Sender<bool>);
+
+impl Audio for ToneSender {
+    fn set_tone(&mut self, on: bool) {
+        // Without a receiver, the window is closed; the VM keeps running.
+        let _ = self.0.send(on);
+    }
+}
+
+/// Creates a connected sender and the receiver of its tones.
+pub fn tone_channel() -> (ToneSender, Receiver<bool>) {
+    let (sender, receiver) = mpsc::channel();
+    (ToneSender(sender), receiver)
+}
+
+/// Plays the beep through SFML, as told by a `ToneSender`.
+pub struct SfmlAudio<'a> {
+    sound: Sound<'a>,
+    tone: bool,
+    /// Keep every beep going for at least this many frames.
+    min_beep_frames: u32,
+    beep_frames_left: u32,
+}
+
+impl<'a> SfmlAudio<'a> {
+    pub fn new(buffer: &'a SoundBuffer, min_beep_frames: u32) -> SfmlAudio<'a> {
+        let mut sound = Sound::with_buffer(buffer);
+        sound.set_looping(true);
+        SfmlAudio {
+            sound,
+            tone: false,
+            min_beep_frames,
+            beep_frames_left: 0,
+        }
+    }
+
+    /// Called once per frame of the window, to start or stop the sound.
+    pub fn frame(&mut self, paused: bool) {
+        if !paused && (self.tone || self.beep_frames_left > 0) {
+            if self.sound.status() != SoundStatus::Playing {
+                self.sound.play();
+            }
+        } else {
+            self.sound.stop();
+        }
+        self.beep_frames_left = self.beep_frames_left.saturating_sub(1);
+    }
+}
+
+impl Audio for SfmlAudio<'_> {
+    fn set_tone(&mut self, on: bool) {
+        if on && !self.tone {
+            self.beep_frames_left = self.min_beep_frames;
+        }
+        self.tone = on;
+    }
+}
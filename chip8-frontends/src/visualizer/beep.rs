@@ -0,0 +1,94 @@
+use std::f32::consts::PI;
+
+/// The sample rate of the beep in Hz. The beep is a loop of one second.
+pub const SAMPLE_RATE: u32 = 44_100;
+/// The loudest sample, an eighth of the full range, since a square wave is
+/// loud at the same amplitude.
+const AMPLITUDE: f32 = i16::MAX as f32 / 8.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    /// The sound of the COSMAC VIP's beeper.
+    Square,
+    /// A softer tone.
+    Sine,
+}
+
+impl Waveform {
+    /// Parses "square" or "sine".
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+}
+
+/// The tone played while the sound timer is active, synthesized instead of
+/// loaded from a file.
+#[derive(Clone, Copy, Debug)]
+pub struct Beep {
+    /// In Hz.
+    pub frequency: u32,
+    pub waveform: Waveform,
+}
+
+impl Default for Beep {
+    fn default() -> Beep {
+        Beep {
+            frequency: 440,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+impl Beep {
+    /// One second of mono samples at `SAMPLE_RATE`. It holds a whole number
+    /// of periods, so that it loops without a click.
+    pub fn samples(&self) -> Vec<i16> {
+        (0..SAMPLE_RATE as u64)
+            .map(|i| {
+                let phase =
+                    (i * self.frequency as u64 % SAMPLE_RATE as u64) as f32 / SAMPLE_RATE as f32;
+                let level = match self.waveform {
+                    Waveform::Square if phase < 0.5 => 1.0,
+                    Waveform::Square => -1.0,
+                    Waveform::Sine => (2.0 * PI * phase).sin(),
+                };
+                (level * AMPLITUDE) as i16
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples() {
+        let beep = Beep {
+            frequency: 441,
+            waveform: Waveform::Square,
+        };
+        let samples = beep.samples();
+        assert_eq!(samples.len(), SAMPLE_RATE as usize);
+        // 441 Hz is a period of 100 samples, high for the first half.
+        assert!(samples[..50].iter().all(|sample| *sample > 0));
+        assert!(samples[50..100].iter().all(|sample| *sample < 0));
+        assert_eq!(samples[0], samples[100]);
+        // The loop ends right before a new period.
+        assert!(samples[SAMPLE_RATE as usize - 1] < 0);
+
+        let sine = Beep {
+            waveform: Waveform::Sine,
+            ..beep
+        };
+        let samples = sine.samples();
+        assert_eq!(samples[0], 0);
+        assert_eq!(samples[25], AMPLITUDE as i16);
+        assert_eq!(Waveform::from_name("sine"), Some(Waveform::Sine));
+        assert_eq!(Waveform::from_name("noise"), None);
+    }
+}
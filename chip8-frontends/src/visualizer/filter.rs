@@ -0,0 +1,215 @@
+use chip8_core::basics::{HIRES_SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip8_core::pgm::Rgb;
+use sfml::graphics::Color;
+
+/// The colors of all pixels of one frame, indexed by x, then y. Rows below
+/// the visible height stay transparent.
+pub type Frame = [[Color; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+
+/// A frame of the visible `width` x `height` pixels, white where `lit` and
+/// transparent elsewhere, as the filters receive it.
+pub fn lit_frame(width: u8, height: u8, lit: impl Fn(u8, u8) -> bool) -> Frame {
+    let mut frame = [[Color::TRANSPARENT; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+    for x in 0..width {
+        for y in 0..height {
+            if lit(x, y) {
+                frame[x as usize][y as usize] = Color::WHITE;
+            }
+        }
+    }
+    frame
+}
+
+/// Averages every `factor` x `factor` block of the visible part of a frame,
+/// drawn over the background color, into one color. This is the thumbnail of
+/// what the window shows, like `Chip8Core::thumbnail` is of the logical
+/// display.
+pub fn frame_thumbnail(
+    frame: &Frame,
+    width: u8,
+    height: u8,
+    background: Color,
+    factor: u8,
+) -> Vec<Vec<Rgb>> {
+    assert!(factor > 0);
+    let blend = |color: Color, channel: fn(Color) -> u8| {
+        let alpha = color.a as u32;
+        (channel(color) as u32 * alpha + channel(background) as u32 * (255 - alpha)) / 255
+    };
+    let channels: [fn(Color) -> u8; 3] = [|c| c.r, |c| c.g, |c| c.b];
+    let block = factor as u32 * factor as u32;
+    (0..(width / factor) as usize)
+        .map(|x| {
+            (0..(height / factor) as usize)
+                .map(|y| {
+                    let pixels = (0..factor as usize).flat_map(|dx| {
+                        (0..factor as usize).map(move |dy| {
+                            frame[x * factor as usize + dx][y * factor as usize + dy]
+                        })
+                    });
+                    let mut sum = [0; 3];
+                    for color in pixels {
+                        for (total, channel) in sum.iter_mut().zip(channels) {
+                            *total += blend(color, channel);
+                        }
+                    }
+                    sum.map(|total| (total / block) as u8)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// One stage of post-processing between the VM's display and the window.
+/// Filters are applied once per frame, in order, and may keep state across
+/// frames.
+pub trait FrameFilter: Send {
+    fn apply(&mut self, frame: &mut Frame);
+}
+
+/// A sequence of filters that are applied one after another.
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn FrameFilter>>,
+}
+
+impl FilterChain {
+    pub fn push(&mut self, filter: Box<dyn FrameFilter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn apply(&mut self, frame: &mut Frame) {
+        for filter in self.filters.iter_mut() {
+            filter.apply(frame);
+        }
+    }
+}
+
+/// Lets pixels fade out over a number of frames after they were turned off.
+pub struct Fade {
+    duration: u32,
+    remaining: [[u32; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+    colors: Frame,
+}
+
+impl Fade {
+    pub fn new(duration: u32) -> Fade {
+        Fade {
+            duration,
+            remaining: [[0; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+            colors: [[Color::TRANSPARENT; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+        }
+    }
+}
+
+impl FrameFilter for Fade {
+    fn apply(&mut self, frame: &mut Frame) {
+        if self.duration == 0 {
+            return;
+        }
+        let states = self
+            .remaining
+            .iter_mut()
+            .flatten()
+            .zip(self.colors.iter_mut().flatten());
+        for (pixel, (remaining, color)) in frame.iter_mut().flatten().zip(states) {
+            if pixel.a > 0 {
+                *remaining = self.duration;
+                *color = *pixel;
+            } else if *remaining > 0 {
+                *remaining -= 1;
+                let alpha = color.a as u32 * *remaining / self.duration;
+                *pixel = Color {
+                    a: alpha as u8,
+                    ..*color
+                };
+            }
+        }
+    }
+}
+
+/// Draws all pixels in one color, keeping their transparency.
+pub struct Tint(pub Color);
+
+impl FrameFilter for Tint {
+    fn apply(&mut self, frame: &mut Frame) {
+        for pixel in frame.iter_mut().flatten() {
+            *pixel = Color {
+                a: pixel.a,
+                ..self.0
+            };
+        }
+    }
+}
+
+/// Draws all pixels in a color that slowly cycles through all hues.
+pub struct PaletteCycle {
+    period: u32,
+    frame_count: u32,
+}
+
+impl PaletteCycle {
+    /// Creates a palette cycle that runs through all hues in `period` frames.
+    pub fn new(period: u32) -> PaletteCycle {
+        PaletteCycle {
+            period,
+            frame_count: 0,
+        }
+    }
+}
+
+impl FrameFilter for PaletteCycle {
+    fn apply(&mut self, frame: &mut Frame) {
+        self.frame_count = (self.frame_count + 1) % self.period;
+        let hue = self.frame_count as f32 * 360.0 / self.period as f32;
+        Tint(hue_to_color(hue)).apply(frame);
+    }
+}
+
+/// Converts a hue in degrees with full saturation and value to a color.
+fn hue_to_color(hue: f32) -> Color {
+    let sector = (hue / 60.0) % 6.0;
+    let rising = (255.0 * sector.fract()) as u8;
+    let falling = 255 - rising;
+    match sector as u32 {
+        0 => Color::rgb(255, rising, 0),
+        1 => Color::rgb(falling, 255, 0),
+        2 => Color::rgb(0, 255, rising),
+        3 => Color::rgb(0, falling, 255),
+        4 => Color::rgb(rising, 0, 255),
+        _ => Color::rgb(255, 0, falling),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade_and_tint() {
+        let mut chain = FilterChain::default();
+        chain.push(Box::new(Fade::new(2)));
+        chain.push(Box::new(Tint(Color::RED)));
+        let mut frame = [[Color::TRANSPARENT; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+        frame[3][4] = Color::WHITE;
+        chain.apply(&mut frame);
+        assert_eq!(frame[3][4], Color::RED);
+        assert_eq!(frame[0][0], Color::rgba(255, 0, 0, 0));
+
+        let mut frame = [[Color::TRANSPARENT; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+        chain.apply(&mut frame);
+        assert_eq!(frame[3][4], Color::rgba(255, 0, 0, 127));
+        let mut frame = [[Color::TRANSPARENT; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+        chain.apply(&mut frame);
+        assert_eq!(frame[3][4], Color::rgba(255, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_frame_thumbnail() {
+        let mut frame = lit_frame(4, 2, |x, y| x == 0 || (x, y) == (2, 0));
+        Tint(Color::RED).apply(&mut frame);
+        frame[3][1] = Color::rgba(0, 0, 255, 51);
+        let thumbnail = frame_thumbnail(&frame, 4, 2, Color::rgb(0, 100, 0), 2);
+        assert_eq!(thumbnail, [vec![[127, 50, 0]], vec![[63, 70, 12]]]);
+    }
+}
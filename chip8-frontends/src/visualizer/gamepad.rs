@@ -0,0 +1,131 @@
+use sfml::window::joystick::{Axis, BUTTON_COUNT};
+use std::collections::{HashMap, HashSet};
+
+/// How far a stick or the D-pad must be pushed to count as a direction, out
+/// of 100.
+const AXIS_THRESHOLD: f32 = 50.0;
+
+/// A button or direction of a gamepad. The directions are given by both the
+/// D-pad and the left stick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadInput {
+    Button(u32),
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl GamepadInput {
+    /// Parses "up", "down", "left", "right" or "button0" to "button31".
+    pub fn from_name(name: &str) -> Option<GamepadInput> {
+        match name.to_lowercase().as_str() {
+            "up" => Some(GamepadInput::Up),
+            "down" => Some(GamepadInput::Down),
+            "left" => Some(GamepadInput::Left),
+            "right" => Some(GamepadInput::Right),
+            name => name
+                .strip_prefix("button")
+                .and_then(|button| button.parse().ok())
+                .filter(|button| *button < BUTTON_COUNT)
+                .map(GamepadInput::Button),
+        }
+    }
+}
+
+/// The inputs held on all connected gamepads, which SFML reports as joystick
+/// events. Every gamepad uses the same mapping to CHIP-8 keys.
+#[derive(Default)]
+pub struct Gamepads {
+    mapping: HashMap<GamepadInput, u8>,
+    /// The inputs held, with the index of the gamepad.
+    held: HashSet<(u32, GamepadInput)>,
+}
+
+impl Gamepads {
+    /// Maps the inputs to CHIP-8 keys, as given by the ROM config.
+    pub fn new(mapping: &HashMap<u8, GamepadInput>) -> Gamepads {
+        Gamepads {
+            mapping: mapping.iter().map(|(key, input)| (*input, *key)).collect(),
+            held: HashSet::new(),
+        }
+    }
+
+    pub fn button(&mut self, gamepad: u32, button: u32, pressed: bool) {
+        self.set(gamepad, GamepadInput::Button(button), pressed);
+    }
+
+    /// Handles the movement of a stick or the D-pad, to a position between
+    /// -100 and 100. Other axes, e.g. of the triggers, are ignored.
+    pub fn axis_moved(&mut self, gamepad: u32, axis: Axis, position: f32) {
+        let (negative, positive) = match axis {
+            Axis::X | Axis::PovX => (GamepadInput::Left, GamepadInput::Right),
+            Axis::Y | Axis::PovY => (GamepadInput::Up, GamepadInput::Down),
+            _ => return,
+        };
+        self.set(gamepad, negative, position <= -AXIS_THRESHOLD);
+        self.set(gamepad, positive, position >= AXIS_THRESHOLD);
+    }
+
+    /// Releases everything held on a gamepad that was disconnected.
+    pub fn disconnected(&mut self, gamepad: u32) {
+        self.held.retain(|(index, _)| *index != gamepad);
+    }
+
+    fn set(&mut self, gamepad: u32, input: GamepadInput, held: bool) {
+        if held {
+            self.held.insert((gamepad, input));
+        } else {
+            self.held.remove(&(gamepad, input));
+        }
+    }
+
+    /// The CHIP-8 keys held down on any gamepad.
+    pub fn keys_down(&self) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (_, input) in self.held.iter() {
+            if let Some(key) = self.mapping.get(input) {
+                keys[*key as usize] = true;
+            }
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(GamepadInput::from_name("Up"), Some(GamepadInput::Up));
+        assert_eq!(
+            GamepadInput::from_name("button3"),
+            Some(GamepadInput::Button(3))
+        );
+        assert_eq!(GamepadInput::from_name("button32"), None);
+        assert_eq!(GamepadInput::from_name("trigger"), None);
+    }
+
+    #[test]
+    fn test_keys_down() {
+        let mapping = [
+            (1, GamepadInput::Up),
+            (4, GamepadInput::Down),
+            (5, GamepadInput::Button(0)),
+        ];
+        let mut gamepads = Gamepads::new(&mapping.iter().copied().collect());
+        gamepads.axis_moved(0, Axis::PovY, -100.0);
+        gamepads.button(1, 0, true);
+        let keys = gamepads.keys_down();
+        assert!(keys[1] && keys[5] && !keys[4]);
+
+        // Back to the center releases the direction, the other gamepad
+        // still holds its button.
+        gamepads.axis_moved(0, Axis::PovY, 10.0);
+        gamepads.axis_moved(1, Axis::Z, 100.0);
+        assert_eq!(gamepads.keys_down().iter().filter(|k| **k).count(), 1);
+        gamepads.disconnected(1);
+        assert_eq!(gamepads.keys_down(), [false; 16]);
+    }
+}
@@ -0,0 +1,129 @@
+/// A recorded sequence of CHIP-8 keys, one entry per frame, that can be
+/// replayed with a hotkey.
+#[derive(Default)]
+pub struct InputMacro {
+    /// The keys held down in each frame, indexed by key.
+    frames: Vec<[bool; 16]>,
+    recording: bool,
+    /// The next frame to replay, if the macro is playing.
+    playing: Option<usize>,
+}
+
+impl InputMacro {
+    /// Starts recording a new macro, replacing the old one, or stops the
+    /// current recording. Returns whether a recording is running now.
+    pub fn toggle_recording(&mut self) -> bool {
+        if !self.recording {
+            self.frames.clear();
+            self.playing = None;
+        }
+        self.recording = !self.recording;
+        self.recording
+    }
+
+    /// Replays the recorded macro from the beginning, unless it is being
+    /// recorded right now.
+    pub fn play(&mut self) {
+        if !self.recording {
+            self.playing = Some(0);
+        }
+    }
+
+    /// The keys to press in this frame of the replay, advancing it by a
+    /// frame. No keys if the macro is not playing.
+    pub fn play_frame(&mut self) -> [bool; 16] {
+        let frame = match self.playing {
+            Some(frame) => frame,
+            None => return [false; 16],
+        };
+        match self.frames.get(frame) {
+            Some(keys) => {
+                self.playing = Some(frame + 1);
+                *keys
+            }
+            None => {
+                self.playing = None;
+                [false; 16]
+            }
+        }
+    }
+
+    /// Records the keys the VM sees in this frame, if recording.
+    pub fn record_frame(&mut self, keys_down: [bool; 16]) {
+        if self.recording {
+            self.frames.push(keys_down);
+        }
+    }
+
+    /// The length of the macro in frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The key presses of the macro as `startup` entries for `roms.toml`,
+    /// ordered by their first frame.
+    pub fn to_startup_entries(&self) -> String {
+        let mut presses = Vec::new();
+        for key in 0..16 {
+            let mut start = 0;
+            while start < self.frames.len() {
+                let held = self.frames[start][key];
+                let duration = self.frames[start..]
+                    .iter()
+                    .take_while(|keys| keys[key] == held)
+                    .count();
+                if held {
+                    presses.push((start, key, duration));
+                }
+                start += duration;
+            }
+        }
+        presses.sort_unstable();
+        let entries: Vec<_> = presses
+            .iter()
+            .map(|(start, key, duration)| {
+                format!(
+                    "{{ frame = {}, key = {}, duration = {} }}",
+                    start, key, duration
+                )
+            })
+            .collect();
+        format!("startup = [{}]", entries.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_play() {
+        let mut input_macro = InputMacro::default();
+        let keys = |down: &[usize]| {
+            let mut keys = [false; 16];
+            for key in down {
+                keys[*key] = true;
+            }
+            keys
+        };
+        let frames = [keys(&[]), keys(&[4]), keys(&[4, 6]), keys(&[]), keys(&[6])];
+        assert!(input_macro.toggle_recording());
+        for frame in frames {
+            input_macro.record_frame(frame);
+        }
+        assert!(!input_macro.toggle_recording());
+        assert_eq!(input_macro.len(), 5);
+        assert_eq!(
+            input_macro.to_startup_entries(),
+            "startup = [{ frame = 1, key = 4, duration = 2 }, { frame = 2, key = 6, duration = 1 }, \
+             { frame = 4, key = 6, duration = 1 }]"
+        );
+
+        assert_eq!(input_macro.play_frame(), keys(&[]));
+        input_macro.play();
+        let replayed: Vec<_> = (0..6).map(|_| input_macro.play_frame()).collect();
+        assert_eq!(replayed[..5], frames);
+        assert_eq!(replayed[5], keys(&[]));
+        assert_eq!(input_macro.play_frame(), keys(&[]));
+    }
+}
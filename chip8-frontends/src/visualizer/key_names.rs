@@ -0,0 +1,133 @@
+use sfml::window::Key;
+
+/// Every key by the name that `Debug` prints for it, which is also its name in
+/// the config files.
+const KEY_NAMES: [(&str, Key); 101] = [
+    ("A", Key::A),
+    ("B", Key::B),
+    ("C", Key::C),
+    ("D", Key::D),
+    ("E", Key::E),
+    ("F", Key::F),
+    ("G", Key::G),
+    ("H", Key::H),
+    ("I", Key::I),
+    ("J", Key::J),
+    ("K", Key::K),
+    ("L", Key::L),
+    ("M", Key::M),
+    ("N", Key::N),
+    ("O", Key::O),
+    ("P", Key::P),
+    ("Q", Key::Q),
+    ("R", Key::R),
+    ("S", Key::S),
+    ("T", Key::T),
+    ("U", Key::U),
+    ("V", Key::V),
+    ("W", Key::W),
+    ("X", Key::X),
+    ("Y", Key::Y),
+    ("Z", Key::Z),
+    ("Num0", Key::Num0),
+    ("Num1", Key::Num1),
+    ("Num2", Key::Num2),
+    ("Num3", Key::Num3),
+    ("Num4", Key::Num4),
+    ("Num5", Key::Num5),
+    ("Num6", Key::Num6),
+    ("Num7", Key::Num7),
+    ("Num8", Key::Num8),
+    ("Num9", Key::Num9),
+    ("Escape", Key::Escape),
+    ("LControl", Key::LControl),
+    ("LShift", Key::LShift),
+    ("LAlt", Key::LAlt),
+    ("LSystem", Key::LSystem),
+    ("RControl", Key::RControl),
+    ("RShift", Key::RShift),
+    ("RAlt", Key::RAlt),
+    ("RSystem", Key::RSystem),
+    ("Menu", Key::Menu),
+    ("LBracket", Key::LBracket),
+    ("RBracket", Key::RBracket),
+    ("SemiColon", Key::SemiColon),
+    ("Comma", Key::Comma),
+    ("Period", Key::Period),
+    ("Quote", Key::Quote),
+    ("Slash", Key::Slash),
+    ("BackSlash", Key::BackSlash),
+    ("Tilde", Key::Tilde),
+    ("Equal", Key::Equal),
+    ("Dash", Key::Dash),
+    ("Space", Key::Space),
+    ("Return", Key::Return),
+    ("BackSpace", Key::BackSpace),
+    ("Tab", Key::Tab),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("End", Key::End),
+    ("Home", Key::Home),
+    ("Insert", Key::Insert),
+    ("Delete", Key::Delete),
+    ("Add", Key::Add),
+    ("Subtract", Key::Subtract),
+    ("Multiply", Key::Multiply),
+    ("Divide", Key::Divide),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("Up", Key::Up),
+    ("Down", Key::Down),
+    ("Numpad0", Key::Numpad0),
+    ("Numpad1", Key::Numpad1),
+    ("Numpad2", Key::Numpad2),
+    ("Numpad3", Key::Numpad3),
+    ("Numpad4", Key::Numpad4),
+    ("Numpad5", Key::Numpad5),
+    ("Numpad6", Key::Numpad6),
+    ("Numpad7", Key::Numpad7),
+    ("Numpad8", Key::Numpad8),
+    ("Numpad9", Key::Numpad9),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("F13", Key::F13),
+    ("F14", Key::F14),
+    ("F15", Key::F15),
+    ("Pause", Key::Pause),
+];
+
+/// Finds the key with the given SFML name, e.g. "F12".
+pub fn key_from_name(name: &str) -> Option<Key> {
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| *key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_from_name() {
+        assert_eq!(KEY_NAMES.len(), Key::Count as usize);
+        for (name, key) in KEY_NAMES.iter() {
+            assert_eq!(format!("{:?}", key), *name);
+        }
+        assert_eq!(key_from_name("F12"), Some(Key::F12));
+        assert_eq!(key_from_name("Numpad8"), Some(Key::Numpad8));
+        assert_eq!(key_from_name("Count"), None);
+        assert_eq!(key_from_name("Unknown"), None);
+        assert_eq!(key_from_name("f12"), None);
+    }
+}
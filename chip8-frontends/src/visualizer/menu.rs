@@ -0,0 +1,219 @@
+use super::overlay::{self, TEXT_COLOR};
+use chip8_core::pgm::Rgb;
+use sfml::graphics::{Color, RectangleShape, RenderTarget, RenderWindow, Shape, Transformable};
+use sfml::system::Vector2f;
+use sfml::window::{ContextSettings, Event, Key, Style, VideoMode};
+
+/// The number of ROMs per row of the menu and the number of rows shown at
+/// once; the menu scrolls to the selected ROM.
+const COLUMNS: usize = 4;
+const VISIBLE_ROWS: usize = 3;
+/// The size of a pixel of a thumbnail on the window.
+const THUMBNAIL_SCALE: f32 = 4.0;
+/// The size of the thumbnails that `--thumbnails` creates for low-res ROMs,
+/// used if no ROM has a thumbnail.
+const DEFAULT_THUMBNAIL_SIZE: (usize, usize) = (32, 16);
+/// The space around and between the tiles of the ROMs, in window pixels.
+const GAP: f32 = 12.0;
+/// The width of the frame around the selected ROM, in window pixels.
+const FRAME: f32 = 3.0;
+const NAME_COLOR: Color = Color {
+    r: 160,
+    g: 160,
+    b: 160,
+    a: 255,
+};
+const PLACEHOLDER_COLOR: Color = Color {
+    r: 40,
+    g: 40,
+    b: 40,
+    a: 255,
+};
+
+/// A ROM listed in the menu.
+pub struct MenuEntry {
+    pub name: String,
+    /// The cached preview of the ROM, indexed as `[x][y]`, if there is one.
+    pub thumbnail: Option<Vec<Vec<Rgb>>>,
+}
+
+/// The entry selected after pressing `key` in a grid of `len` entries with
+/// `COLUMNS` entries per row.
+fn move_selection(selected: usize, len: usize, key: Key) -> usize {
+    match key {
+        Key::Left => selected.saturating_sub(1),
+        Key::Right => (selected + 1).min(len - 1),
+        Key::Up => selected.checked_sub(COLUMNS).unwrap_or(selected),
+        Key::Down if selected + COLUMNS < len => selected + COLUMNS,
+        _ => selected,
+    }
+}
+
+/// The first row to show, scrolling from `first_row` as little as needed
+/// to show the selected entry.
+fn first_visible_row(selected: usize, first_row: usize) -> usize {
+    let row = selected / COLUMNS;
+    if row < first_row {
+        row
+    } else if row >= first_row + VISIBLE_ROWS {
+        row + 1 - VISIBLE_ROWS
+    } else {
+        first_row
+    }
+}
+
+/// The size of the largest thumbnail, in thumbnail pixels.
+fn thumbnail_size(entries: &[MenuEntry]) -> (usize, usize) {
+    entries
+        .iter()
+        .filter_map(|entry| entry.thumbnail.as_ref())
+        .map(|thumbnail| (thumbnail.len(), thumbnail.first().map_or(0, Vec::len)))
+        .reduce(|(w1, h1), (w2, h2)| (w1.max(w2), h1.max(h2)))
+        .unwrap_or(DEFAULT_THUMBNAIL_SIZE)
+}
+
+/// The name of an entry, shortened to fit below its thumbnail.
+fn short_name(name: &str, thumbnail_width: f32) -> String {
+    let fits = (1..=name.chars().count())
+        .take_while(|chars| overlay::text_size(*chars).x <= thumbnail_width)
+        .last()
+        .unwrap_or(0);
+    name.chars().take(fits).collect()
+}
+
+fn draw_menu(window: &mut RenderWindow, entries: &[MenuEntry], selected: usize, first_row: usize) {
+    let (width, height) = thumbnail_size(entries);
+    let thumbnail_size = Vector2f::new(width as f32, height as f32) * THUMBNAIL_SCALE;
+    let name_height = overlay::text_size(1).y;
+    let tile_size = thumbnail_size + Vector2f::new(GAP, 1.5 * GAP + name_height);
+    let shown = entries
+        .iter()
+        .enumerate()
+        .skip(first_row * COLUMNS)
+        .take(VISIBLE_ROWS * COLUMNS);
+    for (index, entry) in shown {
+        let (row, column) = (index / COLUMNS - first_row, index % COLUMNS);
+        let position = Vector2f::new(GAP, GAP)
+            + Vector2f::new(column as f32 * tile_size.x, row as f32 * tile_size.y);
+        let mut frame = RectangleShape::with_size(thumbnail_size);
+        frame.set_position(position);
+        frame.set_fill_color(PLACEHOLDER_COLOR);
+        if index == selected {
+            frame.set_outline_thickness(FRAME);
+            frame.set_outline_color(TEXT_COLOR);
+        }
+        window.draw(&frame);
+        if let Some(thumbnail) = &entry.thumbnail {
+            overlay::draw_thumbnail(window, position, thumbnail, THUMBNAIL_SCALE);
+        }
+        let color = if index == selected {
+            TEXT_COLOR
+        } else {
+            NAME_COLOR
+        };
+        overlay::draw_text(
+            window,
+            position + Vector2f::new(0.0, thumbnail_size.y + GAP / 2.0),
+            &short_name(&entry.name, thumbnail_size.x),
+            color,
+        );
+    }
+}
+
+/// Opens a window that shows the ROMs with their thumbnails in a grid, and
+/// returns the index of the one chosen with the arrow keys and Return, or
+/// `None` if the window is closed or Escape is pressed.
+pub fn choose_rom(entries: &[MenuEntry]) -> Option<usize> {
+    if entries.is_empty() {
+        return None;
+    }
+    let (width, height) = thumbnail_size(entries);
+    let rows = entries.len().div_ceil(COLUMNS).min(VISIBLE_ROWS);
+    let tile_width = width as f32 * THUMBNAIL_SCALE + GAP;
+    let tile_height = height as f32 * THUMBNAIL_SCALE + 1.5 * GAP + overlay::text_size(1).y;
+    let video_mode = VideoMode::new(
+        (COLUMNS as f32 * tile_width + GAP) as u32,
+        (rows as f32 * tile_height + GAP) as u32,
+        32,
+    );
+    let mut window = RenderWindow::new(
+        video_mode,
+        "Chip 8 Emulator",
+        Style::CLOSE,
+        &ContextSettings::default(),
+    );
+    window.set_framerate_limit(30);
+    let mut selected = 0;
+    let mut first_row = 0;
+    while window.is_open() {
+        while let Some(event) = window.poll_event() {
+            match event {
+                Event::Closed
+                | Event::KeyPressed {
+                    code: Key::Escape, ..
+                } => window.close(),
+                Event::KeyPressed {
+                    code: Key::Return, ..
+                } => {
+                    window.close();
+                    return Some(selected);
+                }
+                Event::KeyPressed { code, .. } => {
+                    selected = move_selection(selected, entries.len(), code)
+                }
+                _ => (),
+            }
+        }
+        first_row = first_visible_row(selected, first_row);
+        window.clear(Color::BLACK);
+        draw_menu(&mut window, entries, selected, first_row);
+        window.display();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_selection() {
+        assert_eq!(move_selection(0, 10, Key::Left), 0);
+        assert_eq!(move_selection(0, 10, Key::Right), 1);
+        assert_eq!(move_selection(9, 10, Key::Right), 9);
+        assert_eq!(move_selection(2, 10, Key::Up), 2);
+        assert_eq!(move_selection(6, 10, Key::Up), 2);
+        assert_eq!(move_selection(5, 10, Key::Down), 9);
+        assert_eq!(move_selection(6, 10, Key::Down), 6);
+        assert_eq!(move_selection(6, 10, Key::A), 6);
+    }
+
+    #[test]
+    fn test_first_visible_row() {
+        assert_eq!(first_visible_row(11, 0), 0);
+        assert_eq!(first_visible_row(12, 0), 1);
+        assert_eq!(first_visible_row(8, 1), 1);
+        assert_eq!(first_visible_row(3, 1), 0);
+        assert_eq!(first_visible_row(23, 0), 3);
+    }
+
+    #[test]
+    fn test_thumbnail_size() {
+        let entry = |thumbnail: Option<(usize, usize)>| MenuEntry {
+            name: String::from("pong"),
+            thumbnail: thumbnail.map(|(width, height)| vec![vec![[0; 3]; height]; width]),
+        };
+        assert_eq!(thumbnail_size(&[entry(None)]), DEFAULT_THUMBNAIL_SIZE);
+        assert_eq!(
+            thumbnail_size(&[entry(Some((32, 16))), entry(None), entry(Some((32, 32)))]),
+            (32, 32)
+        );
+    }
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name("pong", 128.0), "pong");
+        assert_eq!(short_name("spaceinvaders", 128.0), "spaceinvad");
+        assert_eq!(short_name("pong", 1.0), "");
+    }
+}
@@ -0,0 +1,839 @@
+extern crate sfml;
+
+mod audio;
+pub mod beep;
+pub mod filter;
+pub mod gamepad;
+mod input_macro;
+mod key_names;
+pub mod menu;
+mod overlay;
+pub mod remap;
+mod svg;
+
+use audio::SfmlAudio;
+use beep::{Beep, SAMPLE_RATE};
+use chip8_core::basics::{HIRES_SCREEN_HEIGHT, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip8_core::control::{Command, ControlBus, CpuState, Event as ControlEvent};
+use chip8_core::display_channel::{self, DisplayMirror};
+use chip8_core::pgm::{self, Rgb};
+use chip8_core::save_state::SaveState;
+use chip8_core::stats::FrameStats;
+use chip8_core::testing::ScriptedKeypad;
+use chip8_core::vm::{Audio, Display, VMInterface};
+use filter::{frame_thumbnail, lit_frame, Fade, FilterChain, FrameFilter, PaletteCycle, Tint};
+use gamepad::{GamepadInput, Gamepads};
+use input_macro::InputMacro;
+pub use key_names::key_from_name;
+use remap::{KeymapStore, Remap, RemapStep};
+use sfml::audio::SoundBuffer;
+use sfml::graphics::{Color, RectangleShape, RenderTarget, RenderWindow, Shape, Transformable};
+use sfml::system::{SfBox, Vector2f};
+use sfml::window::{ContextSettings, Event, Style, VideoMode};
+use std::iter;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const SCALE: usize = 16;
+const FRAME_INTERVAL: Duration = Duration::from_micros(16667);
+const FRAME_STATS_CAPACITY: usize = 600;
+const KEY_LOG_CAPACITY: usize = 16;
+/// The number of recent key events shown by the debug overlay.
+const KEY_LOG_SHOWN: usize = 4;
+/// The number of frames for one full cycle through all hues.
+const PALETTE_CYCLE_FRAMES: u32 = 600;
+/// The number of save state slots per ROM, numbered from 0.
+pub const SAVE_SLOTS: u8 = 10;
+/// The downscale factor of the thumbnails stored with save states.
+const SLOT_THUMBNAIL_FACTOR: u8 = 2;
+/// How long the slot picker stays on screen after selecting a slot.
+const SLOT_PICKER_TIME: Duration = Duration::from_secs(2);
+/// How long the controls are shown after the window opens, see
+/// `VisualizerOptions::show_controls`.
+const CONTROLS_TIME: Duration = Duration::from_secs(5);
+
+/// Maps a physical key to a CHIP-8 key that is pressed and released
+/// periodically while the physical key is held.
+#[derive(Clone, Copy)]
+pub struct Turbo {
+    pub key: sfml::window::Key,
+    pub chip8_key: u8,
+    /// The length of one press-and-release cycle in frames.
+    pub period: u32,
+}
+
+/// Keys that control the emulator rather than the game. `None` disables a
+/// hotkey.
+#[derive(Clone, Copy)]
+pub struct Hotkeys {
+    /// Prints the keypad state and the most recent key events.
+    pub keypad_info: Option<sfml::window::Key>,
+    /// Starts or stops recording an input macro.
+    pub record_macro: Option<sfml::window::Key>,
+    /// Replays the recorded input macro.
+    pub play_macro: Option<sfml::window::Key>,
+    /// Saves the current frame as an SVG image.
+    pub screenshot: Option<sfml::window::Key>,
+    /// Shows or hides the registers, timers and stack on top of the screen.
+    pub debug_overlay: Option<sfml::window::Key>,
+    /// Pauses or resumes the emulation.
+    pub pause: Option<sfml::window::Key>,
+    /// Saves the state of the VM to the selected slot.
+    pub save_state: Option<sfml::window::Key>,
+    /// Loads the state of the VM from the selected slot.
+    pub load_state: Option<sfml::window::Key>,
+    /// Selects the next save state slot.
+    pub next_slot: Option<sfml::window::Key>,
+    /// Runs the game backwards while held.
+    pub rewind: Option<sfml::window::Key>,
+    /// Enters or leaves the remap mode, which changes the keymap.
+    pub remap: Option<sfml::window::Key>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Hotkeys {
+        Hotkeys {
+            keypad_info: Some(sfml::window::Key::F12),
+            record_macro: Some(sfml::window::Key::F9),
+            play_macro: Some(sfml::window::Key::F10),
+            screenshot: Some(sfml::window::Key::F5),
+            debug_overlay: Some(sfml::window::Key::F1),
+            pause: Some(sfml::window::Key::F3),
+            save_state: Some(sfml::window::Key::F6),
+            load_state: Some(sfml::window::Key::F7),
+            next_slot: Some(sfml::window::Key::F8),
+            rewind: Some(sfml::window::Key::BackSpace),
+            remap: Some(sfml::window::Key::F2),
+        }
+    }
+}
+
+impl Hotkeys {
+    pub fn is_hotkey(&self, key: sfml::window::Key) -> bool {
+        [
+            self.keypad_info,
+            self.record_macro,
+            self.play_macro,
+            self.screenshot,
+            self.debug_overlay,
+            self.pause,
+            self.save_state,
+            self.load_state,
+            self.next_slot,
+            self.rewind,
+            self.remap,
+        ]
+        .contains(&Some(key))
+    }
+
+    /// Disables all hotkeys that are also used by the game, either in the
+    /// keymap or as turbo key, and returns a warning for each of them.
+    pub fn resolve_conflicts(
+        &mut self,
+        keymap: &HashMap<u8, sfml::window::Key>,
+        turbo: Option<Turbo>,
+    ) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (name, hotkey) in [
+            ("keypad_info", &mut self.keypad_info),
+            ("record_macro", &mut self.record_macro),
+            ("play_macro", &mut self.play_macro),
+            ("screenshot", &mut self.screenshot),
+            ("debug_overlay", &mut self.debug_overlay),
+            ("pause", &mut self.pause),
+            ("save_state", &mut self.save_state),
+            ("load_state", &mut self.load_state),
+            ("next_slot", &mut self.next_slot),
+            ("rewind", &mut self.rewind),
+            ("remap", &mut self.remap),
+        ] {
+            let key = match *hotkey {
+                Some(key) => key,
+                None => continue,
+            };
+            let game_key = keymap
+                .iter()
+                .find(|(_, k)| **k == key)
+                .map(|(chip8_key, _)| format!("CHIP-8 key {:X}", chip8_key))
+                .or_else(|| {
+                    turbo
+                        .filter(|turbo| turbo.key == key)
+                        .map(|_| String::from("the turbo key"))
+                });
+            if let Some(game_key) = game_key {
+                warnings.push(format!(
+                    "Hotkey {} ({:?}) is also mapped to {}; the hotkey is disabled.",
+                    name, key, game_key
+                ));
+                *hotkey = None;
+            }
+        }
+        warnings
+    }
+}
+
+/// Per-ROM settings of the visualizer.
+pub struct VisualizerOptions {
+    /// The number of frames a pixel needs to fade out after it was turned off.
+    pub display_fade: u32,
+    pub keymap: HashMap<u8, sfml::window::Key>,
+    /// Where the keymap is saved after it was changed in the remap mode. It
+    /// is only changed for this run if `None`.
+    pub keymap_store: Option<Box<dyn KeymapStore>>,
+    /// The gamepad inputs that press CHIP-8 keys, in addition to the keymap.
+    pub gamepad: HashMap<u8, GamepadInput>,
+    pub turbo: Option<Turbo>,
+    /// Latch every key press until the program reads it, so that short taps
+    /// are not missed while the program is busy. Games that check whether a
+    /// key is still held may misbehave.
+    pub key_latch: bool,
+    /// Raise the pause flag while the window does not have the focus.
+    pub pause_in_background: bool,
+    /// Open the window with the emulation paused, until the pause hotkey is
+    /// pressed.
+    pub start_paused: bool,
+    /// Slowly cycle the hue of lit pixels instead of drawing them white.
+    pub palette_cycle: bool,
+    /// Keep every beep going for at least this many frames, even if the sound
+    /// timer runs out earlier. 0 plays beeps exactly as long as the timer.
+    pub min_beep_frames: u32,
+    pub hotkeys: Hotkeys,
+    /// The color of lit pixels, unless the palette is cycled.
+    pub fill_color: Color,
+    pub background_color: Color,
+    /// Additional post-processing, applied after fading and coloring.
+    pub filters: Vec<Box<dyn FrameFilter>>,
+    /// Keys pressed automatically right after boot.
+    pub startup_input: ScriptedKeypad,
+    /// The tone played while the sound timer is active.
+    pub beep: Beep,
+    /// Where screenshots are saved.
+    pub screenshot_dir: PathBuf,
+    /// Where the save state slots of the ROM are stored.
+    pub save_state_dir: PathBuf,
+    /// Save screenshots and save state thumbnails of the logical display,
+    /// white on black, instead of the faded and colored frame that is shown.
+    pub raw_images: bool,
+    /// Show the keymap on top of the screen for the first seconds.
+    pub show_controls: bool,
+}
+
+pub struct Visualizer {
+    join_handle: JoinHandle<()>,
+}
+
+struct VisualizerInternals<'a> {
+    window: RenderWindow,
+    pixels: [[RectangleShape<'a>; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize],
+    display: DisplayMirror,
+    sound_buffer: SfBox<SoundBuffer>,
+    /// The tones of the `ToneSender` that is the audio of the VM.
+    tones: Receiver<bool>,
+    options: VisualizerOptions,
+    filters: FilterChain,
+    bus: ControlBus,
+}
+
+impl<'a> VisualizerInternals<'a> {
+    fn new(
+        display: DisplayMirror,
+        tones: Receiver<bool>,
+        mut options: VisualizerOptions,
+        bus: ControlBus,
+    ) -> VisualizerInternals<'a> {
+        let filters = VisualizerInternals::init_filters(&mut options);
+        // Taller screens get smaller pixels, so that the window keeps its height.
+        let (width, height) = display.size();
+        let scale = SCALE * SCREEN_HEIGHT as usize / height as usize;
+        VisualizerInternals {
+            window: VisualizerInternals::init_window(width, height, scale),
+            pixels: VisualizerInternals::init_pixels(scale),
+            display,
+            sound_buffer: SoundBuffer::from_samples(&options.beep.samples(), 1, SAMPLE_RATE)
+                .unwrap(),
+            tones,
+            options,
+            filters,
+            bus,
+        }
+    }
+
+    /// Builds the filter chain: fading, then coloring, then the extra filters
+    /// of the options.
+    fn init_filters(options: &mut VisualizerOptions) -> FilterChain {
+        let mut filters = display_filters(
+            options.display_fade,
+            options.palette_cycle,
+            options.fill_color,
+        );
+        for filter in options.filters.drain(..) {
+            filters.push(filter);
+        }
+        filters
+    }
+
+    fn init_window(width: u8, height: u8, scale: usize) -> RenderWindow {
+        let video_mode = VideoMode::new(
+            width as u32 * scale as u32,
+            height as u32 * scale as u32,
+            32,
+        );
+        let mut window = RenderWindow::new(
+            video_mode,
+            "Chip 8 Emulator",
+            Style::CLOSE,
+            &ContextSettings::default(),
+        );
+        window.set_key_repeat_enabled(false);
+        window
+    }
+
+    fn init_pixels(
+        scale: usize,
+    ) -> [[RectangleShape<'static>; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize] {
+        let mut pixels: [[RectangleShape; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize] =
+            iter::repeat(
+                iter::repeat(RectangleShape::new())
+                    .collect::<arrayvec::ArrayVec<_>>()
+                    .into_inner()
+                    .unwrap(),
+            )
+            .collect::<arrayvec::ArrayVec<_>>()
+            .into_inner()
+            .unwrap();
+        for x in 0..SCREEN_WIDTH as usize {
+            for y in 0..HIRES_SCREEN_HEIGHT as usize {
+                let pixel = &mut pixels[x][y];
+                pixel.set_size(Vector2f::new(scale as f32, scale as f32));
+                pixel.set_position(Vector2f::new((scale * x) as f32, (scale * y) as f32));
+                pixel.set_fill_color(Color::WHITE);
+            }
+        }
+        pixels
+    }
+}
+
+impl Visualizer {
+    /// Opens the window in a new thread and takes over the display and the
+    /// audio of the VM, keeping what it shows. `bus` controls the executor running the VM,
+    /// which is paused until the window is open, or until the pause hotkey
+    /// with `start_paused`, and shut down once the window is closed.
+    pub fn new(
+        vm_interface: Arc<Mutex<VMInterface>>,
+        options: VisualizerOptions,
+        bus: ControlBus,
+    ) -> Visualizer {
+        let (tone_sender, tones) = audio::tone_channel();
+        let mirror = {
+            let mut interface = vm_interface.lock().unwrap();
+            let (width, height) = interface.display.size();
+            let (mut display, mirror) = display_channel::channel(width, height);
+            let lit: Vec<_> = (0..width)
+                .flat_map(|x| (0..height).map(move |y| (x, y)))
+                .filter(|(x, y)| interface.display.get(*x, *y) > 0)
+                .collect();
+            if !lit.is_empty() {
+                display.draw_pixels(&lit);
+                display.frame();
+            }
+            interface.display = Box::new(display);
+            interface.set_audio(Box::new(tone_sender));
+            mirror
+        };
+        bus.send(Command::Pause);
+        let join_handle = std::thread::spawn(move || {
+            let mut internals = VisualizerInternals::new(mirror, tones, options, bus);
+            if !internals.options.start_paused {
+                internals.bus.send(Command::Resume);
+            }
+            run(&mut internals);
+            internals.bus.send(Command::Shutdown);
+        });
+        Visualizer { join_handle }
+    }
+
+    pub fn wait_for_close(self) {
+        self.join_handle.join().unwrap();
+    }
+}
+
+/// Remembers the most recent presses and releases of CHIP-8 keys, so that
+/// users can check whether their keymap reaches the VM.
+struct KeyLog {
+    start: Instant,
+    events: VecDeque<(Duration, u8, bool)>,
+}
+
+impl KeyLog {
+    fn new() -> KeyLog {
+        KeyLog {
+            start: Instant::now(),
+            events: VecDeque::with_capacity(KEY_LOG_CAPACITY),
+        }
+    }
+
+    fn record(&mut self, key: u8, pressed: bool) {
+        if self.events.len() == KEY_LOG_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back((self.start.elapsed(), key, pressed));
+    }
+
+    /// The most recent `count` events, oldest first, e.g. `12.345s 3 pressed`.
+    fn lines(&self, count: usize) -> Vec<String> {
+        let skipped = self.events.len().saturating_sub(count);
+        self.events
+            .iter()
+            .skip(skipped)
+            .map(|(time, key, pressed)| {
+                let action = if *pressed { "pressed" } else { "released" };
+                format!("{:.3}s {:X} {}", time.as_secs_f32(), key, action)
+            })
+            .collect()
+    }
+
+    fn print(&self, keys_pressed: &[bool; 16], keys_down: &[bool; 16]) {
+        let mask = |keys: &[bool; 16]| {
+            keys.iter()
+                .enumerate()
+                .filter(|(_, pressed)| **pressed)
+                .fold(0u16, |mask, (i, _)| mask | 1 << i)
+        };
+        println!(
+            "Keypad: {:#018b}, VM sees: {:#018b}",
+            mask(keys_pressed),
+            mask(keys_down)
+        );
+        for (time, key, pressed) in self.events.iter() {
+            let action = if *pressed { "pressed" } else { "released" };
+            println!("  {:>10.3?} {:X} {}", time, key, action);
+        }
+    }
+}
+
+/// Writes an SVG screenshot to `dir`, named after the current time.
+fn save_screenshot(dir: &Path, svg: &str) -> io::Result<PathBuf> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("chip8-{}.svg", time.as_millis()));
+    fs::write(&path, svg)?;
+    Ok(path)
+}
+
+/// The filters that fade and color the display like the window does, without
+/// the extra filters of the options.
+pub fn display_filters(display_fade: u32, palette_cycle: bool, fill_color: Color) -> FilterChain {
+    let mut filters = FilterChain::default();
+    filters.push(Box::new(Fade::new(display_fade)));
+    if palette_cycle {
+        filters.push(Box::new(PaletteCycle::new(PALETTE_CYCLE_FRAMES)));
+    } else {
+        filters.push(Box::new(Tint(fill_color)));
+    }
+    filters
+}
+
+/// The file of a save state slot in `dir`.
+pub fn slot_file(dir: &Path, slot: u8) -> PathBuf {
+    dir.join(format!("slot{}.json", slot))
+}
+
+/// The thumbnail of the state in a save state slot in `dir`: a PPM image of
+/// what the window showed, or a PGM image of the logical display.
+fn slot_thumbnail_file(dir: &Path, slot: u8, extension: &str) -> PathBuf {
+    dir.join(format!("slot{}.{}", slot, extension))
+}
+
+/// Writes a state to a save state slot in `dir`, along with its thumbnail:
+/// `shown`, the thumbnail of what the window showed, or the logical display
+/// of the state if it is `None`.
+pub fn write_save_state(
+    dir: &Path,
+    slot: u8,
+    state: &SaveState,
+    shown: Option<&[Vec<Rgb>]>,
+) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = slot_file(dir, slot);
+    fs::write(&path, state.to_json())?;
+    let (thumbnail, extension, other) = match shown {
+        Some(shown) => (pgm::encode_rgb(shown), "ppm", "pgm"),
+        None => (
+            pgm::encode(&state.thumbnail(SLOT_THUMBNAIL_FACTOR)),
+            "pgm",
+            "ppm",
+        ),
+    };
+    fs::write(slot_thumbnail_file(dir, slot, extension), thumbnail)?;
+    // The thumbnail of the other kind shows the state that was replaced.
+    let _ = fs::remove_file(slot_thumbnail_file(dir, slot, other));
+    Ok(path)
+}
+
+/// The thumbnail of a save state slot, or `None` if the slot is empty or
+/// its thumbnail cannot be read.
+fn read_slot_thumbnail(dir: &Path, slot: u8) -> Option<Vec<Vec<Rgb>>> {
+    ["ppm", "pgm"].iter().find_map(|extension| {
+        let image = fs::read(slot_thumbnail_file(dir, slot, extension)).ok()?;
+        pgm::decode_rgb(&image).ok()
+    })
+}
+
+fn read_save_state(dir: &Path, slot: u8) -> Result<SaveState, String> {
+    let json = fs::read_to_string(slot_file(dir, slot)).map_err(|e| e.to_string())?;
+    SaveState::from_json(&json).map_err(|e| e.to_string())
+}
+
+fn run(internals: &mut VisualizerInternals) {
+    let mut keys_pressed = [false; 16];
+    let mut turbo_held = false;
+    let mut turbo_frame = 0;
+    let mut frame_index: u32 = 0;
+    let mut audio = SfmlAudio::new(&internals.sound_buffer, internals.options.min_beep_frames);
+    let mut frame_stats = FrameStats::new(FRAME_STATS_CAPACITY);
+    let mut render_stats = FrameStats::new(FRAME_STATS_CAPACITY);
+    let mut key_log = KeyLog::new();
+    let mut input_macro = InputMacro::default();
+    let mut screenshot_requested = false;
+    // The keys last sent to the executor.
+    let mut keys_sent = [false; 16];
+    let mut gamepads = Gamepads::new(&internals.options.gamepad);
+    let mut remap = Remap::default();
+    let mut paused = internals.options.start_paused;
+    // Paused with the hotkey, which focus changes do not undo.
+    let mut paused_by_hotkey = paused;
+    let mut overlay = false;
+    let mut save_slot = 0;
+    // The slot picker, shown until the given time with the thumbnail of the
+    // selected slot.
+    let mut slot_picker: Option<(Instant, Option<Vec<Vec<Rgb>>>)> = None;
+    // The controls are shown until this time.
+    let controls_until = Instant::now() + CONTROLS_TIME;
+    // The last frame drawn, with its size, and its thumbnail when a state is
+    // being saved.
+    let mut shown_frame = (lit_frame(0, 0, |_, _| false), 0, 0);
+    let mut saved_thumbnail = None;
+    // The state shown by the overlay, once the executor sent it.
+    let mut cpu_state: Option<CpuState> = None;
+
+    while internals.window.is_open() {
+        let frame_start = Instant::now();
+        let mut pressed_this_frame = [false; 16];
+
+        // Handle events
+        while let Some(event) = internals.window.poll_event() {
+            match event {
+                Event::Closed => internals.window.close(),
+                // The release of a held rewind key goes unnoticed without
+                // the focus.
+                Event::LostFocus if internals.options.pause_in_background => {
+                    internals.bus.send(Command::Rewind(false));
+                    internals.bus.send(Command::Pause);
+                    paused = true;
+                }
+                Event::LostFocus => internals.bus.send(Command::Rewind(false)),
+                Event::GainedFocus
+                    if internals.options.pause_in_background && !paused_by_hotkey =>
+                {
+                    internals.bus.send(Command::Resume);
+                    paused = false;
+                }
+                Event::KeyPressed { code, .. } if internals.options.hotkeys.remap == Some(code) => {
+                    remap.toggle();
+                    // The keys held now are released for the game.
+                    keys_pressed = [false; 16];
+                    turbo_held = false;
+                }
+                Event::KeyPressed { code, .. } if remap.is_active() => {
+                    let options = &mut internals.options;
+                    let reserved = options.hotkeys.is_hotkey(code)
+                        || options.turbo.is_some_and(|turbo| turbo.key == code);
+                    if let RemapStep::Remapped(chip8_key) =
+                        remap.key_pressed(code, reserved, &mut options.keymap)
+                    {
+                        println!("Mapped CHIP-8 key {:X} to {:?}", chip8_key, code);
+                        let keymap = &options.keymap;
+                        match options
+                            .keymap_store
+                            .as_mut()
+                            .map(|store| store.save(keymap))
+                        {
+                            Some(Ok(location)) => println!("Saved the keymap to {}", location),
+                            Some(Err(e)) => eprintln!("Could not save the keymap: {}", e),
+                            None => (),
+                        }
+                    }
+                }
+                Event::KeyPressed { code, .. } => {
+                    if internals
+                        .options
+                        .turbo
+                        .is_some_and(|turbo| turbo.key == code)
+                    {
+                        turbo_held = true;
+                        turbo_frame = 0;
+                    }
+                    if let Some((i, _)) = internals.options.keymap.iter().find(|(_, k)| **k == code)
+                    {
+                        keys_pressed[*i as usize] = true;
+                        pressed_this_frame[*i as usize] = true;
+                        key_log.record(*i, true);
+                        if internals.options.key_latch {
+                            internals.bus.send(Command::LatchKey(*i));
+                        }
+                    }
+                    if internals.options.hotkeys.keypad_info == Some(code) {
+                        key_log.print(&keys_pressed, &keys_sent);
+                    }
+                    if internals.options.hotkeys.record_macro == Some(code) {
+                        if input_macro.toggle_recording() {
+                            println!("Recording input macro...");
+                        } else {
+                            println!(
+                                "Recorded input macro of {} frames:\n{}",
+                                input_macro.len(),
+                                input_macro.to_startup_entries()
+                            );
+                        }
+                    }
+                    if internals.options.hotkeys.play_macro == Some(code) {
+                        input_macro.play();
+                    }
+                    if internals.options.hotkeys.screenshot == Some(code) {
+                        screenshot_requested = true;
+                    }
+                    if internals.options.hotkeys.debug_overlay == Some(code) {
+                        overlay = !overlay;
+                        internals.bus.send(Command::Inspect(overlay));
+                        cpu_state = None;
+                    }
+                    if internals.options.hotkeys.pause == Some(code) {
+                        paused_by_hotkey = !paused_by_hotkey;
+                        paused = paused_by_hotkey;
+                        if paused {
+                            internals.bus.send(Command::Pause);
+                        } else {
+                            internals.bus.send(Command::Resume);
+                        }
+                    }
+                    if internals.options.hotkeys.save_state == Some(code) {
+                        let (frame, width, height) = &shown_frame;
+                        saved_thumbnail = (!internals.options.raw_images).then(|| {
+                            frame_thumbnail(
+                                frame,
+                                *width,
+                                *height,
+                                internals.options.background_color,
+                                SLOT_THUMBNAIL_FACTOR,
+                            )
+                        });
+                        internals.bus.send(Command::SaveState { slot: save_slot });
+                    }
+                    if internals.options.hotkeys.load_state == Some(code) {
+                        match read_save_state(&internals.options.save_state_dir, save_slot) {
+                            Ok(state) => internals.bus.send(Command::LoadState {
+                                slot: save_slot,
+                                state: Box::new(state),
+                            }),
+                            Err(e) => eprintln!("Could not load slot {}: {}", save_slot, e),
+                        }
+                    }
+                    if internals.options.hotkeys.next_slot == Some(code) {
+                        save_slot = (save_slot + 1) % SAVE_SLOTS;
+                        let thumbnail =
+                            read_slot_thumbnail(&internals.options.save_state_dir, save_slot);
+                        slot_picker = Some((Instant::now() + SLOT_PICKER_TIME, thumbnail));
+                    }
+                    if internals.options.hotkeys.rewind == Some(code) {
+                        internals.bus.send(Command::Rewind(true));
+                    }
+                }
+                Event::KeyReleased { code, .. } => {
+                    if internals.options.hotkeys.rewind == Some(code) {
+                        internals.bus.send(Command::Rewind(false));
+                    }
+                    if internals
+                        .options
+                        .turbo
+                        .is_some_and(|turbo| turbo.key == code)
+                    {
+                        turbo_held = false;
+                    }
+                    if let Some((i, _)) = internals.options.keymap.iter().find(|(_, k)| **k == code)
+                    {
+                        keys_pressed[*i as usize] = false;
+                        key_log.record(*i, false);
+                    }
+                }
+                Event::JoystickButtonPressed { joystickid, button } => {
+                    gamepads.button(joystickid, button, true)
+                }
+                Event::JoystickButtonReleased { joystickid, button } => {
+                    gamepads.button(joystickid, button, false)
+                }
+                Event::JoystickMoved {
+                    joystickid,
+                    axis,
+                    position,
+                } => gamepads.axis_moved(joystickid, axis, position),
+                Event::JoystickDisconnected { joystickid } => gamepads.disconnected(joystickid),
+                _ => { /* do nothing */ }
+            }
+        }
+
+        // Update keymap in VM.
+        {
+            let mut keys_down = keys_pressed;
+            let gamepad_keys = gamepads.keys_down();
+            let startup_keys = internals.options.startup_input.keys_down(frame_index);
+            frame_index = frame_index.saturating_add(1);
+            let macro_keys = input_macro.play_frame();
+            for key in 0..16 {
+                keys_down[key] |= gamepad_keys[key] || startup_keys[key] || macro_keys[key];
+            }
+            if let Some(turbo) = internals.options.turbo.filter(|_| turbo_held) {
+                if turbo_frame % turbo.period < turbo.period / 2 {
+                    keys_down[turbo.chip8_key as usize] = true;
+                }
+                turbo_frame += 1;
+            }
+            // The changes are queued as key events, so that the VM sees
+            // every press, including those released within this frame.
+            for key in 0..16u8 {
+                let (down, sent) = (keys_down[key as usize], keys_sent[key as usize]);
+                if pressed_this_frame[key as usize] && !down && !sent {
+                    internals.bus.send(Command::KeyEvent { key, pressed: true });
+                    internals.bus.send(Command::KeyEvent {
+                        key,
+                        pressed: false,
+                    });
+                } else if down != sent {
+                    internals.bus.send(Command::KeyEvent { key, pressed: down });
+                }
+            }
+            keys_sent = keys_down;
+            input_macro.record_frame(keys_sent);
+        }
+
+        // Events of the executor. The error that stopped the program is
+        // shown in the title bar.
+        for event in internals.bus.events() {
+            match event {
+                ControlEvent::Error(error) => internals
+                    .window
+                    .set_title(&format!("Chip 8 Emulator - stopped: {}", error)),
+                ControlEvent::State(state) if overlay => cpu_state = Some(state),
+                ControlEvent::Saved { slot, state } => {
+                    let saved = state.map_err(|e| e.to_string()).and_then(|state| {
+                        write_save_state(
+                            &internals.options.save_state_dir,
+                            slot,
+                            &state,
+                            saved_thumbnail.take().as_deref(),
+                        )
+                        .map_err(|e| e.to_string())
+                    });
+                    match saved {
+                        Ok(path) => println!("Saved slot {} to {}", slot, path.display()),
+                        Err(e) => eprintln!("Could not save slot {}: {}", slot, e),
+                    }
+                }
+                ControlEvent::Loaded { slot, result } => match result {
+                    Ok(()) => println!("Loaded slot {}", slot),
+                    Err(e) => eprintln!("Could not load slot {}: {}", slot, e),
+                },
+                ControlEvent::Warning(warning) => eprintln!("Warning: {}", warning),
+                ControlEvent::Stopped { .. } | ControlEvent::State(_) => (),
+            }
+        }
+
+        for tone in internals.tones.try_iter() {
+            audio.set_tone(tone);
+        }
+        audio.frame(paused);
+
+        // Draw once the VM completes a frame, or redraw the last one if it
+        // does not, e.g. while paused.
+        internals.display.wait_for_frame(FRAME_INTERVAL);
+        let render_start = Instant::now();
+        let (width, height) = internals.display.size();
+        let mut frame = lit_frame(width, height, |x, y| internals.display.get(x, y));
+        let raw_frame = frame;
+        internals.filters.apply(&mut frame);
+        shown_frame = (frame, width, height);
+        if screenshot_requested {
+            screenshot_requested = false;
+            let svg = if internals.options.raw_images {
+                svg::frame_to_svg(&raw_frame, width, height, Color::BLACK)
+            } else {
+                svg::frame_to_svg(&frame, width, height, internals.options.background_color)
+            };
+            match save_screenshot(&internals.options.screenshot_dir, &svg) {
+                Ok(path) => println!("Saved screenshot to {}", path.display()),
+                Err(e) => eprintln!("Could not save screenshot: {}", e),
+            }
+        }
+        internals.window.clear(internals.options.background_color);
+        for (pixel, color) in internals
+            .pixels
+            .iter_mut()
+            .flatten()
+            .zip(frame.iter().flatten())
+        {
+            pixel.set_fill_color(*color);
+            internals.window.draw(pixel);
+        }
+        if slot_picker
+            .as_ref()
+            .is_some_and(|(until, _)| *until <= render_start)
+        {
+            slot_picker = None;
+        }
+        if remap.is_active() {
+            overlay::draw_lines(
+                &mut internals.window,
+                &remap.prompt(&internals.options.keymap),
+            );
+        } else if let Some((_, thumbnail)) = &slot_picker {
+            overlay::draw_slot(&mut internals.window, save_slot, thumbnail.as_deref());
+        } else if internals.options.show_controls && render_start < controls_until {
+            overlay::draw_lines(
+                &mut internals.window,
+                &overlay::controls_lines(&internals.options.keymap),
+            );
+        } else if let Some(state) = &cpu_state {
+            let mut lines = Vec::new();
+            if let (Some(p50), Some(p99)) =
+                (render_stats.percentile(50), render_stats.percentile(99))
+            {
+                lines.push(overlay::timing_line("RENDER", p50, p99));
+            }
+            lines.extend(key_log.lines(KEY_LOG_SHOWN));
+            overlay::draw_overlay(&mut internals.window, state, &lines);
+        }
+        render_stats.record(render_start.elapsed());
+        internals.window.display();
+
+        // Timing
+        let frame_duration = frame_start.elapsed();
+        frame_stats.record(frame_duration);
+        if frame_duration >= 2 * FRAME_INTERVAL {
+            eprintln!(
+                "Warning: frame took {:?}, dropped {} frame(s).",
+                frame_duration,
+                frame_duration.as_micros() / FRAME_INTERVAL.as_micros() - 1
+            );
+        }
+    }
+
+    println!("Frame time: {}", frame_stats.summary());
+    println!("Render time: {}", render_stats.summary());
+}
@@ -0,0 +1,337 @@
+use chip8_core::control::CpuState;
+use chip8_core::pgm::Rgb;
+use sfml::graphics::{Color, RectangleShape, RenderTarget, RenderWindow, Shape, Transformable};
+use sfml::system::Vector2f;
+use sfml::window::Key;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The size of a pixel of the overlay font on the window.
+const FONT_SCALE: f32 = 3.0;
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// The space around the text and between lines and characters, in font pixels.
+const MARGIN: usize = 2;
+const SPACING: usize = 1;
+pub const TEXT_COLOR: Color = Color {
+    r: 0,
+    g: 255,
+    b: 128,
+    a: 255,
+};
+/// The size of a pixel of a save state thumbnail on the window.
+const THUMBNAIL_SCALE: f32 = 6.0;
+const BACKGROUND_COLOR: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 192,
+};
+
+/// The CHIP-8 keys in the layout of the COSMAC VIP keypad.
+const KEYPAD_ROWS: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+/// The width of a key with its mapped key in `controls_lines`.
+const CONTROLS_COLUMN_WIDTH: usize = 11;
+
+/// A 3x5 pixel font for the characters used by the overlay. Every row is a
+/// bit mask with the leftmost pixel in the highest of three bits.
+const GLYPHS: [(char, [u8; GLYPH_HEIGHT]); 46] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('x', [0b000, 0b000, 0b101, 0b010, 0b101]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]),
+    (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+];
+
+/// The glyph of a character, or a question mark for unknown characters.
+/// Lowercase letters without a glyph of their own are shown in uppercase.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    let find = |c: char| GLYPHS.iter().find(|(glyph_char, _)| *glyph_char == c);
+    find(c)
+        .or_else(|| find(c.to_ascii_uppercase()))
+        .or_else(|| find('?'))
+        .map(|(_, rows)| *rows)
+        .unwrap()
+}
+
+/// The lines of text shown by the overlay.
+fn overlay_lines(state: &CpuState) -> Vec<String> {
+    let mut lines = vec![
+        format!("PC {:#05X}  {}", state.pc.0, state.next),
+        format!(
+            "I  {:#05X}  DT {:02X}  ST {:02X}  COLLISIONS {}",
+            state.i.0, state.delay_timer.0, state.sound_timer.0, state.draw_collisions
+        ),
+    ];
+    for (row, values) in state.registers.chunks(4).enumerate() {
+        let line: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value.0))
+            .collect();
+        lines.push(line.join("  "));
+    }
+    let stack: Vec<_> = state
+        .stack
+        .iter()
+        .map(|addr| format!("{:#05X}", addr.0))
+        .collect();
+    lines.push(format!("STACK {}", stack.join(" ")));
+    lines.push(format!("KEYS  {:#06X}", state.keypad));
+    if let Some((p50, p99)) = state.emulation_time {
+        lines.push(timing_line("EMU", p50, p99));
+    }
+    lines.extend(state.watches.iter().cloned());
+    lines
+}
+
+/// A line with the median and 99th percentile of a duration per frame, e.g.
+/// `EMU    P50 0.12MS  P99 0.34MS`.
+pub fn timing_line(label: &str, p50: Duration, p99: Duration) -> String {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    format!("{:<6} P50 {:.2}MS  P99 {:.2}MS", label, ms(p50), ms(p99))
+}
+
+/// The lines that show which key on the keyboard presses which CHIP-8 key,
+/// laid out like the keypad.
+pub fn controls_lines(keymap: &HashMap<u8, Key>) -> Vec<String> {
+    let mut lines = vec![String::from("CONTROLS")];
+    for row in KEYPAD_ROWS.iter() {
+        let line: String = row
+            .iter()
+            .map(|chip8_key| {
+                let key = keymap
+                    .get(chip8_key)
+                    .map_or(String::from("-"), |key| format!("{:?}", key));
+                format!(
+                    "{:<width$}",
+                    format!("{:X} {}", chip8_key, key),
+                    width = CONTROLS_COLUMN_WIDTH
+                )
+            })
+            .collect();
+        lines.push(line.trim_end().to_uppercase());
+    }
+    lines
+}
+
+/// Draws the state of the core in the top left corner of the window, on a
+/// translucent background, followed by the lines of the frontend.
+pub fn draw_overlay(window: &mut RenderWindow, state: &CpuState, frontend_lines: &[String]) {
+    let mut lines = overlay_lines(state);
+    lines.extend_from_slice(frontend_lines);
+    draw_lines(window, &lines);
+}
+
+/// Draws lines of text like the overlay. Characters without a glyph are
+/// shown as question marks.
+pub fn draw_lines(window: &mut RenderWindow, lines: &[String]) {
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    let width = 2 * MARGIN + columns * (GLYPH_WIDTH + SPACING) - SPACING;
+    let height = 2 * MARGIN + lines.len() * (GLYPH_HEIGHT + SPACING) - SPACING;
+    let mut background = RectangleShape::new();
+    background.set_size(Vector2f::new(
+        width as f32 * FONT_SCALE,
+        height as f32 * FONT_SCALE,
+    ));
+    background.set_fill_color(BACKGROUND_COLOR);
+    window.draw(&background);
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = MARGIN + row * (GLYPH_HEIGHT + SPACING);
+        let position = Vector2f::new(MARGIN as f32, top as f32) * FONT_SCALE;
+        draw_text(window, position, line, TEXT_COLOR);
+    }
+}
+
+/// Draws a line of text in the font of the overlay, without a background,
+/// with its top left corner at `position` in window pixels.
+pub fn draw_text(window: &mut RenderWindow, position: Vector2f, line: &str, color: Color) {
+    let mut pixel = RectangleShape::new();
+    pixel.set_size(Vector2f::new(FONT_SCALE, FONT_SCALE));
+    pixel.set_fill_color(color);
+    for (column, c) in line.chars().enumerate() {
+        let left = column * (GLYPH_WIDTH + SPACING);
+        for (y, bits) in glyph(c).iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if bits >> (GLYPH_WIDTH - 1 - x) & 1 == 1 {
+                    pixel.set_position(
+                        position + Vector2f::new((left + x) as f32, y as f32) * FONT_SCALE,
+                    );
+                    window.draw(&pixel);
+                }
+            }
+        }
+    }
+}
+
+/// The size of a line of `chars` characters drawn by `draw_text`, in window
+/// pixels.
+pub fn text_size(chars: usize) -> Vector2f {
+    let width = (chars * (GLYPH_WIDTH + SPACING)).saturating_sub(SPACING);
+    Vector2f::new(width as f32, GLYPH_HEIGHT as f32) * FONT_SCALE
+}
+
+/// Draws a thumbnail, indexed as `[x][y]`, with its top left corner at
+/// `position` and `scale` window pixels per thumbnail pixel.
+pub fn draw_thumbnail(
+    window: &mut RenderWindow,
+    position: Vector2f,
+    thumbnail: &[Vec<Rgb>],
+    scale: f32,
+) {
+    let mut pixel = RectangleShape::new();
+    pixel.set_size(Vector2f::new(scale, scale));
+    for (x, column) in thumbnail.iter().enumerate() {
+        for (y, [r, g, b]) in column.iter().enumerate() {
+            pixel.set_position(position + Vector2f::new(x as f32, y as f32) * scale);
+            pixel.set_fill_color(Color::rgb(*r, *g, *b));
+            window.draw(&pixel);
+        }
+    }
+}
+
+/// The title of the save state slot picker.
+pub fn slot_title(slot: u8, thumbnail: Option<&[Vec<Rgb>]>) -> String {
+    match thumbnail {
+        Some(_) => format!("SLOT {}", slot),
+        None => format!("SLOT {}  EMPTY", slot),
+    }
+}
+
+/// Draws the selected save state slot with the thumbnail of its state,
+/// indexed as `[x][y]`, below the title.
+pub fn draw_slot(window: &mut RenderWindow, slot: u8, thumbnail: Option<&[Vec<Rgb>]>) {
+    draw_lines(window, &[slot_title(slot, thumbnail)]);
+    let thumbnail = match thumbnail {
+        Some(thumbnail) => thumbnail,
+        None => return,
+    };
+    let top = (2 * MARGIN + GLYPH_HEIGHT) as f32 * FONT_SCALE;
+    let width = thumbnail.len();
+    let height = thumbnail.first().map_or(0, Vec::len);
+    let margin = MARGIN as f32 * FONT_SCALE;
+    let mut background = RectangleShape::new();
+    background.set_position(Vector2f::new(0.0, top));
+    background.set_size(Vector2f::new(
+        width as f32 * THUMBNAIL_SCALE + 2.0 * margin,
+        height as f32 * THUMBNAIL_SCALE + margin,
+    ));
+    background.set_fill_color(BACKGROUND_COLOR);
+    window.draw(&background);
+    draw_thumbnail(
+        window,
+        Vector2f::new(margin, top),
+        thumbnail,
+        THUMBNAIL_SCALE,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::basics::{Address, Value};
+
+    #[test]
+    fn test_controls_lines() {
+        let keymap: HashMap<u8, Key> = [(0x1, Key::Num1), (0xC, Key::Num4), (0xA, Key::Z)]
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(
+            controls_lines(&keymap),
+            vec![
+                "CONTROLS",
+                "1 NUM1     2 -        3 -        C NUM4",
+                "4 -        5 -        6 -        D -",
+                "7 -        8 -        9 -        E -",
+                "A Z        0 -        B -        F -",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlay_lines() {
+        let mut registers = [Value(0); 16];
+        registers[0xA] = Value(0x3C);
+        let state = CpuState {
+            pc: Address(0x2A4),
+            next: String::from("DRW V0, V1, 5"),
+            registers,
+            i: Address(0x300),
+            delay_timer: Value(12),
+            sound_timer: Value(0),
+            stack: vec![Address(0x204), Address(0x22A)],
+            draw_collisions: 3,
+            keypad: 0x0088,
+            watches: vec![String::from("score = 0x0A")],
+            emulation_time: Some((Duration::from_micros(120), Duration::from_micros(2500))),
+        };
+        let lines = overlay_lines(&state);
+        assert_eq!(lines[0], "PC 0x2A4  DRW V0, V1, 5");
+        assert_eq!(lines[1], "I  0x300  DT 0C  ST 00  COLLISIONS 3");
+        assert_eq!(lines[4], "V8 00  V9 00  VA 3C  VB 00");
+        assert_eq!(lines[6], "STACK 0x204 0x22A");
+        assert_eq!(lines[7], "KEYS  0x0088");
+        assert_eq!(lines[8], "EMU    P50 0.12MS  P99 2.50MS");
+        assert_eq!(lines[9], "score = 0x0A");
+        // Every character of the overlay has a glyph.
+        for c in lines.concat().chars() {
+            assert_ne!(glyph(c), glyph('?'));
+        }
+        assert_eq!(glyph('s'), glyph('S'));
+        assert_eq!(glyph('#'), glyph('?'));
+    }
+
+    #[test]
+    fn test_slot_title() {
+        assert_eq!(slot_title(3, Some(&[vec![[0; 3]]])), "SLOT 3");
+        assert_eq!(slot_title(0, None), "SLOT 0  EMPTY");
+    }
+}
@@ -0,0 +1,173 @@
+use sfml::window::Key;
+use std::collections::HashMap;
+
+/// Where a keymap changed in the window is saved, e.g. the ROM's entry in
+/// the config.
+pub trait KeymapStore: Send {
+    /// Saves the keymap and returns where it was saved.
+    fn save(&mut self, keymap: &HashMap<u8, Key>) -> Result<String, String>;
+}
+
+/// The CHIP-8 key that a key of the keyboard names, by its hex digit.
+fn hex_digit(key: Key) -> Option<u8> {
+    let code = key as i32;
+    if (Key::Num0 as i32..=Key::Num9 as i32).contains(&code) {
+        Some((code - Key::Num0 as i32) as u8)
+    } else if (Key::Numpad0 as i32..=Key::Numpad9 as i32).contains(&code) {
+        Some((code - Key::Numpad0 as i32) as u8)
+    } else if (Key::A as i32..=Key::F as i32).contains(&code) {
+        Some((code - Key::A as i32) as u8 + 0xA)
+    } else {
+        None
+    }
+}
+
+/// The result of a key pressed while remapping.
+#[derive(Debug, PartialEq)]
+pub enum RemapStep {
+    /// Nothing changed yet.
+    Waiting,
+    /// The CHIP-8 key was mapped to another key.
+    Remapped(u8),
+    /// The remap mode was left.
+    Done,
+}
+
+/// The remap mode, in which the keymap is changed by first pressing the hex
+/// digit of a CHIP-8 key, then the key that should press it.
+#[derive(Default)]
+pub struct Remap {
+    active: bool,
+    /// The CHIP-8 key that waits for its new key.
+    chip8_key: Option<u8>,
+    /// Why the last key was not accepted.
+    rejected: Option<String>,
+}
+
+impl Remap {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Enters or leaves the remap mode.
+    pub fn toggle(&mut self) {
+        *self = Remap {
+            active: !self.active,
+            ..Remap::default()
+        };
+    }
+
+    /// Handles a key pressed in the remap mode. Escape leaves the mode. A key
+    /// that is `reserved`, e.g. as hotkey, cannot be mapped. The CHIP-8 key
+    /// that had the new key before gets the old key of the remapped one.
+    pub fn key_pressed(
+        &mut self,
+        key: Key,
+        reserved: bool,
+        keymap: &mut HashMap<u8, Key>,
+    ) -> RemapStep {
+        self.rejected = None;
+        if key == Key::Escape {
+            self.toggle();
+            return RemapStep::Done;
+        }
+        let chip8_key = match self.chip8_key {
+            Some(chip8_key) => chip8_key,
+            None => {
+                self.chip8_key = hex_digit(key);
+                if self.chip8_key.is_none() {
+                    self.rejected = Some(format!("{:?} is not 0-F", key));
+                }
+                return RemapStep::Waiting;
+            }
+        };
+        if reserved {
+            self.rejected = Some(format!("{:?} cannot be used", key));
+            return RemapStep::Waiting;
+        }
+        let old_key = keymap.insert(chip8_key, key);
+        let previous_owner = keymap
+            .iter()
+            .find(|(other, k)| **k == key && **other != chip8_key)
+            .map(|(other, _)| *other);
+        if let Some(other) = previous_owner {
+            match old_key {
+                Some(old_key) => keymap.insert(other, old_key),
+                None => keymap.remove(&other),
+            };
+        }
+        self.chip8_key = None;
+        RemapStep::Remapped(chip8_key)
+    }
+
+    /// The prompt shown on the screen while remapping, in the characters of
+    /// the overlay font.
+    pub fn prompt(&self, keymap: &HashMap<u8, Key>) -> Vec<String> {
+        let mut lines = vec![String::from("REMAP KEYS, ESC TO END")];
+        match self.chip8_key {
+            Some(chip8_key) => {
+                let current = keymap
+                    .get(&chip8_key)
+                    .map_or(String::from("NONE"), |key| format!("{:?}", key));
+                lines.push(format!("CHIP-8 KEY {:X} = {}", chip8_key, current));
+                lines.push(String::from("PRESS THE NEW KEY"));
+            }
+            None => lines.push(String::from("PRESS CHIP-8 KEY 0-F")),
+        }
+        if let Some(rejected) = &self.rejected {
+            lines.push(rejected.clone());
+        }
+        lines.iter().map(|line| line.to_uppercase()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_digit() {
+        assert_eq!(hex_digit(Key::Num7), Some(7));
+        assert_eq!(hex_digit(Key::Numpad0), Some(0));
+        assert_eq!(hex_digit(Key::F), Some(0xF));
+        assert_eq!(hex_digit(Key::G), None);
+    }
+
+    #[test]
+    fn test_remap() {
+        let mut keymap: HashMap<u8, Key> = [(1, Key::Q), (2, Key::W)].iter().copied().collect();
+        let mut remap = Remap::default();
+        remap.toggle();
+        assert!(remap.is_active());
+
+        // Not a hex digit, then a hotkey.
+        assert_eq!(
+            remap.key_pressed(Key::X, false, &mut keymap),
+            RemapStep::Waiting
+        );
+        assert_eq!(remap.prompt(&keymap)[2], "X IS NOT 0-F");
+        assert_eq!(
+            remap.key_pressed(Key::Num1, false, &mut keymap),
+            RemapStep::Waiting
+        );
+        assert_eq!(remap.prompt(&keymap)[1], "CHIP-8 KEY 1 = Q");
+        assert_eq!(
+            remap.key_pressed(Key::F1, true, &mut keymap),
+            RemapStep::Waiting
+        );
+
+        // Taking the key of CHIP-8 key 2 swaps the keys.
+        assert_eq!(
+            remap.key_pressed(Key::W, false, &mut keymap),
+            RemapStep::Remapped(1)
+        );
+        assert_eq!(keymap[&1], Key::W);
+        assert_eq!(keymap[&2], Key::Q);
+
+        assert_eq!(
+            remap.key_pressed(Key::Escape, false, &mut keymap),
+            RemapStep::Done
+        );
+        assert!(!remap.is_active());
+    }
+}
@@ -0,0 +1,76 @@
+use super::filter::Frame;
+use sfml::graphics::Color;
+use std::fmt::Write;
+
+/// The size of one CHIP-8 pixel in the exported image, before scaling.
+const SVG_SCALE: u32 = 10;
+
+fn hex(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Renders the visible `width` x `height` part of a frame as an SVG image of
+/// one square per lit pixel, keeping the colors and transparency of the
+/// frame on top of the background color.
+pub fn frame_to_svg(frame: &Frame, width: u8, height: u8, background: Color) -> String {
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" shape-rendering="crispEdges">"#,
+        width as u32 * SVG_SCALE,
+        height as u32 * SVG_SCALE,
+        width,
+        height
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"<rect width="{}" height="{}" fill="{}"/>"#,
+        width,
+        height,
+        hex(background)
+    )
+    .unwrap();
+    for (x, column) in frame.iter().enumerate().take(width as usize) {
+        for (y, color) in column.iter().enumerate().take(height as usize) {
+            if color.a == 0 {
+                continue;
+            }
+            write!(
+                svg,
+                r#"<rect x="{}" y="{}" width="1" height="1" fill="{}""#,
+                x,
+                y,
+                hex(*color)
+            )
+            .unwrap();
+            if color.a < 255 {
+                write!(svg, r#" fill-opacity="{:.3}""#, color.a as f32 / 255.0).unwrap();
+            }
+            svg.push_str("/>\n");
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::basics::{HIRES_SCREEN_HEIGHT, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn test_frame_to_svg() {
+        let mut frame = [[Color::TRANSPARENT; HIRES_SCREEN_HEIGHT as usize]; SCREEN_WIDTH as usize];
+        frame[2][3] = Color::RED;
+        frame[4][5] = Color::rgba(255, 255, 255, 51);
+        // Outside of the visible height.
+        frame[0][40] = Color::WHITE;
+        let svg = frame_to_svg(&frame, SCREEN_WIDTH, SCREEN_HEIGHT, Color::BLACK);
+        assert!(svg.contains(r#"viewBox="0 0 64 32""#));
+        assert!(svg.contains(r##"<rect width="64" height="32" fill="#000000"/>"##));
+        assert!(svg.contains(r##"<rect x="2" y="3" width="1" height="1" fill="#ff0000"/>"##));
+        assert!(svg.contains(r#"fill-opacity="0.200""#));
+        assert_eq!(svg.matches("<rect").count(), 3);
+    }
+}
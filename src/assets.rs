@@ -0,0 +1,106 @@
+use std::{env, path::PathBuf};
+
+/// The name of the emulator's directories in the platform locations.
+const APP_DIR: &str = "chip8";
+/// A file that marks the working directory as a checkout of this repository,
+/// whose configuration is used in place of the platform location.
+const CHECKOUT_MARKER: &str = "roms.toml";
+
+/// Where the emulator reads its configuration and ROMs from and writes the
+/// files it creates (thumbnails, save states, screenshots) to.
+///
+/// Each directory is taken from a `--<name>-dir=` flag, then from a
+/// `CHIP8_<NAME>_DIR` environment variable, and otherwise from the platform
+/// location: `$XDG_CONFIG_HOME`/`$XDG_DATA_HOME` on Linux,
+/// `~/Library/Application Support` on macOS and `%APPDATA%` on Windows.
+/// When started from a checkout of this repository, its files are used.
+pub struct Assets {
+    config_dir: PathBuf,
+    rom_dir: PathBuf,
+    data_dir: PathBuf,
+}
+
+/// Looks up a directory override given as `--<name>-dir=` or `CHIP8_<NAME>_DIR`.
+fn dir_override(flags: &[String], name: &str) -> Option<PathBuf> {
+    let prefix = format!("--{}-dir=", name);
+    flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix(prefix.as_str()))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os(format!("CHIP8_{}_DIR", name.to_uppercase())).map(PathBuf::from))
+}
+
+/// Reads a directory from an environment variable, ignoring empty values.
+fn env_dir(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(target_os = "windows")]
+fn platform_dirs() -> Option<(PathBuf, PathBuf)> {
+    let app_data = env_dir("APPDATA")?;
+    Some((app_data.clone(), app_data))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_dirs() -> Option<(PathBuf, PathBuf)> {
+    let support = env_dir("HOME")?.join("Library/Application Support");
+    Some((support.clone(), support))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_dirs() -> Option<(PathBuf, PathBuf)> {
+    let home = env_dir("HOME");
+    let config = env_dir("XDG_CONFIG_HOME").or_else(|| Some(home.clone()?.join(".config")))?;
+    let data = env_dir("XDG_DATA_HOME").or_else(|| Some(home?.join(".local/share")))?;
+    Some((config, data))
+}
+
+impl Assets {
+    /// Resolves the directories from the command line flags, the environment
+    /// and the platform.
+    pub fn resolve(flags: &[String]) -> Assets {
+        let in_checkout = PathBuf::from(CHECKOUT_MARKER).is_file();
+        let (platform_config, platform_data) = match platform_dirs() {
+            Some((config, data)) if !in_checkout => (config.join(APP_DIR), data.join(APP_DIR)),
+            _ => (PathBuf::from("."), PathBuf::from(".")),
+        };
+        let config_dir = dir_override(flags, "config").unwrap_or(platform_config);
+        Assets {
+            rom_dir: dir_override(flags, "rom").unwrap_or_else(|| config_dir.clone()),
+            data_dir: dir_override(flags, "data").unwrap_or(platform_data),
+            config_dir,
+        }
+    }
+
+    /// A configuration file such as `roms.toml` or `settings.toml`.
+    pub fn config_file(&self, name: &str) -> PathBuf {
+        self.config_dir.join(name)
+    }
+
+    /// A ROM file as named in `roms.toml`. Absolute paths are kept as they are.
+    pub fn rom_file(&self, name: &str) -> PathBuf {
+        self.rom_dir.join(name)
+    }
+
+    /// The directory for screenshots.
+    pub fn screenshot_dir(&self) -> PathBuf {
+        self.data_dir.join("screenshots")
+    }
+
+    /// The directory for the save state slots of a ROM.
+    pub fn save_state_dir(&self, rom_name: &str) -> PathBuf {
+        self.data_dir.join("states").join(rom_name)
+    }
+
+    /// The directory for sprite sheets exported with `--dump-sprites`.
+    pub fn sprite_dir(&self) -> PathBuf {
+        self.data_dir.join("sprites")
+    }
+
+    /// The directory for cached ROM thumbnails.
+    pub fn thumbnail_dir(&self) -> PathBuf {
+        self.data_dir.join(".thumbnails")
+    }
+}
@@ -0,0 +1,145 @@
+use crate::rom_config::RomInfo;
+use chip8::emulator::basics::HIRES_SCREEN_HEIGHT;
+use chip8::emulator::core::Chip8Core;
+use chip8::emulator::quirks::{Quirks, PRESET_NAMES};
+use chip8::emulator::vm::{StepOutcome, VirtualMachine};
+use std::any::Any;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+/// How long every quirks preset is probed, in frames.
+const PROBE_FRAMES: u32 = 10 * 60;
+/// The default instruction sleep of 2ms at 60 frames per second.
+const PROBE_INSTRUCTIONS_PER_FRAME: u32 = 8;
+
+/// How a ROM behaved during a probe run.
+enum ProbeResult {
+    /// Ran all frames; tells whether anything was drawn and how it ended.
+    Ran {
+        drew: bool,
+        last: StepOutcome,
+    },
+    Crashed(String),
+}
+
+/// The message of a caught panic.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or_else(
+            || "unknown error".to_string(),
+            |message| message.to_string(),
+        ),
+    }
+}
+
+/// Runs a ROM headlessly without input under the given quirks.
+fn probe(program: &[u8], quirks: Quirks) -> ProbeResult {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut vm = VirtualMachine::with_quirks(program, quirks);
+        let empty = vm.display_hash();
+        let mut drew = false;
+        let mut last = StepOutcome::Continued;
+        for _ in 0..PROBE_FRAMES {
+            for _ in 0..PROBE_INSTRUCTIONS_PER_FRAME {
+                last = vm.step().map_err(|e| e.to_string())?;
+            }
+            vm.interface.lock().unwrap().tick_timers();
+            drew |= vm.display_hash() != empty;
+        }
+        Ok((drew, last))
+    }));
+    match result {
+        Ok(Ok((drew, last))) => ProbeResult::Ran { drew, last },
+        Ok(Err(message)) => ProbeResult::Crashed(message),
+        Err(payload) => ProbeResult::Crashed(panic_message(payload)),
+    }
+}
+
+/// Checks a ROM file and prints a report with a suggested `roms.toml` entry,
+/// including the notes of `info` if the ROM is known.
+/// Returns false if the file cannot be read or no preset runs it.
+pub fn run_doctor(path: &str, info: Option<RomInfo>) -> bool {
+    let program = match fs::read(path) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", path, e);
+            return false;
+        }
+    };
+    println!("ROM: {} ({} bytes)", path, program.len());
+    let hires = VirtualMachine::new(&program).screen_height() == HIRES_SCREEN_HEIGHT;
+    println!(
+        "Variant: {}",
+        if hires {
+            "two-page hi-res CHIP-8 (64x64)"
+        } else {
+            "CHIP-8 (64x32)"
+        }
+    );
+    let info = info.unwrap_or_default();
+    for line in info.lines() {
+        println!("{}", line);
+    }
+
+    // Probing panics on purpose; keep the panic messages for the report.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results: Vec<_> = PRESET_NAMES
+        .iter()
+        .map(|name| (*name, probe(&program, Quirks::preset(name).unwrap())))
+        .collect();
+    panic::set_hook(hook);
+
+    println!("Quirks presets, {} frames without input:", PROBE_FRAMES);
+    for (name, result) in results.iter() {
+        match result {
+            ProbeResult::Ran { drew, last } => println!(
+                "  {:8} ran, {}, last step {:?}",
+                name,
+                if *drew {
+                    "drew to the screen"
+                } else {
+                    "drew nothing"
+                },
+                last
+            ),
+            ProbeResult::Crashed(message) => println!("  {:8} crashed: {}", name, message),
+        }
+    }
+
+    // Prefer presets that draw something, then the order of PRESET_NAMES.
+    let recommended = results
+        .iter()
+        .filter_map(|(name, result)| match result {
+            ProbeResult::Ran { drew, .. } => Some((!*drew, *name)),
+            ProbeResult::Crashed(_) => None,
+        })
+        .min();
+    match recommended {
+        Some((_, preset)) => {
+            let name = Path::new(path)
+                .file_stem()
+                .map_or("rom".to_string(), |stem| {
+                    stem.to_string_lossy().to_lowercase()
+                });
+            println!("\nSuggested roms.toml entry:\n");
+            println!("[{}]\nfilename = \"{}\"", name, path);
+            if preset != "default" {
+                println!("quirks = \"{}\"", preset);
+            }
+            if let Some(recommended) = info.recommended_quirks.filter(|name| name != preset) {
+                println!(
+                    "\nNote: roms.toml recommends the \"{}\" preset instead.",
+                    recommended
+                );
+            }
+            true
+        }
+        None => {
+            println!("\nNo quirks preset runs this ROM.");
+            false
+        }
+    }
+}
@@ -1,4 +0,0 @@
-pub mod basics;
-pub mod executor;
-pub mod program;
-pub mod vm;
@@ -0,0 +1,163 @@
+use sfml::window::Key;
+use std::collections::HashMap;
+
+/// The keymap of ROMs that do not choose one.
+pub const DEFAULT_PRESET: &str = "hex-pad";
+
+/// Older names of the built-in presets, still accepted in configs.
+const ALIASES: [(&str, &str); 2] = [("default", "hex-pad"), ("table", "left-hand")];
+
+/// Every CHIP-8 key on the key of its hex digit.
+const HEX_PAD: [(u8, Key); 16] = [
+    (0x0, Key::Num0),
+    (0x1, Key::Num1),
+    (0x2, Key::Num2),
+    (0x3, Key::Num3),
+    (0x4, Key::Num4),
+    (0x5, Key::Num5),
+    (0x6, Key::Num6),
+    (0x7, Key::Num7),
+    (0x8, Key::Num8),
+    (0x9, Key::Num9),
+    (0xA, Key::A),
+    (0xB, Key::B),
+    (0xC, Key::C),
+    (0xD, Key::D),
+    (0xE, Key::E),
+    (0xF, Key::F),
+];
+
+/// The 4x4 keypad of the COSMAC VIP on the left hand block of a QWERTZ
+/// keyboard, from 1234 down to YXCV.
+const LEFT_HAND: [(u8, Key); 16] = [
+    (0x1, Key::Num1),
+    (0x2, Key::Num2),
+    (0x3, Key::Num3),
+    (0xC, Key::Num4),
+    (0x4, Key::Q),
+    (0x5, Key::W),
+    (0x6, Key::E),
+    (0xD, Key::R),
+    (0x7, Key::A),
+    (0x8, Key::S),
+    (0x9, Key::D),
+    (0xE, Key::F),
+    (0xA, Key::Y),
+    (0x0, Key::X),
+    (0xB, Key::C),
+    (0xF, Key::V),
+];
+
+/// The directions of the hex pad (2, 4, 6, 8) and 5 in its middle on WASD
+/// and Space, the other digits on their keys.
+const WASD: [(u8, Key); 10] = [
+    (0x2, Key::W),
+    (0x4, Key::A),
+    (0x6, Key::D),
+    (0x8, Key::S),
+    (0x5, Key::Space),
+    (0x0, Key::Num0),
+    (0x1, Key::Num1),
+    (0x3, Key::Num3),
+    (0x7, Key::Num7),
+    (0x9, Key::Num9),
+];
+
+/// Like `WASD`, but on the arrow keys, with the letters on their keys.
+const ARROWS: [(u8, Key); 16] = [
+    (0x2, Key::Up),
+    (0x4, Key::Left),
+    (0x6, Key::Right),
+    (0x8, Key::Down),
+    (0x5, Key::Space),
+    (0x0, Key::Num0),
+    (0x1, Key::Num1),
+    (0x3, Key::Num3),
+    (0x7, Key::Num7),
+    (0x9, Key::Num9),
+    (0xA, Key::A),
+    (0xB, Key::B),
+    (0xC, Key::C),
+    (0xD, Key::D),
+    (0xE, Key::E),
+    (0xF, Key::F),
+];
+
+/// The keymaps that ROM configs refer to by name: the built-in presets and
+/// those defined in the config.
+pub struct KeymapPresets {
+    presets: HashMap<String, HashMap<u8, Key>>,
+}
+
+impl KeymapPresets {
+    pub fn builtin() -> KeymapPresets {
+        let presets = [
+            ("hex-pad", &HEX_PAD[..]),
+            ("left-hand", &LEFT_HAND[..]),
+            ("wasd", &WASD[..]),
+            ("arrows", &ARROWS[..]),
+        ];
+        KeymapPresets {
+            presets: presets
+                .iter()
+                .map(|(name, keys)| (String::from(*name), keys.iter().copied().collect()))
+                .collect(),
+        }
+    }
+
+    /// Adds a preset defined in the config. Built-in presets cannot be
+    /// replaced.
+    pub fn add(&mut self, name: &str, keymap: HashMap<u8, Key>) -> Result<(), String> {
+        if self.get(name).is_some() {
+            return Err(format!("keymap \"{}\" is already defined", name));
+        }
+        self.presets.insert(String::from(name), keymap);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HashMap<u8, Key>> {
+        let name = ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, preset)| *preset);
+        self.presets.get(name)
+    }
+
+    /// The names of all presets, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.presets.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aliases() {
+        let presets = KeymapPresets::builtin();
+        assert_eq!(presets.get("default"), presets.get("hex-pad"));
+        assert_eq!(presets.get("table"), presets.get("left-hand"));
+        assert_eq!(presets.get("hex-pad").unwrap()[&0xA], Key::A);
+        assert_eq!(presets.get("left-hand").unwrap()[&0xA], Key::Y);
+        assert!(presets.get("numpad").is_none());
+        assert_eq!(presets.names(), ["arrows", "hex-pad", "left-hand", "wasd"]);
+    }
+
+    #[test]
+    fn test_add() {
+        let mut presets = KeymapPresets::builtin();
+        let numpad: HashMap<_, _> = [(0x2, Key::Numpad8)].into();
+        presets.add("numpad", numpad.clone()).unwrap();
+        assert_eq!(presets.get("numpad"), Some(&numpad));
+        for name in ["hex-pad", "wasd", "default", "table", "numpad"] {
+            assert_eq!(
+                presets.add(name, HashMap::new()),
+                Err(format!("keymap \"{}\" is already defined", name))
+            );
+        }
+        assert_eq!(presets.get("default").unwrap()[&0x2], Key::Num2);
+    }
+}
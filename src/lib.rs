@@ -1,2 +1,3 @@
-pub mod emulator;
-pub mod visualizer;
+pub use chip8_core as emulator;
+#[cfg(feature = "gui")]
+pub use chip8_frontends::visualizer;
@@ -1,15 +1,329 @@
-mod emulator;
+mod assets;
+mod doctor;
+mod keymaps;
+mod octo;
 mod rom_config;
-mod visualizer;
+mod script;
+mod settings;
+mod states;
+mod terminal_debugger;
+mod test_suite;
+mod thread_options;
+mod thumbnails;
 
-use rom_config::load_rom;
-use std::sync::{Arc, Mutex};
+use assets::Assets;
+use chip8::emulator::cfg::ControlFlowGraph;
+use chip8::emulator::core::Chip8Core;
+use chip8::emulator::movie::Movie;
+use chip8::emulator::self_test::run_self_test;
+use chip8::emulator::{asm, decompile, disasm, octo_asm};
+use doctor::run_doctor;
+use rom_config::{
+    check_frame_hashes, choose_rom, compare_quirks, dump_sprites, generate_thumbnails, load_rom,
+    load_vm, record_frame_hashes, rom_info, LaunchOptions,
+};
+use script::run_script;
+use settings::{load_hotkeys, load_thread_options};
+use states::{export_state, import_state};
+use std::path::Path;
+use std::{env, fs, process};
+use terminal_debugger::run_terminal_debugger;
+use test_suite::run_test_suite;
+
+/// Prints the disassembly of a ROM file. Returns false if it cannot be read.
+fn print_disassembly(path: &str) -> bool {
+    match fs::read(path) {
+        Ok(program) => {
+            for line in disasm::disassemble(&program) {
+                println!("{}", line);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Prints the labelled assembly of a ROM file. Returns false if it cannot be
+/// read.
+fn print_decompiled(path: &str) -> bool {
+    match fs::read(path) {
+        Ok(program) => {
+            print!("{}", decompile::decompile(&program));
+            true
+        }
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Prints the control flow graph of a ROM file in the DOT language. Returns
+/// false if it cannot be read.
+fn print_cfg(path: &str) -> bool {
+    match fs::read(path) {
+        Ok(program) => {
+            print!("{}", ControlFlowGraph::new(&program).to_dot());
+            true
+        }
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Assembles a source file into a ROM file, as Octo code if it has the
+/// extension `.8o`. Returns false if it cannot be read, assembled or written.
+fn assemble_file(source_path: &str, output: &Path) -> bool {
+    let source = match fs::read_to_string(source_path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", source_path, e);
+            return false;
+        }
+    };
+    let octo = Path::new(source_path)
+        .extension()
+        .is_some_and(|ext| ext == "8o");
+    let assembled = if octo {
+        octo_asm::assemble(&source)
+    } else {
+        asm::assemble(&source)
+    };
+    let program = match assembled {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}: {}", source_path, e);
+            return false;
+        }
+    };
+    match fs::write(output, &program) {
+        Ok(()) => {
+            println!("Wrote {} bytes to {}", program.len(), output.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("Cannot write {}: {}", output.display(), e);
+            false
+        }
+    }
+}
+
+/// Runs the built-in self test and prints the result of every case. Returns
+/// false if a case failed.
+fn print_self_test() -> bool {
+    let results = run_self_test();
+    for case in &results {
+        match &case.result {
+            Ok(()) => println!("pass  {}", case.name),
+            Err(e) => println!("FAIL  {}: {}", case.name, e),
+        }
+    }
+    let passed = results.iter().filter(|case| case.result.is_ok()).count();
+    println!("{} of {} checks passed.", passed, results.len());
+    passed == results.len()
+}
+
+/// Replays a movie on a ROM without a window and prints the state it ends
+/// in. Returns false if the movie cannot be read or the program fails.
+fn play_movie(assets: &Assets, rom: &str, path: &str) -> bool {
+    let movie = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Movie::parse(&text))
+    {
+        Ok(movie) => movie,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", path, e);
+            return false;
+        }
+    };
+    let (mut vm, _) = load_vm(assets, rom, None);
+    let result = movie.replay(&mut vm);
+    println!("{}", vm.state_summary(false));
+    println!(
+        "After {} frames, the display hash is {:016x}.",
+        movie.frames(),
+        vm.display_hash()
+    );
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Error: {}, the program stopped.", e);
+            false
+        }
+    }
+}
 
 fn main() {
-    let (executor, vis) = load_rom("connect4");
-    let stop_vm = Arc::new(Mutex::new(false));
-    vis.wait_for_init();
-    executor.run_concurrent_until(stop_vm.clone());
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let assets = Assets::resolve(&flags);
+    let ok = match args.as_slice() {
+        [command, path] if command == "disasm" => Some(print_disassembly(path)),
+        [command, path] if command == "decompile" => Some(print_decompiled(path)),
+        [command, path] if command == "analyze" => {
+            if flags.iter().any(|flag| flag == "--cfg") {
+                Some(print_cfg(path))
+            } else {
+                eprintln!("analyze needs the kind of analysis, e.g. --cfg");
+                Some(false)
+            }
+        }
+        [command, source] if command == "asm" => Some(assemble_file(
+            source,
+            &Path::new(source).with_extension("ch8"),
+        )),
+        [command, source, flag, output] if command == "asm" && flag == "-o" => {
+            Some(assemble_file(source, Path::new(output)))
+        }
+        [command, suite, dir] if command == "test" => {
+            if flags.iter().any(|flag| flag == "--suite") {
+                let update = flags.iter().any(|flag| flag == "--update");
+                Some(run_test_suite(suite, dir, update))
+            } else {
+                eprintln!("test needs the kind of tests, e.g. --suite timendus <dir>");
+                Some(false)
+            }
+        }
+        [command, action, rom, slot] if command == "state" && action == "export" => {
+            Some(export_state(&assets, rom, slot))
+        }
+        [command, action, path] if command == "state" && action == "import" => {
+            Some(import_state(&assets, path))
+        }
+        _ => None,
+    };
+    if let Some(ok) = ok {
+        process::exit(if ok { 0 } else { 1 });
+    }
+    if flags.iter().any(|flag| flag == "--self-test") {
+        process::exit(if print_self_test() { 0 } else { 1 });
+    }
+    let rom = args.first().map_or("connect4", String::as_str);
+    // Images of the logical display instead of what the window shows.
+    let raw_images = flags.iter().any(|flag| flag == "--raw");
+    let pause_in_background = flags.iter().any(|flag| flag == "--pause-in-background");
+    let min_beep_frames = match flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--min-beep="))
+    {
+        Some(frames) => frames.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid number of frames for --min-beep: {}", frames);
+            process::exit(2);
+        }),
+        None => 0,
+    };
+    let preroll_frames = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--preroll="))
+        .map(|frames| {
+            frames.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid number of frames for --preroll: {}", frames);
+                process::exit(2);
+            })
+        });
+    let seed = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--seed="))
+        .map(|seed| {
+            seed.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid seed for --seed: {}", seed);
+                process::exit(2);
+            })
+        });
+    if let Some(path) = flags.iter().find_map(|flag| flag.strip_prefix("--script=")) {
+        let passed = run_script(path);
+        process::exit(if passed { 0 } else { 1 });
+    }
+    if flags.iter().any(|flag| flag == "--doctor") {
+        let ok = run_doctor(rom, rom_info(&assets, rom));
+        process::exit(if ok { 0 } else { 1 });
+    }
+    if let Some(presets) = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--compare-quirks="))
+    {
+        let ok = compare_quirks(&assets, rom, presets);
+        process::exit(if ok { 0 } else { 2 });
+    }
+    if let Some(path) = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--record-hashes="))
+    {
+        let ok = record_frame_hashes(&assets, rom, path);
+        process::exit(if ok { 0 } else { 1 });
+    }
+    if let Some(path) = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--check-hashes="))
+    {
+        let ok = check_frame_hashes(&assets, rom, path);
+        process::exit(if ok { 0 } else { 1 });
+    }
+    if let Some(path) = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--play-movie="))
+    {
+        let ok = play_movie(&assets, rom, path);
+        process::exit(if ok { 0 } else { 1 });
+    }
+    if flags.iter().any(|flag| flag == "--debug") {
+        let (vm, instructions_per_frame) = load_vm(&assets, rom, seed);
+        run_terminal_debugger(vm, instructions_per_frame);
+        return;
+    }
+    if flags.iter().any(|flag| flag == "--dump-sprites") {
+        let ok = dump_sprites(&assets, rom, raw_images);
+        process::exit(if ok { 0 } else { 1 });
+    }
+    if flags.iter().any(|flag| flag == "--thumbnails") {
+        generate_thumbnails(&assets, raw_images);
+        return;
+    }
+    // Without a ROM, the window starts with a menu of the configured ROMs.
+    let chosen_rom = if args.is_empty() {
+        Some(choose_rom(&assets, raw_images).unwrap_or_else(|| process::exit(0)))
+    } else {
+        None
+    };
+    let rom = chosen_rom.as_deref().unwrap_or(rom);
+    let movie_path = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--record-movie="));
+    let launch = LaunchOptions {
+        pause_in_background,
+        min_beep_frames,
+        start_paused: flags.iter().any(|flag| flag == "--start-paused"),
+        // A movie starts at power-on, so nothing may run before it.
+        preroll_frames: if movie_path.is_some() {
+            Some(0)
+        } else {
+            preroll_frames
+        },
+        raw_images,
+        seed,
+    };
+    let (mut executor, vis) = load_rom(rom, launch, load_hotkeys(&assets), &assets);
+    let thread_options = load_thread_options(&assets);
+    executor.set_thread_setup(move || {
+        for warning in thread_options.apply() {
+            eprintln!("Warning: {}", warning);
+        }
+    });
+    if movie_path.is_some() {
+        executor.record_movie();
+    }
+    let executor = executor.run_concurrent();
     vis.wait_for_close();
-    *stop_vm.lock().unwrap() = true;
+    let mut executor = executor.join().unwrap();
+    if let (Some(path), Some(movie)) = (movie_path, executor.take_movie()) {
+        match fs::write(path, movie.to_text()) {
+            Ok(()) => println!("Recorded a movie of {} frames to {}.", movie.frames(), path),
+            Err(e) => eprintln!("Cannot write {}: {}", path, e),
+        }
+    }
 }
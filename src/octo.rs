@@ -0,0 +1,118 @@
+use chip8::emulator::quirks::Quirks;
+use serde::Deserialize;
+use sfml::graphics::Color;
+use std::{fs, io, path::Path, time::Duration};
+
+/// The length of a frame, which Octo's tick rate refers to.
+const FRAME_DURATION: Duration = Duration::from_micros(16667);
+/// The shortest time between two instructions, for huge tick rates.
+const MIN_INSTRUCTION_SLEEP: Duration = Duration::from_micros(1);
+
+/// The options Octo stores in a JSON file next to a ROM, e.g. `game.json`
+/// for `game.ch8`. Only fields with an equivalent in this emulator are read.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OctoOptions {
+    /// Instructions per frame.
+    tickrate: Option<u32>,
+    fill_color: Option<String>,
+    background_color: Option<String>,
+    /// FX55/FX65 leave I unchanged.
+    load_store_quirk: Option<bool>,
+    /// BNNN jumps to NNN + VX.
+    jump_quirks: Option<bool>,
+    /// Sprites are clipped instead of wrapping at the screen edges.
+    clip_quirks: Option<bool>,
+    /// 8XY1, 8XY2 and 8XY3 reset VF.
+    logic_quirks: Option<bool>,
+    /// 8XY6 and 8XYE shift VX in place instead of shifting VY into VX.
+    shift_quirks: Option<bool>,
+    /// DXYN waits for the vertical blank.
+    #[serde(rename = "vBlankQuirks")]
+    vblank_quirks: Option<bool>,
+}
+
+/// Parses a color in the form `#RRGGBB`.
+fn parse_color(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+impl OctoOptions {
+    /// Loads the options stored next to the given ROM file, if there are any.
+    pub fn load(rom_path: &Path) -> Option<OctoOptions> {
+        let path = rom_path.with_extension("json");
+        let options = fs::read_to_string(&path)
+            .and_then(|text| serde_json::from_str(&text).map_err(io::Error::other));
+        match options {
+            Ok(options) => Some(options),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                eprintln!("Ignoring Octo options {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// The time between two instructions for the configured tick rate.
+    pub fn instruction_sleep(&self) -> Option<Duration> {
+        self.tickrate
+            .filter(|tickrate| *tickrate > 0)
+            .map(|tickrate| (FRAME_DURATION / tickrate).max(MIN_INSTRUCTION_SLEEP))
+    }
+
+    /// Overrides the quirks that are set in the options.
+    pub fn apply_quirks(&self, quirks: &mut Quirks) {
+        if let Some(load_store) = self.load_store_quirk {
+            quirks.increment_i = !load_store;
+        }
+        if let Some(jump) = self.jump_quirks {
+            quirks.jump_vx = jump;
+        }
+        if let Some(clip) = self.clip_quirks {
+            quirks.wrap_x = !clip;
+            quirks.wrap_y = !clip;
+        }
+        if let Some(logic) = self.logic_quirks {
+            quirks.vf_reset = logic;
+        }
+        if let Some(shift) = self.shift_quirks {
+            quirks.shift_vy = !shift;
+        }
+        if let Some(vblank) = self.vblank_quirks {
+            quirks.vblank_wait = vblank;
+        }
+    }
+
+    pub fn fill_color(&self) -> Option<Color> {
+        self.fill_color.as_deref().and_then(parse_color)
+    }
+
+    pub fn background_color(&self) -> Option<Color> {
+        self.background_color.as_deref().and_then(parse_color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_sleep() {
+        let options = |tickrate| OctoOptions {
+            tickrate,
+            ..OctoOptions::default()
+        };
+        assert_eq!(options(None).instruction_sleep(), None);
+        assert_eq!(options(Some(0)).instruction_sleep(), None);
+        assert_eq!(
+            options(Some(7)).instruction_sleep(),
+            Some(Duration::from_nanos(2_381_000))
+        );
+        assert_eq!(
+            options(Some(u32::MAX)).instruction_sleep(),
+            Some(MIN_INSTRUCTION_SLEEP)
+        );
+    }
+}
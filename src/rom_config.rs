@@ -1,234 +1,1161 @@
-use crate::emulator::executor::Executor;
-use crate::emulator::vm::VirtualMachine;
-use crate::visualizer::Visualizer;
-use lazy_static::lazy_static;
+use crate::assets::Assets;
+use crate::keymaps::{KeymapPresets, DEFAULT_PRESET};
+use crate::octo::OctoOptions;
+use crate::thumbnails::{cache_thumbnail, read_thumbnail, ThumbnailStyle};
+use chip8::emulator::basics::{Address, MEMORY_SIZE};
+use chip8::emulator::compare::{
+    find_divergence, first_mismatch, format_trace, frame_hashes, parse_trace,
+};
+use chip8::emulator::executor::{Executor, Throttle};
+use chip8::emulator::quirks::Quirks;
+use chip8::emulator::rewind::RewindBuffer;
+use chip8::emulator::sprites::{SpriteRange, SpriteSheet};
+use chip8::emulator::testing::ScriptedKeypad;
+use chip8::emulator::vm::VirtualMachine;
+use chip8::emulator::watch::{Watch, WatchFormat, WatchWidth};
+use chip8::visualizer::beep::{Beep, Waveform};
+use chip8::visualizer::gamepad::GamepadInput;
+use chip8::visualizer::menu::{self, MenuEntry};
+use chip8::visualizer::remap::KeymapStore;
+use chip8::visualizer::{
+    display_filters, key_from_name, Hotkeys, Turbo, Visualizer, VisualizerOptions,
+};
+use serde::Deserialize;
+use sfml::graphics::Color;
 use std::collections::HashMap;
-use std::{fs::File, io::Read, time::Duration};
+use std::panic::{self, AssertUnwindSafe};
+use std::{
+    fs, iter,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
-const TIMER_INTERVAL: Duration = Duration::from_micros(16667);
+/// The 60 Hz tick of the timers and the display, unless a ROM sets `timer_hz`.
+const DEFAULT_TIMER_INTERVAL: Duration = Duration::from_micros(16667);
+/// The range of valid `timer_hz` values.
+const TIMER_HZ_RANGE: std::ops::RangeInclusive<f64> = 1.0..=1000.0;
+/// The range of valid `beep_hz` values, which are audible.
+const BEEP_HZ_RANGE: std::ops::RangeInclusive<u32> = 20..=20000;
+const DEFAULT_DISPLAY_FADE: u32 = 3;
+const DEFAULT_INSTRUCTION_SLEEP: Duration = Duration::from_millis(2);
+/// The size of a sprite pixel in the images of `dump_sprites`.
+const SPRITE_SCALE: usize = 8;
+/// How long `compare_quirks` runs a ROM, in frames.
+const COMPARE_FRAMES: u32 = 60 * 60;
+/// The configuration of the known ROMs, in the config directory.
+const ROMS_FILENAME: &str = "roms.toml";
 
 struct Config {
-    filename: &'static str,
+    filename: String,
     display_fade: u32,
     instruction_sleep: Duration,
+    /// The interval of timer ticks, which also end the frames of the display.
+    timer_interval: Duration,
+    /// Replaces the instruction sleep after drawing or waiting for a key.
+    throttle: Throttle,
     keymap: HashMap<u8, sfml::window::Key>,
+    gamepad: HashMap<u8, GamepadInput>,
+    turbo: Option<Turbo>,
+    key_latch: bool,
+    palette_cycle: bool,
+    beep: Beep,
+    /// Open the window paused, after running `preroll_frames` headlessly.
+    start_paused: bool,
+    preroll_frames: u32,
+    /// Seeds the random numbers, so that every run is the same.
+    seed: Option<u64>,
+    quirks: Quirks,
+    watches: Vec<Watch>,
+    /// Sprites exported by `dump_sprites` in addition to the font.
+    sprites: Vec<SpriteRange>,
+    /// Keys pressed right after boot, e.g. to get past a menu.
+    startup_input: ScriptedKeypad,
+    info: RomInfo,
 }
 
-lazy_static! {
-    static ref DEFAULT_KEYMAP: HashMap<u8, sfml::window::Key> = vec![
-        (0, sfml::window::Key::Num0),
-        (1, sfml::window::Key::Num1),
-        (2, sfml::window::Key::Num2),
-        (3, sfml::window::Key::Num3),
-        (4, sfml::window::Key::Num4),
-        (5, sfml::window::Key::Num5),
-        (6, sfml::window::Key::Num6),
-        (7, sfml::window::Key::Num7),
-        (8, sfml::window::Key::Num8),
-        (9, sfml::window::Key::Num9),
-        (10, sfml::window::Key::A),
-        (11, sfml::window::Key::B),
-        (12, sfml::window::Key::C),
-        (13, sfml::window::Key::D),
-        (14, sfml::window::Key::E),
-        (15, sfml::window::Key::F),
-    ]
-    .into_iter()
-    .collect();
-
-    static ref TABLE_KEYMAP: HashMap<u8, sfml::window::Key> = vec![
-        (0, sfml::window::Key::X),
-        (1, sfml::window::Key::Num1),
-        (2, sfml::window::Key::Num2),
-        (3, sfml::window::Key::Num3),
-        (4, sfml::window::Key::Q),
-        (5, sfml::window::Key::W),
-        (6, sfml::window::Key::E),
-        (7, sfml::window::Key::A),
-        (8, sfml::window::Key::S),
-        (9, sfml::window::Key::D),
-        (10, sfml::window::Key::Y),
-        (11, sfml::window::Key::C),
-        (12, sfml::window::Key::Num4),
-        (13, sfml::window::Key::R),
-        (14, sfml::window::Key::F),
-        (15, sfml::window::Key::V),
-    ]
-    .into_iter()
-    .collect();
-}
-
-lazy_static! {
-static ref ROM_MAP: HashMap<&'static str, Config> = vec![
-    ("15puzzle" , Config { 
-        filename: "roms/15PUZZLE",
-        display_fade: 1,
-        instruction_sleep: Duration::from_micros(100),
-        keymap: TABLE_KEYMAP.clone()
-    }),
-    ("blinky" , Config {
-        filename: "roms/BLINKY",
-        display_fade: 1,
-        instruction_sleep: Duration::from_millis(1),
-        keymap: vec![
-            (3, sfml::window::Key::Up),
-            (6, sfml::window::Key::Down),
-            (7, sfml::window::Key::Left),
-            (8, sfml::window::Key::Right),
+/// Descriptive notes about a ROM, shown when it is started and by the ROM
+/// doctor. They do not change how the ROM runs.
+#[derive(Clone, Default)]
+pub struct RomInfo {
+    pub author: Option<String>,
+    /// Where the ROM comes from, e.g. a URL.
+    pub source: Option<String>,
+    /// How to play, in terms of the keys of the keymap.
+    pub controls: Option<String>,
+    /// The quirks preset that the ROM was written for, checked to be valid.
+    pub recommended_quirks: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl RomInfo {
+    /// One "Label: text" line per field that is set.
+    pub fn lines(&self) -> Vec<String> {
+        [
+            ("Author", &self.author),
+            ("Source", &self.source),
+            ("Controls", &self.controls),
+            ("Recommended quirks", &self.recommended_quirks),
+            ("Notes", &self.notes),
         ]
-        .into_iter()
+        .iter()
+        .filter_map(|(label, text)| text.as_ref().map(|text| format!("{}: {}", label, text)))
         .collect()
-    }),
-    ("blitz" , Config { // todo
-        filename: "roms/BLITZ",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("brix" , Config { // todo
-        filename: "roms/BRIX",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("connect4" , Config { // todo
-        filename: "roms/CONNECT4",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(15),
-        keymap: vec![
-            (4, sfml::window::Key::Left),
-            (5, sfml::window::Key::Down),
-            (6, sfml::window::Key::Right),
-        ]
-        .into_iter()
+    }
+}
+
+/// The contents of `ROMS_FILENAME`.
+struct RomConfigs {
+    /// The configuration of ROMs that are not listed, with an empty filename.
+    default: Config,
+    roms: HashMap<String, Config>,
+}
+
+#[derive(Deserialize)]
+struct RomsFile {
+    #[serde(default)]
+    defaults: Defaults,
+    /// Custom keymap presets, each a table from CHIP-8 key to key name.
+    #[serde(default)]
+    keymaps: HashMap<String, HashMap<String, String>>,
+    #[serde(flatten)]
+    roms: HashMap<String, RomEntry>,
+}
+
+/// The `[defaults]` section, used for all settings a ROM entry leaves out.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Defaults {
+    display_fade: Option<u32>,
+    instruction_sleep_us: Option<u64>,
+    draw_sleep_us: Option<u64>,
+    wait_key_sleep_us: Option<u64>,
+    timer_hz: Option<f64>,
+    keymap: Option<KeymapEntry>,
+    gamepad: Option<HashMap<String, String>>,
+    key_latch: Option<bool>,
+    palette_cycle: Option<bool>,
+    beep_hz: Option<u32>,
+    beep_waveform: Option<String>,
+}
+
+/// A ROM entry as written in `ROMS_FILENAME`.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RomEntry {
+    filename: String,
+    display_fade: Option<u32>,
+    instruction_sleep_us: Option<u64>,
+    draw_sleep_us: Option<u64>,
+    wait_key_sleep_us: Option<u64>,
+    timer_hz: Option<f64>,
+    keymap: Option<KeymapEntry>,
+    /// A table from CHIP-8 key (as hex digit) to gamepad input.
+    gamepad: Option<HashMap<String, String>>,
+    turbo: Option<TurboEntry>,
+    key_latch: Option<bool>,
+    palette_cycle: Option<bool>,
+    beep_hz: Option<u32>,
+    beep_waveform: Option<String>,
+    start_paused: Option<bool>,
+    preroll_frames: Option<u32>,
+    seed: Option<u64>,
+    quirks: Option<QuirksEntry>,
+    #[serde(default, rename = "watch")]
+    watches: Vec<WatchEntry>,
+    #[serde(default, rename = "sprite")]
+    sprites: Vec<SpriteEntry>,
+    #[serde(default)]
+    startup: Vec<StartupEntry>,
+    author: Option<String>,
+    source: Option<String>,
+    controls: Option<String>,
+    recommended_quirks: Option<String>,
+    notes: Option<String>,
+}
+
+/// Either the name of a keymap preset or a table from CHIP-8 key (as hex digit)
+/// to key name.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeymapEntry {
+    Preset(String),
+    Keys(HashMap<String, String>),
+}
+
+/// Either the name of a quirks preset or a table of quirk flags.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QuirksEntry {
+    Preset(String),
+    Flags(Quirks),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TurboEntry {
+    key: String,
+    chip8_key: u8,
+    period: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WatchEntry {
+    name: String,
+    address: u16,
+    width: WatchWidth,
+    format: WatchFormat,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpriteEntry {
+    address: u16,
+    height: u8,
+    #[serde(default = "default_sprite_count")]
+    count: u16,
+}
+
+fn default_sprite_count() -> u16 {
+    1
+}
+
+/// Holds a CHIP-8 key down for `duration` frames, starting at `frame`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StartupEntry {
+    frame: u32,
+    key: u8,
+    #[serde(default = "default_startup_duration")]
+    duration: u32,
+}
+
+fn default_startup_duration() -> u32 {
+    1
+}
+
+fn parse_key(name: &str) -> Result<sfml::window::Key, String> {
+    key_from_name(name).ok_or_else(|| format!("unknown key \"{}\"", name))
+}
+
+fn parse_chip8_key(key: &str) -> Result<u8, String> {
+    u8::from_str_radix(key, 16)
+        .ok()
+        .filter(|key| *key < 16)
+        .ok_or_else(|| format!("invalid CHIP-8 key \"{}\"", key))
+}
+
+fn parse_gamepad(inputs: &HashMap<String, String>) -> Result<HashMap<u8, GamepadInput>, String> {
+    inputs
+        .iter()
+        .map(|(chip8_key, input)| {
+            let input = GamepadInput::from_name(input)
+                .ok_or_else(|| format!("unknown gamepad input \"{}\"", input))?;
+            Ok((parse_chip8_key(chip8_key)?, input))
+        })
         .collect()
-    }),
-    ("guess" , Config { // todo
-        filename: "roms/GUESS",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("hidden" , Config { // todo
-        filename: "roms/HIDDEN",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("invaders" , Config { // todo
-        filename: "roms/INVADERS",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("kaleid" , Config { // todo
-        filename: "roms/KALEID",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("maze" , Config { // todo
-        filename: "roms/MAZE",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("merlin" , Config { // todo
-        filename: "roms/MERLIN",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("missile" , Config { // todo
-        filename: "roms/MISSILE",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("pong" , Config { // todo
-        filename: "roms/PONG",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("pong2" , Config { // todo
-        filename: "roms/PONG2",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("puzzle" , Config { // todo
-        filename: "roms/PUZZLE",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(1),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("syzygy" , Config { // todo
-        filename: "roms/SYZYGY",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("tank" , Config { // todo
-        filename: "roms/TANK",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("tetris" , Config { // todo
-        filename: "roms/TETRIS",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("tictac" , Config { // todo
-        filename: "roms/TICTAC",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("ufo" , Config { // todo
-        filename: "roms/UFO",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("vbrix" , Config { // todo
-        filename: "roms/VBRIX",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("vers" , Config { // todo
-        filename: "roms/VERS",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-    ("wipeoff" , Config { // todo
-        filename: "roms/WIPEOFF",
-        display_fade: 3,
-        instruction_sleep: Duration::from_millis(2),
-        keymap: DEFAULT_KEYMAP.clone(),
-    }),
-].into_iter().collect();
-}
-
-fn load_rom_file(filename: &str) -> Vec<u8> {
-    let mut file = File::open(filename).unwrap();
-    let mut raw_rom = Vec::new();
-    file.read_to_end(&mut raw_rom).unwrap();
-    raw_rom
-}
-
-pub fn load_rom(rom_name: &str) -> (Executor, Visualizer) {
-    let config = &ROM_MAP[rom_name];
-    let vm = VirtualMachine::new(&load_rom_file(config.filename));
-    let visualizer = Visualizer::new(
-        vm.interface.clone(),
-        config.display_fade,
-        config.keymap.clone(),
+}
+
+fn parse_keys(keys: &HashMap<String, String>) -> Result<HashMap<u8, sfml::window::Key>, String> {
+    keys.iter()
+        .map(|(chip8_key, key)| Ok((parse_chip8_key(chip8_key)?, parse_key(key)?)))
+        .collect()
+}
+
+impl KeymapEntry {
+    fn to_keymap(&self, presets: &KeymapPresets) -> Result<HashMap<u8, sfml::window::Key>, String> {
+        match self {
+            KeymapEntry::Preset(name) => presets.get(name).cloned().ok_or_else(|| {
+                let names = presets.names().join(", ");
+                format!("unknown keymap \"{}\", expected one of {}", name, names)
+            }),
+            KeymapEntry::Keys(keys) => parse_keys(keys),
+        }
+    }
+}
+
+impl RomEntry {
+    fn to_config(&self, defaults: &Defaults, presets: &KeymapPresets) -> Result<Config, String> {
+        let turbo = match &self.turbo {
+            Some(turbo) => Some(Turbo {
+                key: parse_key(&turbo.key)?,
+                chip8_key: turbo.chip8_key,
+                period: turbo.period,
+            }),
+            None => None,
+        };
+        let mut startup_input = ScriptedKeypad::new();
+        for input in self.startup.iter() {
+            if input.key > 0xF {
+                return Err(format!("invalid startup key {:#X}", input.key));
+            }
+            startup_input = startup_input.press(input.frame, input.key, input.duration);
+        }
+        let sprites = self
+            .sprites
+            .iter()
+            .map(|sprite| {
+                if !(1..=15).contains(&sprite.height) {
+                    return Err(format!("invalid sprite height {}", sprite.height));
+                }
+                Ok(SpriteRange {
+                    start: Address(sprite.address),
+                    height: sprite.height,
+                    count: sprite.count,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        if let Some(watch) = self
+            .watches
+            .iter()
+            .find(|watch| watch.address as usize + watch.width.bytes() > MEMORY_SIZE)
+        {
+            return Err(format!(
+                "watch \"{}\" at {:#05X} is past the end of memory",
+                watch.name, watch.address
+            ));
+        }
+        if let Some(name) = &self.recommended_quirks {
+            if Quirks::preset(name).is_none() {
+                return Err(format!("unknown recommended quirks preset \"{}\"", name));
+            }
+        }
+        let instruction_sleep = match self.instruction_sleep_us.or(defaults.instruction_sleep_us) {
+            Some(0) => return Err(String::from("invalid instruction_sleep_us 0")),
+            Some(us) => Duration::from_micros(us),
+            None => DEFAULT_INSTRUCTION_SLEEP,
+        };
+        let timer_interval = match self.timer_hz.or(defaults.timer_hz) {
+            Some(hz) if TIMER_HZ_RANGE.contains(&hz) => Duration::from_secs_f64(1.0 / hz),
+            Some(hz) => return Err(format!("invalid timer_hz {}", hz)),
+            None => DEFAULT_TIMER_INTERVAL,
+        };
+        let default_beep = Beep::default();
+        let beep = Beep {
+            frequency: match self.beep_hz.or(defaults.beep_hz) {
+                Some(hz) if BEEP_HZ_RANGE.contains(&hz) => hz,
+                Some(hz) => return Err(format!("invalid beep_hz {}", hz)),
+                None => default_beep.frequency,
+            },
+            waveform: match self
+                .beep_waveform
+                .as_ref()
+                .or(defaults.beep_waveform.as_ref())
+            {
+                Some(name) => Waveform::from_name(name)
+                    .ok_or_else(|| format!("unknown beep_waveform \"{}\"", name))?,
+                None => default_beep.waveform,
+            },
+        };
+        Ok(Config {
+            filename: self.filename.clone(),
+            display_fade: self
+                .display_fade
+                .or(defaults.display_fade)
+                .unwrap_or(DEFAULT_DISPLAY_FADE),
+            instruction_sleep,
+            timer_interval,
+            throttle: Throttle {
+                draw: self
+                    .draw_sleep_us
+                    .or(defaults.draw_sleep_us)
+                    .map(Duration::from_micros),
+                wait_key: self
+                    .wait_key_sleep_us
+                    .or(defaults.wait_key_sleep_us)
+                    .map(Duration::from_micros),
+            },
+            keymap: match self.keymap.as_ref().or(defaults.keymap.as_ref()) {
+                Some(keymap) => keymap.to_keymap(presets)?,
+                None => presets.get(DEFAULT_PRESET).unwrap().clone(),
+            },
+            gamepad: match self.gamepad.as_ref().or(defaults.gamepad.as_ref()) {
+                Some(inputs) => parse_gamepad(inputs)?,
+                None => HashMap::new(),
+            },
+            turbo,
+            key_latch: self.key_latch.or(defaults.key_latch).unwrap_or(false),
+            palette_cycle: self
+                .palette_cycle
+                .or(defaults.palette_cycle)
+                .unwrap_or(false),
+            beep,
+            start_paused: self.start_paused.unwrap_or(false),
+            preroll_frames: self.preroll_frames.unwrap_or(0),
+            seed: self.seed,
+            quirks: match &self.quirks {
+                Some(QuirksEntry::Preset(name)) => Quirks::preset(name)
+                    .ok_or_else(|| format!("unknown quirks preset \"{}\"", name))?,
+                Some(QuirksEntry::Flags(quirks)) => *quirks,
+                None => Quirks::default(),
+            },
+            watches: self
+                .watches
+                .iter()
+                .map(|watch| {
+                    Watch::new(
+                        &watch.name,
+                        Address(watch.address),
+                        watch.width,
+                        watch.format,
+                    )
+                })
+                .collect(),
+            sprites,
+            startup_input,
+            info: RomInfo {
+                author: self.author.clone(),
+                source: self.source.clone(),
+                controls: self.controls.clone(),
+                recommended_quirks: self.recommended_quirks.clone(),
+                notes: self.notes.clone(),
+            },
+        })
+    }
+}
+
+/// Reads the configuration of all known ROMs from `ROMS_FILENAME`.
+fn load_rom_configs(assets: &Assets) -> Result<RomConfigs, String> {
+    let text = fs::read_to_string(assets.config_file(ROMS_FILENAME)).map_err(|e| e.to_string())?;
+    parse_rom_configs(&text)
+}
+
+/// Parses the contents of `ROMS_FILENAME`.
+fn parse_rom_configs(text: &str) -> Result<RomConfigs, String> {
+    let file: RomsFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut presets = KeymapPresets::builtin();
+    for (name, keys) in file.keymaps.iter() {
+        let keymap = parse_keys(keys).map_err(|e| format!("[keymaps.{}]: {}", name, e))?;
+        presets
+            .add(name, keymap)
+            .map_err(|e| format!("[keymaps]: {}", e))?;
+    }
+    let default = RomEntry::default()
+        .to_config(&file.defaults, &presets)
+        .map_err(|e| format!("[defaults]: {}", e))?;
+    let roms = file
+        .roms
+        .iter()
+        .map(|(name, entry)| {
+            let config = entry
+                .to_config(&file.defaults, &presets)
+                .map_err(|e| format!("[{}]: {}", name, e))?;
+            Ok((name.clone(), config))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(RomConfigs { default, roms })
+}
+
+/// Like `load_rom_configs`, but reports errors and falls back to the built-in
+/// defaults.
+fn load_rom_configs_or_default(assets: &Assets) -> RomConfigs {
+    load_rom_configs(assets).unwrap_or_else(|e| {
+        eprintln!("Cannot load {}: {}", ROMS_FILENAME, e);
+        RomConfigs {
+            default: RomEntry::default()
+                .to_config(&Defaults::default(), &KeymapPresets::builtin())
+                .unwrap(),
+            roms: HashMap::new(),
+        }
+    })
+}
+
+/// Reads a ROM file, or exits if it cannot be read, e.g. because a missing
+/// file was opened with the emulator.
+fn load_rom_file(filename: &Path) -> Vec<u8> {
+    fs::read(filename).unwrap_or_else(|e| {
+        eprintln!("Cannot read {}: {}", filename.display(), e);
+        process::exit(1);
+    })
+}
+
+/// Finds the name of a ROM in `ROMS_FILENAME`, given either by that name or by
+/// the file name of a path pointing to one of the known ROMs.
+fn find_rom_name<'a>(roms: &'a RomConfigs, rom: &str) -> Option<&'a str> {
+    let stem = Path::new(rom).file_stem()?.to_str()?.to_lowercase();
+    [rom, stem.as_str()]
+        .iter()
+        .find_map(|name| roms.roms.get_key_value(*name))
+        .map(|(name, _)| name.as_str())
+}
+
+/// The name of the directory of the save state slots of a ROM, given by
+/// name or file. Known ROMs and ROM files share their slots if the file is
+/// the known one.
+pub fn state_name(rom: &str) -> String {
+    Path::new(rom)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(rom)
+        .to_lowercase()
+}
+
+/// Finds the configuration for a ROM, given like in `find_rom_name`.
+fn find_config<'a>(roms: &'a RomConfigs, rom: &str) -> Option<&'a Config> {
+    find_rom_name(roms, rom).map(|name| &roms.roms[name])
+}
+
+/// Saves a keymap changed in the window to the ROM's entry in
+/// `ROMS_FILENAME`, keeping the formatting and comments of the file.
+struct RomsFileKeymap {
+    path: PathBuf,
+    rom_name: String,
+}
+
+impl KeymapStore for RomsFileKeymap {
+    fn save(&mut self, keymap: &HashMap<u8, sfml::window::Key>) -> Result<String, String> {
+        let text = fs::read_to_string(&self.path).map_err(|e| e.to_string())?;
+        let mut document: toml_edit::DocumentMut = text
+            .parse()
+            .map_err(|e: toml_edit::TomlError| e.to_string())?;
+        let entry = document
+            .get_mut(&self.rom_name)
+            .and_then(|entry| entry.as_table_like_mut())
+            .ok_or_else(|| format!("no entry [{}]", self.rom_name))?;
+        let mut keys: Vec<_> = keymap.iter().collect();
+        keys.sort_by_key(|(chip8_key, _)| **chip8_key);
+        let table: toml_edit::InlineTable = keys
+            .into_iter()
+            .map(|(chip8_key, key)| (format!("{:X}", chip8_key), format!("{:?}", key)))
+            .collect();
+        let mut value = toml_edit::Value::from(table);
+        // Replaced in place, so that the comments around an existing keymap
+        // stay.
+        match entry.get_mut("keymap") {
+            Some(item) => {
+                if let Some(old) = item.as_value() {
+                    *value.decor_mut() = old.decor().clone();
+                }
+                *item = toml_edit::Item::Value(value);
+            }
+            None => {
+                entry.insert("keymap", toml_edit::Item::Value(value));
+            }
+        }
+        fs::write(&self.path, document.to_string()).map_err(|e| e.to_string())?;
+        Ok(format!("[{}] in {}", self.rom_name, self.path.display()))
+    }
+}
+
+/// The notes about a known ROM, given by name or path like in `load_rom`.
+pub fn rom_info(assets: &Assets, rom: &str) -> Option<RomInfo> {
+    let roms = load_rom_configs_or_default(assets);
+    find_config(&roms, rom).map(|config| config.info.clone())
+}
+
+fn print_controls(keymap: &HashMap<u8, sfml::window::Key>) {
+    let mut keys: Vec<_> = keymap.iter().collect();
+    keys.sort_by_key(|(chip8_key, _)| **chip8_key);
+    println!("Controls:");
+    for (chip8_key, key) in keys {
+        println!("  {:X} -> {:?}", chip8_key, key);
+    }
+}
+
+/// Renders and caches the preview thumbnails of all configured ROMs which do
+/// not have one yet, faded and colored like the window shows them, or of the
+/// logical display if `raw` is set.
+pub fn generate_thumbnails(assets: &Assets, raw: bool) {
+    let roms = load_rom_configs_or_default(assets);
+    let mut names: Vec<_> = roms.roms.keys().collect();
+    names.sort();
+    for name in names {
+        let config = &roms.roms[name];
+        let style = if raw {
+            ThumbnailStyle::Raw
+        } else {
+            ThumbnailStyle::Shown {
+                filters: display_filters(config.display_fade, config.palette_cycle, Color::WHITE),
+                background: Color::BLACK,
+            }
+        };
+        let result = fs::read(assets.rom_file(&config.filename)).and_then(|program| {
+            cache_thumbnail(
+                &assets.thumbnail_dir(),
+                name,
+                &program,
+                config.instruction_sleep,
+                config.timer_interval,
+                &config.startup_input,
+                style,
+            )
+        });
+        if let Err(e) = result {
+            eprintln!("Could not create thumbnail for {}: {}", name, e);
+        }
+    }
+}
+
+/// Shows the configured ROMs with their thumbnails in a window and returns
+/// the name of the chosen one, or `None` if the window was closed. The
+/// thumbnails that are not cached yet are created first, like
+/// `generate_thumbnails` does.
+pub fn choose_rom(assets: &Assets, raw: bool) -> Option<String> {
+    generate_thumbnails(assets, raw);
+    let roms = load_rom_configs_or_default(assets);
+    let mut names: Vec<_> = roms.roms.keys().collect();
+    names.sort();
+    let entries: Vec<_> = names
+        .iter()
+        .map(|name| MenuEntry {
+            name: name.to_string(),
+            thumbnail: read_thumbnail(&assets.thumbnail_dir(), name, raw),
+        })
+        .collect();
+    menu::choose_rom(&entries).map(|index| names[index].to_string())
+}
+
+/// The number of instructions that run in one frame, at least one.
+fn instructions_per_frame(timer_interval: Duration, instruction_sleep: Duration) -> u32 {
+    (timer_interval.as_nanos() / instruction_sleep.as_nanos().max(1)).max(1) as u32
+}
+
+/// Runs a ROM headlessly like `compare_quirks` with its configured quirks
+/// and returns the display hash of every frame, or an error if it panics.
+fn rom_frame_hashes(assets: &Assets, rom: &str) -> Result<Vec<u64>, String> {
+    let roms = load_rom_configs_or_default(assets);
+    let (filename, config) = match find_config(&roms, rom) {
+        Some(config) => (assets.rom_file(&config.filename), config),
+        None => (PathBuf::from(rom), &roms.default),
+    };
+    let program = load_rom_file(&filename);
+    let instructions_per_frame =
+        instructions_per_frame(config.timer_interval, config.instruction_sleep);
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        frame_hashes(
+            &program,
+            config.quirks,
+            &config.startup_input,
+            instructions_per_frame,
+            COMPARE_FRAMES,
+        )
+    }))
+    .map_err(|_| String::from("the emulator panicked"))
+}
+
+/// Writes the display hash of every frame of a ROM to `path`, to be checked
+/// by `check_frame_hashes` of another build of the emulator.
+pub fn record_frame_hashes(assets: &Assets, rom: &str, path: &str) -> bool {
+    let written = rom_frame_hashes(assets, rom)
+        .and_then(|hashes| fs::write(path, format_trace(&hashes)).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => {
+            println!("Recorded {} frames to {}.", COMPARE_FRAMES, path);
+            true
+        }
+        Err(e) => {
+            eprintln!("Cannot record frame hashes: {}", e);
+            false
+        }
+    }
+}
+
+/// Runs a ROM like `record_frame_hashes` and reports the first frame whose
+/// display differs from the trace in `path`. Returns false on a difference.
+pub fn check_frame_hashes(assets: &Assets, rom: &str, path: &str) -> bool {
+    let expected = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|trace| parse_trace(&trace))
+    {
+        Ok(expected) => expected,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", path, e);
+            return false;
+        }
+    };
+    let actual = match rom_frame_hashes(assets, rom) {
+        Ok(actual) => actual,
+        Err(e) => {
+            eprintln!("Cannot run {}: {}", rom, e);
+            return false;
+        }
+    };
+    match first_mismatch(&expected, &actual) {
+        None => {
+            println!("All {} frames match.", actual.len());
+            true
+        }
+        Some(frame) if frame >= expected.len().min(actual.len()) => {
+            println!(
+                "The trace has {} frames, this build ran {}.",
+                expected.len(),
+                actual.len()
+            );
+            false
+        }
+        Some(frame) => {
+            println!("Displays differ from frame {} on.", frame);
+            false
+        }
+    }
+}
+
+/// Runs a ROM with two quirks presets, given as "a,b", and reports the first
+/// frame at which the displays differ. Returns false if the presets are
+/// invalid or one of the VMs crashes.
+pub fn compare_quirks(assets: &Assets, rom: &str, presets: &str) -> bool {
+    let parsed: Option<Vec<Quirks>> = presets.split(',').map(Quirks::preset).collect();
+    let (quirks_a, quirks_b) = match parsed.as_deref() {
+        Some([a, b]) => (*a, *b),
+        _ => {
+            eprintln!(
+                "Expected two quirks presets like \"default,vip\", got \"{}\"",
+                presets
+            );
+            return false;
+        }
+    };
+    let roms = load_rom_configs_or_default(assets);
+    let (filename, config) = match find_config(&roms, rom) {
+        Some(config) => (assets.rom_file(&config.filename), config),
+        None => (PathBuf::from(rom), &roms.default),
+    };
+    let program = load_rom_file(&filename);
+    let instructions_per_frame =
+        instructions_per_frame(config.timer_interval, config.instruction_sleep);
+    let divergence = panic::catch_unwind(AssertUnwindSafe(|| {
+        find_divergence(
+            &program,
+            quirks_a,
+            quirks_b,
+            &config.startup_input,
+            instructions_per_frame,
+            COMPARE_FRAMES,
+        )
+    }));
+    match divergence {
+        Ok(None) => println!("No difference within {} frames.", COMPARE_FRAMES),
+        Ok(Some(divergence)) => {
+            println!(
+                "Displays differ after frame {} ({:.1}s), {} pixel(s) apart.",
+                divergence.frame,
+                (divergence.frame + 1) as f32 * config.timer_interval.as_secs_f32(),
+                divergence.differing_pixels
+            );
+            for (name, vm) in presets.split(',').zip([&divergence.a, &divergence.b]) {
+                println!("{}:\n{}", name, vm.state_summary(false));
+            }
+        }
+        Err(_) => {
+            eprintln!("A VM crashed while comparing the quirks.");
+            return false;
+        }
+    }
+    true
+}
+
+/// Exports the font and the configured sprites of a ROM as PNG images to the
+/// sprite directory of `assets`, in the colors of the window or, if `raw` is
+/// set, in grayscale. Returns false if an image cannot be written.
+pub fn dump_sprites(assets: &Assets, rom: &str, raw: bool) -> bool {
+    let roms = load_rom_configs_or_default(assets);
+    let known = find_config(&roms, rom);
+    let sprites = known.map_or_else(Vec::new, |config| config.sprites.clone());
+    // The colors of the window. Fading and palette cycling change the display
+    // over time, while a sheet shows memory, so they do not apply.
+    let (mut fill, mut background) = (Color::WHITE, Color::BLACK);
+    if let (None, Some(octo)) = (known, OctoOptions::load(Path::new(rom))) {
+        fill = octo.fill_color().unwrap_or(fill);
+        background = octo.background_color().unwrap_or(background);
+    }
+    let rgb = |color: Color| [color.r, color.g, color.b];
+    let (vm, _) = load_vm(assets, rom, None);
+    let name = Path::new(rom).file_stem().map_or_else(
+        || rom.to_string(),
+        |stem| stem.to_string_lossy().to_lowercase(),
+    );
+    let dir = assets.sprite_dir();
+    let sheets = iter::once((format!("{}-font.png", name), SpriteRange::font())).chain(
+        sprites
+            .into_iter()
+            .map(|range| (format!("{}-{:#05x}.png", name, range.start.0), range)),
     );
-    let executor = Executor::new(config.instruction_sleep, TIMER_INTERVAL, vm);
+    let mut ok = true;
+    for (filename, range) in sheets {
+        let sheet = SpriteSheet::new(&vm, range);
+        let png = if raw {
+            sheet.to_png(SPRITE_SCALE)
+        } else {
+            sheet.to_png_rgb(SPRITE_SCALE, rgb(fill), rgb(background))
+        };
+        let path = dir.join(filename);
+        match fs::create_dir_all(&dir).and_then(|_| fs::write(&path, png)) {
+            Ok(()) => println!("Saved {}", path.display()),
+            Err(e) => {
+                eprintln!("Could not save {}: {}", path.display(), e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Loads a ROM like `load_rom`, but without a window, for the terminal
+/// debugger. `seed` overrides the seed of the ROM. Also returns the number of
+/// instructions per frame.
+pub fn load_vm(assets: &Assets, rom: &str, seed: Option<u64>) -> (VirtualMachine, u32) {
+    let roms = load_rom_configs_or_default(assets);
+    let known = find_config(&roms, rom);
+    let (filename, config) = match known {
+        Some(config) => (assets.rom_file(&config.filename), config),
+        None => (PathBuf::from(rom), &roms.default),
+    };
+    let mut quirks = config.quirks;
+    let mut instruction_sleep = config.instruction_sleep;
+    if known.is_none() {
+        if let Some(octo) = OctoOptions::load(Path::new(rom)) {
+            instruction_sleep = octo.instruction_sleep().unwrap_or(instruction_sleep);
+            octo.apply_quirks(&mut quirks);
+        }
+    }
+    let instructions_per_frame = instructions_per_frame(config.timer_interval, instruction_sleep);
+    let mut vm = VirtualMachine::with_quirks(&load_rom_file(&filename), quirks);
+    if let Some(seed) = seed.or(config.seed) {
+        vm.set_seed(seed);
+    }
+    (vm, instructions_per_frame)
+}
+
+/// Runs a VM headlessly and without input for a number of frames, ticking the
+/// timers after every frame. Stops early if the VM fails.
+fn preroll(vm: &mut VirtualMachine, frames: u32, instructions_per_frame: u32) {
+    for frame in 0..frames {
+        for _ in 0..instructions_per_frame {
+            if let Err(e) = vm.step() {
+                eprintln!("Preroll stopped in frame {}: {}", frame, e);
+                return;
+            }
+        }
+        vm.interface.lock().unwrap().tick_timers();
+    }
+}
+
+/// The options of a ROM that are given on the command line.
+pub struct LaunchOptions {
+    /// Pause emulation while the window is not focused.
+    pub pause_in_background: bool,
+    /// Let beeps last at least this many frames.
+    pub min_beep_frames: u32,
+    /// Open the window paused, even if the ROM is not configured so.
+    pub start_paused: bool,
+    /// Overrides the `preroll_frames` of the ROM.
+    pub preroll_frames: Option<u32>,
+    /// Overrides the `seed` of the ROM.
+    pub seed: Option<u64>,
+    /// Save screenshots and save state thumbnails of the logical display
+    /// instead of what is shown.
+    pub raw_images: bool,
+}
+
+/// What `load_rom` starts: the ROM file, how it is run and how it is shown.
+struct RomSetup {
+    filename: PathBuf,
+    instruction_sleep: Duration,
+    timer_interval: Duration,
+    throttle: Throttle,
+    quirks: Quirks,
+    watches: Vec<Watch>,
+    options: VisualizerOptions,
+}
+
+/// Loads a ROM either by its name in `ROMS_FILENAME` or by a path to a ROM file.
+/// Known ROMs are looked up in the ROM directory of `assets`.
+/// Unknown files are started with the `[defaults]` of `ROMS_FILENAME`, which
+/// Octo options stored next to the file override. Hotkeys that collide with
+/// the ROM's keys are disabled. The window opens paused if `launch` or the
+/// ROM says so, after the preroll frames were run headlessly.
+pub fn load_rom(
+    rom: &str,
+    launch: LaunchOptions,
+    hotkeys: Hotkeys,
+    assets: &Assets,
+) -> (Executor, Visualizer) {
+    let roms = load_rom_configs_or_default(assets);
+    let start_config = find_config(&roms, rom).unwrap_or(&roms.default);
+    let start_paused = launch.start_paused || start_config.start_paused;
+    let preroll_frames = launch.preroll_frames.unwrap_or(start_config.preroll_frames);
+    let seed = launch.seed.or(start_config.seed);
+    let rom_name = state_name(rom);
+    let mut setup = match find_config(&roms, rom) {
+        Some(config) => RomSetup {
+            filename: assets.rom_file(&config.filename),
+            instruction_sleep: config.instruction_sleep,
+            timer_interval: config.timer_interval,
+            throttle: config.throttle,
+            quirks: config.quirks,
+            watches: config.watches.clone(),
+            options: VisualizerOptions {
+                display_fade: config.display_fade,
+                keymap: config.keymap.clone(),
+                keymap_store: find_rom_name(&roms, rom).map(|name| {
+                    Box::new(RomsFileKeymap {
+                        path: assets.config_file(ROMS_FILENAME),
+                        rom_name: String::from(name),
+                    }) as Box<dyn KeymapStore>
+                }),
+                gamepad: config.gamepad.clone(),
+                turbo: config.turbo,
+                key_latch: config.key_latch,
+                pause_in_background: launch.pause_in_background,
+                start_paused,
+                palette_cycle: config.palette_cycle,
+                min_beep_frames: launch.min_beep_frames,
+                hotkeys,
+                fill_color: Color::WHITE,
+                background_color: Color::BLACK,
+                filters: Vec::new(),
+                startup_input: config.startup_input.clone(),
+                beep: config.beep,
+                screenshot_dir: assets.screenshot_dir(),
+                save_state_dir: assets.save_state_dir(&rom_name),
+                raw_images: launch.raw_images,
+                show_controls: false,
+            },
+        },
+        None => {
+            let config = &roms.default;
+            let mut instruction_sleep = config.instruction_sleep;
+            let mut quirks = config.quirks;
+            let mut options = VisualizerOptions {
+                display_fade: config.display_fade,
+                keymap: config.keymap.clone(),
+                keymap_store: None,
+                gamepad: config.gamepad.clone(),
+                turbo: None,
+                key_latch: config.key_latch,
+                pause_in_background: launch.pause_in_background,
+                start_paused,
+                palette_cycle: config.palette_cycle,
+                min_beep_frames: launch.min_beep_frames,
+                hotkeys,
+                fill_color: Color::WHITE,
+                background_color: Color::BLACK,
+                filters: Vec::new(),
+                startup_input: ScriptedKeypad::new(),
+                beep: config.beep,
+                screenshot_dir: assets.screenshot_dir(),
+                save_state_dir: assets.save_state_dir(&rom_name),
+                raw_images: launch.raw_images,
+                // Nothing tells the player the keys of an unknown ROM.
+                show_controls: true,
+            };
+            if let Some(octo) = OctoOptions::load(Path::new(rom)) {
+                instruction_sleep = octo.instruction_sleep().unwrap_or(instruction_sleep);
+                octo.apply_quirks(&mut quirks);
+                options.fill_color = octo.fill_color().unwrap_or(options.fill_color);
+                options.background_color =
+                    octo.background_color().unwrap_or(options.background_color);
+            }
+            RomSetup {
+                filename: PathBuf::from(rom),
+                instruction_sleep,
+                timer_interval: config.timer_interval,
+                throttle: config.throttle,
+                quirks,
+                watches: Vec::new(),
+                options,
+            }
+        }
+    };
+    let options = &mut setup.options;
+    for warning in options
+        .hotkeys
+        .resolve_conflicts(&options.keymap, options.turbo)
+    {
+        eprintln!("Warning: {}", warning);
+    }
+    for line in start_config.info.lines() {
+        println!("{}", line);
+    }
+    print_controls(&setup.options.keymap);
+    let mut vm = VirtualMachine::with_quirks(&load_rom_file(&setup.filename), setup.quirks);
+    if let Some(seed) = seed {
+        vm.set_seed(seed);
+    }
+    let instructions_per_frame =
+        instructions_per_frame(setup.timer_interval, setup.instruction_sleep);
+    preroll(&mut vm, preroll_frames, instructions_per_frame);
+    let interface = vm.interface.clone();
+    let mut executor = Executor::new(setup.instruction_sleep, setup.timer_interval, vm);
+    executor.set_throttle(setup.throttle);
+    executor.set_rewind(Some(RewindBuffer::default()));
+    for watch in setup.watches {
+        executor.add_watch(watch);
+    }
+    let visualizer = Visualizer::new(interface, setup.options, executor.control_bus());
     (executor, visualizer)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sfml::window::Key;
+
+    fn keys(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(chip8_key, key)| (chip8_key.to_string(), key.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let keymap = parse_keys(&keys(&[("2", "Up"), ("a", "Space")])).unwrap();
+        assert_eq!(keymap, [(0x2, Key::Up), (0xA, Key::Space)].into());
+        assert_eq!(
+            parse_keys(&keys(&[("10", "Up")])),
+            Err(String::from("invalid CHIP-8 key \"10\""))
+        );
+        assert_eq!(
+            parse_keys(&keys(&[("2", "Nope")])),
+            Err(String::from("unknown key \"Nope\""))
+        );
+    }
+
+    #[test]
+    fn test_custom_keymap() {
+        let configs = parse_rom_configs(
+            r#"
+            [keymaps]
+            numpad = { 2 = "Numpad8", 8 = "Numpad2" }
+
+            [defaults]
+            keymap = "default"
+
+            [pong]
+            filename = "roms/PONG"
+            keymap = "numpad"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            configs.roms["pong"].keymap,
+            [(0x2, Key::Numpad8), (0x8, Key::Numpad2)].into()
+        );
+        assert_eq!(
+            configs.default.keymap,
+            *KeymapPresets::builtin().get("hex-pad").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_instruction_sleep() {
+        let configs = parse_rom_configs(
+            "[defaults]\ntimer_hz = 50\n[fast]\nfilename = \"fast\"\ninstruction_sleep_us = 1",
+        )
+        .unwrap();
+        let config = &configs.roms["fast"];
+        assert_eq!(
+            instructions_per_frame(config.timer_interval, config.instruction_sleep),
+            20_000
+        );
+        assert_eq!(
+            instructions_per_frame(
+                configs.default.timer_interval,
+                configs.default.instruction_sleep
+            ),
+            10
+        );
+        assert_eq!(
+            parse_rom_configs("[pong]\nfilename = \"pong\"\ninstruction_sleep_us = 0").err(),
+            Some(String::from("[pong]: invalid instruction_sleep_us 0"))
+        );
+        assert_eq!(
+            instructions_per_frame(Duration::from_millis(16), Duration::ZERO),
+            16_000_000
+        );
+    }
+
+    #[test]
+    fn test_watch_address() {
+        let config = |watch: &str| {
+            parse_rom_configs(&format!("[pong]\nfilename = \"pong\"\nwatch = [{}]", watch))
+                .map(|configs| configs.roms["pong"].watches.len())
+        };
+        let watch = r#"{ name = "score", address = 0xFFF, width = "byte", format = "hex" }"#;
+        assert_eq!(config(watch), Ok(1));
+        assert_eq!(
+            config(&watch.replace("byte", "word")),
+            Err(String::from(
+                "[pong]: watch \"score\" at 0xFFF is past the end of memory"
+            ))
+        );
+        assert_eq!(
+            config(&watch.replace("0xFFF", "0x1000")),
+            Err(String::from(
+                "[pong]: watch \"score\" at 0x1000 is past the end of memory"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_custom_keymap_errors() {
+        let error = |text| parse_rom_configs(text).err().unwrap();
+        assert_eq!(
+            error("[keymaps]\nwasd = { 2 = \"Up\" }"),
+            "[keymaps]: keymap \"wasd\" is already defined"
+        );
+        assert_eq!(
+            error("[keymaps]\ntable = { 2 = \"Up\" }"),
+            "[keymaps]: keymap \"table\" is already defined"
+        );
+        assert_eq!(
+            error("[keymaps]\nnumpad = { 2 = \"Nope\" }"),
+            "[keymaps.numpad]: unknown key \"Nope\""
+        );
+        assert!(
+            error("[pong]\nfilename = \"roms/PONG\"\nkeymap = \"numpad\"")
+                .starts_with("[pong]: unknown keymap \"numpad\", expected one of arrows, hex-pad")
+        );
+    }
+    #[test]
+    fn test_save_keymap() {
+        let path = std::env::temp_dir().join(format!("chip8-roms-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "# The known ROMs.\n\
+             [pong]  # two players\n\
+             filename = \"roms/PONG\"\n\
+             # The paddles.\n\
+             keymap = \"hex-pad\"  # for now\n\
+             \n\
+             [tank]\n\
+             filename = \"roms/TANK\"\n",
+        )
+        .unwrap();
+        let mut store = RomsFileKeymap {
+            path: path.clone(),
+            rom_name: String::from("pong"),
+        };
+        let keymap: HashMap<_, _> = [(0x1, Key::W), (0x4, Key::S), (0xC, Key::Up)].into();
+        let location = store.save(&keymap);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(location, Ok(format!("[pong] in {}", path.display())));
+
+        for comment in [
+            "# The known ROMs.",
+            "[pong]  # two players",
+            "# The paddles.",
+            "  # for now",
+        ] {
+            assert!(
+                text.contains(comment),
+                "{:?} is missing in:\n{}",
+                comment,
+                text
+            );
+        }
+        let document: toml::Table = toml::from_str(&text).unwrap();
+        assert_eq!(document["tank"]["filename"].as_str(), Some("roms/TANK"));
+        let saved: HashMap<String, String> = document["pong"]["keymap"].clone().try_into().unwrap();
+        assert_eq!(parse_keys(&saved), Ok(keymap));
+
+        store.rom_name = String::from("missing");
+        fs::write(&path, text).unwrap();
+        let result = store.save(&HashMap::new());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result, Err(String::from("no entry [missing]")));
+    }
+}
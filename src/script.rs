@@ -0,0 +1,370 @@
+use crate::doctor::panic_message;
+use chip8::emulator::basics::{Address, Register, MEMORY_SIZE};
+use chip8::emulator::core::Chip8Core;
+use chip8::emulator::testing::ScriptedKeypad;
+use chip8::emulator::vm::{StepOutcome, VirtualMachine};
+use serde::Deserialize;
+use std::fmt::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use std::{fs, io, thread};
+
+/// A regression test for a ROM, written in TOML: the ROM runs headlessly for
+/// a number of frames while scripted keys are pressed, then the state of the
+/// VM is checked against the listed assertions.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Script {
+    rom: String,
+    frames: u32,
+    #[serde(default = "default_instructions_per_frame")]
+    instructions_per_frame: u32,
+    /// Fails the script once this many instructions were executed.
+    max_instructions: Option<u64>,
+    /// Seeds the random numbers, so that every run draws the same ones.
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    input: Vec<Input>,
+    wait_key: Option<WaitKey>,
+    #[serde(default, rename = "assert")]
+    assertions: Vec<Assertion>,
+}
+
+fn default_instructions_per_frame() -> u32 {
+    10
+}
+
+/// Holds a CHIP-8 key down for `duration` frames, starting at `frame`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Input {
+    frame: u32,
+    key: u8,
+    #[serde(default = "default_input_duration")]
+    duration: u32,
+}
+
+fn default_input_duration() -> u32 {
+    1
+}
+
+/// What to do when the ROM waits for a key (FX0A) for `timeout` frames
+/// without one being pressed: press `key` for a frame, or fail the script if
+/// no key is given.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WaitKey {
+    timeout: u32,
+    key: Option<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum Assertion {
+    /// `V0` to `VF`, `I` or `PC`.
+    Register {
+        register: String,
+        equals: u16,
+    },
+    Memory {
+        memory: u16,
+        equals: u8,
+    },
+    /// The display hash as a hexadecimal string.
+    Display {
+        display_hash: String,
+    },
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Assertion::Register { register, equals } => write!(f, "{} == {:#X}", register, equals),
+            Assertion::Memory { memory, equals } => {
+                write!(f, "[{:#05X}] == {:#04X}", memory, equals)
+            }
+            Assertion::Display { display_hash } => write!(f, "display hash == {}", display_hash),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number '{}'", text))
+}
+
+impl Assertion {
+    /// Returns the actual value if the assertion does not hold.
+    fn check(&self, vm: &VirtualMachine) -> Result<(), String> {
+        let (actual, expected) = match self {
+            Assertion::Register { register, equals } => {
+                let actual = match register.to_uppercase().as_str() {
+                    "I" => vm.get_register_i().0,
+                    "PC" => vm.program_counter.0,
+                    name => match name.strip_prefix('V').map(|n| u8::from_str_radix(n, 16)) {
+                        Some(Ok(n)) if n < 16 => vm.get_register(Register(n)).0 as u16,
+                        _ => return Err(format!("unknown register '{}'", register)),
+                    },
+                };
+                (actual as u64, *equals as u64)
+            }
+            Assertion::Memory { memory, equals } => {
+                if *memory as usize >= MEMORY_SIZE {
+                    return Err(String::from("address out of memory"));
+                }
+                (vm.memory(Address(*memory)).0 as u64, *equals as u64)
+            }
+            Assertion::Display { display_hash } => (vm.display_hash(), parse_number(display_hash)?),
+        };
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(format!("actual value is {:#X}", actual))
+        }
+    }
+}
+
+fn run(script: &Script) -> Result<VirtualMachine, String> {
+    let program =
+        fs::read(&script.rom).map_err(|e| format!("cannot read {}: {}", script.rom, e))?;
+    let keys = script.input.iter().map(|input| input.key);
+    if let Some(key) = keys
+        .chain(script.wait_key.as_ref().and_then(|wait_key| wait_key.key))
+        .find(|key| *key > 0xF)
+    {
+        return Err(format!("invalid key {:#X}", key));
+    }
+    let mut vm = VirtualMachine::new(&program);
+    vm.set_seed(script.seed);
+    vm.set_instruction_budget(script.max_instructions);
+    let mut keypad = script
+        .input
+        .iter()
+        .fold(ScriptedKeypad::new(), |keypad, input| {
+            keypad.press(input.frame, input.key, input.duration)
+        });
+    let mut waiting_frames = 0;
+    for frame in 0..script.frames {
+        keypad.next_frame(&mut vm.interface.lock().unwrap());
+        if let Some(wait_key) = script.wait_key.as_ref() {
+            if waiting_frames >= wait_key.timeout {
+                match wait_key.key {
+                    Some(key) => vm.interface.lock().unwrap().keys_down[key as usize] = true,
+                    None => {
+                        return Err(format!(
+                            "waited for a key for {} frames until frame {}",
+                            waiting_frames, frame
+                        ))
+                    }
+                }
+                waiting_frames = 0;
+            }
+        }
+        let mut outcome = StepOutcome::Continued;
+        for _ in 0..script.instructions_per_frame {
+            outcome = vm
+                .try_step()
+                .map_err(|e| format!("{} at frame {}", e, frame))?;
+        }
+        if outcome == StepOutcome::WaitingForKey {
+            waiting_frames += 1;
+        } else {
+            waiting_frames = 0;
+        }
+        vm.interface.lock().unwrap().tick_timers();
+    }
+    Ok(vm)
+}
+
+/// Runs the test script at the given path. Returns whether all assertions
+/// held, along with a report listing the result of every assertion.
+fn check_script(path: &Path) -> (bool, String) {
+    let mut report = String::new();
+    let script = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| toml::from_str::<Script>(&text).map_err(|e| e.to_string()))
+    {
+        Ok(script) => script,
+        Err(e) => {
+            writeln!(report, "Cannot load test script {}: {}", path.display(), e).unwrap();
+            return (false, report);
+        }
+    };
+    let vm = match run(&script) {
+        Ok(vm) => vm,
+        Err(e) => {
+            writeln!(report, "{}: {}", path.display(), e).unwrap();
+            return (false, report);
+        }
+    };
+    let mut passed = true;
+    for assertion in script.assertions.iter() {
+        match assertion.check(&vm) {
+            Ok(()) => writeln!(report, "ok     {}", assertion).unwrap(),
+            Err(e) => {
+                writeln!(report, "FAILED {}: {}", assertion, e).unwrap();
+                passed = false;
+            }
+        }
+    }
+    if !passed {
+        report.push_str("Final state:\n");
+        report.push_str(&vm.state_summary(false));
+    }
+    (passed, report)
+}
+
+/// Runs all test scripts (`*.toml`) in a directory on a pool of worker
+/// threads, one per available CPU.
+fn check_script_dir(dir: &Path) -> io::Result<Vec<(PathBuf, bool, String)>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(check_in_parallel(&paths, check_script))
+}
+
+/// Runs `check` on a path, failing the script if it panics.
+fn check_isolated(path: &Path, check: impl Fn(&Path) -> (bool, String)) -> (bool, String) {
+    match panic::catch_unwind(AssertUnwindSafe(|| check(path))) {
+        Ok(result) => result,
+        Err(payload) => {
+            let report = format!("{}: panicked: {}\n", path.display(), panic_message(payload));
+            (false, report)
+        }
+    }
+}
+
+/// Checks every path with `check` on a pool of worker threads. A panic fails
+/// the script of its path instead of ending the run.
+fn check_in_parallel(
+    paths: &[PathBuf],
+    check: impl Fn(&Path) -> (bool, String) + Sync,
+) -> Vec<(PathBuf, bool, String)> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..workers.min(paths.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let path = match paths.get(index) {
+                    Some(path) => path,
+                    None => break,
+                };
+                let (passed, report) = check_isolated(path, &check);
+                results.lock().unwrap().push((path.clone(), passed, report));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    results
+}
+
+/// Runs the test script at the given path, or all test scripts in the given
+/// directory, and prints the result of every assertion. Returns whether all
+/// assertions held.
+pub fn run_script(path: &str) -> bool {
+    let path = Path::new(path);
+    if !path.is_dir() {
+        let (passed, report) = check_script(path);
+        print!("{}", report);
+        return passed;
+    }
+    let start = Instant::now();
+    let results = match check_script_dir(path) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Cannot read test scripts in {}: {}", path.display(), e);
+            return false;
+        }
+    };
+    if results.is_empty() {
+        eprintln!("No test scripts (*.toml) in {}", path.display());
+        return false;
+    }
+    for (script_path, _, report) in results.iter() {
+        println!("== {}", script_path.display());
+        print!("{}", report);
+    }
+    let passed = results.iter().filter(|(_, passed, _)| *passed).count();
+    println!(
+        "{} of {} script(s) passed in {:?}.",
+        passed,
+        results.len(),
+        start.elapsed()
+    );
+    passed == results.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_assertion() {
+        let vm = VirtualMachine::new(&[0x12, 0x00]);
+        let assertion = |memory| Assertion::Memory {
+            memory,
+            equals: 0x12,
+        };
+        assert_eq!(assertion(0x200).check(&vm), Ok(()));
+        assert_eq!(
+            assertion(0x201).check(&vm),
+            Err(String::from("actual value is 0x0"))
+        );
+        assert_eq!(
+            assertion(0xFFF).check(&vm),
+            Err(String::from("actual value is 0x0"))
+        );
+        assert_eq!(
+            assertion(0x1000).check(&vm),
+            Err(String::from("address out of memory"))
+        );
+    }
+    #[test]
+    fn test_panicking_script() {
+        let paths = [PathBuf::from("a.toml"), PathBuf::from("b.toml")];
+        let results = check_in_parallel(&paths, |path| {
+            if path == Path::new("a.toml") {
+                panic!("index out of bounds");
+            }
+            (true, String::from("ok\n"))
+        });
+        assert_eq!(
+            results,
+            [
+                (
+                    paths[0].clone(),
+                    false,
+                    String::from("a.toml: panicked: index out of bounds\n")
+                ),
+                (paths[1].clone(), true, String::from("ok\n")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_dir() {
+        let dir = std::env::temp_dir().join(format!("chip8-scripts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let passed = run_script(dir.to_str().unwrap());
+        fs::remove_dir(&dir).unwrap();
+        assert!(!passed);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::assets::Assets;
+use crate::thread_options::ThreadOptions;
+use chip8::visualizer::{key_from_name, Hotkeys};
+use serde::Deserialize;
+use sfml::window::Key;
+use std::{fs, io};
+
+/// The user settings, read from the config directory if present.
+const SETTINGS_FILENAME: &str = "settings.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    #[serde(default)]
+    hotkeys: HotkeySettings,
+    #[serde(default)]
+    emulation_thread: ThreadOptions,
+}
+
+/// Hotkeys are given by their SFML key name (e.g. "F12"); an empty string
+/// disables a hotkey.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct HotkeySettings {
+    keypad_info: Option<String>,
+    record_macro: Option<String>,
+    play_macro: Option<String>,
+    screenshot: Option<String>,
+    debug_overlay: Option<String>,
+    pause: Option<String>,
+    save_state: Option<String>,
+    load_state: Option<String>,
+    next_slot: Option<String>,
+    rewind: Option<String>,
+    remap: Option<String>,
+}
+
+/// Turns a hotkey setting into a key, keeping the default if it is not set.
+fn parse_hotkey(name: &Option<String>, default: Option<Key>) -> Result<Option<Key>, String> {
+    match name.as_deref() {
+        None => Ok(default),
+        Some("") => Ok(None),
+        Some(name) => key_from_name(name)
+            .map(Some)
+            .ok_or_else(|| format!("unknown key \"{}\"", name)),
+    }
+}
+
+fn load_settings(assets: &Assets) -> Result<Settings, String> {
+    match fs::read_to_string(assets.config_file(SETTINGS_FILENAME)) {
+        Ok(text) => toml::from_str(&text).map_err(|e| e.to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Loads the options of the emulation thread from the settings file. Invalid
+/// settings are reported and replaced by the defaults.
+pub fn load_thread_options(assets: &Assets) -> ThreadOptions {
+    match load_settings(assets) {
+        Ok(settings) => settings.emulation_thread,
+        Err(e) => {
+            eprintln!("Cannot load {}: {}", SETTINGS_FILENAME, e);
+            ThreadOptions::default()
+        }
+    }
+}
+
+/// Loads the hotkeys from the settings file. Invalid settings are reported
+/// and replaced by the defaults.
+pub fn load_hotkeys(assets: &Assets) -> Hotkeys {
+    let default = Hotkeys::default();
+    let hotkeys = load_settings(assets).and_then(|settings| {
+        Ok(Hotkeys {
+            keypad_info: parse_hotkey(&settings.hotkeys.keypad_info, default.keypad_info)?,
+            record_macro: parse_hotkey(&settings.hotkeys.record_macro, default.record_macro)?,
+            play_macro: parse_hotkey(&settings.hotkeys.play_macro, default.play_macro)?,
+            screenshot: parse_hotkey(&settings.hotkeys.screenshot, default.screenshot)?,
+            debug_overlay: parse_hotkey(&settings.hotkeys.debug_overlay, default.debug_overlay)?,
+            pause: parse_hotkey(&settings.hotkeys.pause, default.pause)?,
+            save_state: parse_hotkey(&settings.hotkeys.save_state, default.save_state)?,
+            load_state: parse_hotkey(&settings.hotkeys.load_state, default.load_state)?,
+            next_slot: parse_hotkey(&settings.hotkeys.next_slot, default.next_slot)?,
+            rewind: parse_hotkey(&settings.hotkeys.rewind, default.rewind)?,
+            remap: parse_hotkey(&settings.hotkeys.remap, default.remap)?,
+        })
+    });
+    match hotkeys {
+        Ok(hotkeys) => hotkeys,
+        Err(e) => {
+            eprintln!("Cannot load {}: {}", SETTINGS_FILENAME, e);
+            default
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::assets::Assets;
+use crate::rom_config::state_name;
+use chip8::emulator::save_state::SaveState;
+use chip8::visualizer::{slot_file, write_save_state, SAVE_SLOTS};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The file that `state export` writes a slot of a ROM to, in the working
+/// directory, and that `state import` reads it back from.
+fn export_file(rom_name: &str, slot: u8) -> PathBuf {
+    PathBuf::from(format!("{}.slot{}.json", rom_name, slot))
+}
+
+fn parse_slot(slot: &str) -> Result<u8, String> {
+    match slot.parse() {
+        Ok(slot) if slot < SAVE_SLOTS => Ok(slot),
+        _ => Err(format!(
+            "invalid slot \"{}\", expected 0 to {}",
+            slot,
+            SAVE_SLOTS - 1
+        )),
+    }
+}
+
+/// The ROM and slot of a file written by `state export`, from its name.
+fn parse_export_file(path: &Path) -> Result<(String, u8), String> {
+    let invalid = || {
+        format!(
+            "cannot tell the ROM and slot from the name of {}, expected <rom>.slot<N>.json",
+            path.display()
+        )
+    };
+    let stem = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".json"))
+        .ok_or_else(invalid)?;
+    let (rom_name, slot) = stem.rsplit_once(".slot").ok_or_else(invalid)?;
+    if rom_name.is_empty() {
+        return Err(invalid());
+    }
+    Ok((rom_name.to_lowercase(), parse_slot(slot)?))
+}
+
+/// Copies the state in a slot in `dir` to `output`, checking that it can be
+/// loaded.
+fn export_slot(dir: &Path, slot: u8, output: &Path) -> Result<(), String> {
+    let path = slot_file(dir, slot);
+    let json =
+        fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let state = SaveState::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+    fs::write(output, state.to_json())
+        .map_err(|e| format!("cannot write {}: {}", output.display(), e))
+}
+
+/// Checks the state in `input` like loading it would and writes it to a
+/// slot in `dir`, along with a new thumbnail of its logical display; there
+/// is no window whose colors it could show.
+fn import_slot(input: &Path, dir: &Path, slot: u8) -> Result<PathBuf, String> {
+    let json =
+        fs::read_to_string(input).map_err(|e| format!("cannot read {}: {}", input.display(), e))?;
+    let state = SaveState::from_json(&json).map_err(|e| format!("{}: {}", input.display(), e))?;
+    write_save_state(dir, slot, &state, None)
+        .map_err(|e| format!("cannot write slot {}: {}", slot, e))
+}
+
+/// Exports a save state slot of a ROM to `<rom>.slot<N>.json` in the working
+/// directory for editing. Returns false if the slot cannot be read.
+pub fn export_state(assets: &Assets, rom: &str, slot: &str) -> bool {
+    let rom_name = state_name(rom);
+    let result = parse_slot(slot).and_then(|slot| {
+        let output = export_file(&rom_name, slot);
+        export_slot(&assets.save_state_dir(&rom_name), slot, &output).map(|()| output)
+    });
+    match result {
+        Ok(output) => {
+            println!("Wrote {}", output.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("Cannot export the state: {}", e);
+            false
+        }
+    }
+}
+
+/// Imports a file written by `state export` back into the slot it came
+/// from. Returns false if it is no valid save state.
+pub fn import_state(assets: &Assets, path: &str) -> bool {
+    let path = Path::new(path);
+    let result = parse_export_file(path)
+        .and_then(|(rom_name, slot)| import_slot(path, &assets.save_state_dir(&rom_name), slot));
+    match result {
+        Ok(slot) => {
+            println!("Wrote {}", slot.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("Cannot import the state: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::emulator::vm::VirtualMachine;
+    use std::{env, process};
+
+    #[test]
+    fn test_parse_export_file() {
+        assert_eq!(
+            parse_export_file(Path::new("saves/Pong.slot3.json")),
+            Ok((String::from("pong"), 3))
+        );
+        assert_eq!(
+            parse_export_file(Path::new("a.slot.b.slot0.json")),
+            Ok((String::from("a.slot.b"), 0))
+        );
+        assert!(parse_export_file(Path::new("pong.json")).is_err());
+        assert!(parse_export_file(Path::new(".slot1.json")).is_err());
+        assert!(parse_export_file(Path::new("pong.slot10.json")).is_err());
+        assert!(parse_export_file(Path::new("pong.slot1.txt")).is_err());
+    }
+
+    #[test]
+    fn test_export_import() {
+        let dir = env::temp_dir().join(format!("chip8-states-{}", process::id()));
+        let file = dir.join("export.json");
+        let mut vm = VirtualMachine::new(&[0x60, 0x12, 0x12, 0x02]);
+        vm.step().unwrap();
+        write_save_state(&dir.join("from"), 1, &vm.save_state(), None).unwrap();
+
+        export_slot(&dir.join("from"), 1, &file).unwrap();
+        let edited = fs::read_to_string(&file).unwrap().replacen(
+            "\"program_counter\": 514",
+            "\"program_counter\": 768",
+            1,
+        );
+        fs::write(&file, edited).unwrap();
+        import_slot(&file, &dir.join("to"), 2).unwrap();
+        let json = fs::read_to_string(slot_file(&dir.join("to"), 2)).unwrap();
+        let state = SaveState::from_json(&json).unwrap();
+        assert_eq!(state.program_counter, 0x300);
+        assert_eq!(state.registers[0], 0x12);
+        assert!(dir.join("to/slot2.pgm").is_file());
+
+        // Invalid edits are rejected before the slot is written.
+        fs::write(&file, json.replacen("\"version\": 2", "\"version\": 1", 1)).unwrap();
+        assert!(import_slot(&file, &dir.join("to"), 3).is_err());
+        assert!(!slot_file(&dir.join("to"), 3).exists());
+        assert!(export_slot(&dir.join("from"), 4, &file).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,489 @@
+use chip8::emulator::basics::{Address, MEMORY_SIZE};
+use chip8::emulator::core::Chip8Core;
+use chip8::emulator::debugger::{Condition, Debugger, MemorySearch, SearchFilter, Stop};
+use chip8::emulator::program::Instruction;
+use chip8::emulator::vm::VirtualMachine;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+
+/// How many instructions are listed before and after the program counter.
+const DISASSEMBLY_BEFORE: u16 = 4;
+const DISASSEMBLY_AFTER: u16 = 8;
+/// How many instructions `disasm` lists by default.
+const DISASM_COUNT: u16 = 16;
+/// How long `continue` runs without reaching a breakpoint, in frames.
+const CONTINUE_FRAMES: u32 = 60 * 60;
+/// How many addresses `find` lists at most.
+const FIND_LISTED: usize = 32;
+
+const HELP: &str = "\
+Commands:
+  step [N]          execute N instructions (default 1); an empty line steps once
+  continue          run until a breakpoint, a key wait or for a minute of frames
+  break ADDR|COND   stop at an address or when a condition like 'V3 == 0x1F' becomes true
+  delete ADDR|COND  remove a breakpoint
+  mem ADDR [LEN]    dump LEN bytes of memory (default 16)
+  disasm ADDR [N]   disassemble N instructions of the current memory (default 16)
+  find VALUE        narrow the memory search to the addresses holding VALUE
+  find changed|unchanged|increased|decreased
+                    narrow the memory search by comparing with the last search
+  find new          start a new memory search with all addresses
+  find B1 B2 ...    list the addresses at which a byte pattern occurs
+  key K...|none     hold exactly the CHIP-8 keys K down, or release all
+  keys              show the keys the program sees held, latched and queued
+  help              show this text
+  quit              leave the debugger";
+
+/// Parses a number written in hex with a "0x" prefix or in decimal.
+fn parse_number(text: &str) -> Result<u16, String> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("invalid number '{}'", text))
+}
+
+fn parse_address(text: &str) -> Result<Address, String> {
+    match parse_number(text)? {
+        addr if (addr as usize) < MEMORY_SIZE => Ok(Address(addr)),
+        addr => Err(format!("address {:#X} is out of memory", addr)),
+    }
+}
+
+/// The screen drawn with half block characters, two pixel rows per line.
+/// Lines with a pixel in `target` are drawn on a blue background.
+fn render_screen(vm: &VirtualMachine, target: &[(u8, u8)]) -> String {
+    let (width, height) = vm.screen_size();
+    let mut screen = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let bottom = y + 1 < height && vm.pixel(x, y + 1);
+            let block = match (vm.pixel(x, y), bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            };
+            if target.contains(&(x, y)) || target.contains(&(x, y + 1)) {
+                let _ = write!(screen, "\x1b[44m{}\x1b[0m", block);
+            } else {
+                screen.push(block);
+            }
+        }
+        screen.push('\n');
+    }
+    screen
+}
+
+/// If the next instruction is DXYN, the sprite it draws, zoomed to two
+/// characters per pixel, along with the screen positions of the rectangle it
+/// draws to.
+fn render_sprite(vm: &VirtualMachine) -> Option<(String, Vec<(u8, u8)>)> {
+    let (vx, vy, n) = match vm.current_instruction() {
+        Ok(Instruction::Draw(vx, vy, n)) => (vx, vy, n.0),
+        _ => return None,
+    };
+    let (x, y) = vm.sprite_position(vx, vy, 0, 0)?;
+    let mut preview = format!(
+        "Sprite at I={:#05X}, {} rows, drawn to ({}, {}):\n",
+        vm.get_register_i().0,
+        n,
+        x,
+        y
+    );
+    for row in vm.sprite_rows(n) {
+        let pixels: String = (0..8)
+            .map(|x_off| match row & (128 >> x_off) {
+                0 => "··",
+                _ => "██",
+            })
+            .collect();
+        let _ = writeln!(preview, "  {:02X} {}", row, pixels);
+    }
+    let target = (0..n)
+        .flat_map(|y_off| (0..8).map(move |x_off| (x_off, y_off)))
+        .filter_map(|(x_off, y_off)| vm.sprite_position(vx, vy, x_off, y_off))
+        .collect();
+    Some((preview, target))
+}
+
+/// The disassembly around the program counter, with the next instruction
+/// marked by '>' and breakpoints by '*'.
+fn render_disassembly(debugger: &mut Debugger) -> String {
+    let pc = debugger.vm().program_counter();
+    let start = Address(pc.0.saturating_sub(2 * DISASSEMBLY_BEFORE));
+    let count = (pc.0 - start.0) / 2 + DISASSEMBLY_AFTER;
+    let lines = debugger.disassemble(start, count);
+    let mut disassembly = String::new();
+    for (addr, text) in lines {
+        let _ = writeln!(
+            disassembly,
+            "{}{} {:#05X}  {}",
+            if addr == pc { '>' } else { ' ' },
+            if debugger.breakpoints().contains(addr) {
+                '*'
+            } else {
+                ' '
+            },
+            addr.0,
+            text
+        );
+    }
+    disassembly
+}
+
+/// Lists `count` instructions decoded from the current memory from `start`.
+fn render_instructions(debugger: &Debugger, start: Address, count: u16) -> String {
+    let mut listing = String::new();
+    for (addr, text) in debugger.disassemble(start, count) {
+        let _ = writeln!(listing, "{:#05X}  {}", addr.0, text);
+    }
+    listing
+}
+
+fn render_memory(debugger: &Debugger, start: Address, len: u16) -> String {
+    let bytes = debugger.memory(start, len);
+    let mut dump = String::new();
+    for (row, values) in bytes.chunks(16).enumerate() {
+        let values: Vec<_> = values
+            .iter()
+            .map(|value| format!("{:02X}", value.0))
+            .collect();
+        let _ = writeln!(
+            dump,
+            "{:#05X}  {}",
+            start.0 as usize + 16 * row,
+            values.join(" ")
+        );
+    }
+    dump
+}
+
+/// The keypad bit mask, the latched key and the key events that the program
+/// has not seen yet.
+fn render_keys(vm: &VirtualMachine) -> String {
+    let interface = vm.interface.lock().unwrap();
+    let held: Vec<_> = (0..16u8)
+        .filter(|key| interface.keys_down[*key as usize])
+        .map(|key| format!("{:X}", key))
+        .collect();
+    let latched = interface
+        .latched_key
+        .map_or("none".to_string(), |key| format!("{:X}", key));
+    let pending: Vec<_> = interface
+        .key_events
+        .pending()
+        .map(|event| {
+            let action = if event.pressed { "pressed" } else { "released" };
+            format!("{:X} {}", event.key, action)
+        })
+        .collect();
+    format!(
+        "Keypad: {:#06X} (held: {})\nLatched: {}\nQueued events: {}",
+        interface.keypad(),
+        if held.is_empty() {
+            "none".to_string()
+        } else {
+            held.join(" ")
+        },
+        latched,
+        if pending.is_empty() {
+            "none".to_string()
+        } else {
+            pending.join(", ")
+        }
+    )
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    match parse_number(text)? {
+        byte if byte <= 0xFF => Ok(byte as u8),
+        _ => Err(format!("'{}' is not a byte", text)),
+    }
+}
+
+/// Lists addresses along with the bytes they currently hold.
+fn render_addresses(debugger: &Debugger, addresses: &[Address]) -> String {
+    let listed: Vec<_> = addresses
+        .iter()
+        .take(FIND_LISTED)
+        .map(|addr| format!("{:#05X}={:02X}", addr.0, debugger.vm().memory(*addr).0))
+        .collect();
+    let mut text = format!("{} address(es): {}", addresses.len(), listed.join(" "));
+    if addresses.len() > FIND_LISTED {
+        text.push_str(" ...");
+    }
+    text
+}
+
+/// Executes a `find` command: searches a byte pattern or narrows down the
+/// ongoing memory search.
+fn find(
+    debugger: &Debugger,
+    search: &mut Option<MemorySearch>,
+    args: &[&str],
+) -> Result<String, String> {
+    let filter = match args {
+        [] => None,
+        ["new"] => {
+            *search = Some(MemorySearch::new(debugger.vm()));
+            return Ok("Started a new search.".to_string());
+        }
+        ["changed"] => Some(SearchFilter::Changed),
+        ["unchanged"] => Some(SearchFilter::Unchanged),
+        ["increased"] => Some(SearchFilter::Increased),
+        ["decreased"] => Some(SearchFilter::Decreased),
+        [value] => Some(SearchFilter::Equal(parse_byte(value)?)),
+        pattern => {
+            let pattern = pattern
+                .iter()
+                .map(|byte| parse_byte(byte))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(render_addresses(debugger, &debugger.find(&pattern)));
+        }
+    };
+    let search = match (search, filter) {
+        (Some(search), _) => search,
+        (search, Some(SearchFilter::Equal(_))) => search.insert(MemorySearch::new(debugger.vm())),
+        (search, _) => {
+            *search = Some(MemorySearch::new(debugger.vm()));
+            return Ok("Started a new search; run the program and search again.".to_string());
+        }
+    };
+    if let Some(filter) = filter {
+        search.narrow(debugger.vm(), filter);
+    }
+    Ok(render_addresses(
+        debugger,
+        &search.candidates().collect::<Vec<_>>(),
+    ))
+}
+
+/// Clears the terminal and draws the state of the VM and the output of the
+/// last command.
+fn redraw(debugger: &mut Debugger, message: &str) {
+    let mut stack: Vec<_> = debugger
+        .stack()
+        .iter()
+        .map(|addr| format!("{:#05X}", addr.0))
+        .collect();
+    if stack.is_empty() {
+        stack.push("empty".to_string());
+    }
+    let mut breakpoints: Vec<_> = debugger
+        .breakpoints()
+        .iter()
+        .map(|addr| format!("{:#05X}", addr.0))
+        .collect();
+    breakpoints.extend(debugger.breakpoints().conditions().map(|c| c.to_string()));
+    let (sprite, target) = render_sprite(debugger.vm()).unwrap_or_default();
+    print!("\x1b[2J\x1b[H");
+    print!("{}", render_screen(debugger.vm(), &target));
+    println!("{}", "─".repeat(debugger.vm().screen_size().0 as usize));
+    print!("{}", render_disassembly(debugger));
+    print!("{}", sprite);
+    println!();
+    print!("{}", debugger.vm().state_summary(true));
+    println!("Stack: {}", stack.join(" "));
+    println!(
+        "Pixels erased by the last draw: {}",
+        debugger.vm().draw_collisions()
+    );
+    println!("Breakpoints: {}", breakpoints.join(", "));
+    if !message.is_empty() {
+        println!("\n{}", message.trim_end());
+    }
+    print!("> ");
+    let _ = io::stdout().flush();
+}
+
+/// Runs whole frames, ticking the timers in between, until the debugger
+/// stops for another reason than the end of a frame or the program waits for
+/// a key that is not pressed.
+fn continue_running(debugger: &mut Debugger, instructions_per_frame: u32) -> String {
+    for _ in 0..CONTINUE_FRAMES {
+        match debugger.run(instructions_per_frame as u64) {
+            Ok(Stop::StepLimit) => {}
+            Ok(Stop::Breakpoint(addr)) => return format!("Breakpoint at {:#05X}.", addr.0),
+            Ok(Stop::Condition(condition)) => return format!("{} became true.", condition),
+            Ok(Stop::Halted) => return "The program halted.".to_string(),
+            Err(e) => return format!("Error: {}", e),
+        }
+        debugger.vm().interface.lock().unwrap().tick_timers();
+        if debugger.vm().is_waiting_for_key() {
+            return "The program waits for a key.".to_string();
+        }
+    }
+    format!("Ran {} frames without stopping.", CONTINUE_FRAMES)
+}
+
+/// Executes a command line and returns the text to show below the state,
+/// or `None` to quit.
+fn execute(
+    debugger: &mut Debugger,
+    search: &mut Option<MemorySearch>,
+    line: &str,
+    instructions_per_frame: u32,
+) -> Option<String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("step");
+    let args: Vec<_> = words.collect();
+    let result = match command {
+        "step" | "s" => args
+            .first()
+            .map_or(Ok(1), |count| parse_number(count))
+            .and_then(|count| {
+                for _ in 0..count {
+                    debugger.step().map_err(|e| format!("Error: {}", e))?;
+                }
+                Ok(String::new())
+            }),
+        "continue" | "c" => Ok(continue_running(debugger, instructions_per_frame)),
+        "break" | "b" | "delete" | "d" => {
+            let add = command.starts_with('b');
+            let target = args.join(" ");
+            if let Ok(addr) = parse_address(&target) {
+                let changed = if add {
+                    debugger.breakpoints().add(addr)
+                } else {
+                    debugger.breakpoints().remove(addr)
+                };
+                Ok(if changed {
+                    String::new()
+                } else {
+                    "Nothing changed.".to_string()
+                })
+            } else {
+                target.parse::<Condition>().map(|condition| {
+                    let changed = if add {
+                        debugger.breakpoints().add_condition(condition)
+                    } else {
+                        debugger.breakpoints().remove_condition(condition)
+                    };
+                    if changed {
+                        String::new()
+                    } else {
+                        "Nothing changed.".to_string()
+                    }
+                })
+            }
+        }
+        "mem" | "m" => match args.as_slice() {
+            [start] => parse_address(start).map(|start| render_memory(debugger, start, 16)),
+            [start, len] => parse_address(start)
+                .and_then(|start| Ok(render_memory(debugger, start, parse_number(len)?))),
+            _ => Err("usage: mem ADDR [LEN]".to_string()),
+        },
+        "disasm" | "u" => match args.as_slice() {
+            [start] => {
+                parse_address(start).map(|start| render_instructions(debugger, start, DISASM_COUNT))
+            }
+            [start, count] => parse_address(start)
+                .and_then(|start| Ok(render_instructions(debugger, start, parse_number(count)?))),
+            _ => Err("usage: disasm ADDR [N]".to_string()),
+        },
+        "find" | "f" => find(debugger, search, &args),
+        "key" | "k" => {
+            let mut keys = [false; 16];
+            let held = args
+                .iter()
+                .filter(|arg| **arg != "none")
+                .try_for_each(|key| match u8::from_str_radix(key, 16) {
+                    Ok(key) if key < 16 => {
+                        keys[key as usize] = true;
+                        Ok(())
+                    }
+                    _ => Err(format!("invalid key '{}'", key)),
+                });
+            held.map(|()| {
+                debugger.vm().interface.lock().unwrap().keys_down = keys;
+                String::new()
+            })
+        }
+        "keys" => Ok(render_keys(debugger.vm())),
+        "help" | "h" => Ok(HELP.to_string()),
+        "quit" | "q" => return None,
+        _ => Err(format!("Unknown command '{}', try 'help'.", command)),
+    };
+    Some(result.unwrap_or_else(|e| e))
+}
+
+/// Runs an interactive debugger for a VM on the terminal: the screen,
+/// disassembly and registers are shown after every command read from stdin.
+pub fn run_terminal_debugger(vm: VirtualMachine, instructions_per_frame: u32) {
+    let mut debugger = Debugger::new(vm);
+    let mut search = None;
+    redraw(&mut debugger, "Type 'help' for a list of commands.");
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match execute(&mut debugger, &mut search, &line, instructions_per_frame) {
+            Some(message) => redraw(&mut debugger, &message),
+            None => return,
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(debugger: &mut Debugger, line: &str) -> String {
+        execute(debugger, &mut None, line, 10).unwrap()
+    }
+
+    #[test]
+    fn test_disasm() {
+        let program = [
+            0x60, 0x12, // LD V0, 0x12
+            0x61, 0x34, // LD V1, 0x34
+            0xA3, 0x00, // LD I, 0x300
+            0xF1, 0x55, // LD [I], V1
+        ];
+        let mut debugger = Debugger::new(VirtualMachine::new(&program));
+        assert_eq!(run(&mut debugger, "disasm 0x300 1"), "0x300  NOP\n");
+        run(&mut debugger, "step 4");
+        assert_eq!(run(&mut debugger, "u 0x300 1"), "0x300  JP 0x234\n");
+        assert_eq!(run(&mut debugger, "disasm 0x202").lines().count(), 16);
+        assert_eq!(run(&mut debugger, "disasm 0xFFC 4").lines().count(), 2);
+        assert_eq!(
+            run(&mut debugger, "disasm 0x1000"),
+            "address 0x1000 is out of memory"
+        );
+        assert_eq!(run(&mut debugger, "disasm"), "usage: disasm ADDR [N]");
+    }
+
+    #[test]
+    fn test_sprite_preview() {
+        let program = [
+            0x60, 0x3E, // LD V0, 62
+            0x61, 0x01, // LD V1, 1
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD0, 0x12, // DRW V0, V1, 2
+            0x12, 0x08, // JP 0x208
+            0xF0, 0x90, // the sprite
+        ];
+        let mut debugger = Debugger::new(VirtualMachine::new(&program));
+        assert!(render_sprite(debugger.vm()).is_none());
+        run(&mut debugger, "step 3");
+        let (preview, target) = render_sprite(debugger.vm()).unwrap();
+        assert_eq!(
+            preview,
+            "Sprite at I=0x20A, 2 rows, drawn to (62, 1):\n  \
+             F0 ████████········\n  \
+             90 ██····██········\n"
+        );
+        // The sprite wraps around to the left edge.
+        assert_eq!(target.len(), 16);
+        assert_eq!(target[..3], [(62, 1), (63, 1), (0, 1)]);
+        let screen = render_screen(debugger.vm(), &target);
+        let first_line = screen.lines().next().unwrap();
+        assert_eq!(first_line.matches("\x1b[44m").count(), 8);
+        assert!(first_line.ends_with("\x1b[44m \x1b[0m\x1b[44m \x1b[0m"));
+        run(&mut debugger, "step");
+        assert!(render_sprite(debugger.vm()).is_none());
+    }
+}
@@ -0,0 +1,488 @@
+use chip8::emulator::basics::SCREEN_WIDTH;
+use chip8::emulator::quirks::Quirks;
+use chip8::emulator::vm::{StepOutcome, VirtualMachine};
+use std::fs;
+use std::path::Path;
+
+/// How long a ROM of a suite runs at most before its screen is checked.
+const MAX_FRAMES: u32 = 1800;
+const INSTRUCTIONS_PER_FRAME: u32 = 1000;
+/// How long the key that chooses a test from a menu is held.
+const MENU_KEY_FRAMES: u32 = 5;
+
+/// A run of a ROM of a test suite, whose final screen is compared with the
+/// expected screen `<name>.txt` next to the ROM.
+struct SuiteCase {
+    name: &'static str,
+    rom: &'static str,
+    /// The quirks preset, e.g. "vip".
+    quirks: &'static str,
+    /// The key pressed when the ROM first waits for one, to choose a test
+    /// from its menu.
+    menu_key: Option<u8>,
+    /// Whether the ROM shows its results as check marks and crosses, by
+    /// which it is judged instead of by an expected screen.
+    marks: bool,
+    /// The names of the results that the ROM shows as the last lines with
+    /// marks on the screen, top to bottom.
+    checks: &'static [&'static str],
+}
+
+/// The ROMs of the Timendus chip8-test-suite
+/// (https://github.com/Timendus/chip8-test-suite) that run without a human.
+/// The keypad, beep and scrolling tests are left out. The logos have no
+/// marks, so they are compared with expected screens.
+const TIMENDUS: [SuiteCase; 5] = [
+    SuiteCase {
+        name: "1-chip8-logo",
+        rom: "1-chip8-logo.ch8",
+        quirks: "default",
+        menu_key: None,
+        marks: false,
+        checks: &[],
+    },
+    SuiteCase {
+        name: "2-ibm-logo",
+        rom: "2-ibm-logo.ch8",
+        quirks: "default",
+        menu_key: None,
+        marks: false,
+        checks: &[],
+    },
+    SuiteCase {
+        name: "3-corax+",
+        rom: "3-corax+.ch8",
+        quirks: "default",
+        menu_key: None,
+        marks: true,
+        checks: &[],
+    },
+    SuiteCase {
+        name: "4-flags",
+        rom: "4-flags.ch8",
+        quirks: "default",
+        menu_key: None,
+        marks: true,
+        checks: &[],
+    },
+    // Key 1 chooses the CHIP-8 tests, which expect the quirks of the COSMAC
+    // VIP.
+    SuiteCase {
+        name: "5-quirks-chip8",
+        rom: "5-quirks.ch8",
+        quirks: "vip",
+        menu_key: Some(1),
+        marks: true,
+        checks: &[
+            "vF reset",
+            "memory",
+            "display wait",
+            "clipping",
+            "shifting",
+            "jumping",
+        ],
+    },
+];
+
+/// Renders the screen like the expected files: one line per row, `#` for lit
+/// and `.` for dark pixels.
+fn render(vm: &VirtualMachine) -> String {
+    let mut screen = String::new();
+    for row in vm.framebuffer().chunks(SCREEN_WIDTH as usize) {
+        screen.extend(row.iter().map(|lit| if *lit { '#' } else { '.' }));
+        screen.push('\n');
+    }
+    screen
+}
+
+/// Runs a ROM until it jumps to itself or `MAX_FRAMES` have passed, pressing
+/// the menu key of the case once the ROM waits for a key, and returns its
+/// screen.
+fn run(case: &SuiteCase, program: &[u8]) -> Result<String, String> {
+    let quirks = Quirks::preset(case.quirks)
+        .ok_or_else(|| format!("unknown quirks preset \"{}\"", case.quirks))?;
+    let mut vm = VirtualMachine::with_quirks(program, quirks);
+    vm.set_seed(0);
+    // The frame at which the menu key was pressed.
+    let mut menu_pressed: Option<u32> = None;
+    for frame in 0..MAX_FRAMES {
+        if let (Some(key), Some(pressed)) = (case.menu_key, menu_pressed) {
+            vm.interface.lock().unwrap().keys_down[key as usize] =
+                frame < pressed + MENU_KEY_FRAMES;
+        }
+        for _ in 0..INSTRUCTIONS_PER_FRAME {
+            match vm.step() {
+                Ok(StepOutcome::Halted) => return Ok(render(&vm)),
+                Ok(StepOutcome::WaitingForKey) if menu_pressed.is_none() => {
+                    menu_pressed = case.menu_key.map(|_| frame + 1);
+                }
+                Ok(_) => (),
+                Err(e) => return Err(format!("{} at frame {}\n{}", e, frame, render(&vm))),
+            }
+        }
+        vm.interface.lock().unwrap().tick_timers();
+    }
+    Ok(render(&vm))
+}
+
+/// The lines of text on two screens, as ranges of rows that are dark on both
+/// screens above and below.
+fn text_lines(expected: &str, actual: &str) -> Vec<(usize, usize)> {
+    let blank = |row: Option<&str>| row.is_none_or(|row| !row.contains('#'));
+    let (expected, actual): (Vec<_>, Vec<_>) =
+        (expected.lines().collect(), actual.lines().collect());
+    let rows = expected.len().max(actual.len());
+    let mut lines = Vec::new();
+    let mut start = None;
+    for y in 0..=rows {
+        let dark = y == rows || blank(expected.get(y).copied()) && blank(actual.get(y).copied());
+        match (start, dark) {
+            (None, false) => start = Some(y),
+            (Some(top), true) => {
+                lines.push((top, y));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    lines
+}
+
+/// Describes which lines of text differ between the screens.
+fn breakdown(expected: &str, actual: &str) -> Vec<String> {
+    let (expected_rows, actual_rows): (Vec<_>, Vec<_>) =
+        (expected.lines().collect(), actual.lines().collect());
+    text_lines(expected, actual)
+        .iter()
+        .enumerate()
+        .map(|(index, (top, bottom))| {
+            let same = (*top..*bottom).all(|y| expected_rows.get(y) == actual_rows.get(y));
+            let result = if same { "ok    " } else { "FAILED" };
+            format!("  {} line {}", result, index + 1)
+        })
+        .collect()
+}
+
+/// A result that the ROMs of the suite draw after a check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mark {
+    Check,
+    Cross,
+}
+
+/// Recognizes a glyph, given as rows trimmed to its lit pixels, as a mark.
+///
+/// The marks are told apart from letters by their shape: a cross is a
+/// square that is symmetric along both axes, with lit corners and center
+/// and dark edge centers. A check mark has a short arm falling from the
+/// left to its lowest point and a long arm rising from there to the top
+/// right corner, with strokes at most half as high as the glyph.
+fn mark(glyph: &[Vec<bool>]) -> Option<Mark> {
+    let height = glyph.len();
+    let width = glyph.first().map_or(0, Vec::len);
+    if width < 3 || height < 3 {
+        return None;
+    }
+    let lit = |x: usize, y: usize| glyph[y][x];
+    let symmetric = (0..height).all(|y| {
+        (0..width)
+            .all(|x| lit(x, y) == lit(width - 1 - x, y) && lit(x, y) == lit(x, height - 1 - y))
+    });
+    let (center_x, center_y) = (width / 2, height / 2);
+    if width == height
+        && symmetric
+        && lit(0, 0)
+        && lit(center_x, center_y)
+        && !lit(center_x, 0)
+        && !lit(0, center_y)
+    {
+        return Some(Mark::Cross);
+    }
+    // The lit rows of every column, which must be one short run.
+    let runs = (0..width)
+        .map(|x| {
+            let rows: Vec<_> = (0..height).filter(|y| lit(x, *y)).collect();
+            let (top, bottom) = (*rows.first()?, *rows.last()?);
+            let stroke = bottom - top + 1 == rows.len() && rows.len() <= height.div_ceil(2);
+            stroke.then_some((top, bottom))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let lowest = runs.iter().position(|(_, bottom)| *bottom == height - 1)?;
+    let falls = runs[..=lowest]
+        .windows(2)
+        .all(|pair| pair[0].1 <= pair[1].1);
+    let rises = runs[lowest..].windows(2).all(|pair| pair[0].0 >= pair[1].0);
+    let check = 0 < lowest
+        && lowest < width - 1
+        && runs[0].0 > 0
+        && runs[width - 1].0 == 0
+        && falls
+        && rises;
+    check.then_some(Mark::Check)
+}
+
+/// The glyphs of a line of text, given as its rows: the runs of columns
+/// with a lit pixel, each trimmed to its lit rows.
+fn glyphs(rows: &[Vec<bool>]) -> Vec<Vec<Vec<bool>>> {
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let lit = |x: usize, row: &Vec<bool>| row.get(x).copied().unwrap_or(false);
+    let mut glyphs = Vec::new();
+    let mut start = None;
+    for x in 0..=width {
+        let column_lit = x < width && rows.iter().any(|row| lit(x, row));
+        match (start, column_lit) {
+            (None, true) => start = Some(x),
+            (Some(left), false) => {
+                let glyph: Vec<Vec<bool>> = rows
+                    .iter()
+                    .map(|row| (left..x).map(|x| lit(x, row)).collect())
+                    .filter(|row: &Vec<bool>| row.contains(&true))
+                    .collect();
+                glyphs.push(glyph);
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    glyphs
+}
+
+/// The marks on every line of text of a screen that has any.
+fn marked_lines(screen: &str) -> Vec<Vec<Mark>> {
+    let rows: Vec<Vec<bool>> = screen
+        .lines()
+        .map(|row| row.chars().map(|c| c == '#').collect())
+        .collect();
+    text_lines(screen, screen)
+        .into_iter()
+        .map(|(top, bottom)| {
+            glyphs(&rows[top..bottom])
+                .iter()
+                .filter_map(|glyph| mark(glyph))
+                .collect()
+        })
+        .filter(|marks: &Vec<Mark>| !marks.is_empty())
+        .collect()
+}
+
+/// Judges a screen by its marks. Returns whether every line with marks has
+/// no cross and there are lines for all checks of the case, along with a
+/// line per marked line, naming the last ones after the checks.
+fn judge(case: &SuiteCase, screen: &str) -> (bool, Vec<String>) {
+    let lines = marked_lines(screen);
+    if lines.len() < case.checks.len().max(1) {
+        let found = format!(
+            "  found {} line(s) with check marks or crosses, expected {}",
+            lines.len(),
+            case.checks.len().max(1)
+        );
+        return (false, vec![found]);
+    }
+    // The index of the line of the first check.
+    let first_check = lines.len() - case.checks.len();
+    let report = lines
+        .iter()
+        .enumerate()
+        .map(|(index, marks)| {
+            let name = match index.checked_sub(first_check) {
+                Some(i) => case.checks[i].to_string(),
+                None => format!("line {}", index + 1),
+            };
+            let result = if marks.contains(&Mark::Cross) {
+                "FAILED"
+            } else {
+                "ok    "
+            };
+            format!("  {} {}", result, name)
+        })
+        .collect();
+    let passed = lines.iter().all(|marks| !marks.contains(&Mark::Cross));
+    (passed, report)
+}
+
+/// Runs a case and judges its screen by its marks, or compares it with the
+/// expected one, which is written instead if `update` is set. Returns whether
+/// it passed, along with a report.
+fn check(case: &SuiteCase, dir: &Path, update: bool) -> (bool, String) {
+    let rom = dir.join(case.rom);
+    let program = match fs::read(&rom) {
+        Ok(program) => program,
+        Err(e) => return (false, format!("cannot read {}: {}", rom.display(), e)),
+    };
+    let actual = match run(case, &program) {
+        Ok(actual) => actual,
+        Err(e) => return (false, e),
+    };
+    if case.marks {
+        return match judge(case, &actual) {
+            (true, _) => (true, String::new()),
+            (false, report) => (false, format!("{}\n{}", report.join("\n"), actual)),
+        };
+    }
+    let expected_file = dir.join(format!("{}.txt", case.name));
+    if update {
+        return match fs::write(&expected_file, &actual) {
+            Ok(()) => (true, format!("wrote {}", expected_file.display())),
+            Err(e) => (
+                false,
+                format!("cannot write {}: {}", expected_file.display(), e),
+            ),
+        };
+    }
+    match fs::read_to_string(&expected_file) {
+        Ok(expected) if expected == actual => (true, String::new()),
+        Ok(expected) => {
+            let mut report = breakdown(&expected, &actual).join("\n");
+            report.push_str(&format!("\nexpected\n{}but got\n{}", expected, actual));
+            (false, report)
+        }
+        Err(e) => (
+            false,
+            format!(
+                "cannot read {}: {}; check the screen by hand and write it with --update\n{}",
+                expected_file.display(),
+                e,
+                actual
+            ),
+        ),
+    }
+}
+
+/// Runs the ROMs of a test suite from `dir` and prints the result of every
+/// ROM. With `update`, the screens of the ROMs without marks are recorded as
+/// the expected ones.
+/// Returns whether all of them passed.
+pub fn run_test_suite(suite: &str, dir: &str, update: bool) -> bool {
+    let cases = match suite {
+        "timendus" => &TIMENDUS,
+        _ => {
+            eprintln!("Unknown test suite \"{}\", expected \"timendus\"", suite);
+            return false;
+        }
+    };
+    let mut passed = 0;
+    for case in cases.iter() {
+        let (ok, report) = check(case, Path::new(dir), update);
+        println!("{}  {}", if ok { "pass" } else { "FAIL" }, case.name);
+        if !report.is_empty() {
+            println!("{}", report.trim_end());
+        }
+        if ok {
+            passed += 1;
+        }
+    }
+    println!("{} of {} ROMs passed.", passed, cases.len());
+    passed == cases.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_lines() {
+        let expected = "....\n.##.\n....\n#...\n#...\n";
+        let actual = "....\n.##.\n..#.\n....\n#...\n";
+        assert_eq!(text_lines(expected, actual), [(1, 5)]);
+        assert_eq!(text_lines(expected, expected), [(1, 2), (3, 5)]);
+        assert_eq!(text_lines("....\n", "....\n"), []);
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let expected = "#.\n..\n#.\n..\n#.\n";
+        let actual = "#.\n..\n.#\n..\n#.\n";
+        assert_eq!(
+            breakdown(expected, actual),
+            ["  ok     line 1", "  FAILED line 2", "  ok     line 3"]
+        );
+    }
+
+    /// Draws lines of glyphs, given as rows of `#` and `.`, with a dark
+    /// column between glyphs and a dark row between lines.
+    fn screen(lines: &[&[&str]]) -> String {
+        let mut screen = String::new();
+        for glyphs in lines {
+            let height = glyphs
+                .iter()
+                .map(|glyph| glyph.lines().count())
+                .max()
+                .unwrap();
+            for y in 0..height {
+                let row: Vec<_> = glyphs
+                    .iter()
+                    .map(|glyph| {
+                        let width = glyph.lines().next().unwrap().len();
+                        glyph.lines().nth(y).map_or(".".repeat(width), String::from)
+                    })
+                    .collect();
+                screen.push_str(&row.join("."));
+                screen.push('\n');
+            }
+            screen.push_str("..\n");
+        }
+        screen
+    }
+
+    const CHECK: &str = "....#\n...#.\n#.#..\n.#...";
+    const CROSS: &str = "#...#\n.#.#.\n..#..\n.#.#.\n#...#";
+    /// Letters of the 3x5 font of the suite that resemble marks.
+    const LETTER_J: &str = "..#\n..#\n..#\n#.#\n.#.";
+    const LETTER_V: &str = "#.#\n#.#\n#.#\n#.#\n.#.";
+    const LETTER_X: &str = "#.#\n#.#\n.#.\n#.#\n#.#";
+
+    #[test]
+    fn test_marks() {
+        let glyph = |text: &str| -> Vec<Vec<bool>> {
+            text.lines()
+                .map(|row| row.chars().map(|c| c == '#').collect())
+                .collect()
+        };
+        assert_eq!(mark(&glyph(CHECK)), Some(Mark::Check));
+        assert_eq!(mark(&glyph(CROSS)), Some(Mark::Cross));
+        let thick_check = "....##\n...##.\n##.##.\n.###..\n..#...";
+        assert_eq!(mark(&glyph(thick_check)), Some(Mark::Check));
+        for letter in [LETTER_J, LETTER_V, LETTER_X, "###\n#.#\n###"] {
+            assert_eq!(mark(&glyph(letter)), None, "{}", letter);
+        }
+    }
+
+    #[test]
+    fn test_marked_lines() {
+        let screen = screen(&[
+            &[LETTER_J, LETTER_V],
+            &[LETTER_V, CHECK, LETTER_X],
+            &[CROSS, CHECK],
+        ]);
+        assert_eq!(
+            marked_lines(&screen),
+            [vec![Mark::Check], vec![Mark::Cross, Mark::Check]]
+        );
+    }
+
+    #[test]
+    fn test_judge() {
+        let case = &TIMENDUS[4];
+        let mut lines: Vec<&[&str]> = vec![&[LETTER_J]];
+        lines.extend([&[LETTER_V, CHECK] as &[&str]; 6]);
+        let (passed, report) = judge(case, &screen(&lines));
+        assert!(passed);
+        assert_eq!(report.len(), 6);
+        assert_eq!(report[0], "  ok     vF reset");
+
+        lines[2] = &[LETTER_V, CROSS];
+        let (passed, report) = judge(case, &screen(&lines));
+        assert!(!passed);
+        assert_eq!(report[1], "  FAILED memory");
+        assert_eq!(report[5], "  ok     jumping");
+
+        let (passed, report) = judge(case, &screen(&lines[..4]));
+        assert!(!passed);
+        assert_eq!(
+            report,
+            ["  found 3 line(s) with check marks or crosses, expected 6"]
+        );
+        let (passed, _) = judge(&TIMENDUS[2], &screen(&[&[LETTER_J]]));
+        assert!(!passed);
+    }
+}
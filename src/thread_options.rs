@@ -0,0 +1,101 @@
+use serde::Deserialize;
+
+/// Scheduling options for the thread that runs the emulation, to keep its
+/// timing stable on a busy machine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThreadOptions {
+    /// Raise the priority of the thread above that of normal threads.
+    #[serde(default)]
+    pub high_priority: bool,
+    /// Pin the thread to the CPU core with this index.
+    pub core: Option<usize>,
+}
+
+/// The nice value of the emulation thread with `high_priority`.
+#[cfg(target_os = "linux")]
+const HIGH_PRIORITY_NICE: libc::c_int = -10;
+
+impl ThreadOptions {
+    /// Applies the options to the calling thread. Returns a warning for every
+    /// option that could not be applied, e.g. for lack of permissions.
+    pub fn apply(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.high_priority {
+            if let Err(e) = raise_priority() {
+                warnings.push(format!("Cannot raise the emulation thread priority: {}", e));
+            }
+        }
+        if let Some(core) = self.core {
+            if let Err(e) = pin_to_core(core) {
+                warnings.push(format!(
+                    "Cannot pin the emulation thread to core {}: {}",
+                    core, e
+                ));
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn raise_priority() -> Result<(), String> {
+    // On Linux, the nice value set for a thread ID only applies to that thread.
+    let result = unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, tid, HIGH_PRIORITY_NICE)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) -> Result<(), String> {
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if core >= 8 * std::mem::size_of::<libc::cpu_set_t>() {
+            return Err("no such core".to_string());
+        }
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn raise_priority() -> Result<(), String> {
+    Err("not supported on this platform".to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_core: usize) -> Result<(), String> {
+    Err("not supported on this platform".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_applies_nothing() {
+        assert!(ThreadOptions::default().apply().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_core() {
+        let options = ThreadOptions {
+            high_priority: false,
+            core: Some(usize::MAX),
+        };
+        let warnings = options.apply();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Cannot pin the emulation thread to core"));
+    }
+}
@@ -0,0 +1,115 @@
+use chip8::emulator::clock::VirtualClock;
+use chip8::emulator::executor::Executor;
+use chip8::emulator::pgm::{self, Rgb};
+use chip8::emulator::testing::ScriptedKeypad;
+use chip8::emulator::vm::VirtualMachine;
+use chip8::visualizer::filter::{frame_thumbnail, lit_frame, FilterChain};
+use sfml::graphics::Color;
+use std::{
+    fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const THUMBNAIL_FACTOR: u8 = 2;
+/// How long a ROM runs before its display is captured.
+const PREVIEW_TIME: Duration = Duration::from_secs(3);
+
+/// How a thumbnail shows the display.
+pub enum ThumbnailStyle {
+    /// Like the window: the frames pass through the filters that fade and
+    /// color them, on top of the background color.
+    Shown {
+        filters: FilterChain,
+        background: Color,
+    },
+    /// The logical display, white on black.
+    Raw,
+}
+
+/// The cached thumbnail of a ROM: a PPM image of what the window shows, or a
+/// PGM image of the logical display if `raw` is set.
+fn thumbnail_path(dir: &Path, rom_name: &str, raw: bool) -> PathBuf {
+    let extension = if raw { "pgm" } else { "ppm" };
+    dir.join(format!("{}.{}", rom_name, extension))
+}
+
+/// Runs a ROM headlessly for a few seconds, pressing the startup keys once
+/// per timer interval, and returns a thumbnail of its display, encoded as
+/// an image in the style. Returns `None` if the ROM crashes the VM.
+fn render_thumbnail(
+    program: &[u8],
+    instruction_sleep: Duration,
+    timer_interval: Duration,
+    startup_input: &ScriptedKeypad,
+    style: ThumbnailStyle,
+) -> Option<Vec<u8>> {
+    let vm = VirtualMachine::new(program);
+    let mut executor =
+        Executor::with_clock(instruction_sleep, timer_interval, vm, VirtualClock::new());
+    let mut startup_input = startup_input.clone();
+    let mut style = style;
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let frames = PREVIEW_TIME.as_nanos() / timer_interval.as_nanos();
+        let mut shown: Vec<Vec<Rgb>> = Vec::new();
+        for _ in 0..frames {
+            startup_input.next_frame(&mut executor.core().interface().lock().unwrap());
+            executor.run_for(timer_interval);
+            if let ThumbnailStyle::Shown {
+                filters,
+                background,
+            } = &mut style
+            {
+                let core = executor.core();
+                let (width, height) = core.screen_size();
+                let mut frame = lit_frame(width, height, |x, y| core.pixel(x, y));
+                filters.apply(&mut frame);
+                shown = frame_thumbnail(&frame, width, height, *background, THUMBNAIL_FACTOR);
+            }
+        }
+        match style {
+            ThumbnailStyle::Shown { .. } => pgm::encode_rgb(&shown),
+            ThumbnailStyle::Raw => pgm::encode(&executor.core().thumbnail(THUMBNAIL_FACTOR)),
+        }
+    }))
+    .ok()
+}
+
+/// Reads the cached thumbnail of a ROM from `dir`, of the logical display if
+/// `raw` is set, or `None` if there is none or it cannot be read.
+pub fn read_thumbnail(dir: &Path, rom_name: &str, raw: bool) -> Option<Vec<Vec<Rgb>>> {
+    let image = fs::read(thumbnail_path(dir, rom_name, raw)).ok()?;
+    pgm::decode_rgb(&image).ok()
+}
+
+/// Creates the cached thumbnail of a ROM in `dir` in a style, unless it
+/// already exists.
+pub fn cache_thumbnail(
+    dir: &Path,
+    rom_name: &str,
+    program: &[u8],
+    instruction_sleep: Duration,
+    timer_interval: Duration,
+    startup_input: &ScriptedKeypad,
+    style: ThumbnailStyle,
+) -> io::Result<()> {
+    let path = thumbnail_path(dir, rom_name, matches!(style, ThumbnailStyle::Raw));
+    if path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    match render_thumbnail(
+        program,
+        instruction_sleep,
+        timer_interval,
+        startup_input,
+        style,
+    ) {
+        Some(thumbnail) => fs::write(&path, thumbnail),
+        None => Err(io::Error::other(format!(
+            "{} crashed while rendering its thumbnail",
+            rom_name
+        ))),
+    }
+}
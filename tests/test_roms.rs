@@ -0,0 +1,116 @@
+extern crate chip8;
+use chip8::emulator::{
+    basics::SCREEN_WIDTH,
+    quirks::Quirks,
+    vm::{StepOutcome, VirtualMachine},
+};
+use serde::Deserialize;
+use std::{env, fs, path::Path};
+
+/// The test ROMs and their expected screens, relative to the package root.
+const CASES_DIR: &str = "tests/emulator";
+const CASES_FILE: &str = "tests/emulator/cases.toml";
+
+/// Set to write the actual screens to the expected files instead of
+/// comparing them, e.g. after adding a case.
+const UPDATE_VARIABLE: &str = "UPDATE_EXPECTED";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CasesFile {
+    #[serde(rename = "case")]
+    cases: Vec<Case>,
+}
+
+/// A test ROM that runs headlessly until it jumps to itself, then shows the
+/// screen in `expected`: one line per row, `#` for lit and `.` for dark
+/// pixels.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    rom: String,
+    expected: String,
+    /// Fails the case if the ROM has not halted after this many steps.
+    #[serde(default = "default_max_steps")]
+    max_steps: u64,
+    /// The quirks preset, e.g. "vip".
+    #[serde(default = "default_quirks")]
+    quirks: String,
+}
+
+fn default_max_steps() -> u64 {
+    100_000
+}
+
+fn default_quirks() -> String {
+    String::from("default")
+}
+
+fn render(vm: &VirtualMachine) -> String {
+    let mut screen = String::new();
+    for row in vm.framebuffer().chunks(SCREEN_WIDTH as usize) {
+        screen.extend(row.iter().map(|lit| if *lit { '#' } else { '.' }));
+        screen.push('\n');
+    }
+    screen
+}
+
+/// Runs a case and returns its screen, or why it did not halt.
+fn run(case: &Case) -> Result<String, String> {
+    let rom = Path::new(CASES_DIR).join(&case.rom);
+    let program = fs::read(&rom).map_err(|e| format!("cannot read {}: {}", rom.display(), e))?;
+    let quirks = Quirks::preset(&case.quirks)
+        .ok_or_else(|| format!("unknown quirks preset \"{}\"", case.quirks))?;
+    let mut vm = VirtualMachine::with_quirks(&program, quirks);
+    vm.set_seed(0);
+    for _ in 0..case.max_steps {
+        match vm.step() {
+            Ok(StepOutcome::Halted) => return Ok(render(&vm)),
+            Ok(_) => (),
+            Err(e) => return Err(format!("{}\n{}", e, render(&vm))),
+        }
+    }
+    Err(format!(
+        "not halted after {} steps\n{}",
+        case.max_steps,
+        render(&vm)
+    ))
+}
+
+/// Checks a case, or updates its expected screen. Returns the failure.
+fn check(case: &Case, update: bool) -> Option<String> {
+    let actual = match run(case) {
+        Ok(actual) => actual,
+        Err(e) => return Some(e),
+    };
+    let expected_file = Path::new(CASES_DIR).join(&case.expected);
+    if update {
+        return fs::write(&expected_file, &actual)
+            .err()
+            .map(|e| format!("cannot write {}: {}", expected_file.display(), e));
+    }
+    match fs::read_to_string(&expected_file) {
+        Ok(expected) if expected == actual => None,
+        Ok(expected) => Some(format!("expected\n{}but got\n{}", expected, actual)),
+        Err(e) => Some(format!("cannot read {}: {}", expected_file.display(), e)),
+    }
+}
+
+#[test]
+fn test_roms() {
+    let text = fs::read_to_string(CASES_FILE).unwrap();
+    let file: CasesFile = toml::from_str(&text).unwrap();
+    let update = env::var_os(UPDATE_VARIABLE).is_some();
+    let failures: Vec<_> = file
+        .cases
+        .iter()
+        .filter_map(|case| check(case, update).map(|failure| format!("{}: {}", case.rom, failure)))
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} ROMs failed:\n{}",
+        failures.len(),
+        file.cases.len(),
+        failures.join("\n")
+    );
+}